@@ -0,0 +1,115 @@
+use std::sync::{Arc, OnceLock};
+
+use http::{Method, Response, StatusCode};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use tracing::info;
+
+use crate::{fanout::FanoutWrite, split::BuilderSplit};
+
+/// The path prefix of the backend endpoints, followed by `{index}/enable` or `{index}/disable`.
+pub const BACKENDS_PATH_PREFIX: &str = "/backends/";
+
+/// The path of the builder split endpoint, optionally followed by `/{weight}`.
+pub const BUILDER_SPLIT_PATH: &str = "/builder-split";
+
+/// Admin endpoints served without authentication on the metrics listener.
+///
+/// `POST /backends/{index}/disable` drains the builder at the given index from
+/// the fanout, and `POST /backends/{index}/enable` restores it.
+///
+/// `GET /builder-split` returns the percentage of requests sent to the new
+/// builders during a cutover, and `POST /builder-split/{weight}` changes it.
+#[derive(Clone, Debug, Default)]
+pub struct Admin {
+    builders: Arc<OnceLock<FanoutWrite>>,
+    builder_split: Arc<OnceLock<BuilderSplit>>,
+}
+
+impl Admin {
+    /// Sets the builder fanout whose targets are enabled and disabled.
+    ///
+    /// The fanout shares its targets with the RPC listeners, so reloads are
+    /// picked up without setting it again.
+    pub fn set_builders(&self, fanout: &FanoutWrite) {
+        let _ = self.builders.set(fanout.clone());
+    }
+
+    /// Sets the [`BuilderSplit`] whose weight is read and changed.
+    pub fn set_builder_split(&self, builder_split: &BuilderSplit) {
+        let _ = self.builder_split.set(builder_split.clone());
+    }
+
+    /// Returns the admin response for the request, if it is an admin path.
+    pub fn response(&self, method: &Method, path: &str) -> Option<Response<Full<Bytes>>> {
+        if let Some(weight) = path
+            .strip_prefix(BUILDER_SPLIT_PATH)
+            .filter(|weight| weight.is_empty() || weight.starts_with('/'))
+        {
+            return self.builder_split_response(method, weight);
+        }
+
+        let (index, action) = path.strip_prefix(BACKENDS_PATH_PREFIX)?.split_once('/')?;
+        let enabled = match action {
+            "enable" => true,
+            "disable" => false,
+            _ => return None,
+        };
+        if method != Method::POST {
+            return Some(response(StatusCode::METHOD_NOT_ALLOWED, "Use POST"));
+        }
+
+        let client = index.parse::<usize>().ok().and_then(|index| {
+            self.builders
+                .get()
+                .and_then(|fanout| fanout.targets().get(index).cloned())
+        });
+        let Some(client) = client else {
+            return Some(response(StatusCode::NOT_FOUND, "Unknown backend"));
+        };
+
+        client.set_enabled(enabled);
+        info!(target: "tx-proxy::admin", url = %client.display_url(), index, enabled, "Updated backend");
+        Some(response(
+            StatusCode::OK,
+            if enabled { "enabled" } else { "disabled" },
+        ))
+    }
+
+    fn builder_split_response(
+        &self,
+        method: &Method,
+        weight: &str,
+    ) -> Option<Response<Full<Bytes>>> {
+        let Some(split) = self.builder_split.get() else {
+            return Some(response(StatusCode::NOT_FOUND, "No builder split"));
+        };
+
+        match (method, weight.strip_prefix('/')) {
+            (&Method::GET, None) => Some(response(StatusCode::OK, split.weight().to_string())),
+            (&Method::POST, Some(weight)) => {
+                let Some(weight) = weight
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|weight| split.set_weight(*weight).is_ok())
+                else {
+                    return Some(response(
+                        StatusCode::BAD_REQUEST,
+                        "Weight must be between 0 and 100",
+                    ));
+                };
+                info!(target: "tx-proxy::admin", weight, "Updated builder split");
+                Some(response(StatusCode::OK, weight.to_string()))
+            }
+            (_, None) => Some(response(StatusCode::METHOD_NOT_ALLOWED, "Use GET")),
+            (_, Some(_)) => Some(response(StatusCode::METHOD_NOT_ALLOWED, "Use POST")),
+        }
+    }
+}
+
+fn response(status: StatusCode, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .body(Full::new(body.into()))
+        .unwrap()
+}
@@ -1,23 +1,119 @@
-use jsonwebtoken::{Algorithm, DecodingKey, Validation, errors::ErrorKind};
+#[cfg(feature = "cli")]
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, errors::ErrorKind};
+#[cfg(feature = "cli")]
 use pin_project::pin_project;
+#[cfg(feature = "cli")]
 use std::{
+    collections::HashSet,
     pin::Pin,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use std::{
+    sync::Arc,
     task::{Context, Poll},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
-use alloy_rpc_types_engine::{Claims, JwtError, JwtSecret};
-use http::{HeaderMap, Response, StatusCode, header};
+use alloy_primitives::{hex, keccak256};
+#[cfg(feature = "cli")]
+use alloy_rpc_types_engine::JwtError;
+use alloy_rpc_types_engine::{Claims, JwtSecret};
+use http::{Extensions, HeaderValue, header};
+#[cfg(feature = "cli")]
+use http::{HeaderMap, Response, StatusCode};
+#[cfg(feature = "cli")]
 use jsonrpsee::{
     http_client::{HttpBody, HttpResponse},
     server::HttpRequest,
 };
+#[cfg(feature = "cli")]
+use serde::Deserialize;
 use tower::{Layer, Service};
 use tracing::error;
 
+#[cfg(feature = "cli")]
+use crate::metrics::ProxyMetrics;
+
+/// The default tolerance in seconds for `iat` claims issued ahead of the local clock.
+#[cfg(feature = "cli")]
+pub const DEFAULT_JWT_CLOCK_SKEW_SECS: u64 = 5;
+
+/// How long before its expiry a cached outbound token is re-signed.
+#[cfg(feature = "cli")]
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5);
+
+/// How long a cached outbound token without an `exp` claim is reused, keeping
+/// its `iat` claim within the tolerance of targets checking it.
+#[cfg(feature = "cli")]
+const TOKEN_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The identity of requests on listeners without authentication.
+pub const ANONYMOUS_IDENTITY: &str = "anonymous";
+
+/// The identity of authenticated requests whose identity claim is missing or not known.
+#[cfg(feature = "cli")]
+pub const UNKNOWN_IDENTITY: &str = "unknown";
+
+/// The caller of a request, used to label metrics and logs.
+///
+/// Attached to authenticated requests as an extension by [`AuthService`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CallerIdentity(String);
+
+impl CallerIdentity {
+    /// Returns the identity attached to a request, or [`ANONYMOUS_IDENTITY`] if
+    /// the request was not authenticated.
+    pub fn from_extensions(extensions: &Extensions) -> Self {
+        extensions
+            .get::<Self>()
+            .cloned()
+            .unwrap_or_else(|| Self(ANONYMOUS_IDENTITY.to_string()))
+    }
+
+    /// Returns the identity as a label value.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Reads the caller identity from a claim of inbound tokens.
+///
+/// Only the known identities are reported as is, any other or missing value is
+/// reported as [`UNKNOWN_IDENTITY`] to bound the cardinality of metric labels.
+#[cfg(feature = "cli")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IdentityClaim {
+    claim: String,
+    known: Arc<HashSet<String>>,
+}
+
+#[cfg(feature = "cli")]
+impl IdentityClaim {
+    /// Creates a new [`IdentityClaim`] reading the given claim, e.g. `sub` or `iss`.
+    pub fn new(claim: impl Into<String>, known: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            claim: claim.into(),
+            known: Arc::new(known.into_iter().collect()),
+        }
+    }
+
+    fn identity(&self, claims: &serde_json::Map<String, serde_json::Value>) -> CallerIdentity {
+        let identity = claims
+            .get(&self.claim)
+            .and_then(serde_json::Value::as_str)
+            .filter(|identity| self.known.contains(*identity))
+            .unwrap_or(UNKNOWN_IDENTITY);
+        CallerIdentity(identity.to_string())
+    }
+}
+
+#[cfg(feature = "cli")]
 pub struct AuthLayer {
     validator: JwtAuthValidator,
 }
 
+#[cfg(feature = "cli")]
 impl AuthLayer {
     /// Creates an instance of [`AuthLayer`].
     pub const fn new(validator: JwtAuthValidator) -> Self {
@@ -25,6 +121,7 @@ impl AuthLayer {
     }
 }
 
+#[cfg(feature = "cli")]
 impl<S> Layer<S> for AuthLayer {
     type Service = AuthService<S>;
 
@@ -38,6 +135,7 @@ impl<S> Layer<S> for AuthLayer {
 
 /// This type is the actual implementation of the middleware. It follows the [`Service`]
 /// specification to correctly proxy Http requests to its inner service after headers validation.
+#[cfg(feature = "cli")]
 #[derive(Clone, Debug)]
 pub struct AuthService<S> {
     /// Performs auth validation logics
@@ -46,6 +144,7 @@ pub struct AuthService<S> {
     inner: S,
 }
 
+#[cfg(feature = "cli")]
 impl<S> Service<HttpRequest> for AuthService<S>
 where
     S: Service<HttpRequest, Response = HttpResponse>,
@@ -67,15 +166,19 @@ where
     /// Returns a future that wraps either:
     /// - The inner service future for authorized requests
     /// - An error Http response in case of authorization errors
-    fn call(&mut self, req: HttpRequest) -> Self::Future {
-        match self.validator.validate(req.headers()) {
-            Ok(_) => ResponseFuture::future(self.inner.call(req)),
+    fn call(&mut self, mut req: HttpRequest) -> Self::Future {
+        match self.validator.authenticate(req.headers()) {
+            Ok(identity) => {
+                req.extensions_mut().insert(identity);
+                ResponseFuture::future(self.inner.call(req))
+            }
             Err(res) => ResponseFuture::invalid_auth(res),
         }
     }
 }
 
 /// A future representing the response of an RPC request
+#[cfg(feature = "cli")]
 #[pin_project]
 pub struct ResponseFuture<F> {
     /// The kind of response future, error or pending
@@ -83,6 +186,7 @@ pub struct ResponseFuture<F> {
     kind: Kind<F>,
 }
 
+#[cfg(feature = "cli")]
 impl<F> ResponseFuture<F> {
     const fn future(future: F) -> Self {
         Self {
@@ -99,6 +203,7 @@ impl<F> ResponseFuture<F> {
     }
 }
 
+#[cfg(feature = "cli")]
 #[pin_project(project = KindProj)]
 enum Kind<F> {
     Future {
@@ -110,6 +215,7 @@ enum Kind<F> {
     },
 }
 
+#[cfg(feature = "cli")]
 impl<F, E> Future for ResponseFuture<F>
 where
     F: Future<Output = Result<HttpResponse, E>>,
@@ -130,25 +236,71 @@ where
 /// Implements JWT validation logics and integrates
 /// to an Http [`AuthLayer`][crate::AuthLayer]
 /// by implementing the [`AuthValidator`] trait.
+#[cfg(feature = "cli")]
 #[derive(Debug, Clone)]
 pub struct JwtAuthValidator {
     secret: JwtSecret,
+    clock_skew_secs: u64,
+    /// Records the age of accepted tokens when set.
+    metrics: Option<Arc<ProxyMetrics>>,
+    /// Reads the caller identity from the accepted tokens when set.
+    identity_claim: Option<IdentityClaim>,
 }
 
+#[cfg(feature = "cli")]
 impl JwtAuthValidator {
     /// Creates a new instance of [`JwtAuthValidator`].
     /// Validation logics are implemented by the `secret`
     /// argument (see [`JwtSecret`]).
     pub const fn new(secret: JwtSecret) -> Self {
-        Self { secret }
+        Self {
+            secret,
+            clock_skew_secs: DEFAULT_JWT_CLOCK_SKEW_SECS,
+            metrics: None,
+            identity_claim: None,
+        }
+    }
+
+    /// Sets the tolerance in seconds for `iat` claims issued ahead of the local clock.
+    pub const fn with_clock_skew(mut self, clock_skew_secs: u64) -> Self {
+        self.clock_skew_secs = clock_skew_secs;
+        self
+    }
+
+    /// Records the age of accepted tokens, `now - iat`, to `jwt_age_seconds`.
+    pub fn with_age_metrics(mut self, metrics: Arc<ProxyMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Sets the [`IdentityClaim`] the caller identity is read from. Without it,
+    /// every authenticated caller is [`UNKNOWN_IDENTITY`].
+    pub fn with_identity_claim(mut self, identity_claim: Option<IdentityClaim>) -> Self {
+        self.identity_claim = identity_claim;
+        self
     }
 }
 
+#[cfg(feature = "cli")]
 impl JwtAuthValidator {
     pub fn validate(&self, headers: &HeaderMap) -> Result<(), HttpResponse> {
+        self.authenticate(headers).map(|_| ())
+    }
+
+    /// Validates the JWT like [`JwtAuthValidator::validate`] and returns the caller identity.
+    pub fn authenticate(&self, headers: &HeaderMap) -> Result<CallerIdentity, HttpResponse> {
+        let clock_skew_secs = self.clock_skew_secs;
         match get_bearer(headers) {
-            Some(jwt) => match validate(&self.secret, &jwt) {
-                Ok(_) => Ok(()),
+            Some(jwt) => match decode_inbound(&self.secret, &jwt, clock_skew_secs) {
+                Ok(claims) => {
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_jwt_age(unix_now().saturating_sub(claims.claims.iat) as f64);
+                    }
+                    Ok(match &self.identity_claim {
+                        Some(identity_claim) => identity_claim.identity(&claims.other),
+                        None => CallerIdentity(UNKNOWN_IDENTITY.to_string()),
+                    })
+                }
                 Err(e) => {
                     error!(target: "tx-proxy::jwt-validator", "Invalid JWT: {e}");
                     let response = err_response(e);
@@ -165,37 +317,349 @@ impl JwtAuthValidator {
     }
 }
 
+#[cfg(feature = "cli")]
 pub fn validate(secret: &JwtSecret, jwt: &str) -> Result<(), JwtError> {
-    let validation = Validation::new(Algorithm::HS256);
+    validate_with_clock_skew(secret, jwt, DEFAULT_JWT_CLOCK_SKEW_SECS)
+}
+
+/// Validates the JWT signature and claims, tolerating `iat` claims
+/// up to `clock_skew_secs` ahead of the local clock.
+#[cfg(feature = "cli")]
+pub fn validate_with_clock_skew(
+    secret: &JwtSecret,
+    jwt: &str,
+    clock_skew_secs: u64,
+) -> Result<(), JwtError> {
+    decode_with_clock_skew(secret, jwt, clock_skew_secs).map(|_| ())
+}
+
+/// Validates the JWT like [`validate_with_clock_skew`] and returns its claims.
+#[cfg(feature = "cli")]
+pub fn decode_with_clock_skew(
+    secret: &JwtSecret,
+    jwt: &str,
+    clock_skew_secs: u64,
+) -> Result<Claims, JwtError> {
+    decode_inbound(secret, jwt, clock_skew_secs).map(|claims| claims.claims)
+}
+
+/// The claims of an inbound token, including the ones not checked by validation.
+#[cfg(feature = "cli")]
+#[derive(Deserialize)]
+struct InboundClaims {
+    #[serde(flatten)]
+    claims: Claims,
+    #[serde(flatten)]
+    other: serde_json::Map<String, serde_json::Value>,
+}
+
+#[cfg(feature = "cli")]
+fn decode_inbound(
+    secret: &JwtSecret,
+    jwt: &str,
+    clock_skew_secs: u64,
+) -> Result<InboundClaims, JwtError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = clock_skew_secs;
     let bytes = secret.as_bytes();
 
-    if let Err(err) =
-        jsonwebtoken::decode::<Claims>(jwt, &DecodingKey::from_secret(bytes), &validation)
-    {
-        match *err.kind() {
+    let claims = match jsonwebtoken::decode::<InboundClaims>(
+        jwt,
+        &DecodingKey::from_secret(bytes),
+        &validation,
+    ) {
+        Ok(token) => token.claims,
+        Err(err) => match *err.kind() {
             ErrorKind::InvalidSignature => Err(JwtError::InvalidSignature)?,
             ErrorKind::InvalidAlgorithm => Err(JwtError::UnsupportedSignatureAlgorithm)?,
             _ => {
                 let detail = format!("{err}");
                 Err(JwtError::JwtDecodingError(detail))?
             }
-        }
+        },
     };
 
-    Ok(())
+    if claims.claims.iat > unix_now() + clock_skew_secs {
+        Err(JwtError::InvalidIssuanceTimestamp)?
+    }
+
+    Ok(claims)
+}
+
+/// Returns the first 8 hex characters of a hash of the secret, identifying
+/// the secret in logs without revealing it.
+pub fn fingerprint(secret: &JwtSecret) -> String {
+    hex::encode(&keccak256(secret.as_bytes())[..4])
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// How requests to a target are authenticated, by [`OutboundJwtLayer`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OutboundAuth {
+    /// Tokens with only an `iat` claim, as signed by rollup-boost, signed per request.
+    RollupBoostDefault(JwtSecret),
+    /// Tokens with the configured claims, requires the `cli` feature.
+    #[cfg(feature = "cli")]
+    Custom(ClaimsConfig),
+}
+
+impl OutboundAuth {
+    /// Returns the secret tokens are signed with.
+    pub fn secret(&self) -> &JwtSecret {
+        match self {
+            Self::RollupBoostDefault(secret) => secret,
+            #[cfg(feature = "cli")]
+            Self::Custom(config) => &config.secret,
+        }
+    }
+}
+
+impl From<JwtSecret> for OutboundAuth {
+    fn from(secret: JwtSecret) -> Self {
+        Self::RollupBoostDefault(secret)
+    }
+}
+
+/// The claims signed into the tokens sent to a target.
+#[cfg(feature = "cli")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ClaimsConfig {
+    secret: JwtSecret,
+    /// The `iss` claim, omitted if unset.
+    issuer: Option<String>,
+    /// Sets the `exp` claim this long after `iat`, tokens do not expire if unset.
+    lifetime: Option<Duration>,
+    /// Static claims added to every token.
+    extra: serde_json::Map<String, serde_json::Value>,
+}
+
+#[cfg(feature = "cli")]
+impl ClaimsConfig {
+    /// Creates a [`ClaimsConfig`] signing tokens with only an `iat` claim.
+    pub fn new(secret: JwtSecret) -> Self {
+        Self {
+            secret,
+            issuer: None,
+            lifetime: None,
+            extra: serde_json::Map::new(),
+        }
+    }
+
+    /// Sets the `iss` claim.
+    pub fn with_issuer(mut self, issuer: Option<String>) -> Self {
+        self.issuer = issuer;
+        self
+    }
+
+    /// Sets the token lifetime, added to `iat` for the `exp` claim.
+    pub fn with_lifetime(mut self, lifetime: Option<Duration>) -> Self {
+        self.lifetime = lifetime;
+        self
+    }
+
+    /// Adds static claims to every token. `iat`, `exp` and `iss` are always
+    /// taken from the other settings.
+    pub fn with_extra_claims(mut self, extra: serde_json::Map<String, serde_json::Value>) -> Self {
+        self.extra = extra;
+        self
+    }
+
+    /// Signs a token issued at `iat`.
+    fn encode(&self, iat: u64) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut claims = self.extra.clone();
+        claims.insert("iat".to_string(), iat.into());
+        claims.remove("exp");
+        if let Some(lifetime) = self.lifetime {
+            claims.insert("exp".to_string(), (iat + lifetime.as_secs()).into());
+        }
+        claims.remove("iss");
+        if let Some(issuer) = &self.issuer {
+            claims.insert("iss".to_string(), issuer.clone().into());
+        }
+
+        jsonwebtoken::encode(
+            &Header::new(Algorithm::HS256),
+            &claims,
+            &EncodingKey::from_secret(self.secret.as_bytes()),
+        )
+    }
+
+    /// Returns how long a token can be reused before it is re-signed.
+    fn reuse_for(&self) -> Duration {
+        match self.lifetime {
+            Some(lifetime) => lifetime.saturating_sub(TOKEN_REFRESH_MARGIN.min(lifetime / 2)),
+            None => TOKEN_REFRESH_INTERVAL,
+        }
+    }
+}
+
+/// A signed token and when it must be re-signed.
+#[cfg(feature = "cli")]
+#[derive(Debug)]
+struct CachedToken {
+    header: HeaderValue,
+    refresh_at: Instant,
+}
+
+/// Signs tokens from a [`ClaimsConfig`], reusing the last token until shortly before it expires.
+#[cfg(feature = "cli")]
+#[derive(Debug)]
+struct TokenCache {
+    config: ClaimsConfig,
+    cached: Mutex<Option<CachedToken>>,
+}
+
+#[cfg(feature = "cli")]
+impl TokenCache {
+    /// Returns the `Authorization` header value, re-signing the token if it is due.
+    fn authorization(&self) -> Result<HeaderValue, jsonwebtoken::errors::Error> {
+        let mut cached = self.cached.lock().unwrap();
+        if let Some(token) = cached
+            .as_ref()
+            .filter(|token| token.refresh_at > Instant::now())
+        {
+            return Ok(token.header.clone());
+        }
+
+        let header = bearer(&self.config.encode(unix_now())?);
+        *cached = Some(CachedToken {
+            header: header.clone(),
+            refresh_at: Instant::now() + self.config.reuse_for(),
+        });
+        Ok(header)
+    }
+}
+
+/// Returns the `Authorization` header value carrying the token.
+fn bearer(jwt: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!("Bearer {jwt}")).expect("a JWT is a valid header value")
+}
+
+/// Signs the tokens of an [`OutboundAuth`].
+#[derive(Debug)]
+enum Signer {
+    Default(JwtSecret),
+    #[cfg(feature = "cli")]
+    Custom(TokenCache),
+}
+
+impl Signer {
+    /// Returns the `Authorization` header value of the next request.
+    fn authorization(&self) -> Result<HeaderValue, String> {
+        match self {
+            Self::Default(secret) => {
+                let claims = Claims {
+                    iat: unix_now(),
+                    exp: None,
+                };
+                let jwt = secret.encode(&claims).map_err(|err| err.to_string())?;
+                Ok(bearer(&jwt))
+            }
+            #[cfg(feature = "cli")]
+            Self::Custom(cache) => cache.authorization().map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// A [`Layer`] authenticating outbound requests with tokens signed as set by
+/// an [`OutboundAuth`].
+#[derive(Clone, Debug)]
+pub struct OutboundJwtLayer {
+    signer: Arc<Signer>,
+}
+
+impl OutboundJwtLayer {
+    /// Creates a new [`OutboundJwtLayer`] signing tokens as set by `auth`.
+    pub fn new(auth: OutboundAuth) -> Self {
+        let signer = match auth {
+            OutboundAuth::RollupBoostDefault(secret) => Signer::Default(secret),
+            #[cfg(feature = "cli")]
+            OutboundAuth::Custom(config) => Signer::Custom(TokenCache {
+                config,
+                cached: Mutex::new(None),
+            }),
+        };
+        Self {
+            signer: Arc::new(signer),
+        }
+    }
+}
+
+impl<S> Layer<S> for OutboundJwtLayer {
+    type Service = OutboundJwtService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        OutboundJwtService {
+            signer: self.signer.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OutboundJwtService<S> {
+    signer: Arc<Signer>,
+    inner: S,
+}
+
+impl<S, B> Service<http::Request<B>> for OutboundJwtService<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: http::Request<B>) -> Self::Future {
+        match self.signer.authorization() {
+            Ok(authorization) => {
+                req.headers_mut()
+                    .insert(header::AUTHORIZATION, authorization);
+            }
+            // The target rejects the request, which is counted as an auth failure
+            Err(err) => {
+                error!(target: "tx-proxy::jwt-signer", %err, "Failed to sign outbound JWT");
+            }
+        }
+        self.inner.call(req)
+    }
 }
 
 /// This is an utility function that retrieves a bearer
 /// token from an authorization Http header.
+///
+/// The `Bearer` scheme must start the header and is matched case-insensitively.
+/// Requests carrying more than one authorization header are rejected.
+#[cfg(feature = "cli")]
 fn get_bearer(headers: &HeaderMap) -> Option<String> {
-    let header = headers.get(header::AUTHORIZATION)?;
-    let auth: &str = header.to_str().ok()?;
-    let prefix = "Bearer ";
-    let index = auth.find(prefix)?;
-    let token: &str = &auth[index + prefix.len()..];
-    Some(token.into())
+    let mut values = headers.get_all(header::AUTHORIZATION).iter();
+    let header = values.next()?;
+    if values.next().is_some() {
+        error!(target: "tx-proxy::jwt-validator", "Multiple authorization headers provided");
+        return None;
+    }
+
+    let auth: &str = header.to_str().ok()?.trim();
+    let (scheme, token) = auth.split_once(' ')?;
+    if !scheme.eq_ignore_ascii_case("Bearer") {
+        return None;
+    }
+
+    let token = token.trim();
+    (!token.is_empty()).then(|| token.into())
 }
 
+#[cfg(feature = "cli")]
 fn err_response(err: JwtError) -> HttpResponse {
     // We build a response from an error message.
     // We don't cope with headers or other structured fields.
@@ -206,7 +670,7 @@ fn err_response(err: JwtError) -> HttpResponse {
         .expect("This should never happen")
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "cli"))]
 mod tests {
     use super::*;
     use alloy_rpc_types_engine::{Claims, JwtError, JwtSecret};
@@ -231,7 +695,12 @@ mod tests {
         valid_jwt().await;
         missing_jwt_error().await;
         wrong_jwt_signature_error().await;
-        jwt_decode_error().await
+        jwt_decode_error().await;
+        lowercase_bearer_scheme().await;
+        embedded_bearer_scheme_error().await;
+        multiple_authorization_headers_error().await;
+        clock_skew_within_tolerance().await;
+        clock_skew_exceeds_tolerance_error().await
     }
 
     async fn valid_jwt() {
@@ -275,7 +744,153 @@ mod tests {
         assert_eq!(body, "JWT decoding error: InvalidToken".to_string());
     }
 
+    async fn lowercase_bearer_scheme() {
+        let jwt = valid_token(0);
+        let (status, _) = send_request_with_auth(&[format!("bearer {jwt}")]).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    async fn embedded_bearer_scheme_error() {
+        let jwt = valid_token(0);
+        let (status, body) = send_request_with_auth(&[format!("Basic xyzBearer {jwt}")]).await;
+        let expected = JwtError::MissingOrInvalidAuthorizationHeader;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, expected.to_string());
+    }
+
+    async fn multiple_authorization_headers_error() {
+        let jwt = valid_token(0);
+        let (status, body) =
+            send_request_with_auth(&[format!("Bearer {jwt}"), format!("Bearer {jwt}")]).await;
+        let expected = JwtError::MissingOrInvalidAuthorizationHeader;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, expected.to_string());
+    }
+
+    async fn clock_skew_within_tolerance() {
+        let (status, _) = send_request(Some(valid_token(3))).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    async fn clock_skew_exceeds_tolerance_error() {
+        let (status, body) = send_request(Some(valid_token(60))).await;
+        let expected = JwtError::InvalidIssuanceTimestamp;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, expected.to_string());
+    }
+
+    #[test]
+    fn test_get_bearer() {
+        let bearer = |values: &[&str]| {
+            let mut headers = HeaderMap::new();
+            for value in values {
+                headers.append(header::AUTHORIZATION, value.parse().unwrap());
+            }
+            get_bearer(&headers)
+        };
+
+        assert_eq!(bearer(&["Bearer abc"]), Some("abc".to_string()));
+        assert_eq!(bearer(&["bEaReR abc"]), Some("abc".to_string()));
+        assert_eq!(bearer(&["  Bearer   abc  "]), Some("abc".to_string()));
+        assert_eq!(bearer(&["Basic xyzBearer abc"]), None);
+        assert_eq!(bearer(&["Bearer "]), None);
+        assert_eq!(bearer(&["Bearer abc", "Bearer abc"]), None);
+        assert_eq!(bearer(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_outbound_default_claims() {
+        let secret = JwtSecret::from_hex(SECRET).unwrap();
+        let mut service = OutboundJwtLayer::new(secret.into()).layer(tower::service_fn(
+            |req: http::Request<()>| async move {
+                Ok::<_, std::convert::Infallible>(req.headers()[header::AUTHORIZATION].clone())
+            },
+        ));
+
+        let authorization = service.call(http::Request::new(())).await.unwrap();
+        let jwt = authorization
+            .to_str()
+            .unwrap()
+            .strip_prefix("Bearer ")
+            .unwrap();
+        let claims = decode_with_clock_skew(&secret, jwt, 0).unwrap();
+        assert_eq!(claims.exp, None);
+        assert!(claims.iat.abs_diff(to_u64(SystemTime::now())) <= 1);
+    }
+
+    /// Validates a token issued at `iat` and returns the sum and count of recorded JWT ages.
+    fn recorded_jwt_age(iat: u64) -> (f64, u64) {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let validator = JwtAuthValidator::new(JwtSecret::from_hex(SECRET).unwrap())
+            .with_age_metrics(Arc::new(ProxyMetrics::new()));
+
+        let claims = Claims {
+            iat,
+            exp: Some(10000000000),
+        };
+        let jwt = JwtSecret::from_hex(SECRET)
+            .unwrap()
+            .encode(&claims)
+            .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {jwt}").parse().unwrap(),
+        );
+        metrics::with_local_recorder(&recorder, || {
+            let _ = validator.validate(&headers);
+        });
+
+        let rendered = handle.render();
+        let value = |name: &str| {
+            rendered
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.trim().parse::<f64>().ok())
+        };
+        (
+            value("jwt_age_seconds_sum").unwrap_or_default(),
+            value("jwt_age_seconds_count").unwrap_or_default() as u64,
+        )
+    }
+
+    #[test]
+    fn test_jwt_age_metric() {
+        let now = to_u64(SystemTime::now());
+
+        let (age, count) = recorded_jwt_age(now - 3600);
+        assert_eq!(count, 1);
+        assert!((3600.0..3602.0).contains(&age), "{age}");
+
+        let (age, count) = recorded_jwt_age(now);
+        assert_eq!(count, 1);
+        assert!(age < 2.0, "{age}");
+
+        // Tokens issued within the clock skew tolerance record a zero age
+        let (age, count) = recorded_jwt_age(now + 3);
+        assert_eq!(count, 1);
+        assert_eq!(age, 0.0);
+
+        // Rejected tokens are not recorded
+        let (_, count) = recorded_jwt_age(now + 60);
+        assert_eq!(count, 0);
+    }
+
+    /// Returns a token signed with the server secret, issued `skew` seconds in the future.
+    fn valid_token(skew: u64) -> String {
+        let claims = Claims {
+            iat: to_u64(SystemTime::now()) + skew,
+            exp: Some(10000000000),
+        };
+        let secret = JwtSecret::from_hex(SECRET).unwrap();
+        secret.encode(&claims).unwrap()
+    }
+
     async fn send_request(jwt: Option<String>) -> (StatusCode, String) {
+        send_request_with_auth(&[format!("Bearer {}", jwt.unwrap_or_default())]).await
+    }
+
+    async fn send_request_with_auth(auth: &[String]) -> (StatusCode, String) {
         let server = spawn_server().await;
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(1))
@@ -283,14 +898,14 @@ mod tests {
             .unwrap();
 
         let body = r#"{"jsonrpc": "2.0", "method": "greet_melkor", "params": [], "id": 1}"#;
-        let response = client
+        let mut request = client
             .post(format!("http://{AUTH_ADDR}:{AUTH_PORT}"))
-            .bearer_auth(jwt.unwrap_or_default())
             .body(body)
-            .header(header::CONTENT_TYPE, "application/json")
-            .send()
-            .await
-            .unwrap();
+            .header(header::CONTENT_TYPE, "application/json");
+        for value in auth {
+            request = request.header(header::AUTHORIZATION, value);
+        }
+        let response = request.send().await.unwrap();
         let status = response.status();
         let body = response.text().await.unwrap();
 
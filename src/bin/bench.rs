@@ -0,0 +1,301 @@
+//! Drives a sustained `eth_sendRawTransaction` load through the full proxy
+//! stack against in-process mock targets, and reports the end-to-end latency
+//! as JSON on stdout.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use eyre::{Result, eyre};
+use http_body_util::BodyExt;
+use hyper::{body::Incoming, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use jsonrpsee::{
+    core::client::ClientT,
+    http_client::{HttpClient, HttpClientBuilder},
+};
+use serde_json::json;
+use tokio::{net::TcpListener, task::JoinHandle};
+use tx_proxy::{cli::Cli, fanout::SelectionStrategy, probe::Probes};
+
+/// The JWT secret given to the proxy, the mock targets do not check it.
+const JWT_SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+/// How long to wait for background L2 forwards before counting target requests.
+const DRAIN_PERIOD: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Parser)]
+#[clap(about = "Measures the latency the proxy adds over its builder targets")]
+struct Args {
+    /// Number of mock builder targets
+    #[arg(long, default_value_t = 3)]
+    builders: usize,
+
+    /// Artificial latency of each mock builder in milliseconds.
+    ///
+    /// A single value applies to every builder, the last value is repeated otherwise.
+    #[arg(long, value_delimiter = ',', default_values_t = [10])]
+    builder_latency_ms: Vec<u64>,
+
+    /// Fraction of mock builder responses that are JSON-RPC errors
+    #[arg(long, default_value_t = 0.0)]
+    builder_error_rate: f64,
+
+    /// Number of mock L2 targets
+    #[arg(long, default_value_t = 1)]
+    l2s: usize,
+
+    /// Requests sent per second
+    #[arg(long, default_value_t = 100)]
+    rate: u64,
+
+    /// How long to send requests for, in seconds
+    #[arg(long, default_value_t = 10)]
+    duration_secs: u64,
+
+    /// Strategy used to select the response returned to the caller
+    #[arg(long, value_enum, default_value_t = SelectionStrategy::DeclarationOrder)]
+    selection_strategy: SelectionStrategy,
+
+    /// Exit with an error if the p50 latency exceeds the slowest builder
+    /// latency by more than this many milliseconds.
+    #[arg(long)]
+    max_overhead_ms: Option<f64>,
+}
+
+/// A mock target answering every request after a fixed delay.
+struct MockTarget {
+    addr: SocketAddr,
+    requests: Arc<AtomicU64>,
+    join_handle: JoinHandle<()>,
+}
+
+impl Drop for MockTarget {
+    fn drop(&mut self) {
+        self.join_handle.abort();
+    }
+}
+
+impl MockTarget {
+    async fn serve(latency: Duration, error_rate: f64) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(AtomicU64::new(0));
+
+        let counter = requests.clone();
+        let join_handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let counter = counter.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| {
+                        Self::respond(req, counter.clone(), latency, error_rate)
+                    });
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+
+        Ok(Self {
+            addr,
+            requests,
+            join_handle,
+        })
+    }
+
+    async fn respond(
+        req: hyper::Request<Incoming>,
+        counter: Arc<AtomicU64>,
+        latency: Duration,
+        error_rate: f64,
+    ) -> Result<hyper::Response<String>, hyper::Error> {
+        let body = req.into_body().collect().await?.to_bytes();
+        let id = serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|mut body| body.get_mut("id").map(serde_json::Value::take))
+            .unwrap_or_default();
+        tokio::time::sleep(latency).await;
+
+        // Spread errors evenly rather than randomly, so runs are comparable
+        let count = counter.fetch_add(1, Ordering::Relaxed);
+        let is_error =
+            ((count + 1) as f64 * error_rate).floor() > (count as f64 * error_rate).floor();
+        let response = if is_error {
+            json!({ "jsonrpc": "2.0", "error": { "code": -32000, "message": "mock error" }, "id": id })
+        } else {
+            json!({ "jsonrpc": "2.0", "result": format!("0x{count:064x}"), "id": id })
+        };
+
+        Ok(hyper::Response::builder()
+            .header("content-type", "application/json")
+            .body(response.to_string())
+            .expect("valid response"))
+    }
+
+    fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+}
+
+/// Returns the latency at the given percentile of sorted latencies.
+fn percentile(sorted: &[Duration], percentile: f64) -> Duration {
+    if sorted.is_empty() {
+        return Duration::ZERO;
+    }
+    let index = ((sorted.len() - 1) as f64 * percentile).round() as usize;
+    sorted[index]
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+async fn run(args: Args) -> Result<bool> {
+    if args.builders == 0 || args.l2s == 0 || args.rate == 0 {
+        return Err(eyre!("--builders, --l2s and --rate must be positive"));
+    }
+
+    let latencies = (0..args.builders)
+        .map(|index| {
+            let latency = args
+                .builder_latency_ms
+                .get(index)
+                .or(args.builder_latency_ms.last())
+                .copied()
+                .unwrap_or_default();
+            Duration::from_millis(latency)
+        })
+        .collect::<Vec<_>>();
+    let mut builders = Vec::with_capacity(args.builders);
+    for latency in &latencies {
+        builders.push(MockTarget::serve(*latency, args.builder_error_rate).await?);
+    }
+    let mut l2s = Vec::with_capacity(args.l2s);
+    for _ in 0..args.l2s {
+        l2s.push(MockTarget::serve(Duration::ZERO, 0.0).await?);
+    }
+
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+    let mut cli_args = vec![
+        "tx-proxy".to_string(),
+        format!("--builder-jwt-token={JWT_SECRET}"),
+        format!("--l2-jwt-token={JWT_SECRET}"),
+        format!("--http-addr={}", addr.ip()),
+        format!("--http-port={}", addr.port()),
+        format!(
+            "--selection-strategy={}",
+            clap::ValueEnum::to_possible_value(&args.selection_strategy)
+                .expect("strategies are not skipped")
+                .get_name()
+        ),
+    ];
+    cli_args.extend(
+        builders
+            .iter()
+            .map(|target| format!("--builder-urls={}", target.url())),
+    );
+    cli_args.extend(
+        l2s.iter()
+            .map(|target| format!("--l2-urls={}", target.url())),
+    );
+    let cli = Cli::try_parse_from(cli_args)?;
+    let server_handle = cli
+        .serve(
+            None,
+            Arc::new(Default::default()),
+            None,
+            Probes::default(),
+            &cli.targets()?,
+        )
+        .await?;
+
+    let client: HttpClient = HttpClientBuilder::default().build(format!("http://{addr}"))?;
+    let total = args.rate * args.duration_secs;
+    let mut interval = tokio::time::interval(Duration::from_secs(1) / args.rate as u32);
+    let mut requests = Vec::with_capacity(total as usize);
+    for nonce in 0..total {
+        interval.tick().await;
+        let client = client.clone();
+        requests.push(tokio::spawn(async move {
+            let tx = format!("0x{nonce:064x}");
+            let now = Instant::now();
+            let result = client
+                .request::<serde_json::Value, _>("eth_sendRawTransaction", (tx,))
+                .await;
+            (now.elapsed(), result.is_ok())
+        }));
+    }
+
+    let mut response_latencies = Vec::with_capacity(requests.len());
+    let mut errors = 0;
+    for request in requests {
+        let (latency, ok) = request.await?;
+        if ok {
+            response_latencies.push(latency);
+        } else {
+            errors += 1;
+        }
+    }
+    response_latencies.sort();
+
+    tokio::time::sleep(DRAIN_PERIOD).await;
+    server_handle.stop()?;
+
+    let slowest_builder = latencies.iter().max().copied().unwrap_or_default();
+    let p50 = percentile(&response_latencies, 0.50);
+    let overhead = millis(p50) - millis(slowest_builder);
+    let targets = builders
+        .iter()
+        .enumerate()
+        .map(|(index, target)| (format!("builder-{index}"), target))
+        .chain(
+            l2s.iter()
+                .enumerate()
+                .map(|(index, target)| (format!("l2-{index}"), target)),
+        )
+        .map(|(name, target)| (name, target.requests.load(Ordering::Relaxed).into()))
+        .collect::<serde_json::Map<_, _>>();
+
+    let report = json!({
+        "requests": total,
+        "errors": errors,
+        "error_rate": errors as f64 / total as f64,
+        "latency_ms": {
+            "p50": millis(p50),
+            "p95": millis(percentile(&response_latencies, 0.95)),
+            "p99": millis(percentile(&response_latencies, 0.99)),
+            "max": millis(response_latencies.last().copied().unwrap_or_default()),
+        },
+        "slowest_builder_ms": millis(slowest_builder),
+        "overhead_p50_ms": overhead,
+        "targets": targets,
+    });
+    println!("{report}");
+
+    Ok(args
+        .max_overhead_ms
+        .is_none_or(|max_overhead| overhead <= max_overhead))
+}
+
+#[tokio::main]
+async fn main() {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    match run(Args::parse()).await {
+        Ok(true) => {}
+        Ok(false) => {
+            eprintln!("Overhead exceeds --max-overhead-ms");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("Fatal Error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
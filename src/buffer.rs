@@ -0,0 +1,196 @@
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::{StatusCode, header::RETRY_AFTER};
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+    types::{
+        ErrorObject,
+        error::{SERVER_IS_BUSY_CODE, SERVER_IS_BUSY_MSG},
+    },
+};
+use metrics::gauge;
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::metrics::ProxyMetrics;
+use crate::rpc::retry_after_value;
+
+/// The default cap on the bytes of request and response bodies buffered at once.
+pub const DEFAULT_MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+
+/// Accounts for the request and response bodies buffered in memory across all
+/// in-flight requests.
+///
+/// Bodies are reserved with [`BufferBudget::reserve`] once buffered, and released
+/// when the returned [`BufferGuard`] is dropped. Clones share the same count.
+#[derive(Clone, Debug)]
+pub struct BufferBudget {
+    used: Arc<AtomicUsize>,
+    max: usize,
+}
+
+impl BufferBudget {
+    /// Creates a new [`BufferBudget`] shedding requests above `max` buffered bytes.
+    pub fn new(max: usize) -> Self {
+        Self {
+            used: Arc::new(AtomicUsize::new(0)),
+            max,
+        }
+    }
+
+    /// Returns the number of bytes currently buffered.
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns true if new requests must be shed.
+    pub fn is_exhausted(&self) -> bool {
+        self.used() >= self.max
+    }
+
+    /// Accounts for a buffered body until the returned guard is dropped.
+    ///
+    /// Never fails, bodies already read are accounted for even above the cap.
+    pub fn reserve(&self, bytes: usize) -> BufferGuard {
+        let used = self.used.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        gauge!("buffered_body_bytes").set(used as f64);
+        BufferGuard {
+            used: self.used.clone(),
+            bytes,
+        }
+    }
+}
+
+/// Releases the bytes of a buffered body from its [`BufferBudget`] when dropped.
+///
+/// Travels with the buffered body, in [`RpcRequest`](crate::rpc::RpcRequest) or
+/// the extensions of a request or response, shared by clones through an [`Arc`].
+#[derive(Debug)]
+pub struct BufferGuard {
+    used: Arc<AtomicUsize>,
+    bytes: usize,
+}
+
+impl BufferGuard {
+    /// Returns the number of bytes accounted for by the guard.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for BufferGuard {
+    fn drop(&mut self) {
+        let used = self.used.fetch_sub(self.bytes, Ordering::Relaxed) - self.bytes;
+        gauge!("buffered_body_bytes").set(used as f64);
+    }
+}
+
+/// A [`Layer`] that sheds requests while the [`BufferBudget`] is exhausted,
+/// before their bodies are read.
+///
+/// Accepted requests carry the budget in their extensions, so the layers and
+/// targets buffering their bodies account for them.
+#[derive(Clone, Debug)]
+pub struct BufferLayer {
+    pub budget: BufferBudget,
+    pub metrics: Arc<ProxyMetrics>,
+    /// The delay sent in a `Retry-After` header with shed requests, if any.
+    pub retry_after: Option<Duration>,
+}
+
+impl BufferLayer {
+    /// Creates a new [`BufferLayer`] with the given budget.
+    pub fn new(budget: BufferBudget, metrics: Arc<ProxyMetrics>) -> Self {
+        Self {
+            budget,
+            metrics,
+            retry_after: None,
+        }
+    }
+
+    /// Sets the delay clients are asked to wait before retrying a shed request
+    /// with a `Retry-After` header. Not sent if `None`, the default.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+}
+
+impl<S> Layer<S> for BufferLayer {
+    type Service = BufferService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        BufferService {
+            budget: self.budget.clone(),
+            metrics: self.metrics.clone(),
+            retry_after: self.retry_after,
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BufferService<S> {
+    budget: BufferBudget,
+    metrics: Arc<ProxyMetrics>,
+    retry_after: Option<Duration>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for BufferService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut request: HttpRequest<HttpBody>) -> Self::Future {
+        if self.budget.is_exhausted() {
+            warn!(target: "tx-proxy::buffer", used = self.budget.used(), "Shedding request, too many bytes buffered");
+            self.metrics.record_buffer_shed_request();
+            let response = server_busy_response(StatusCode::OK, self.retry_after);
+            return Box::pin(async { Ok(response) });
+        }
+
+        request.extensions_mut().insert(self.budget.clone());
+        let fut = self.inner.call(request);
+        Box::pin(async move { fut.await.map_err(Into::into) })
+    }
+}
+
+/// Returns a JSON-RPC error to the caller when the request is shed, with the
+/// given HTTP status.
+pub(crate) fn server_busy_response(
+    status: StatusCode,
+    retry_after: Option<Duration>,
+) -> HttpResponse {
+    let error = ErrorObject::owned(SERVER_IS_BUSY_CODE, SERVER_IS_BUSY_MSG, None::<()>);
+    let mut builder = HttpResponse::builder()
+        .status(status)
+        .header("Content-Type", "application/json");
+    if let Some(retry_after) = retry_after {
+        builder = builder.header(RETRY_AFTER, retry_after_value(retry_after));
+    }
+    builder
+        .body(HttpBody::from(
+            serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": null }).to_string(),
+        ))
+        .unwrap()
+}
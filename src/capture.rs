@@ -0,0 +1,140 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use http::{HeaderMap, Uri};
+use hyper::body::Bytes;
+use jsonrpsee::{core::BoxError, http_client::HttpBody};
+use serde_json::{Map, Value, json};
+use tracing::warn;
+
+use crate::{
+    fanout::{Outcome, TargetOutcome},
+    rpc::{RpcRequest, RpcResponse},
+};
+
+/// The value written in place of a header value unless headers are captured.
+const REDACTED: &str = "<redacted>";
+
+/// Writes requests for a single method, and the responses of every target, to
+/// a JSONL file for debugging.
+#[derive(Debug)]
+pub struct Capture {
+    method: String,
+    capture_headers: bool,
+    file: Mutex<File>,
+}
+
+/// A target response recorded by a [`Capture`].
+pub struct CapturedResponse {
+    /// The URL of the target, if it is still configured.
+    pub target: Option<Uri>,
+    /// The response body, or the error if the request failed.
+    pub response: Result<Bytes, String>,
+}
+
+impl CapturedResponse {
+    /// Creates a [`CapturedResponse`] from the result of a request to a target.
+    pub fn new(target: Option<Uri>, result: &Result<RpcResponse<HttpBody>, BoxError>) -> Self {
+        Self {
+            target,
+            response: match result {
+                Ok(response) => Ok(response.body.clone()),
+                Err(err) => Err(err.to_string()),
+            },
+        }
+    }
+}
+
+impl From<&TargetOutcome> for CapturedResponse {
+    fn from(target: &TargetOutcome) -> Self {
+        Self {
+            target: Some(target.url.clone()),
+            response: match &target.outcome {
+                Outcome::Success(response) | Outcome::RpcError(response) => {
+                    Ok(response.body.clone())
+                }
+                Outcome::TransportError(err) => Err(err.to_string()),
+            },
+        }
+    }
+}
+
+impl Capture {
+    /// Creates a [`Capture`] of the given method, appending to the file at `path`.
+    pub fn new(method: impl Into<String>, path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            method: method.into(),
+            capture_headers: false,
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Writes request header values instead of redacting them.
+    pub fn with_headers(mut self, capture_headers: bool) -> Self {
+        self.capture_headers = capture_headers;
+        self
+    }
+
+    /// Returns true if requests for the method are captured.
+    pub fn matches(&self, method: &str) -> bool {
+        self.method == method
+    }
+
+    /// Appends a line with the request and the target responses.
+    ///
+    /// Failures are logged rather than returned, so capturing never fails a request.
+    pub fn record(&self, request: &RpcRequest, responses: &[CapturedResponse]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let responses = responses
+            .iter()
+            .map(|captured| {
+                let target = captured.target.as_ref().map(Uri::to_string);
+                match &captured.response {
+                    Ok(body) => json!({ "target": target, "response": body_value(body) }),
+                    Err(err) => json!({ "target": target, "error": err }),
+                }
+            })
+            .collect::<Vec<_>>();
+        let line = json!({
+            "timestamp_ms": timestamp,
+            "request_id": request.request_id,
+            "method": request.method,
+            "headers": self.headers(&request.parts.headers),
+            "request": body_value(&request.body),
+            "responses": responses,
+        });
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(err) = writeln!(file, "{line}") {
+            warn!(target: "tx-proxy::capture", %err, "Failed to write capture");
+        }
+    }
+
+    fn headers(&self, headers: &HeaderMap) -> Map<String, Value> {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.capture_headers {
+                    String::from_utf8_lossy(value.as_bytes()).into_owned()
+                } else {
+                    REDACTED.to_string()
+                };
+                (name.to_string(), value.into())
+            })
+            .collect()
+    }
+}
+
+/// Returns the body as JSON, or as a string if it is not valid JSON.
+fn body_value(body: &[u8]) -> Value {
+    serde_json::from_slice(body).unwrap_or_else(|_| String::from_utf8_lossy(body).into())
+}
@@ -1,37 +1,90 @@
-use crate::auth::{AuthLayer, JwtAuthValidator};
-use crate::metrics::ProxyMetrics;
+use crate::admin::Admin;
+use crate::auth::{
+    AuthLayer, DEFAULT_JWT_CLOCK_SKEW_SECS, IdentityClaim, JwtAuthValidator, OutboundAuth,
+};
+use crate::buffer::{BufferBudget, BufferLayer, DEFAULT_MAX_BUFFERED_BYTES};
+use crate::capture::Capture;
+use crate::coalesce::{CoalesceLayer, DEFAULT_COALESCE_TTL_MS, InflightCoalescer};
+use crate::config::{JwtClaimsConfig, ListenerConfig, ProxyConfig, TargetGroupConfig};
+use crate::diagnose::{Check, DiagnosticReport, check_reachable, check_target};
+use crate::edge::EdgeLayer;
+use crate::filter::{ALLOWED_METHODS, MethodFilter, MethodFilterLayer};
+use crate::maintenance::{MaintenanceSchedule, MaintenanceWindow};
+use crate::metrics::{DEFAULT_LATENCY_BUCKETS, ProxyMetrics, prometheus_builder};
+use crate::ordering::{DEFAULT_MAX_HOLD_MS, DEFAULT_MAX_SENDERS, NonceOrderingLayer, NonceTracker};
+use crate::otlp::{DEFAULT_METRICS_OTLP_INTERVAL_MS, OtlpRecorder, meter_provider, resource};
+use crate::probe::{
+    DEFAULT_LIVENESS_PATH, DEFAULT_READINESS_PATH, DEFAULT_STARTUP_PROBE_TIMEOUT_SECS, Probes,
+    query_chain_ids, sd_notify_ready, wait_for_builder,
+};
+use crate::process::{
+    DEFAULT_FD_LEAK_SAMPLES, DEFAULT_PROCESS_METRICS_INTERVAL_SECS, run_process_metrics,
+};
 use crate::proxy::ProxyLayer;
-use crate::{client::HttpClient, fanout::FanoutWrite, validation::ValidationLayer};
-use alloy_rpc_types_engine::JwtSecret;
-use clap::Parser;
+use crate::redact::Redactor;
+use crate::replay::{DEFAULT_REPLAY_MAX_ENTRIES, ReplayCache, ReplayLayer};
+use crate::rpc::{
+    DEFAULT_PBH_ERROR_PREFIX, DEFAULT_UNAVAILABLE_ERROR_CODE, DEFAULT_UNAVAILABLE_ERROR_MESSAGE,
+    MethodCategory, MethodResultValidator, PbhErrorMatcher, UnavailableError,
+};
+use crate::sampling::{RequestSampler, TraceSampling};
+use crate::saturation::{DEFAULT_SATURATION_WINDOW_MS, SaturationLayer, SaturationMonitor};
+use crate::scrape::ScrapeLayer;
+use crate::secret::{SECRET_AGE_CHECK_INTERVAL, SecretAgeMonitor, SecretFile};
+use crate::shed::{DEFAULT_SHED_PERCENT, DEFAULT_SHED_WINDOW_MS, LatencyShedLayer, LatencyShedder};
+use crate::split::{BuilderSplit, MAX_SPLIT_WEIGHT};
+use crate::subscribe::{DEFAULT_WS_RECONNECT_BACKOFF_MS, SubscribeBackend, SubscribeLayer};
+use crate::timeline::DEFAULT_SLOW_REQUEST_MS;
+use crate::tls::TlsRoots;
+use crate::{
+    client::{
+        DEFAULT_MAX_RESPONSE_BYTES, DEFAULT_MAX_RETRY_AFTER_SECS, HttpClient,
+        MAX_TIMEOUT_JITTER_PCT, UpstreamIdentity, jittered_timeout,
+    },
+    fanout::{FanoutMode, FanoutWrite, Hedge, SelectionStrategy, Targets, TargetsDiff},
+    reload::TargetReloader,
+    validation::{L2ForwardLimit, L2ForwardOverflow, LocalMethods, ValidationLayer},
+};
+use alloy_primitives::hex;
+use alloy_rpc_types_engine::{Claims, JwtSecret};
+use clap::parser::ValueSource;
+use clap::{Arg, ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser};
 use eyre::Context as _;
 use eyre::{Result, eyre};
-use http::{Request, Response, StatusCode};
+use futures::future;
+use http::{
+    HeaderMap, HeaderName, HeaderValue, Request, Response, StatusCode, header::AUTHORIZATION,
+    uri::PathAndQuery,
+};
 use http_body_util::Full;
 use hyper::Uri;
 use hyper::body::Bytes;
 use hyper::{server::conn::http1, service::service_fn};
 use hyper_util::rt::TokioIo;
 use jsonrpsee::server::ServerHandle;
+use jsonrpsee::types::error::INTERNAL_ERROR_CODE;
 use jsonrpsee::{RpcModule, server::Server};
-use metrics_exporter_prometheus::PrometheusBuilder;
 use metrics_exporter_prometheus::PrometheusHandle;
-use metrics_util::layers::{PrefixLayer, Stack};
+use metrics_util::layers::{FanoutBuilder, PrefixLayer, Stack};
+use opentelemetry::global;
 use opentelemetry::trace::TracerProvider as _;
-use opentelemetry::{KeyValue, global};
 use opentelemetry_otlp::WithExportConfig;
-use opentelemetry_sdk::{Resource, propagation::TraceContextPropagator};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
 use paste::paste;
 use rollup_boost::{HealthLayer, LogFormat};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::fmt;
 use std::fs;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::net::TcpListener;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::net::{TcpListener, TcpSocket};
 use tokio::signal::unix::{SignalKind, signal};
 use tracing::level_filters::LevelFilter;
 use tracing::{Level, Metadata};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use tracing_opentelemetry::OpenTelemetryLayer;
 use tracing_subscriber::Layer;
 use tracing_subscriber::filter::Targets;
@@ -39,7 +92,19 @@ use tracing_subscriber::layer::{Context, Filter, SubscriberExt};
 
 pub const DEFAULT_HTTP_PORT: u16 = 8545;
 pub const DEFAULT_METRICS_PORT: u16 = 9090;
+pub const DEFAULT_METRICS_MAX_RESTARTS: u32 = 3;
+/// The default listen backlog for the RPC and metrics listeners.
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+/// Delay before restarting a metrics server that exited.
+const METRICS_RESTART_BACKOFF: Duration = Duration::from_secs(1);
 pub const DEFAULT_OTLP_URL: &str = "http://localhost:4317";
+pub const DEFAULT_TIMEOUT: u64 = 1000;
+/// The default maximum number of concurrent connections on each RPC listener.
+pub const DEFAULT_MAX_CONCURRENT_CONNECTIONS: u32 = 500;
+/// The default time in seconds in-flight requests are given to complete at shutdown.
+pub const DEFAULT_SHUTDOWN_GRACE_SECS: u64 = 30;
+/// The prefix of the environment variables arguments are read from.
+pub const ENV_PREFIX: &str = "TX_PROXY_";
 
 struct TraceFilter;
 
@@ -49,7 +114,7 @@ impl<S> Filter<S> for TraceFilter {
     }
 }
 
-#[derive(clap::Parser)]
+#[derive(Clone, clap::Parser)]
 #[clap(about, version, author)]
 pub struct Cli {
     #[clap(flatten)]
@@ -58,121 +123,1225 @@ pub struct Cli {
     #[clap(flatten)]
     pub l2_targets: L2Targets,
 
+    /// New builder URLs receiving `--builder-split-weight` percent of the builder
+    /// requests during a cutover, with the same settings as the builders
+    #[arg(long, env = "TX_PROXY_BUILDER_SPLIT_URLS", value_delimiter = ',')]
+    pub builder_split_urls: Vec<Uri>,
+
+    /// Percentage of builder requests sent to `--builder-split-urls`, adjustable
+    /// at runtime through `POST /builder-split/{weight}` on the metrics listener
+    #[arg(long, env = "TX_PROXY_BUILDER_SPLIT_WEIGHT", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=MAX_SPLIT_WEIGHT as i64))]
+    pub builder_split_weight: u8,
+
     /// JWT Secret for the RPC server
-    #[clap(long, env, value_name = "HEX")]
+    #[clap(long, env = "TX_PROXY_JWT_TOKEN", value_name = "HEX")]
     pub jwt_token: Option<JwtSecret>,
 
     /// Path to a JWT secret for the RPC server
-    #[clap(long, env, value_name = "PATH")]
+    #[clap(long, env = "TX_PROXY_JWT_PATH", value_name = "PATH")]
     pub jwt_path: Option<PathBuf>,
 
+    /// Tolerance in seconds for JWT `iat` claims issued ahead of the local clock
+    #[clap(long, env = "TX_PROXY_JWT_CLOCK_SKEW_SECS", default_value_t = DEFAULT_JWT_CLOCK_SKEW_SECS)]
+    pub jwt_clock_skew_secs: u64,
+
+    /// Claim of inbound JWTs identifying the caller in metrics and logs, e.g. `sub` or `iss`
+    #[clap(long, env = "TX_PROXY_IDENTITY_CLAIM", value_name = "CLAIM")]
+    pub identity_claim: Option<String>,
+
+    /// Values of `--identity-claim` reported as is, others are reported as `unknown`
+    #[clap(long, env = "TX_PROXY_KNOWN_IDENTITIES", value_delimiter = ',')]
+    pub known_identities: Vec<String>,
+
     /// The address to bind the HTTP server to.
-    #[clap(long, env, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    #[clap(long, env = "TX_PROXY_HTTP_ADDR", default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
     pub http_addr: IpAddr,
 
     /// The port to bind the HTTP server to.
-    #[clap(long, env, default_value_t = DEFAULT_HTTP_PORT)]
+    #[clap(long, env = "TX_PROXY_HTTP_PORT", default_value_t = DEFAULT_HTTP_PORT)]
     pub http_port: u16,
 
     /// Enable Prometheus metrics
-    #[arg(long, env, default_value = "false")]
+    #[arg(long, env = "TX_PROXY_METRICS", default_value = "false")]
     pub metrics: bool,
 
     /// Host to run the metrics server on
-    #[arg(long, env, default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
+    #[arg(long, env = "TX_PROXY_METRICS_HOST", default_value_t = IpAddr::V4(Ipv4Addr::UNSPECIFIED))]
     pub metrics_host: IpAddr,
 
     /// Port to run the metrics server on
-    #[arg(long, env, default_value_t = DEFAULT_METRICS_PORT)]
+    #[arg(long, env = "TX_PROXY_METRICS_PORT", default_value_t = DEFAULT_METRICS_PORT)]
     pub metrics_port: u16,
 
+    /// Restart the metrics server if it exits, up to `--metrics-max-restarts` times
+    #[arg(
+        long,
+        env = "TX_PROXY_RESTART_METRICS_ON_CRASH",
+        default_value = "false"
+    )]
+    pub restart_metrics_on_crash: bool,
+
+    /// Maximum number of metrics server restarts with `--restart-metrics-on-crash`
+    #[arg(long, env = "TX_PROXY_METRICS_MAX_RESTARTS", default_value_t = DEFAULT_METRICS_MAX_RESTARTS)]
+    pub metrics_max_restarts: u32,
+
+    /// Keep proxying if the metrics server fails to bind or serve,
+    /// instead of shutting down.
+    #[arg(long, env = "TX_PROXY_METRICS_OPTIONAL", default_value = "false")]
+    pub metrics_optional: bool,
+
+    /// Histogram bucket boundaries in seconds for the request latency metrics
+    #[arg(long, env = "TX_PROXY_METRICS_LATENCY_BUCKETS", value_delimiter = ',', default_values_t = DEFAULT_LATENCY_BUCKETS.to_vec())]
+    pub metrics_latency_buckets: Vec<f64>,
+
+    /// Interval in seconds between samples of the process and runtime metrics,
+    /// such as open file descriptors and resident memory. 0 disables them
+    #[arg(long, env = "TX_PROXY_PROCESS_METRICS_INTERVAL_SECS", default_value_t = DEFAULT_PROCESS_METRICS_INTERVAL_SECS)]
+    pub process_metrics_interval_secs: u64,
+
+    /// Number of consecutive samples over which open file descriptors growing
+    /// while the request rate is flat are reported as a possible leak
+    #[arg(long, env = "TX_PROXY_FD_LEAK_SAMPLES", default_value_t = DEFAULT_FD_LEAK_SAMPLES)]
+    pub fd_leak_samples: usize,
+
+    /// Maximum age in days of the target JWT secret files, warning at 80% of it
+    /// and logging an error once exceeded. Ages are recorded regardless
+    #[arg(long, env = "TX_PROXY_JWT_MAX_AGE_DAYS", value_name = "DAYS")]
+    pub jwt_max_age_days: Option<u64>,
+
+    /// Record the age of accepted JWTs to the `jwt_age_seconds` histogram
+    #[arg(long, env = "TX_PROXY_METRICS_JWT_AGE", default_value = "false")]
+    pub metrics_jwt_age: bool,
+
+    /// Serve `GET /metrics` on the RPC port instead of starting the metrics server
+    #[arg(long, env = "TX_PROXY_METRICS_ON_RPC_PORT", default_value = "false")]
+    pub metrics_on_rpc_port: bool,
+
+    /// Require the listener JWT to scrape metrics with `--metrics-on-rpc-port`
+    #[arg(long, env = "TX_PROXY_METRICS_AUTH", default_value = "false")]
+    pub metrics_auth: bool,
+
+    /// Export metrics to the OTLP endpoint, alongside the Prometheus exporter if enabled
+    #[arg(long, env = "TX_PROXY_METRICS_OTLP", default_value = "false")]
+    pub metrics_otlp: bool,
+
+    /// Interval in milliseconds between exports of metrics to the OTLP endpoint
+    #[arg(long, env = "TX_PROXY_METRICS_OTLP_INTERVAL_MS", default_value_t = DEFAULT_METRICS_OTLP_INTERVAL_MS)]
+    pub metrics_otlp_interval_ms: u64,
+
     // Enable tracing
-    #[arg(long, env, default_value = "false")]
+    #[arg(long, env = "TX_PROXY_TRACING", default_value = "false")]
     pub tracing: bool,
 
     /// OTLP endpoint
-    #[arg(long, env, default_value = DEFAULT_OTLP_URL)]
+    #[arg(long, env = "TX_PROXY_OTLP_ENDPOINT", default_value = DEFAULT_OTLP_URL)]
     pub otlp_endpoint: Uri,
 
+    /// Ratio of request traces exported, between 0 and 1
+    ///
+    /// Requests ending in a PBH error, an upstream failure or above the latency
+    /// threshold are summarized in a separate trace when not sampled.
+    #[arg(long, env = "TX_PROXY_TRACE_SAMPLE_RATIO", default_value_t = 1.0, value_parser = parse_sample_ratio)]
+    pub trace_sample_ratio: f64,
+
+    /// Latency in milliseconds above which requests are summarized when their trace is not sampled
+    #[arg(long, env = "TX_PROXY_TRACE_LATENCY_THRESHOLD_MS")]
+    pub trace_latency_threshold_ms: Option<u64>,
+
     /// Log level
-    #[arg(long, env, default_value = "info")]
+    #[arg(long, env = "TX_PROXY_LOG_LEVEL", default_value = "info")]
     pub log_level: Level,
 
     /// Log format
-    #[arg(long, env, default_value = "text")]
+    #[arg(long, env = "TX_PROXY_LOG_FORMAT", default_value = "text")]
     pub log_format: LogFormat,
 
     /// Directory to write logs to
-    #[arg(long, env)]
+    #[arg(long, env = "TX_PROXY_LOG_DIR")]
     pub log_dir: Option<PathBuf>,
 
     /// Maximum number of concurrent connections to allow.
     ///
     /// Defaults to 500.
-    #[clap(long = "http.max-concurrent-connections", env, default_value_t = 500)]
+    #[clap(
+        long = "http.max-concurrent-connections",
+        env = "TX_PROXY_MAX_CONCURRENT_CONNECTIONS",
+        default_value_t = DEFAULT_MAX_CONCURRENT_CONNECTIONS
+    )]
     pub max_concurrent_connections: u32,
+
+    /// Maximum number of pending connections queued on the RPC and metrics listeners
+    #[arg(long, env = "TX_PROXY_LISTEN_BACKLOG", default_value_t = DEFAULT_LISTEN_BACKLOG)]
+    pub listen_backlog: u32,
+
+    /// Origins allowed to make cross-origin requests, or `*` for any origin.
+    ///
+    /// May be repeated, or given as a comma separated list. CORS is disabled if not set.
+    #[arg(
+        long,
+        visible_alias = "cors-origin",
+        env = "TX_PROXY_CORS_ORIGINS",
+        value_delimiter = ','
+    )]
+    pub cors_origins: Vec<String>,
+
+    /// Strategy used to select the response returned to the caller.
+    #[arg(long, env = "TX_PROXY_SELECTION_STRATEGY", value_enum, default_value_t = SelectionStrategy::DeclarationOrder)]
+    pub selection_strategy: SelectionStrategy,
+
+    /// Method prefixes allowed through the validation layer.
+    ///
+    /// Defaults to `eth_` and `net_peerCount`.
+    #[arg(long, env = "TX_PROXY_ALLOWED_METHODS", value_delimiter = ',')]
+    pub allowed_methods: Vec<String>,
+
+    /// Send requests to one builder at a time, also sending to the next builder
+    /// if no response arrived within this many milliseconds.
+    ///
+    /// Only methods in `--hedge-categories` are hedged. Disabled if not set.
+    #[arg(long, env = "TX_PROXY_HEDGE_DELAY_MS")]
+    pub hedge_delay_ms: Option<u64>,
+
+    /// Method categories hedged with `--hedge-delay-ms`, others are sent to all builders
+    #[arg(long, env = "TX_PROXY_HEDGE_CATEGORIES", value_enum, value_delimiter = ',', default_values_t = [MethodCategory::Read])]
+    pub hedge_categories: Vec<MethodCategory>,
+
+    /// Methods forwarded to the L2 targets once validated by the builders.
+    /// Other methods are answered by the builders only.
+    ///
+    /// Defaults to forwarding all methods.
+    #[arg(long, env = "TX_PROXY_L2_FORWARD_METHODS", value_delimiter = ',')]
+    pub l2_forward_methods: Vec<String>,
+
+    /// Methods that must be accepted by every builder. If any builder fails or
+    /// returns an error for one of them, the caller gets an error and the
+    /// request is not forwarded to L2.
+    #[arg(long, env = "TX_PROXY_STRICT_METHODS", value_delimiter = ',')]
+    pub strict_methods: Vec<String>,
+
+    /// Methods renamed before the allowed methods are checked, e.g. `pbh_sendConditional=eth_sendRawTransactionConditional`, can be repeated
+    #[arg(long = "method-rewrite", env = "TX_PROXY_METHOD_REWRITES", value_delimiter = ',', value_parser = parse_method_rewrite, value_name = "FROM=TO")]
+    pub method_rewrites: Vec<(String, String)>,
+
+    /// JSON-RPC error code of builder PBH validation errors
+    #[arg(long, env = "TX_PROXY_PBH_ERROR_CODE", allow_negative_numbers = true, default_value_t = INTERNAL_ERROR_CODE)]
+    pub pbh_error_code: i32,
+
+    /// Message prefix of builder PBH validation errors
+    #[arg(long, env = "TX_PROXY_PBH_ERROR_PREFIX", default_value = DEFAULT_PBH_ERROR_PREFIX)]
+    pub pbh_error_prefix: String,
+
+    /// JSON-RPC error code returned when no builder or L2 target responded
+    #[arg(long, env = "TX_PROXY_UNAVAILABLE_ERROR_CODE", allow_negative_numbers = true, default_value_t = DEFAULT_UNAVAILABLE_ERROR_CODE)]
+    pub unavailable_error_code: i32,
+
+    /// JSON-RPC error message returned when no builder or L2 target responded
+    #[arg(long, env = "TX_PROXY_UNAVAILABLE_ERROR_MESSAGE", default_value = DEFAULT_UNAVAILABLE_ERROR_MESSAGE)]
+    pub unavailable_error_message: String,
+
+    /// Seconds clients are asked to wait in a `Retry-After` header before retrying
+    /// a request shed as server busy or failed as upstream unavailable.
+    ///
+    /// Not sent if not set.
+    #[arg(long, env = "TX_PROXY_RETRY_AFTER_SECS")]
+    pub retry_after_secs: Option<u64>,
+
+    /// Regex whose matches are replaced with `[redacted]` in the message and data of
+    /// error responses returned to callers, in addition to IPv4 addresses and
+    /// `internal.` hostnames, can be repeated
+    #[arg(
+        long = "redact-pattern",
+        env = "TX_PROXY_REDACT_PATTERNS",
+        value_name = "REGEX"
+    )]
+    pub redact_patterns: Vec<String>,
+
+    /// Validate that responses are complete JSON-RPC 2.0 responses matching the
+    /// request id before returning them, falling back to the next valid response.
+    #[arg(long, env = "TX_PROXY_VALIDATE_RESPONSES", default_value = "false")]
+    pub validate_responses: bool,
+
+    /// Treat success responses with a malformed result for methods with a known
+    /// result shape, such as an `eth_sendRawTransaction` result that is not a
+    /// transaction hash, as failed targets.
+    #[arg(long, env = "TX_PROXY_VALIDATE_RESULTS", default_value = "false")]
+    pub validate_results: bool,
+
+    /// Log a warning with the phase timings of requests to a target taking longer
+    /// than this many milliseconds. Disabled if 0
+    #[arg(long, env = "TX_PROXY_SLOW_REQUEST_MS", default_value_t = DEFAULT_SLOW_REQUEST_MS)]
+    pub slow_request_ms: u64,
+
+    /// Send requests to each target in the order they were received, queueing up
+    /// to this many requests per target. Requests beyond it fail for that target.
+    /// Requests are sent concurrently if not set
+    #[arg(long, env = "TX_PROXY_TARGET_QUEUE_DEPTH")]
+    pub target_queue_depth: Option<usize>,
+
+    /// Reject raw transactions whose max fee per gas, or gas price for legacy
+    /// transactions, is above this many wei with error code -32011, before
+    /// they are fanned out. Disabled if not set
+    #[arg(long, env = "TX_PROXY_MAX_FEE_PER_GAS_CAP", value_name = "WEI")]
+    pub max_fee_per_gas_cap: Option<u128>,
+
+    /// Forward raw transactions directly to L2 when no builder responded, without
+    /// PBH validation, marking the response with `X-TxProxy-Degraded: builders-unavailable`
+    #[arg(
+        long,
+        env = "TX_PROXY_FALLBACK_TO_L2_ON_BUILDER_OUTAGE",
+        default_value = "false"
+    )]
+    pub fallback_to_l2_on_builder_outage: bool,
+
+    /// Await the L2 forward of validated requests and compare the L2 response to
+    /// the builder response, logging divergences and marking the builder response
+    /// returned to the caller with an `X-TxProxy-L2-Divergence` header
+    #[arg(long, env = "TX_PROXY_TEE_COMPARE", default_value = "false")]
+    pub tee_compare: bool,
+
+    /// Send each raw transaction to a builder picked from its sender first,
+    /// then fan out to the remaining builders.
+    ///
+    /// Builders are picked by consistent hashing, so reloading targets only
+    /// moves the senders of added or removed builders.
+    #[arg(
+        long,
+        env = "TX_PROXY_STICKY_SENDER",
+        alias = "sticky-by-sender",
+        default_value = "false"
+    )]
+    pub sticky_sender: bool,
+
+    /// Hold `eth_sendRawTransaction` submissions until lower nonces
+    /// from the same sender have been processed.
+    #[arg(long, env = "TX_PROXY_ORDER_BY_NONCE", default_value = "false")]
+    pub order_by_nonce: bool,
+
+    /// Maximum time in milliseconds to hold a submission waiting for a lower nonce
+    #[arg(long, env = "TX_PROXY_ORDER_BY_NONCE_MAX_HOLD_MS", default_value_t = DEFAULT_MAX_HOLD_MS)]
+    pub order_by_nonce_max_hold_ms: u64,
+
+    /// Window in milliseconds within which a request repeated by the same client
+    /// with an identical body is answered without being forwarded again.
+    ///
+    /// Disabled if not set.
+    #[arg(long, env = "TX_PROXY_REQUEST_REPLAY_WINDOW_MS")]
+    pub request_replay_window_ms: Option<u64>,
+
+    /// Maximum number of recent requests tracked for replay protection
+    #[arg(long, env = "TX_PROXY_REQUEST_REPLAY_MAX_ENTRIES", default_value_t = DEFAULT_REPLAY_MAX_ENTRIES)]
+    pub request_replay_max_entries: usize,
+
+    /// Join requests identical to a request still in flight, such as a
+    /// transaction retried by a client on its own timeout, instead of fanning
+    /// them out again. Every caller receives the same response
+    #[arg(long, env = "TX_PROXY_COALESCE_INFLIGHT", default_value = "false")]
+    pub coalesce_inflight: bool,
+
+    /// Time in milliseconds after which a request in flight is no longer joined
+    #[arg(long, env = "TX_PROXY_COALESCE_INFLIGHT_TTL_MS", default_value_t = DEFAULT_COALESCE_TTL_MS)]
+    pub coalesce_inflight_ttl_ms: u64,
+
+    /// Emit a `tx_submitted` event on the `tx-proxy::tx_events` tracing target
+    /// for each builder accepting a raw transaction.
+    #[arg(long, env = "TX_PROXY_TX_EVENTS", default_value = "false")]
+    pub tx_events: bool,
+
+    /// Reject notifications, requests without an id, with an error instead of
+    /// fanning them out without waiting for responses.
+    #[arg(long, env = "TX_PROXY_REJECT_NOTIFICATIONS", default_value = "false")]
+    pub reject_notifications: bool,
+
+    /// Minimum number of builders that must return a JSON-RPC result before a
+    /// result is returned to the caller, an error is returned otherwise.
+    #[arg(long, env = "TX_PROXY_BUILDER_MIN_SUCCESS", default_value_t = 1)]
+    pub builder_min_success: usize,
+
+    /// Forward to the L2 targets one at a time in the order they are declared,
+    /// trying the next target only if the previous one failed, instead of
+    /// forwarding to all of them
+    #[arg(long, env = "TX_PROXY_L2_ORDERED", default_value = "false")]
+    pub l2_ordered: bool,
+
+    /// The name identifying this instance to the targets, in the `User-Agent`
+    /// and `X-Proxy-Instance` headers of every request. Defaults to the hostname
+    #[arg(long, env = "TX_PROXY_INSTANCE_NAME")]
+    pub instance_name: Option<String>,
+
+    /// Do not identify this instance to the targets with the `User-Agent` and
+    /// `X-Proxy-Instance` headers
+    #[arg(long, env = "TX_PROXY_NO_IDENTIFY_UPSTREAM", default_value = "false")]
+    pub no_identify_upstream: bool,
+
+    /// Answer `web3_clientVersion` with the name and version of the proxy
+    /// instead of the builders.
+    #[arg(long, env = "TX_PROXY_LOCAL_CLIENT_VERSION", default_value = "false")]
+    pub local_client_version: bool,
+
+    /// Answer `net_version` with this network id instead of the builders.
+    #[arg(long, env = "TX_PROXY_LOCAL_NET_VERSION")]
+    pub local_net_version: Option<u64>,
+
+    /// Answer `proxy_metrics` with a JSON snapshot of the Prometheus metrics on
+    /// authenticated listeners, with `--metrics` or `--metrics-on-rpc-port`
+    #[arg(long, env = "TX_PROXY_RPC_METRICS", default_value = "false")]
+    pub rpc_metrics: bool,
+
+    /// Only forward a request to L2 if at least this many builders returned the
+    /// same result. When builders return different results, the result of the
+    /// majority is returned, or an error if there is none.
+    ///
+    /// Disabled if not set.
+    #[arg(long, env = "TX_PROXY_REQUIRE_CONSISTENT_SUCCESS")]
+    pub require_consistent_success: Option<usize>,
+
+    /// Spread the response timeouts of the targets of each group evenly within
+    /// this percentage of the configured timeout, so a slow upstream does not
+    /// time every target out at once and their retries do not line up.
+    /// Disabled if 0
+    #[arg(long, env = "TX_PROXY_TIMEOUT_JITTER_PCT", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=MAX_TIMEOUT_JITTER_PCT as i64))]
+    pub timeout_jitter_pct: u8,
+
+    /// Still forward a validated request to L2 when its caller went away before
+    /// the builders responded.
+    #[arg(
+        long,
+        env = "TX_PROXY_L2_FORWARD_ON_ABORT",
+        default_value = "true",
+        action = ArgAction::Set
+    )]
+    pub l2_forward_on_abort: bool,
+
+    /// Forward a validated request to L2 before answering its caller, instead
+    /// of in the background once the caller has been answered.
+    #[arg(long, env = "TX_PROXY_L2_FORWARD_BLOCKING", default_value = "false")]
+    pub l2_forward_blocking: bool,
+
+    /// Maximum number of L2 forwards in flight after the caller has been answered.
+    ///
+    /// Unbounded if not set.
+    #[arg(long, env = "TX_PROXY_MAX_L2_FORWARD_INFLIGHT")]
+    pub max_l2_forward_inflight: Option<usize>,
+
+    /// Maximum bytes of request and response bodies buffered across in-flight requests.
+    ///
+    /// New requests are shed with a server busy error while the limit is reached.
+    #[arg(long, env = "TX_PROXY_MAX_BUFFERED_BYTES", default_value_t = DEFAULT_MAX_BUFFERED_BYTES)]
+    pub max_buffered_bytes: usize,
+
+    /// Shed a share of new requests with a 503 status while the P99 latency of
+    /// recent requests is above this many milliseconds. Disabled if not set
+    #[arg(long, env = "TX_PROXY_SHED_LATENCY_MS")]
+    pub shed_latency_ms: Option<u64>,
+
+    /// Percentage of new requests shed while the P99 latency is above `--shed-latency-ms`
+    #[arg(long, env = "TX_PROXY_SHED_PERCENT", default_value_t = DEFAULT_SHED_PERCENT, value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub shed_percent: u8,
+
+    /// Milliseconds of recent requests the P99 latency is computed over
+    #[arg(long, env = "TX_PROXY_SHED_WINDOW_MS", default_value_t = DEFAULT_SHED_WINDOW_MS)]
+    pub shed_window_ms: u64,
+
+    /// Ask callers to back off with a `Retry-After` header once at least this
+    /// percentage of the builders rate limited a request, with a 429 status or a
+    /// -32005 error, within `--saturation-window-ms`. Disabled if not set
+    #[arg(long, env = "TX_PROXY_SATURATION_THRESHOLD", value_parser = clap::value_parser!(u8).range(1..=100))]
+    pub saturation_threshold: Option<u8>,
+
+    /// Milliseconds a rate limited request counts its builder as saturated
+    #[arg(long, env = "TX_PROXY_SATURATION_WINDOW_MS", default_value_t = DEFAULT_SATURATION_WINDOW_MS)]
+    pub saturation_window_ms: u64,
+
+    /// While `--saturation-threshold` is reached, shed the same percentage of new
+    /// requests as of saturated builders with a server busy error
+    #[arg(
+        long,
+        env = "TX_PROXY_SHED_ON_UPSTREAM_SATURATION",
+        default_value = "false",
+        requires = "saturation_threshold"
+    )]
+    pub shed_on_upstream_saturation: bool,
+
+    /// What to do with an L2 forward once `--max-l2-forward-inflight` is reached
+    #[arg(long, env = "TX_PROXY_L2_FORWARD_OVERFLOW", value_enum, default_value_t = L2ForwardOverflow::Drop)]
+    pub l2_forward_overflow: L2ForwardOverflow,
+
+    /// WebSocket URL `eth_subscribe` requests are bridged to, streaming
+    /// notifications back to the client as Server-Sent Events.
+    ///
+    /// Only `newHeads` subscriptions are supported. Disabled if not set.
+    #[arg(long, env = "TX_PROXY_SUBSCRIBE_WS_URL", value_name = "URL")]
+    pub subscribe_ws_url: Option<String>,
+
+    /// Hex encoded JWT secret used to authenticate to `--subscribe-ws-url`
+    #[arg(long, env = "TX_PROXY_SUBSCRIBE_JWT_TOKEN", value_name = "HEX")]
+    pub subscribe_jwt_token: Option<JwtSecret>,
+
+    /// Interval in seconds of the WebSocket pings sent to `--subscribe-ws-url`,
+    /// so intermediaries do not drop idle subscriptions. Disabled if not set
+    #[arg(long, env = "TX_PROXY_WS_PING_INTERVAL_SECS", value_name = "SECS")]
+    pub ws_ping_interval_secs: Option<u64>,
+
+    /// Delay in milliseconds before resubscribing to `--subscribe-ws-url` after it
+    /// dropped a subscription, and between failed attempts
+    #[arg(long, env = "TX_PROXY_WS_RECONNECT_BACKOFF_MS", default_value_t = DEFAULT_WS_RECONNECT_BACKOFF_MS)]
+    pub ws_reconnect_backoff_ms: u64,
+
+    /// Write requests for this method, and every builder response, to `--capture-path`
+    #[arg(long, env = "TX_PROXY_CAPTURE_METHOD", requires = "capture_path")]
+    pub capture_method: Option<String>,
+
+    /// JSONL file captured requests are appended to
+    #[arg(
+        long,
+        env = "TX_PROXY_CAPTURE_PATH",
+        value_name = "PATH",
+        requires = "capture_method"
+    )]
+    pub capture_path: Option<PathBuf>,
+
+    /// Write request header values to the capture file instead of redacting them
+    #[arg(long, env = "TX_PROXY_CAPTURE_HEADERS", default_value = "false")]
+    pub capture_headers: bool,
+
+    /// Start the metrics listener for the probes and admin endpoints even without
+    /// `--metrics`, or with `--metrics-on-rpc-port`. `/metrics` is then not served on it
+    #[arg(long, env = "TX_PROXY_PROBES", default_value = "false")]
+    pub probes: bool,
+
+    /// Liveness probe path served without authentication on the metrics listener
+    #[arg(long, env = "TX_PROXY_PROBE_LIVENESS_PATH", default_value = DEFAULT_LIVENESS_PATH)]
+    pub probe_liveness_path: String,
+
+    /// Readiness probe path served without authentication on the metrics listener
+    #[arg(long, env = "TX_PROXY_PROBE_READINESS_PATH", default_value = DEFAULT_READINESS_PATH)]
+    pub probe_readiness_path: String,
+
+    /// Seconds to wait at startup for a builder to accept connections before
+    /// reporting ready. Set to 0 to report ready without waiting.
+    #[arg(long, visible_alias = "startup-probe-timeout", env = "TX_PROXY_STARTUP_PROBE_TIMEOUT_SECS", default_value_t = DEFAULT_STARTUP_PROBE_TIMEOUT_SECS)]
+    pub startup_probe_timeout_secs: u64,
+
+    /// Exit instead of reporting ready with a warning when no builder was
+    /// reachable within the startup probe timeout
+    #[arg(
+        long,
+        env = "TX_PROXY_STARTUP_PROBE_EXIT_ON_TIMEOUT",
+        default_value = "false"
+    )]
+    pub startup_probe_exit_on_timeout: bool,
+
+    /// Seconds to wait for in-flight requests to complete at shutdown before
+    /// exiting regardless
+    #[arg(long, env = "TX_PROXY_SHUTDOWN_GRACE_SECS", default_value_t = DEFAULT_SHUTDOWN_GRACE_SECS)]
+    pub shutdown_grace_secs: u64,
+
+    /// Notify systemd with `READY=1` once the startup probe completed
+    #[arg(long, env = "TX_PROXY_SD_NOTIFY", default_value = "false")]
+    pub sd_notify: bool,
+
+    /// Query `eth_chainId` on every target at startup and log the results
+    #[arg(long, env = "TX_PROXY_CHECK_CHAIN_ID", default_value = "false")]
+    pub check_chain_id: bool,
+
+    /// Chain id every target must report at startup, failing the startup otherwise
+    ///
+    /// Implies `--check-chain-id`.
+    #[arg(long, env = "TX_PROXY_EXPECTED_CHAIN_ID")]
+    pub expected_chain_id: Option<u64>,
+
+    /// Path to a TOML config file. Command line flags take precedence over file values.
+    #[arg(long, env = "TX_PROXY_CONFIG", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print the resolved configuration, and where each value came from, then exit
+    #[arg(long, default_value = "false")]
+    pub print_config: bool,
+
+    /// Where each argument value came from, set by [`Cli::try_parse_env_from`].
+    #[arg(skip)]
+    pub sources: Vec<ResolvedArg>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// What to do with the configuration, running the proxy if not set.
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Command {
+    /// Run the proxy
+    Run,
+    /// Check the configuration and every target, print a report and exit,
+    /// with a non-zero exit code if a critical check failed
+    Diagnose(DiagnoseArgs),
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct DiagnoseArgs {
+    /// Print the report as JSON
+    #[arg(long, default_value = "false")]
+    pub json: bool,
+}
+
+/// Where the value of an argument came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ArgSource {
+    Flag,
+    Env(String),
+    /// An unprefixed environment variable, read when the prefixed one is not set.
+    ///
+    /// Deprecated, support will be removed in the next release.
+    DeprecatedEnv(String),
+    Default,
+    Unset,
+}
+
+impl fmt::Display for ArgSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Flag => write!(f, "flag"),
+            Self::Env(name) => write!(f, "env {name}"),
+            Self::DeprecatedEnv(name) => write!(f, "deprecated env {name}"),
+            Self::Default => write!(f, "default"),
+            Self::Unset => write!(f, "unset"),
+        }
+    }
+}
+
+/// The value of an argument as parsed, and where it came from.
+#[derive(Clone, Debug)]
+pub struct ResolvedArg {
+    /// The argument id, the field name
+    pub id: String,
+    /// The raw value, comma separated if repeated. Secrets are redacted.
+    pub value: Option<String>,
+    pub source: ArgSource,
+}
+
+impl ResolvedArg {
+    fn new(arg: &Arg, matches: &ArgMatches, deprecated: bool) -> Self {
+        let id = arg.get_id().as_str();
+        let env = arg
+            .get_env()
+            .map(|env| env.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let source = match matches.value_source(id) {
+            Some(ValueSource::CommandLine) => ArgSource::Flag,
+            Some(ValueSource::EnvVariable) if deprecated => ArgSource::DeprecatedEnv(env),
+            Some(ValueSource::EnvVariable) => ArgSource::Env(env),
+            Some(ValueSource::DefaultValue) => ArgSource::Default,
+            _ => ArgSource::Unset,
+        };
+        let value = matches.get_raw(id).map(|values| {
+            if id.ends_with("jwt_token") {
+                return "<redacted>".to_string();
+            }
+            values
+                .map(|value| printed_value(id, &value.to_string_lossy()))
+                .collect::<Vec<_>>()
+                .join(",")
+        });
+
+        Self {
+            id: id.to_string(),
+            value,
+            source,
+        }
+    }
+}
+
+/// Returns a value of an argument as printed, with the secrets it carries redacted.
+fn printed_value(id: &str, value: &str) -> String {
+    // Target headers carry API keys, only their names are printed
+    if id.ends_with("_headers") {
+        return match value.split_once('=') {
+            Some((name, _)) => format!("{name}=<redacted>"),
+            None => "<redacted>".to_string(),
+        };
+    }
+    // Proxy URLs may carry credentials in their userinfo
+    if id.ends_with("_http_proxy") {
+        if let Some((scheme, rest)) = value.split_once("://") {
+            let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+            if let Some(at) = rest[..authority_end].rfind('@') {
+                return format!("{scheme}://<redacted>@{}", &rest[at + 1..]);
+            }
+        }
+    }
+    value.to_string()
+}
+
+/// The name of the listener configured from the command line.
+pub const DEFAULT_LISTENER_NAME: &str = "default";
+
+/// State shared by the middleware of every listener.
+struct SharedLayers {
+    nonce_tracker: Option<Arc<NonceTracker>>,
+    replay_cache: Option<Arc<ReplayCache>>,
+    coalescer: Option<Arc<InflightCoalescer>>,
+    capture: Option<Arc<Capture>>,
+    subscribe_backend: Option<Arc<SubscribeBackend>>,
+    l2_forward_limit: Option<L2ForwardLimit>,
+    buffer_budget: BufferBudget,
+    latency_shedder: Option<Arc<LatencyShedder>>,
+    saturation: Option<Arc<SaturationMonitor>>,
+    redactor: Redactor,
+    metrics_handle: Option<PrometheusHandle>,
+}
+
+/// An RPC listener. All listeners share the same targets.
+#[derive(Clone, Debug)]
+pub struct Listener {
+    /// Name used in logs
+    pub name: String,
+    /// Address to bind to
+    pub addr: SocketAddr,
+    /// JWT secret required by the listener, or `None` for an unauthenticated listener
+    pub jwt_secret: Option<JwtSecret>,
+}
+
+impl Listener {
+    /// Creates a [`Listener`] from its config, reading the JWT secret if configured.
+    pub fn from_config(config: &ListenerConfig) -> Result<Self> {
+        let jwt_secret = match (&config.jwt_token, &config.jwt_path) {
+            (Some(token), _) => Some(JwtSecret::from_hex(token)?),
+            (None, Some(path)) => Some(JwtSecret::from_file(path)?),
+            (None, None) => None,
+        };
+
+        Ok(Self {
+            name: config.name.clone(),
+            addr: config.addr,
+            jwt_secret,
+        })
+    }
 }
 
 impl Cli {
-    pub async fn run(self) -> Result<()> {
+    /// Parses the command line and environment, exiting on error. Variables
+    /// set in a `.env` file, if there is one, are loaded first.
+    ///
+    /// See [`Cli::try_parse_env_from`].
+    pub fn parse_env() -> Self {
+        dotenvy::dotenv().ok();
+        Self::try_parse_env_from(std::env::args_os()).unwrap_or_else(|err| err.exit())
+    }
+
+    /// Parses the given arguments and the environment, recording where each value came from.
+    ///
+    /// Arguments are read from `TX_PROXY_` prefixed environment variables, falling
+    /// back to the deprecated unprefixed variable if the prefixed one is not set.
+    pub fn try_parse_env_from<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let mut command = Self::command();
+        let mut deprecated = HashSet::new();
+        for arg in Self::command().get_arguments() {
+            let Some(env) = arg.get_env().and_then(|env| env.to_str()) else {
+                continue;
+            };
+            let Some(legacy) = env.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            if std::env::var_os(env).is_none() && std::env::var_os(legacy).is_some() {
+                let legacy = legacy.to_string();
+                command = command.mut_arg(arg.get_id(), |arg| arg.env(legacy));
+                deprecated.insert(arg.get_id().clone());
+            }
+        }
+
+        let matches = command.try_get_matches_from_mut(args)?;
+        let mut cli = Self::from_arg_matches(&matches)?;
+        cli.sources = command
+            .get_arguments()
+            .filter(|arg| {
+                !matches!(
+                    arg.get_action(),
+                    ArgAction::Help
+                        | ArgAction::HelpShort
+                        | ArgAction::HelpLong
+                        | ArgAction::Version
+                )
+            })
+            .map(|arg| ResolvedArg::new(arg, &matches, deprecated.contains(arg.get_id())))
+            .collect();
+        Ok(cli)
+    }
+
+    /// Returns the arguments read from a deprecated unprefixed environment variable.
+    pub fn deprecated_env(&self) -> impl Iterator<Item = &ResolvedArg> {
+        self.sources
+            .iter()
+            .filter(|arg| matches!(arg.source, ArgSource::DeprecatedEnv(_)))
+    }
+
+    /// Prints each argument, its value and where it came from.
+    fn print_resolved_config(&self) {
+        for arg in &self.sources {
+            let value = arg.value.as_deref().unwrap_or("");
+            println!("{} = {value} ({})", arg.id, arg.source);
+        }
+        if let Some(path) = &self.config {
+            println!(
+                "# Unset values are filled from the config file {}",
+                path.display()
+            );
+        }
+    }
+
+    pub async fn run(mut self) -> Result<()> {
+        if self.print_config {
+            self.print_resolved_config();
+            return Ok(());
+        }
+        if let Some(Command::Diagnose(args)) = self.command.clone() {
+            let report = self.diagnose().await?;
+            if args.json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{report}");
+            }
+            let failures = report.critical_failures().count();
+            if failures > 0 {
+                return Err(eyre!("{failures} critical diagnostic checks failed"));
+            }
+            return Ok(());
+        }
+
         rustls::crypto::ring::default_provider()
             .install_default()
             .expect("TLS Error: Failed to install default provider");
 
-        let (metrics_shutdown_sender, metrics_shutdown_receiver) = tokio::sync::oneshot::channel();
+        let args = self.clone();
+        let config = self.load_config()?;
+        config.validate()?;
+
+        let (metrics_shutdown_sender, mut metrics_shutdown_receiver) =
+            tokio::sync::oneshot::channel();
         self.init_tracing()?;
-        let metrics = self.init_metrics(metrics_shutdown_sender)?;
+        for arg in self.deprecated_env() {
+            if let ArgSource::DeprecatedEnv(env) = &arg.source {
+                warn!(env = %env, replacement = %format!("{ENV_PREFIX}{env}"), "Reading deprecated unprefixed environment variable, it will be ignored in the next release");
+            }
+        }
+        let mut probes = Probes::new(&self.probe_liveness_path, &self.probe_readiness_path);
+        if self.startup_probe_timeout_secs > 0 {
+            probes = probes.with_startup_gate();
+        }
+        let admin = Admin::default();
+        let (metrics, metrics_handle) =
+            self.init_metrics(metrics_shutdown_sender, probes.clone(), admin.clone())?;
 
-        let jwt_secret = self.jwt_secret()?;
-        let handle = self.serve(jwt_secret, metrics).await?;
+        let listeners = config.resolve_listeners()?;
+        let targets = self.targets()?;
+        if self.check_chain_id || self.expected_chain_id.is_some() {
+            self.check_chain_ids(&targets).await?;
+        }
+        let authenticated = listeners
+            .iter()
+            .filter(|listener| listener.jwt_secret.is_some())
+            .count();
+        info!(
+            version = env!("CARGO_PKG_VERSION"),
+            strategy = ?self.selection_strategy,
+            l2_forward = if self.l2_forward_blocking { "blocking" } else { "background" },
+            builders = targets.builder.targets().len(),
+            builder_split = targets.builder_split.as_ref().map_or(0, |split| split.fanout().targets().len()),
+            l2 = targets.l2.targets().len(),
+            listeners = listeners.len(),
+            auth = match authenticated {
+                0 => "none",
+                n if n == listeners.len() => "jwt",
+                _ => "partial",
+            },
+            "Starting tx-proxy"
+        );
+        tokio::spawn(
+            self.secret_age_monitor(metrics.clone())
+                .run(SECRET_AGE_CHECK_INTERVAL),
+        );
+        admin.set_builders(&targets.builder);
+        if let Some(split) = &targets.builder_split {
+            admin.set_builder_split(split);
+        }
+        let handles = self
+            .serve_listeners(
+                &listeners,
+                metrics.clone(),
+                metrics_handle,
+                probes.clone(),
+                &targets,
+            )
+            .await?;
+        let grace = Duration::from_secs(self.shutdown_grace_secs);
+        let stop_all = || stop_servers(&handles, grace);
+        let mut startup = Box::pin(self.startup_probe(targets.builder.clone(), probes.clone()));
+        let reloader = TargetReloader::new(args, config, targets, probes, metrics);
         let mut sigterm = signal(SignalKind::terminate()).unwrap();
+        let mut sighup = signal(SignalKind::hangup()).unwrap();
+        let mut startup_done = false;
 
-        tokio::select! {
-            _ = handle.clone().stopped() => {
-                error!("Server stopped unexpectedly or crashed");
-                Err(eyre::eyre!("Server stopped unexpectedly or crashed"))
-            },
-            _ = tokio::signal::ctrl_c() => {
-                error!("Received Ctrl-C, shutting down...");
-                handle.stop()?;
-                Ok(())
-            },
-            _ = metrics_shutdown_receiver, if self.metrics => {
-                error!("Metrics server shut down, shutting down...");
-                handle.stop()?;
-                Ok(())
-            },
-            _ = sigterm.recv() => {
-                error!("Received SIGTERM, shutting down...");
-                handle.stop()?;
-                Ok(())
+        loop {
+            tokio::select! {
+                _ = future::select_all(handles.iter().cloned().map(|handle| Box::pin(handle.stopped()))) => {
+                    error!("Server stopped unexpectedly or crashed");
+                    stop_all().await;
+                    return Err(eyre::eyre!("Server stopped unexpectedly or crashed"));
+                },
+                _ = tokio::signal::ctrl_c() => {
+                    error!("Received Ctrl-C, shutting down...");
+                    stop_all().await;
+                    return Ok(());
+                },
+                _ = &mut metrics_shutdown_receiver, if self.serves_metrics() && !self.metrics_optional => {
+                    error!("Metrics server shut down, shutting down...");
+                    stop_all().await;
+                    return Ok(());
+                },
+                started = &mut startup, if !startup_done => {
+                    startup_done = true;
+                    if !started && self.startup_probe_exit_on_timeout {
+                        error!("No builder reachable at startup, shutting down...");
+                        stop_all().await;
+                        return Err(eyre::eyre!("No builder reachable at startup"));
+                    }
+                },
+                _ = sigterm.recv() => {
+                    error!("Received SIGTERM, shutting down...");
+                    stop_all().await;
+                    return Ok(());
+                },
+                _ = sighup.recv() => {
+                    info!("Received SIGHUP, reloading targets...");
+                    if let Err(err) = reloader.reload() {
+                        error!(%err, "Failed to reload targets");
+                    }
+                }
             }
         }
     }
 
+    /// Checks the configuration, the JWT secrets, every target, and the
+    /// reachability of the metrics and OTLP endpoints.
+    ///
+    /// Target and JWT checks are critical, the endpoints may not be reachable
+    /// without the proxy running.
+    pub async fn diagnose(&mut self) -> Result<DiagnosticReport> {
+        let _ = rustls::crypto::ring::default_provider().install_default();
+        let config = self.load_config()?;
+
+        let jwt_check = |name: &str, path: Option<&PathBuf>, secret: Result<JwtSecret>| {
+            let target = path.map(|path| path.display().to_string());
+            match secret {
+                Ok(_) => Check::passed(name, target, "valid secret"),
+                Err(err) => Check::failed(name, target, format!("{err:#}")),
+            }
+        };
+        let mut checks = vec![
+            jwt_check(
+                "builder.jwt",
+                self.builder_targets.builder_jwt_path.as_ref(),
+                self.builder_targets.get_jwt(),
+            ),
+            jwt_check(
+                "l2.jwt",
+                self.l2_targets.l2_jwt_path.as_ref(),
+                self.l2_targets.get_jwt(),
+            ),
+        ];
+        match config.resolve_listeners() {
+            Ok(listeners) => checks.extend(listeners.into_iter().map(|listener| {
+                let detail = match listener.jwt_secret {
+                    Some(_) => "valid secret",
+                    None => "unauthenticated",
+                };
+                Check::passed("listener.jwt", Some(listener.name), detail)
+            })),
+            Err(err) => checks.push(Check::failed("listener.jwt", None, format!("{err:#}"))),
+        }
+
+        match self.targets() {
+            Ok(targets) => {
+                for (kind, fanout) in [("builder", &targets.builder), ("l2", &targets.l2)] {
+                    let clients = fanout.targets();
+                    let results = future::join_all(
+                        clients
+                            .iter()
+                            .filter(|client| client.is_enabled())
+                            .map(|client| check_target(kind, client)),
+                    )
+                    .await;
+                    checks.extend(results.into_iter().flatten());
+                }
+            }
+            Err(err) => checks.push(Check::failed("targets", None, format!("{err:#}"))),
+        }
+
+        if self.serves_metrics() {
+            let host = if self.metrics_host.is_unspecified() {
+                IpAddr::V4(Ipv4Addr::LOCALHOST)
+            } else {
+                self.metrics_host
+            };
+            let url = format!("http://{}", SocketAddr::new(host, self.metrics_port)).parse()?;
+            checks.push(check_reachable("metrics", &url).await.with_critical(false));
+        }
+        if self.tracing || self.metrics_otlp {
+            checks.push(
+                check_reachable("otlp", &self.otlp_endpoint)
+                    .await
+                    .with_critical(false),
+            );
+        }
+
+        Ok(DiagnosticReport::new(checks))
+    }
+
+    /// Queries the chain id of every builder and L2 target and logs it.
+    ///
+    /// Fails if `--expected-chain-id` is set and a target reports another chain
+    /// id, or its chain id could not be read.
+    pub async fn check_chain_ids(&self, targets: &Targets) -> Result<()> {
+        let mut mismatched = vec![];
+        for (kind, fanout) in [("builder", &targets.builder), ("l2", &targets.l2)] {
+            for (url, chain_id) in query_chain_ids(fanout).await {
+                match (chain_id, self.expected_chain_id) {
+                    (Ok(chain_id), Some(expected)) if chain_id != expected => {
+                        error!(kind, %url, chain_id, expected, "Target is on an unexpected chain");
+                        mismatched.push(url);
+                    }
+                    (Ok(chain_id), _) => info!(kind, %url, chain_id, "Target chain id"),
+                    (Err(err), Some(_)) => {
+                        error!(kind, %url, %err, "Failed to read the chain id of a target");
+                        mismatched.push(url);
+                    }
+                    (Err(err), None) => {
+                        warn!(kind, %url, %err, "Failed to read the chain id of a target")
+                    }
+                }
+            }
+        }
+
+        match self.expected_chain_id {
+            Some(expected) if !mismatched.is_empty() => Err(eyre!(
+                "Targets not confirmed on chain {expected}: {}",
+                mismatched.join(", ")
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Waits for a builder to be reachable, then lifts the startup gate of the
+    /// probes and notifies systemd if enabled. Returns false if the wait timed out
+    /// and `--startup-probe-exit-on-timeout` is set, in which case neither happens.
+    async fn startup_probe(&self, builders: FanoutWrite, probes: Probes) -> bool {
+        if self.startup_probe_timeout_secs > 0 {
+            let timeout = Duration::from_secs(self.startup_probe_timeout_secs);
+            if !wait_for_builder(&builders, timeout).await {
+                if self.startup_probe_exit_on_timeout {
+                    return false;
+                }
+                warn!(
+                    timeout_secs = self.startup_probe_timeout_secs,
+                    "No builder reachable at startup, reporting ready anyway"
+                );
+            }
+            probes.set_started();
+        }
+
+        if self.sd_notify {
+            if let Err(err) = sd_notify_ready() {
+                warn!(%err, "Failed to notify systemd");
+            }
+        }
+        true
+    }
+
+    /// Reads the config file, if any, and fills in any values not provided on the
+    /// command line. Returns the resulting config.
+    pub fn load_config(&mut self) -> Result<ProxyConfig> {
+        let Some(path) = &self.config else {
+            return Ok(ProxyConfig::from(self.clone()));
+        };
+
+        let config = ProxyConfig::from_file(path)?;
+        self.merge(config)
+            .wrap_err_with(|| format!("Invalid config file {}", path.display()))
+    }
+
+    /// Builds the builder and L2 target sets.
+    pub fn targets(&self) -> Result<Targets> {
+        let identity = self.upstream_identity()?;
+        Ok(Targets {
+            builder: self
+                .builder_targets
+                .build(
+                    self.target_queue_depth,
+                    self.timeout_jitter_pct,
+                    identity.as_ref(),
+                )?
+                .with_validate_responses(self.validate_responses)
+                .with_result_validator(self.validate_results.then_some(MethodResultValidator))
+                .with_min_success(self.builder_min_success)
+                .with_slow_request_threshold(self.slow_request_threshold()),
+            l2: self
+                .l2_targets
+                .build(
+                    self.target_queue_depth,
+                    self.timeout_jitter_pct,
+                    identity.as_ref(),
+                )?
+                .with_validate_responses(self.validate_responses)
+                .with_result_validator(self.validate_results.then_some(MethodResultValidator))
+                .with_slow_request_threshold(self.slow_request_threshold())
+                .with_mode(if self.l2_ordered {
+                    FanoutMode::Ordered
+                } else {
+                    FanoutMode::All
+                }),
+            builder_split: self.builder_split()?,
+        })
+    }
+
+    /// Returns the [`TraceSampling`] summarizing notable requests, if traces are sampled.
+    fn trace_sampling(&self) -> Option<TraceSampling> {
+        (self.tracing && self.trace_sample_ratio < 1.0)
+            .then(|| TraceSampling::new(self.trace_latency_threshold_ms.map(Duration::from_millis)))
+    }
+
+    /// Returns the duration above which requests to a target are logged, if enabled.
+    fn slow_request_threshold(&self) -> Option<Duration> {
+        (self.slow_request_ms > 0).then(|| Duration::from_millis(self.slow_request_ms))
+    }
+
+    /// Returns the [`UpstreamIdentity`] sent to the targets, unless disabled.
+    pub fn upstream_identity(&self) -> Result<Option<UpstreamIdentity>> {
+        if self.no_identify_upstream {
+            return Ok(None);
+        }
+        let instance_name = self.instance_name.clone().unwrap_or_else(hostname);
+        UpstreamIdentity::new(&instance_name)
+            .map(Some)
+            .wrap_err_with(|| format!("Invalid instance name {instance_name:?}"))
+    }
+
+    /// Returns the [`SecretAgeMonitor`] checking the age of the target JWT secrets.
+    fn secret_age_monitor(&self, metrics: Arc<ProxyMetrics>) -> SecretAgeMonitor {
+        let max_age = self
+            .jwt_max_age_days
+            .map(|days| Duration::from_secs(days * 86_400));
+        SecretAgeMonitor::new(max_age, metrics)
+            .with_secret("builder", self.builder_targets.secret_file())
+            .with_secret("l2", self.l2_targets.secret_file())
+    }
+
+    /// Builds the split to the new builders, if any are configured.
+    fn builder_split(&self) -> Result<Option<BuilderSplit>> {
+        if self.builder_split_urls.is_empty() {
+            return Ok(None);
+        }
+
+        let targets = BuilderTargets {
+            builder_urls: self.builder_split_urls.clone(),
+            ..self.builder_targets.clone()
+        };
+        let identity = self.upstream_identity()?;
+        let fanout = targets
+            .build(
+                self.target_queue_depth,
+                self.timeout_jitter_pct,
+                identity.as_ref(),
+            )?
+            .with_validate_responses(self.validate_responses)
+            .with_result_validator(self.validate_results.then_some(MethodResultValidator))
+            .with_min_success(self.builder_min_success)
+            .with_slow_request_threshold(self.slow_request_threshold());
+        Ok(Some(BuilderSplit::new(fanout, self.builder_split_weight)?))
+    }
+
     fn init_metrics(
         &self,
         shutdown_sender: tokio::sync::oneshot::Sender<()>,
-    ) -> Result<Arc<ProxyMetrics>> {
-        if self.metrics {
-            let recorder = PrometheusBuilder::new().build_recorder();
-            let handle = recorder.handle();
+        probes: Probes,
+        admin: Admin,
+    ) -> Result<(Arc<ProxyMetrics>, Option<PrometheusHandle>)> {
+        let prometheus = self.metrics || self.metrics_on_rpc_port;
+        let mut handle = None;
+        if prometheus || self.metrics_otlp {
+            let mut recorders = FanoutBuilder::default();
+            if prometheus {
+                let recorder = prometheus_builder(&self.metrics_latency_buckets)?.build_recorder();
+                handle = Some(recorder.handle());
+                recorders = recorders.add_recorder(recorder);
+            }
+            if self.metrics_otlp {
+                let provider = meter_provider(
+                    &self.otlp_endpoint,
+                    Duration::from_millis(self.metrics_otlp_interval_ms),
+                )?;
+                recorders = recorders.add_recorder(OtlpRecorder::new(provider));
+            }
 
-            Stack::new(recorder)
+            Stack::new(recorders.build())
                 .push(PrefixLayer::new("tx-proxy"))
                 .install()?;
+            ProxyMetrics::describe();
+            if self.process_metrics_interval_secs > 0 {
+                tokio::spawn(run_process_metrics(
+                    Duration::from_secs(self.process_metrics_interval_secs),
+                    self.fd_leak_samples,
+                ));
+            }
+        }
 
-            // Start the metrics server
-            let addr = SocketAddr::new(self.metrics_host, self.metrics_port);
-            tokio::spawn(async move {
-                if let Err(e) = init_metrics_server(addr, handle).await {
-                    error!(message = "Error starting metrics server", error = %e);
-                }
-                let _ = shutdown_sender.send(());
-            });
+        let rpc_handle = if self.metrics_on_rpc_port {
+            // Scraped through the RPC listeners, see `ScrapeLayer`
+            handle.take()
+        } else {
+            handle.clone().filter(|_| self.rpc_metrics)
+        };
+        if !self.serves_metrics() {
+            return Ok((Arc::new(ProxyMetrics::new()), rpc_handle));
         }
 
-        Ok(Arc::new(ProxyMetrics::new()))
+        // Start the metrics server, only serving the probes and admin endpoints without a handle
+        let addr = SocketAddr::new(self.metrics_host, self.metrics_port);
+        let optional = self.metrics_optional;
+        let backlog = self.listen_backlog;
+        let max_restarts = if self.restart_metrics_on_crash {
+            self.metrics_max_restarts
+        } else {
+            0
+        };
+        tokio::spawn(async move {
+            if let Err(e) = supervise_metrics_server(
+                addr,
+                backlog,
+                handle,
+                probes,
+                admin,
+                max_restarts,
+                METRICS_RESTART_BACKOFF,
+            )
+            .await
+            {
+                error!(message = "Error starting metrics server", error = %e);
+            }
+            if optional {
+                error!("Metrics server stopped, continuing without metrics");
+                return;
+            }
+            let _ = shutdown_sender.send(());
+        });
+
+        Ok((Arc::new(ProxyMetrics::new()), rpc_handle))
+    }
+
+    /// Returns true if the standalone metrics server is started.
+    fn serves_metrics(&self) -> bool {
+        (self.metrics && !self.metrics_on_rpc_port) || self.probes
     }
 
     fn init_tracing(&self) -> Result<()> {
@@ -199,14 +1368,8 @@ impl Cli {
                 .context("Failed to create OTLP exporter")?;
             let provider_builder = opentelemetry_sdk::trace::SdkTracerProvider::builder()
                 .with_batch_exporter(otlp_exporter)
-                .with_resource(
-                    Resource::builder_empty()
-                        .with_attributes([
-                            KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
-                            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
-                        ])
-                        .build(),
-                );
+                .with_sampler(RequestSampler::new(self.trace_sample_ratio))
+                .with_resource(resource());
 
             let provider = provider_builder.build();
             let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
@@ -341,49 +1504,285 @@ impl Cli {
         Ok(())
     }
 
-    async fn serve(
+    /// Builds the validation and L2 fanout pipeline and starts the RPC server.
+    pub async fn serve(
         &self,
         jwt_secret: Option<JwtSecret>,
         metrics: Arc<ProxyMetrics>,
+        metrics_handle: Option<PrometheusHandle>,
+        probes: Probes,
+        targets: &Targets,
     ) -> Result<ServerHandle> {
-        let module = RpcModule::new(());
-        if let Some(secret) = jwt_secret {
-            let middleware = tower::ServiceBuilder::new()
-                .layer(AuthLayer::new(JwtAuthValidator::new(secret)))
-                .layer(HealthLayer)
-                .layer(ValidationLayer::new(
-                    self.builder_targets.build()?,
-                    metrics.clone(),
+        let listener = Listener {
+            name: DEFAULT_LISTENER_NAME.to_string(),
+            addr: SocketAddr::new(self.http_addr, self.http_port),
+            jwt_secret,
+        };
+        let mut handles = self
+            .serve_listeners(&[listener], metrics, metrics_handle, probes, targets)
+            .await?;
+        Ok(handles.remove(0))
+    }
+
+    /// Starts an RPC server for each listener, all sharing the same targets.
+    pub async fn serve_listeners(
+        &self,
+        listeners: &[Listener],
+        metrics: Arc<ProxyMetrics>,
+        metrics_handle: Option<PrometheusHandle>,
+        probes: Probes,
+        targets: &Targets,
+    ) -> Result<Vec<ServerHandle>> {
+        let shared = SharedLayers {
+            nonce_tracker: self.order_by_nonce.then(|| {
+                Arc::new(NonceTracker::new(
+                    Duration::from_millis(self.order_by_nonce_max_hold_ms),
+                    DEFAULT_MAX_SENDERS,
+                ))
+            }),
+            replay_cache: self.request_replay_window_ms.map(|window_ms| {
+                Arc::new(ReplayCache::new(
+                    Duration::from_millis(window_ms),
+                    self.request_replay_max_entries,
                 ))
-                .layer(ProxyLayer::new(self.l2_targets.build()?, metrics.clone()));
+            }),
+            coalescer: self.coalesce_inflight.then(|| {
+                Arc::new(InflightCoalescer::new(Duration::from_millis(
+                    self.coalesce_inflight_ttl_ms,
+                )))
+            }),
+            capture: match (&self.capture_method, &self.capture_path) {
+                (Some(method), Some(path)) => Some(Arc::new(
+                    Capture::new(method, path)
+                        .wrap_err_with(|| {
+                            format!("Failed to open capture file {}", path.display())
+                        })?
+                        .with_headers(self.capture_headers),
+                )),
+                _ => None,
+            },
+            subscribe_backend: self.subscribe_ws_url.as_ref().map(|url| {
+                Arc::new(
+                    SubscribeBackend::new(url)
+                        .with_jwt_secret(self.subscribe_jwt_token)
+                        .with_ping_interval(self.ws_ping_interval_secs.map(Duration::from_secs))
+                        .with_reconnect_backoff(Duration::from_millis(
+                            self.ws_reconnect_backoff_ms,
+                        )),
+                )
+            }),
+            l2_forward_limit: self
+                .max_l2_forward_inflight
+                .map(|max_inflight| L2ForwardLimit::new(max_inflight, self.l2_forward_overflow)),
+            buffer_budget: BufferBudget::new(self.max_buffered_bytes),
+            latency_shedder: self.shed_latency_ms.map(|threshold_ms| {
+                Arc::new(
+                    LatencyShedder::new(Duration::from_millis(threshold_ms))
+                        .with_window(Duration::from_millis(self.shed_window_ms))
+                        .with_percent(self.shed_percent),
+                )
+            }),
+            saturation: self.saturation_threshold.map(|threshold| {
+                Arc::new(
+                    SaturationMonitor::new(targets.builder.clone(), threshold)
+                        .with_window(Duration::from_millis(self.saturation_window_ms))
+                        .with_retry_after(self.retry_after())
+                        .with_shed(self.shed_on_upstream_saturation),
+                )
+            }),
+            redactor: Redactor::new(&self.redact_patterns)?,
+            metrics_handle,
+        };
+
+        probes.set_builders(&targets.builder);
+
+        let mut handles = Vec::with_capacity(listeners.len());
+        for listener in listeners {
+            match self
+                .start_listener(listener, &shared, &metrics, targets)
+                .await
+            {
+                Ok(handle) => handles.push(handle),
+                Err(err) => {
+                    for handle in &handles {
+                        let _ = handle.stop();
+                    }
+                    return Err(err.wrap_err(format!("Failed to start listener {}", listener.name)));
+                }
+            }
+        }
+        probes.set_rpc_bound(true);
+
+        Ok(handles)
+    }
 
-            let server = Server::builder()
-                .set_http_middleware(middleware)
-                .max_connections(self.max_concurrent_connections)
-                .build(SocketAddr::new(self.http_addr, self.http_port))
-                .await?;
+    async fn start_listener(
+        &self,
+        listener: &Listener,
+        shared: &SharedLayers,
+        metrics: &Arc<ProxyMetrics>,
+        targets: &Targets,
+    ) -> Result<ServerHandle> {
+        let module = RpcModule::new(());
+        let authenticated = listener.jwt_secret.is_some();
+        let validator = listener.jwt_secret.map(|secret| {
+            let validator = JwtAuthValidator::new(secret)
+                .with_clock_skew(self.jwt_clock_skew_secs)
+                .with_identity_claim(self.identity_claim.as_ref().map(|claim| {
+                    IdentityClaim::new(claim.clone(), self.known_identities.iter().cloned())
+                }));
+            if self.metrics_jwt_age {
+                validator.with_age_metrics(metrics.clone())
+            } else {
+                validator
+            }
+        });
+        let scrape_layer = ScrapeLayer::new(
+            shared
+                .metrics_handle
+                .clone()
+                .filter(|_| self.metrics_on_rpc_port),
+        )
+        .with_validator(validator.clone().filter(|_| self.metrics_auth));
+        if self.rpc_metrics && !authenticated {
+            warn!(target: "tx-proxy::cli", listener = %listener.name, "Not answering proxy_metrics on an unauthenticated listener");
+        }
+        let metrics_snapshot = shared
+            .metrics_handle
+            .clone()
+            .filter(|_| self.rpc_metrics && authenticated);
+        let auth_layer = validator.map(AuthLayer::new);
+        let local_methods = self.local_methods().with_metrics_snapshot(metrics_snapshot);
+        let method_rewrites: HashMap<String, String> =
+            self.method_rewrites.iter().cloned().collect();
+        let method_filter = Arc::new(
+            MethodFilter::new(self.allowed_methods())
+                .with_local_methods(local_methods.methods())
+                .with_method_rewrites(method_rewrites.clone()),
+        );
 
-            info!(target: "tx-proxy::cli", addr = %server.local_addr()?, "Building Authenticated RPC server");
+        let middleware = tower::ServiceBuilder::new()
+            .layer(scrape_layer)
+            .layer(EdgeLayer::new().with_cors_origins(self.cors_origins.clone()))
+            .option_layer(auth_layer)
+            .layer(
+                BufferLayer::new(shared.buffer_budget.clone(), metrics.clone())
+                    .with_retry_after(self.retry_after()),
+            )
+            .layer(HealthLayer)
+            .layer(SubscribeLayer::new(shared.subscribe_backend.clone()))
+            .layer(ReplayLayer::new(
+                shared.replay_cache.clone(),
+                metrics.clone(),
+            ))
+            .layer(CoalesceLayer::new(
+                shared.coalescer.clone(),
+                metrics.clone(),
+            ))
+            .layer(NonceOrderingLayer::new(
+                shared.nonce_tracker.clone(),
+                metrics.clone(),
+            ))
+            .layer(
+                LatencyShedLayer::new(shared.latency_shedder.clone(), metrics.clone())
+                    .with_retry_after(self.retry_after()),
+            )
+            .layer(SaturationLayer::new(
+                shared.saturation.clone(),
+                metrics.clone(),
+            ))
+            .layer(MethodFilterLayer::new(method_filter, metrics.clone()))
+            .layer(
+                ValidationLayer::new(targets.builder.clone(), metrics.clone())
+                    .with_selection_strategy(self.selection_strategy)
+                    .with_sticky_sender(self.sticky_sender)
+                    .with_l2_forward_methods(self.l2_forward_methods.clone())
+                    .with_capture(shared.capture.clone())
+                    .with_tx_events(self.tx_events)
+                    .with_l2_forward_limit(shared.l2_forward_limit.clone())
+                    .with_reject_notifications(self.reject_notifications)
+                    .with_l2_forward_on_abort(self.l2_forward_on_abort)
+                    .with_l2_forward_blocking(self.l2_forward_blocking)
+                    .with_require_consistent_success(self.require_consistent_success)
+                    .with_local_methods(local_methods)
+                    .with_strict_methods(self.strict_methods.clone())
+                    .with_method_rewrites(method_rewrites)
+                    .with_pbh_error_matcher(PbhErrorMatcher::new(
+                        self.pbh_error_code,
+                        self.pbh_error_prefix.clone(),
+                    ))
+                    .with_unavailable_error(self.unavailable_error())
+                    .with_redactor(shared.redactor.clone())
+                    .with_hedge(self.hedge())
+                    .with_builder_split(targets.builder_split.clone())
+                    .with_trace_sampling(self.trace_sampling())
+                    .with_max_fee_per_gas_cap(self.max_fee_per_gas_cap)
+                    .with_fallback_to_l2(self.fallback_to_l2_on_builder_outage)
+                    .with_tee_compare(self.tee_compare),
+            )
+            .layer(
+                ProxyLayer::new(targets.l2.clone(), metrics.clone())
+                    .with_selection_strategy(self.selection_strategy)
+                    .with_unavailable_error(self.unavailable_error())
+                    .with_redactor(shared.redactor.clone()),
+            );
 
-            Ok(server.start(module))
+        let tcp_listener = bind_listener(listener.addr, self.listen_backlog)?;
+        let server = Server::builder()
+            .set_http_middleware(middleware)
+            .max_connections(self.max_concurrent_connections)
+            .build_from_tcp(tcp_listener.into_std()?)?;
+
+        if authenticated {
+            info!(target: "tx-proxy::cli", listener = %listener.name, addr = %server.local_addr()?, "Building Authenticated RPC server");
         } else {
-            let middleware = tower::ServiceBuilder::new()
-                .layer(HealthLayer)
-                .layer(ValidationLayer::new(
-                    self.builder_targets.build()?,
-                    metrics.clone(),
-                ))
-                .layer(ProxyLayer::new(self.l2_targets.build()?, metrics.clone()));
+            info!(target: "tx-proxy::cli", listener = %listener.name, addr = %server.local_addr()?, "Building Unauthenticated RPC server");
+        }
 
-            let server = Server::builder()
-                .set_http_middleware(middleware)
-                .max_connections(self.max_concurrent_connections)
-                .build(format!("{}:{}", self.http_addr, self.http_port))
-                .await?;
+        Ok(server.start(module))
+    }
 
-            info!(target: "tx-proxy::cli", addr = %server.local_addr()?, "Building Unauthenticated RPC server");
+    /// Fills in any values not provided on the command line from the given config,
+    /// see [`ProxyConfig::with_defaults_from`]. Returns the resulting config.
+    pub fn merge(&mut self, config: ProxyConfig) -> Result<ProxyConfig> {
+        let merged = ProxyConfig::from(self.clone()).with_defaults_from(&config)?;
+        merged.apply(self)?;
+        Ok(merged)
+    }
 
-            Ok(server.start(module))
+    fn unavailable_error(&self) -> UnavailableError {
+        UnavailableError::new(
+            self.unavailable_error_code,
+            self.unavailable_error_message.clone(),
+        )
+        .with_retry_after(self.retry_after())
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        self.retry_after_secs.map(Duration::from_secs)
+    }
+
+    fn local_methods(&self) -> LocalMethods {
+        let mut local_methods = LocalMethods::default();
+        if self.local_client_version {
+            local_methods = local_methods.with_client_version();
+        }
+        if let Some(net_version) = self.local_net_version {
+            local_methods = local_methods.with_net_version(net_version);
+        }
+        local_methods
+    }
+
+    fn hedge(&self) -> Option<Hedge> {
+        self.hedge_delay_ms
+            .map(|delay| Hedge::new(Duration::from_millis(delay), self.hedge_categories.clone()))
+    }
+
+    fn allowed_methods(&self) -> Vec<String> {
+        if self.allowed_methods.is_empty() {
+            ALLOWED_METHODS.iter().map(|m| m.to_string()).collect()
+        } else {
+            self.allowed_methods.clone()
         }
     }
 
@@ -398,28 +1797,53 @@ impl Cli {
     }
 }
 
-pub(crate) async fn init_metrics_server(
+/// Binds a listener with `SO_REUSEADDR` set, so the address can be bound again
+/// while connections from a previous listener are in `TIME_WAIT`.
+pub fn bind_listener(addr: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+/// Serves Prometheus metrics if a handle is given, the unauthenticated liveness
+/// and readiness probes, and the unauthenticated [`Admin`] endpoints.
+pub async fn init_metrics_server(
     addr: SocketAddr,
-    handle: PrometheusHandle,
+    backlog: u32,
+    handle: Option<PrometheusHandle>,
+    probes: Probes,
+    admin: Admin,
 ) -> eyre::Result<()> {
-    let listener = TcpListener::bind(addr).await?;
+    let listener = bind_listener(addr, backlog)?;
     info!("Metrics server running on {}", addr);
 
     loop {
         match listener.accept().await {
             Ok((stream, _)) => {
                 let handle = handle.clone();
+                let probes = probes.clone();
+                let admin = admin.clone();
                 tokio::task::spawn(async move {
-                    let service = service_fn(move |_req: Request<hyper::body::Incoming>| {
-                        let response = match _req.uri().path() {
-                            "/metrics" => Response::builder()
+                    let service = service_fn(move |req: Request<hyper::body::Incoming>| {
+                        let response = match (req.uri().path(), &handle) {
+                            ("/metrics", Some(handle)) => Response::builder()
                                 .header("content-type", "text/plain")
                                 .body(Full::new(Bytes::from(handle.render())))
                                 .unwrap(),
-                            _ => Response::builder()
-                                .status(StatusCode::NOT_FOUND)
-                                .body(Full::new(Bytes::new()))
-                                .unwrap(),
+                            (path, _) => probes
+                                .response(path)
+                                .or_else(|| admin.response(req.method(), path))
+                                .unwrap_or_else(|| {
+                                    Response::builder()
+                                        .status(StatusCode::NOT_FOUND)
+                                        .body(Full::new(Bytes::new()))
+                                        .unwrap()
+                                }),
                         };
                         async { Ok::<_, hyper::Error>(response) }
                     });
@@ -439,52 +1863,524 @@ pub(crate) async fn init_metrics_server(
     }
 }
 
+/// Stops the servers, then waits for their in-flight requests to complete
+/// for up to `grace` so a stuck connection cannot hang the shutdown.
+async fn stop_servers(handles: &[ServerHandle], grace: Duration) {
+    for handle in handles {
+        let _ = handle.stop();
+    }
+    let stopped = future::join_all(handles.iter().cloned().map(|handle| handle.stopped()));
+    if tokio::time::timeout(grace, stopped).await.is_err() {
+        warn!(
+            grace_secs = grace.as_secs(),
+            "Servers did not stop within the shutdown grace period, exiting"
+        );
+    }
+}
+
+/// Runs the metrics server, restarting it after `backoff` up to `max_restarts`
+/// times if it exits. Returns the last error once restarts are exhausted.
+pub async fn supervise_metrics_server(
+    addr: SocketAddr,
+    backlog: u32,
+    handle: Option<PrometheusHandle>,
+    probes: Probes,
+    admin: Admin,
+    max_restarts: u32,
+    backoff: Duration,
+) -> eyre::Result<()> {
+    let mut restarts = 0;
+    loop {
+        let err =
+            match init_metrics_server(addr, backlog, handle.clone(), probes.clone(), admin.clone())
+                .await
+            {
+                Ok(()) => eyre!("Metrics server exited"),
+                Err(err) => err,
+            };
+        if restarts >= max_restarts {
+            return Err(err);
+        }
+
+        restarts += 1;
+        warn!(%err, restarts, max_restarts, "Metrics server exited, restarting");
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+/// Encodes a claim with the secret and validates it, so a secret that cannot
+/// sign requests fails at startup rather than on every forwarded request.
+fn self_test_jwt(secret: &JwtSecret) -> Result<()> {
+    let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let jwt = secret.encode(&Claims { iat, exp: None })?;
+    crate::auth::validate(secret, &jwt)?;
+    Ok(())
+}
+
+/// Parses a trace sample ratio between 0 and 1.
+fn parse_sample_ratio(s: &str) -> Result<f64, String> {
+    let ratio = s
+        .parse::<f64>()
+        .map_err(|err| format!("invalid sample ratio `{s}`: {err}"))?;
+    if !(0.0..=1.0).contains(&ratio) {
+        return Err(format!("sample ratio {ratio} is not between 0 and 1"));
+    }
+    Ok(ratio)
+}
+
+/// Parses a `FROM=TO` method rewrite.
+/// Returns the hostname of the machine, or the name of the crate if it cannot be read.
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string())
+}
+
+fn parse_method_rewrite(s: &str) -> Result<(String, String), String> {
+    let (from, to) = s
+        .split_once('=')
+        .filter(|(from, to)| !from.is_empty() && !to.is_empty())
+        .ok_or_else(|| format!("invalid method rewrite `{s}`, expected FROM=TO"))?;
+    Ok((from.to_string(), to.to_string()))
+}
+
+/// Parses a `METHOD=PATH` override of the path requests are sent to.
+fn parse_path_override(s: &str) -> Result<(String, PathAndQuery), String> {
+    let (method, path) = s
+        .split_once('=')
+        .filter(|(method, _)| !method.is_empty())
+        .ok_or_else(|| format!("invalid path override `{s}`, expected METHOD=PATH"))?;
+    Ok((method.to_string(), parse_request_path(path)?))
+}
+
+/// Parses the path, and optional query, requests are sent to.
+pub(crate) fn parse_request_path(path: &str) -> Result<PathAndQuery, String> {
+    if !path.starts_with('/') {
+        return Err(format!(
+            "invalid path `{path}`, expected it to start with `/`"
+        ));
+    }
+    path.parse::<PathAndQuery>()
+        .map_err(|err| format!("invalid path `{path}`: {err}"))
+}
+
+/// Parses a `NAME=VALUE` header added to requests to the targets.
+pub(crate) fn parse_header(s: &str) -> Result<(HeaderName, HeaderValue), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid header `{s}`, expected NAME=VALUE"))?;
+    let name = HeaderName::try_from(name.trim())
+        .map_err(|err| format!("invalid header name `{name}`: {err}"))?;
+    if name == AUTHORIZATION {
+        return Err("the authorization header is set by the target JWT".to_string());
+    }
+    let value = HeaderValue::try_from(value.trim())
+        .map_err(|err| format!("invalid header value for `{name}`: {err}"))?;
+    Ok((name, value))
+}
+
+/// Parses a `URL=WINDOW` maintenance window of a target.
+fn parse_maintenance(s: &str) -> Result<(Uri, MaintenanceWindow), String> {
+    let (url, window) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid maintenance window `{s}`, expected URL=WINDOW"))?;
+    let url = url
+        .trim()
+        .parse::<Uri>()
+        .map_err(|err| format!("invalid maintenance URL `{url}`: {err}"))?;
+    Ok((url, window.parse()?))
+}
+
+/// Parses the maintenance windows of the targets keyed by their URL.
+fn maintenance_windows<'a>(
+    maintenance: impl IntoIterator<Item = (&'a String, &'a Vec<String>)>,
+) -> Result<Vec<(Uri, MaintenanceWindow)>> {
+    let mut windows = Vec::new();
+    for (url, url_windows) in maintenance {
+        let url = url.parse::<Uri>()?;
+        for window in url_windows {
+            windows.push((
+                url.clone(),
+                window.parse().map_err(|err: String| eyre!(err))?,
+            ));
+        }
+    }
+    Ok(windows)
+}
+
 macro_rules! define_rpc_args {
     ($(($name:ident, $prefix:ident)),*) => {
         $(
             paste! {
                 #[derive(Parser, Debug, Clone, PartialEq, Eq)]
                 pub struct $name {
-                    /// RPC URLs
-                    #[arg(long, env)]
+                    /// RPC URLs, comma separated or repeated
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _URLS>])), value_delimiter = ',')]
                     pub [<$prefix _urls>]: Vec<Uri>,
 
                     /// Hex encoded JWT secret to use for an authenticated RPC server.
-                    #[arg(long, env, value_name = "HEX")]
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _JWT_TOKEN>])), value_name = "HEX")]
                     pub [<$prefix _jwt_token>]: Option<JwtSecret>,
 
                     /// Path to a JWT secret to use for an authenticated RPC server.
-                    #[arg(long, env, value_name = "PATH")]
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _JWT_PATH>])), value_name = "PATH")]
                     pub [<$prefix _jwt_path>]: Option<PathBuf>,
 
-                    /// Timeout for http calls in milliseconds
-                    #[arg(long, env, default_value_t = 1000)]
-                    pub [<$prefix _timeout>]: u64,
+                    /// Timeout for http calls in milliseconds, sets both the connect and response timeouts
+                    ///
+                    /// Defaults to 1000.
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _TIMEOUT>])))]
+                    pub [<$prefix _timeout>]: Option<u64>,
+
+                    /// Timeout for establishing a connection in milliseconds, overriding the timeout
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _CONNECT_TIMEOUT_MS>])))]
+                    pub [<$prefix _connect_timeout_ms>]: Option<u64>,
+
+                    /// Timeout for the whole request in milliseconds, including connecting, overriding the timeout
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _RESPONSE_TIMEOUT_MS>])))]
+                    pub [<$prefix _response_timeout_ms>]: Option<u64>,
+
+                    /// Maximum response body size in bytes collected from each target
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _MAX_RESPONSE_BYTES>])), default_value_t = DEFAULT_MAX_RESPONSE_BYTES)]
+                    pub [<$prefix _max_response_bytes>]: usize,
+
+                    /// Longest delay in seconds a target asking to be retried later with a `Retry-After`
+                    /// header is skipped for, longer delays are clamped to it
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _MAX_RETRY_AFTER_SECS>])), default_value_t = DEFAULT_MAX_RETRY_AFTER_SECS)]
+                    pub [<$prefix _max_retry_after_secs>]: u64,
+
+                    /// Methods renamed before requests are forwarded, e.g. `eth_sendRawTransaction=eth_sendRawTransactionPass`
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _METHOD_REWRITES>])), value_delimiter = ',', value_parser = parse_method_rewrite, value_name = "FROM=TO")]
+                    pub [<$prefix _method_rewrites>]: Vec<(String, String)>,
+
+                    /// Path requests for a method are sent to instead of the path of the target URLs,
+                    /// e.g. `eth_sendBundle=/bundle`. Methods are matched after rewrites
+                    #[arg(long = concat!(stringify!($prefix), "-path-override"), env = concat!("TX_PROXY_", stringify!([<$prefix:upper _PATH_OVERRIDES>])), value_delimiter = ',', value_parser = parse_path_override, value_name = "METHOD=PATH")]
+                    pub [<$prefix _path_overrides>]: Vec<(String, PathAndQuery)>,
+
+                    /// Do not send the `X-Idempotency-Key` header, for targets that reject unknown headers
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _NO_IDEMPOTENCY_KEY>])), default_value = "false")]
+                    pub [<$prefix _no_idempotency_key>]: bool,
+
+                    /// Identity each target must send in the `X-Builder-Identity` response header,
+                    /// responses without it are treated as failures
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _EXPECT_IDENTITY>])), value_name = "NAME")]
+                    pub [<$prefix _expect_identity>]: Option<String>,
+
+                    /// Header added to every request to the targets, e.g. `X-Api-Key=secret`, can be repeated
+                    #[arg(long = concat!(stringify!($prefix), "-header"), env = concat!("TX_PROXY_", stringify!([<$prefix:upper _HEADERS>])), value_parser = parse_header, value_name = "NAME=VALUE")]
+                    pub [<$prefix _headers>]: Vec<(HeaderName, HeaderValue)>,
+
+                    /// PEM bundle of the CA certificates trusted to authenticate the targets, instead of the native roots
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _CA_FILE>])), value_name = "PATH", conflicts_with = stringify!([<$prefix _use_webpki_roots>]))]
+                    pub [<$prefix _ca_file>]: Option<PathBuf>,
+
+                    /// Trust the root certificates compiled into the binary instead of the native roots,
+                    /// for images without a certificate store
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _USE_WEBPKI_ROOTS>])), default_value = "false")]
+                    pub [<$prefix _use_webpki_roots>]: bool,
+
+                    /// HTTP proxy the connections to the targets are tunneled through with `CONNECT` requests,
+                    /// e.g. `http://proxy:3128`
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _HTTP_PROXY>])), value_name = "URL")]
+                    pub [<$prefix _http_proxy>]: Option<Uri>,
+
+                    /// Maximum number of requests sent to each target at once, the excess waiting for a
+                    /// request to complete. Unbounded if unset
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _MAX_CONCURRENT_PER_TARGET>])))]
+                    pub [<$prefix _max_concurrent_per_target>]: Option<usize>,
+
+                    /// Claims signed into the JWTs sent to each target, only read from the config file
+                    #[arg(skip)]
+                    pub [<$prefix _jwt_claims>]: Vec<(Uri, JwtClaimsConfig)>,
+
+                    /// Window during which a target is skipped as if disabled, e.g.
+                    /// `http://builder:8545=Tue 02:00-03:00 UTC` or `URL=START/END` in RFC 3339, can be repeated
+                    #[arg(long, env = concat!("TX_PROXY_", stringify!([<$prefix:upper _MAINTENANCE>])), value_delimiter = ';', value_parser = parse_maintenance, value_name = "URL=WINDOW")]
+                    pub [<$prefix _maintenance>]: Vec<(Uri, MaintenanceWindow)>,
                 }
 
                 impl $name {
                     fn get_jwt(&self) -> Result<JwtSecret> {
-                        if let Some(secret) = &self.[<$prefix _jwt_token>] {
-                            Ok(secret.clone())
+                        let secret = if let Some(secret) = &self.[<$prefix _jwt_token>] {
+                            secret.clone()
                         } else if let Some(path) = &self.[<$prefix _jwt_path>] {
-                            Ok(JwtSecret::from_file(path)?)
+                            JwtSecret::from_file(path).wrap_err_with(|| {
+                                format!("Invalid {} JWT secret in {}", stringify!($prefix), path.display())
+                            })?
                         } else {
-                            Err(eyre!(
+                            return Err(eyre!(
                                 "No JWT secret provided. Please provide either a hex encoded JWT secret or a path to a file containing the JWT secret."
-                            ))
+                            ));
+                        };
+
+                        self_test_jwt(&secret).wrap_err_with(|| {
+                            format!("{} JWT secret failed the self-test", stringify!($prefix))
+                        })?;
+                        Ok(secret)
+                    }
+
+                    /// Returns the file the JWT secret is read from, `None` if it is passed inline.
+                    pub fn secret_file(&self) -> Option<SecretFile> {
+                        if self.[<$prefix _jwt_token>].is_some() {
+                            return None;
                         }
+                        self.[<$prefix _jwt_path>]
+                            .clone()
+                            .map(|path| SecretFile::new(stringify!($prefix), path))
                     }
 
-                    pub fn build(&self) -> Result<FanoutWrite> {
+                    /// Returns the root certificates trusted to authenticate the targets.
+                    fn tls_roots(&self) -> TlsRoots {
+                        match &self.[<$prefix _ca_file>] {
+                            Some(path) => TlsRoots::CaFile(path.clone()),
+                            None if self.[<$prefix _use_webpki_roots>] => TlsRoots::WebPki,
+                            None => TlsRoots::Native,
+                        }
+                    }
+
+                    /// Creates the targets from a [`TargetGroupConfig`].
+                    pub fn from_config(config: &TargetGroupConfig) -> Result<Self> {
+                        Ok(Self {
+                            [<$prefix _urls>]: config
+                                .urls
+                                .iter()
+                                .map(|url| url.parse::<Uri>())
+                                .collect::<Result<_, _>>()?,
+                            [<$prefix _jwt_token>]: config
+                                .jwt_token
+                                .as_deref()
+                                .map(JwtSecret::from_hex)
+                                .transpose()?,
+                            [<$prefix _jwt_path>]: config.jwt_path.clone(),
+                            [<$prefix _timeout>]: config.timeout_ms,
+                            [<$prefix _connect_timeout_ms>]: config.connect_timeout_ms,
+                            [<$prefix _response_timeout_ms>]: config.response_timeout_ms,
+                            [<$prefix _max_response_bytes>]: config.max_response_bytes,
+                            [<$prefix _max_retry_after_secs>]: config.max_retry_after_secs,
+                            [<$prefix _method_rewrites>]: config
+                                .method_rewrites
+                                .iter()
+                                .map(|(from, to)| (from.clone(), to.clone()))
+                                .collect(),
+                            [<$prefix _path_overrides>]: config
+                                .path_overrides
+                                .iter()
+                                .map(|(method, path)| Ok((method.clone(), parse_request_path(path).map_err(|err| eyre!(err))?)))
+                                .collect::<Result<_>>()?,
+                            [<$prefix _no_idempotency_key>]: config.no_idempotency_key,
+                            [<$prefix _expect_identity>]: config.expect_identity.clone(),
+                            [<$prefix _headers>]: config
+                                .headers
+                                .iter()
+                                .map(|header| parse_header(header).map_err(|err| eyre!(err)))
+                                .collect::<Result<_>>()?,
+                            [<$prefix _ca_file>]: config.ca_file.clone(),
+                            [<$prefix _use_webpki_roots>]: config.use_webpki_roots,
+                            [<$prefix _http_proxy>]: config
+                                .http_proxy
+                                .as_deref()
+                                .map(str::parse::<Uri>)
+                                .transpose()?,
+                            [<$prefix _jwt_claims>]: config
+                                .jwt_claims
+                                .iter()
+                                .map(|(url, claims)| Ok((url.parse::<Uri>()?, claims.clone())))
+                                .collect::<Result<_>>()?,
+                            [<$prefix _max_concurrent_per_target>]: config.max_concurrent_per_target,
+                            [<$prefix _maintenance>]: maintenance_windows(&config.maintenance)?,
+                        })
+                    }
+
+                    /// Returns the settings of the targets as a [`TargetGroupConfig`].
+                    pub fn to_config(&self) -> TargetGroupConfig {
+                        TargetGroupConfig {
+                            urls: self.[<$prefix _urls>].iter().map(Uri::to_string).collect(),
+                            jwt_token: self
+                                .[<$prefix _jwt_token>]
+                                .map(|secret| hex::encode(secret.as_bytes())),
+                            jwt_path: self.[<$prefix _jwt_path>].clone(),
+                            timeout_ms: self.[<$prefix _timeout>],
+                            connect_timeout_ms: self.[<$prefix _connect_timeout_ms>],
+                            response_timeout_ms: self.[<$prefix _response_timeout_ms>],
+                            max_response_bytes: self.[<$prefix _max_response_bytes>],
+                            max_retry_after_secs: self.[<$prefix _max_retry_after_secs>],
+                            method_rewrites: self.[<$prefix _method_rewrites>].iter().cloned().collect(),
+                            path_overrides: self
+                                .[<$prefix _path_overrides>]
+                                .iter()
+                                .map(|(method, path)| (method.clone(), path.to_string()))
+                                .collect(),
+                            headers: self
+                                .[<$prefix _headers>]
+                                .iter()
+                                .map(|(name, value)| {
+                                    format!("{name}={}", String::from_utf8_lossy(value.as_bytes()))
+                                })
+                                .collect(),
+                            no_idempotency_key: self.[<$prefix _no_idempotency_key>],
+                            expect_identity: self.[<$prefix _expect_identity>].clone(),
+                            ca_file: self.[<$prefix _ca_file>].clone(),
+                            use_webpki_roots: self.[<$prefix _use_webpki_roots>],
+                            http_proxy: self.[<$prefix _http_proxy>].as_ref().map(Uri::to_string),
+                            max_concurrent_per_target: self.[<$prefix _max_concurrent_per_target>],
+                            jwt_claims: self
+                                .[<$prefix _jwt_claims>]
+                                .iter()
+                                .map(|(url, claims)| (url.to_string(), claims.clone()))
+                                .collect(),
+                            maintenance: self.[<$prefix _maintenance>].iter().fold(
+                                BTreeMap::new(),
+                                |mut maintenance, (url, window)| {
+                                    maintenance
+                                        .entry(url.to_string())
+                                        .or_insert_with(Vec::new)
+                                        .push(window.to_string());
+                                    maintenance
+                                },
+                            ),
+                        }
+                    }
+
+                    /// Builds the fanout to the configured targets, sending requests to each
+                    /// target in order through a queue of `queue_depth` requests if set, and
+                    /// identifying the proxy with `identity` if set.
+                    pub fn build(
+                        &self,
+                        queue_depth: Option<usize>,
+                        timeout_jitter_pct: u8,
+                        identity: Option<&UpstreamIdentity>,
+                    ) -> Result<FanoutWrite> {
+                        let (backend, _) = self.rebuild(&[], queue_depth, timeout_jitter_pct, identity)?;
+                        Ok(FanoutWrite::new(backend)
+                            .with_method_rewrites(self.[<$prefix _method_rewrites>].iter().cloned().collect()))
+                    }
+
+                    /// Builds clients for the configured targets, reusing the clients in `current`
+                    /// whose URL, JWT secret, timeouts, proxy, response size limit, headers and path
+                    /// overrides are unchanged.
+                    ///
+                    /// Changes to the queue depth, concurrency limit and upstream identity only
+                    /// apply to new clients, reused clients keep their queue, limit and identity.
+                    /// The response timeout of each target is jittered by its position among the
+                    /// targets, so adding or removing a target replaces the clients of the others
+                    /// when `timeout_jitter_pct` is set.
+                    /// The maintenance windows of reused clients are replaced in place.
+                    pub fn rebuild(
+                        &self,
+                        current: &[HttpClient],
+                        queue_depth: Option<usize>,
+                        timeout_jitter_pct: u8,
+                        identity: Option<&UpstreamIdentity>,
+                    ) -> Result<(Vec<HttpClient>, TargetsDiff)> {
                         let jwt = self.get_jwt()?;
-                        let backend = self.[<$prefix _urls>]
+                        // Loaded once and shared by the clients of every target
+                        let tls = self.tls_roots().load().wrap_err_with(|| {
+                            format!(
+                                "Failed to load the root certificates of the {0} targets, set --{0}-ca-file or --{0}-use-webpki-roots",
+                                stringify!($prefix)
+                            )
+                        })?;
+                        let timeout = self
+                            .[<$prefix _response_timeout_ms>]
+                            .or(self.[<$prefix _timeout>])
+                            .unwrap_or(DEFAULT_TIMEOUT);
+                        let connect_timeout = self
+                            .[<$prefix _connect_timeout_ms>]
+                            .or(self.[<$prefix _timeout>]);
+                        let http_proxy = self.[<$prefix _http_proxy>].as_ref();
+                        let max_response_bytes = self.[<$prefix _max_response_bytes>];
+                        let max_retry_after = Duration::from_secs(self.[<$prefix _max_retry_after_secs>]);
+                        let send_idempotency_key = !self.[<$prefix _no_idempotency_key>];
+                        let expected_identity = self.[<$prefix _expect_identity>].as_deref();
+                        let headers = self.[<$prefix _headers>].iter().cloned().collect::<HeaderMap>();
+                        let path_overrides = self.[<$prefix _path_overrides>].iter().cloned().collect::<HashMap<_, _>>();
+                        let urls = &self.[<$prefix _urls>];
+                        let secret_file = self.secret_file();
+                        let auth = |url: &Uri| {
+                            match self.[<$prefix _jwt_claims>].iter().find(|(claims_url, _)| claims_url == url) {
+                                Some((_, claims)) => OutboundAuth::Custom(claims.claims(jwt)),
+                                None => OutboundAuth::RollupBoostDefault(jwt),
+                            }
+                        };
+
+                        let maintenance = |url: &Uri| {
+                            self.[<$prefix _maintenance>]
+                                .iter()
+                                .filter(|(window_url, _)| window_url == url)
+                                .map(|(_, window)| *window)
+                                .collect::<Vec<_>>()
+                        };
+
+                        let mut diff = TargetsDiff::default();
+                        let backend = urls
                             .iter()
-                            .map(|url| {
-                                HttpClient::new(url.clone(), jwt, self.[<$prefix _timeout>])
+                            .enumerate()
+                            .map(|(index, url)| {
+                                let timeout = jittered_timeout(timeout, timeout_jitter_pct, index, urls.len());
+                                let windows = maintenance(url);
+                                if let Some(client) = current
+                                    .iter()
+                                    .find(|c| {
+                                        c.is_configured_with(
+                                            url,
+                                            &auth(url),
+                                            tls.roots(),
+                                            timeout,
+                                            connect_timeout,
+                                            http_proxy,
+                                            max_response_bytes,
+                                            max_retry_after,
+                                            send_idempotency_key,
+                                            expected_identity,
+                                            &headers,
+                                            &path_overrides,
+                                        )
+                                    })
+                                {
+                                    if client.maintenance_windows() != windows {
+                                        client.set_maintenance(
+                                            (!windows.is_empty()).then(|| MaintenanceSchedule::new(windows)),
+                                        );
+                                    }
+                                    return client.clone();
+                                }
+
+                                if current.iter().any(|c| c.url() == url) {
+                                    diff.changed.push(url.to_string());
+                                } else {
+                                    diff.added.push(url.to_string());
+                                }
+                                HttpClient::new(url.clone(), auth(url), timeout)
+                                    .with_tls(tls.clone())
+                                    .with_connect_timeout(connect_timeout)
+                                    .with_http_proxy(http_proxy.cloned())
+                                    .with_max_response_bytes(max_response_bytes)
+                                    .with_max_retry_after(max_retry_after)
+                                    .with_idempotency_key(send_idempotency_key)
+                                    .with_expected_identity(expected_identity.map(str::to_string))
+                                    .with_headers(headers.clone())
+                                    .with_path_overrides(path_overrides.clone())
+                                    .with_upstream_identity(identity.cloned())
+                                    .with_secret_file(secret_file.clone())
+                                    .with_ordered_dispatch(queue_depth)
+                                    .with_max_concurrent(self.[<$prefix _max_concurrent_per_target>])
+                                    .with_maintenance(
+                                        (!windows.is_empty()).then(|| MaintenanceSchedule::new(windows)),
+                                    )
                             })
                             .collect::<Vec<_>>();
+                        diff.removed = current
+                            .iter()
+                            .filter(|c| !urls.contains(c.url()))
+                            .map(|c| c.url().to_string())
+                            .collect();
 
-                        Ok(FanoutWrite::new(backend))
+                        Ok((backend, diff))
                     }
                 }
             }
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use alloy_primitives::B256;
+use futures::{
+    FutureExt,
+    future::{BoxFuture, Shared},
+};
+use http::{HeaderMap, HeaderValue, StatusCode, header::CONTENT_LENGTH};
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use tokio::time::Instant;
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{metrics::ProxyMetrics, rpc::RpcRequest, validation::with_request_id};
+
+/// The default time in milliseconds after which an in-flight request is no
+/// longer joined, in case its entry leaked.
+pub const DEFAULT_COALESCE_TTL_MS: u64 = 30_000;
+
+/// The response of a coalesced request, shared by every caller that joined it.
+#[derive(Clone, Debug)]
+struct CoalescedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CoalescedResponse {
+    /// Returns the response with the JSON-RPC id of the caller, if it differs
+    /// from the id of the request that was executed, and the request id of
+    /// the caller.
+    fn to_response(
+        &self,
+        executed_id: &serde_json::Value,
+        id: &serde_json::Value,
+        request_id: &str,
+    ) -> HttpResponse {
+        let body = if executed_id == id {
+            self.body.clone()
+        } else {
+            with_id(&self.body, id)
+        };
+        let mut headers = self.headers.clone();
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        let mut response = HttpResponse::new(HttpBody::from(body));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = headers;
+        with_request_id(response, request_id)
+    }
+}
+
+/// The errors of the inner service are not `Clone`, so they are shared as strings.
+type SharedResponse = Shared<BoxFuture<'static, Result<CoalescedResponse, String>>>;
+
+struct InflightEntry {
+    response: SharedResponse,
+    /// The JSON-RPC id of the request that is executed.
+    id: serde_json::Value,
+    /// Distinguishes an entry from a later entry for the same key.
+    generation: u64,
+    started: Instant,
+}
+
+#[derive(Default)]
+struct InflightEntries {
+    map: HashMap<B256, InflightEntry>,
+    next_generation: u64,
+}
+
+/// Tracks the requests currently being executed, so identical requests arriving
+/// in the meantime, e.g. retried by a client on its own timeout, await the same
+/// response instead of being fanned out again.
+///
+/// Raw transactions are keyed by their hash, other requests by the hash of their
+/// method and params. Entries are removed once their response is ready, or
+/// ignored after the TTL if every caller went away before then.
+pub struct InflightCoalescer {
+    entries: Mutex<InflightEntries>,
+    ttl: Duration,
+}
+
+impl InflightCoalescer {
+    /// Creates a new [`InflightCoalescer`] joining requests in flight for at most `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(InflightEntries::default()),
+            ttl,
+        }
+    }
+
+    /// Returns the number of requests in flight.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Returns true if no requests are in flight.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the response of the identical request in flight and its id, or
+    /// starts executing the request with `execute` if there is none. The
+    /// returned flag is true if the request joined another.
+    fn join(
+        self: &Arc<Self>,
+        key: B256,
+        id: serde_json::Value,
+        execute: impl FnOnce() -> BoxFuture<'static, Result<CoalescedResponse, String>>,
+    ) -> (SharedResponse, serde_json::Value, bool) {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries
+            .map
+            .retain(|_, entry| now.duration_since(entry.started) < self.ttl);
+        if let Some(entry) = entries.map.get(&key) {
+            return (entry.response.clone(), entry.id.clone(), true);
+        }
+
+        let generation = entries.next_generation;
+        entries.next_generation += 1;
+        let coalescer = self.clone();
+        let execution = execute();
+        let response = async move {
+            let response = execution.await;
+            coalescer.remove(key, generation);
+            response
+        }
+        .boxed()
+        .shared();
+        entries.map.insert(
+            key,
+            InflightEntry {
+                response: response.clone(),
+                id: id.clone(),
+                generation,
+                started: now,
+            },
+        );
+        (response, id, false)
+    }
+
+    fn remove(&self, key: B256, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries
+            .map
+            .get(&key)
+            .is_some_and(|entry| entry.generation == generation)
+        {
+            entries.map.remove(&key);
+        }
+    }
+}
+
+/// A [`Layer`] that joins requests identical to a request still in flight
+/// instead of forwarding them again, returning the same response to every caller.
+///
+/// The request is executed as long as one of its callers is waiting for it.
+/// Notifications are passed through untouched, as are all requests when no
+/// [`InflightCoalescer`] is configured.
+pub struct CoalesceLayer {
+    pub coalescer: Option<Arc<InflightCoalescer>>,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+impl CoalesceLayer {
+    /// Creates a new [`CoalesceLayer`] with the given coalescer.
+    pub fn new(coalescer: Option<Arc<InflightCoalescer>>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self { coalescer, metrics }
+    }
+}
+
+impl<S> Layer<S> for CoalesceLayer {
+    type Service = CoalesceService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        CoalesceService {
+            coalescer: self.coalescer.clone(),
+            metrics: self.metrics.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CoalesceService<S> {
+    coalescer: Option<Arc<InflightCoalescer>>,
+    metrics: Arc<ProxyMetrics>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for CoalesceService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(coalescer) = self.coalescer.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        let mut service = self.clone();
+        let metrics = self.metrics.clone();
+        service.inner = std::mem::replace(&mut self.inner, service.inner);
+
+        let fut = async move {
+            let rpc_request = RpcRequest::from_parsed_or_request(request).await?;
+            if rpc_request.is_notification {
+                return service
+                    .inner
+                    .call(rpc_request.into())
+                    .await
+                    .map_err(Into::into);
+            }
+
+            let key = rpc_request.tx_hash().unwrap_or(rpc_request.idempotency_key);
+            let id = rpc_request.id();
+            let method = rpc_request.method.clone();
+            let request_id = rpc_request.request_id.clone();
+            let (response, executed_id, joined) = coalescer.join(key, id.clone(), move || {
+                let fut = service.inner.call(rpc_request.into());
+                async move {
+                    let response = fut
+                        .await
+                        .map_err(|err| Into::<BoxError>::into(err).to_string())?;
+                    let (parts, body) = response.into_parts();
+                    let body = body.collect().await.map_err(|err| err.to_string())?;
+                    Ok(CoalescedResponse {
+                        status: parts.status,
+                        headers: parts.headers,
+                        body: body.to_bytes(),
+                    })
+                }
+                .boxed()
+            });
+            if joined {
+                debug!(target: "tx-proxy::coalesce", %method, request.id = %request_id, %key, "joining identical request in flight");
+                metrics.record_coalesced_request();
+            }
+
+            let response = response.await?;
+            Ok(response.to_response(&executed_id, &id, &request_id))
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// Replaces the JSON-RPC id of a response body, returning it untouched if it
+/// is not a JSON object.
+fn with_id(body: &Bytes, id: &serde_json::Value) -> Bytes {
+    match serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(body) {
+        Ok(mut response) => {
+            response.insert("id".to_string(), id.clone());
+            serde_json::Value::Object(response).to_string().into()
+        }
+        Err(_) => body.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::REQUEST_ID_HEADER;
+
+    fn completed() -> CoalescedResponse {
+        CoalescedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(br#"{"jsonrpc":"2.0","result":"0x1","id":1}"#),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_coalesces_until_completed() {
+        let coalescer = Arc::new(InflightCoalescer::new(Duration::from_secs(1)));
+        let (sender, receiver) = tokio::sync::oneshot::channel::<()>();
+        let execute = move || {
+            async move {
+                let _ = receiver.await;
+                Ok(completed())
+            }
+            .boxed()
+        };
+
+        let (first, _, joined) = coalescer.join(B256::ZERO, 1.into(), execute);
+        assert!(!joined);
+        let (second, executed_id, joined) =
+            coalescer.join(B256::ZERO, 2.into(), || unreachable!("joins the first"));
+        assert!(joined);
+        assert_eq!(executed_id, 1);
+
+        sender.send(()).unwrap();
+        let (first, second) = (first.await.unwrap(), second.await.unwrap());
+        assert_eq!(first.body, second.body);
+        assert!(coalescer.is_empty());
+
+        // The id of the caller is returned
+        let response = second.to_response(&executed_id, &"abcdef".into(), "second");
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "second");
+        let content_length = response.headers()[CONTENT_LENGTH].clone();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(content_length, body.len().to_string());
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()["id"],
+            "abcdef"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_abandoned_entries_expire() {
+        let coalescer = Arc::new(InflightCoalescer::new(Duration::from_millis(50)));
+        let (abandoned, _, _) =
+            coalescer.join(B256::ZERO, 1.into(), || futures::future::pending().boxed());
+        drop(abandoned);
+        assert_eq!(coalescer.len(), 1);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        let (_, _, joined) =
+            coalescer.join(B256::ZERO, 1.into(), || async { Ok(completed()) }.boxed());
+        assert!(!joined);
+    }
+}
@@ -0,0 +1,1564 @@
+use alloy_primitives::hex;
+use alloy_rpc_types_engine::JwtSecret;
+use clap::Parser;
+use eyre::{Context as _, Result};
+use http::{Uri, uri::Scheme};
+use jsonrpsee::server::ServerHandle;
+use jsonrpsee::types::error::INTERNAL_ERROR_CODE;
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use crate::auth::{ClaimsConfig, DEFAULT_JWT_CLOCK_SKEW_SECS};
+use crate::buffer::DEFAULT_MAX_BUFFERED_BYTES;
+use crate::cli::{
+    BuilderTargets, Cli, DEFAULT_HTTP_PORT, DEFAULT_LISTEN_BACKLOG, DEFAULT_LISTENER_NAME,
+    DEFAULT_MAX_CONCURRENT_CONNECTIONS, DEFAULT_METRICS_PORT, DEFAULT_OTLP_URL,
+    DEFAULT_SHUTDOWN_GRACE_SECS, L2Targets, Listener, parse_header, parse_request_path,
+};
+use crate::client::{
+    DEFAULT_MAX_RESPONSE_BYTES, DEFAULT_MAX_RETRY_AFTER_SECS, MAX_TIMEOUT_JITTER_PCT,
+};
+use crate::coalesce::DEFAULT_COALESCE_TTL_MS;
+use crate::fanout::SelectionStrategy;
+use crate::maintenance::MaintenanceWindow;
+use crate::metrics::{DEFAULT_LATENCY_BUCKETS, ProxyMetrics};
+use crate::ordering::DEFAULT_MAX_HOLD_MS;
+use crate::probe::Probes;
+use crate::redact::Redactor;
+use crate::replay::DEFAULT_REPLAY_MAX_ENTRIES;
+use crate::rpc::{
+    DEFAULT_PBH_ERROR_PREFIX, DEFAULT_UNAVAILABLE_ERROR_CODE, DEFAULT_UNAVAILABLE_ERROR_MESSAGE,
+    MethodCategory,
+};
+use crate::saturation::DEFAULT_SATURATION_WINDOW_MS;
+use crate::shed::{DEFAULT_SHED_PERCENT, DEFAULT_SHED_WINDOW_MS};
+use crate::split::MAX_SPLIT_WEIGHT;
+use crate::subscribe::DEFAULT_WS_RECONNECT_BACKOFF_MS;
+use crate::timeline::DEFAULT_SLOW_REQUEST_MS;
+use crate::validation::L2ForwardOverflow;
+
+/// Command line arguments not covered by [`ProxyConfig`].
+///
+/// They configure the process running the proxy rather than the proxy itself:
+/// logging, the metrics server and process metrics, the startup probes and
+/// checks, and the config file itself. [`ProxyConfig::serve`] leaves them to
+/// the caller, and the config file cannot set them.
+pub const CLI_ONLY_ARGS: &[&str] = &[
+    "log_level",
+    "log_format",
+    "log_dir",
+    "restart_metrics_on_crash",
+    "metrics_max_restarts",
+    "metrics_optional",
+    "process_metrics_interval_secs",
+    "fd_leak_samples",
+    "metrics_otlp",
+    "metrics_otlp_interval_ms",
+    "jwt_max_age_days",
+    "probe_liveness_path",
+    "probe_readiness_path",
+    "probes",
+    "startup_probe_timeout_secs",
+    "startup_probe_exit_on_timeout",
+    "sd_notify",
+    "check_chain_id",
+    "expected_chain_id",
+    "config",
+    "print_config",
+];
+
+/// Configuration for an RPC listener.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ListenerConfig {
+    /// Name used in logs
+    pub name: String,
+    /// Address to bind to
+    pub addr: SocketAddr,
+    /// Hex encoded JWT secret. The listener is unauthenticated if no secret is configured.
+    pub jwt_token: Option<String>,
+    /// Path to a JWT secret
+    pub jwt_path: Option<PathBuf>,
+}
+
+/// Claims signed into the JWTs sent to a target.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct JwtClaimsConfig {
+    /// The `iss` claim, e.g. identifying the proxy instance
+    pub issuer: Option<String>,
+    /// Token lifetime in seconds, setting the `exp` claim. Tokens do not expire if unset.
+    pub lifetime_secs: Option<u64>,
+    /// Static claims added to every token
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl JwtClaimsConfig {
+    /// Returns the claims to sign with the given secret.
+    pub fn claims(&self, secret: JwtSecret) -> ClaimsConfig {
+        ClaimsConfig::new(secret)
+            .with_issuer(self.issuer.clone())
+            .with_lifetime(self.lifetime_secs.map(Duration::from_secs))
+            .with_extra_claims(self.extra.clone())
+    }
+}
+
+/// Typed configuration of the proxy, read from the `--config` file, or for
+/// constructing and validating it programmatically instead of through the
+/// command line.
+///
+/// Covers every command line argument but the [`CLI_ONLY_ARGS`], which are
+/// read from the environment or default, as on the command line. Defaults
+/// match the command line defaults.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// RPC listeners, all sharing the same targets
+    pub listeners: Vec<ListenerConfig>,
+    /// Builder targets used for validation
+    pub builder: TargetGroupConfig,
+    /// L2 targets that validated requests are forwarded to
+    pub l2: TargetGroupConfig,
+    /// Method prefixes that are allowed through the validation layer, the
+    /// default methods if empty
+    pub allowed_methods: Vec<String>,
+    /// Origins allowed to make cross-origin requests, or `*` for any origin
+    pub cors_origins: Vec<String>,
+    /// How requests are sent to the targets and responses selected
+    pub routing: RoutingConfig,
+    /// Checks and deduplication of requests before they are fanned out
+    pub requests: RequestsConfig,
+    /// Errors returned to callers
+    pub errors: ErrorsConfig,
+    /// Inbound JWT validation
+    pub auth: AuthConfig,
+    /// Identification of the proxy to the targets and their timeouts
+    pub upstream: UpstreamConfig,
+    /// Bridging of `eth_subscribe` to a WebSocket
+    pub subscribe: SubscribeConfig,
+    /// Capture of requests and builder responses to a file
+    pub capture: CaptureConfig,
+    /// Connection and request limits
+    pub limits: LimitsConfig,
+    /// Metrics and tracing
+    pub telemetry: TelemetryConfig,
+}
+
+/// Configuration of a group of fanout targets sharing the same settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TargetGroupConfig {
+    /// RPC URLs
+    pub urls: Vec<String>,
+    /// Hex encoded JWT secret
+    pub jwt_token: Option<String>,
+    /// Path to a JWT secret
+    pub jwt_path: Option<PathBuf>,
+    /// Timeout for http calls in milliseconds, used for both the connect and
+    /// response timeouts unless they are set. Defaults to 1000.
+    #[serde(alias = "timeout")]
+    pub timeout_ms: Option<u64>,
+    /// Timeout for establishing a connection in milliseconds
+    #[serde(alias = "connect_timeout")]
+    pub connect_timeout_ms: Option<u64>,
+    /// Timeout for the whole request in milliseconds, including connecting
+    #[serde(alias = "response_timeout")]
+    pub response_timeout_ms: Option<u64>,
+    /// Maximum response body size in bytes collected from each target
+    pub max_response_bytes: usize,
+    /// Longest `Retry-After` delay in seconds a target is skipped for
+    pub max_retry_after_secs: u64,
+    /// Methods renamed before requests are forwarded, keyed by the original method
+    pub method_rewrites: BTreeMap<String, String>,
+    /// Paths requests for a method are sent to instead of the path of the URLs, keyed by method
+    pub path_overrides: BTreeMap<String, String>,
+    /// Headers added to every request, as `NAME=VALUE`
+    pub headers: Vec<String>,
+    /// Do not send the `X-Idempotency-Key` header
+    pub no_idempotency_key: bool,
+    /// Identity each target must send in the `X-Builder-Identity` response header
+    pub expect_identity: Option<String>,
+    /// PEM bundle of the CA certificates trusted to authenticate the targets
+    pub ca_file: Option<PathBuf>,
+    /// Trust the root certificates compiled into the binary
+    pub use_webpki_roots: bool,
+    /// HTTP proxy the connections to the targets are tunneled through
+    pub http_proxy: Option<String>,
+    /// Maximum number of requests sent to each target at once, unbounded if unset
+    pub max_concurrent_per_target: Option<usize>,
+    /// Claims signed into the JWTs sent to a target, keyed by its URL
+    pub jwt_claims: BTreeMap<String, JwtClaimsConfig>,
+    /// Maintenance windows during which a target is skipped, keyed by its URL,
+    /// e.g. `"Tue 02:00-03:00 UTC"` or an RFC 3339 interval
+    pub maintenance: BTreeMap<String, Vec<String>>,
+}
+
+/// How requests are sent to the targets and which response is returned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RoutingConfig {
+    /// Strategy used to select the response returned to the caller
+    pub selection_strategy: SelectionStrategy,
+    /// Minimum number of builders that must return a result
+    pub builder_min_success: usize,
+    /// Builders that must return the same result before a request is forwarded
+    /// to L2, disabled if unset
+    pub require_consistent_success: Option<usize>,
+    /// Methods that must be accepted by every builder
+    pub strict_methods: Vec<String>,
+    /// Methods renamed before the allowed methods are checked, keyed by the original method
+    pub method_rewrites: BTreeMap<String, String>,
+    /// Delay in milliseconds before a hedged request is also sent to the next
+    /// builder, hedging is disabled if unset
+    pub hedge_delay_ms: Option<u64>,
+    /// Method categories hedged with `hedge_delay_ms`
+    pub hedge_categories: Vec<MethodCategory>,
+    /// Send each raw transaction to a builder picked from its sender first
+    pub sticky_sender: bool,
+    /// Methods forwarded to the L2 targets, all methods if empty
+    pub l2_forward_methods: Vec<String>,
+    /// Forward to L2 before answering the caller
+    pub l2_forward_blocking: bool,
+    /// Still forward to L2 when the caller went away
+    pub l2_forward_on_abort: bool,
+    /// What to do with an L2 forward once `limits.max_l2_forward_inflight` is reached
+    pub l2_forward_overflow: L2ForwardOverflow,
+    /// Forward to the L2 targets one at a time in the order they are declared
+    pub l2_ordered: bool,
+    /// Forward raw transactions directly to L2 when no builder responded
+    pub fallback_to_l2_on_builder_outage: bool,
+    /// Compare the L2 response of validated requests to the builder response
+    pub tee_compare: bool,
+    /// New builder URLs receiving `builder_split_weight` percent of the builder requests
+    pub builder_split_urls: Vec<String>,
+    /// Percentage of builder requests sent to `builder_split_urls`
+    pub builder_split_weight: u8,
+}
+
+/// Checks and deduplication of requests before they are fanned out.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestsConfig {
+    /// Validate that responses are complete JSON-RPC 2.0 responses matching the request id
+    pub validate_responses: bool,
+    /// Treat success responses with a malformed result as failed targets
+    pub validate_results: bool,
+    /// Reject notifications with an error instead of fanning them out
+    pub reject_notifications: bool,
+    /// Maximum fee per gas in wei of raw transactions, unlimited if unset.
+    /// Written as a string, as TOML integers do not fit every amount.
+    #[serde(with = "wei")]
+    pub max_fee_per_gas_cap: Option<u128>,
+    /// Hold submissions until lower nonces from the same sender have been processed
+    pub order_by_nonce: bool,
+    /// Maximum time in milliseconds a submission is held waiting for a lower nonce
+    pub order_by_nonce_max_hold_ms: u64,
+    /// Window in milliseconds within which repeated requests are answered
+    /// without being forwarded again, disabled if unset
+    pub request_replay_window_ms: Option<u64>,
+    /// Maximum number of recent requests tracked for replay protection
+    pub request_replay_max_entries: usize,
+    /// Join requests identical to a request still in flight
+    pub coalesce_inflight: bool,
+    /// Time in milliseconds after which a request in flight is no longer joined
+    pub coalesce_inflight_ttl_ms: u64,
+    /// Answer `web3_clientVersion` with the name and version of the proxy
+    pub local_client_version: bool,
+    /// Answer `net_version` with this network id
+    pub local_net_version: Option<u64>,
+}
+
+/// Errors returned to callers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ErrorsConfig {
+    /// JSON-RPC error code of builder PBH validation errors
+    pub pbh_error_code: i32,
+    /// Message prefix of builder PBH validation errors
+    pub pbh_error_prefix: String,
+    /// JSON-RPC error code returned when no builder or L2 target responded
+    pub unavailable_error_code: i32,
+    /// JSON-RPC error message returned when no builder or L2 target responded
+    pub unavailable_error_message: String,
+    /// Seconds clients are asked to wait before retrying, not sent if unset
+    pub retry_after_secs: Option<u64>,
+    /// Regexes whose matches are redacted from error responses
+    pub redact_patterns: Vec<String>,
+}
+
+/// Validation of the JWTs of inbound requests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Tolerance in seconds for JWT `iat` claims issued ahead of the local clock
+    pub jwt_clock_skew_secs: u64,
+    /// Claim of inbound JWTs identifying the caller in metrics and logs
+    pub identity_claim: Option<String>,
+    /// Values of `identity_claim` reported as is, others are reported as `unknown`
+    pub known_identities: Vec<String>,
+}
+
+/// Identification of the proxy to the targets and the timeouts of the targets.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpstreamConfig {
+    /// Name identifying this instance to the targets, the hostname if unset
+    pub instance_name: Option<String>,
+    /// Do not identify this instance to the targets
+    pub no_identify_upstream: bool,
+    /// Percentage within which the response timeouts of the targets of each
+    /// group are spread
+    pub timeout_jitter_pct: u8,
+}
+
+/// Bridging of `eth_subscribe` requests to a WebSocket.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SubscribeConfig {
+    /// WebSocket URL subscriptions are bridged to, disabled if unset
+    pub ws_url: Option<String>,
+    /// Hex encoded JWT secret used to authenticate to `ws_url`
+    pub jwt_token: Option<String>,
+    /// Interval in seconds of the WebSocket pings, disabled if unset
+    pub ping_interval_secs: Option<u64>,
+    /// Delay in milliseconds before resubscribing after a dropped subscription
+    pub reconnect_backoff_ms: u64,
+}
+
+/// Capture of the requests for a method and every builder response.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptureConfig {
+    /// Method whose requests are captured, disabled if unset
+    pub method: Option<String>,
+    /// JSONL file captured requests are appended to
+    pub path: Option<PathBuf>,
+    /// Write request header values instead of redacting them
+    pub headers: bool,
+}
+
+/// Limits on the connections and requests handled by the proxy.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// Maximum number of concurrent connections on each listener
+    pub max_concurrent_connections: u32,
+    /// Maximum number of pending connections queued on the listeners
+    pub listen_backlog: u32,
+    /// Maximum bytes of request and response bodies buffered across in-flight requests
+    pub max_buffered_bytes: usize,
+    /// Maximum number of L2 forwards in flight, unlimited if unset
+    pub max_l2_forward_inflight: Option<usize>,
+    /// Requests queued for each target to send them in order, sent concurrently if unset
+    pub target_queue_depth: Option<usize>,
+    /// Time in seconds in-flight requests are given to complete at shutdown
+    pub shutdown_grace_secs: u64,
+    /// P99 latency in milliseconds above which new requests are shed, disabled if unset
+    pub shed_latency_ms: Option<u64>,
+    /// Percentage of new requests shed while the P99 latency is above `shed_latency_ms`
+    pub shed_percent: u8,
+    /// Milliseconds of recent requests the P99 latency is computed over
+    pub shed_window_ms: u64,
+    /// Percentage of rate limited builders above which callers are asked to
+    /// back off, disabled if unset
+    pub saturation_threshold: Option<u8>,
+    /// Milliseconds a rate limited request counts its builder as saturated
+    pub saturation_window_ms: u64,
+    /// Shed new requests while `saturation_threshold` is reached
+    pub shed_on_upstream_saturation: bool,
+}
+
+/// Metrics and tracing toggles.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Enable Prometheus metrics
+    pub metrics: bool,
+    /// Address of the metrics server
+    pub metrics_addr: SocketAddr,
+    /// Histogram bucket boundaries in seconds for the request latency metrics
+    pub latency_buckets: Vec<f64>,
+    /// Record the age of accepted JWTs
+    pub metrics_jwt_age: bool,
+    /// Serve `GET /metrics` on the RPC port
+    pub metrics_on_rpc_port: bool,
+    /// Require the listener JWT to scrape metrics on the RPC port
+    pub metrics_auth: bool,
+    /// Answer `proxy_metrics` on authenticated listeners
+    pub rpc_metrics: bool,
+    /// Enable tracing
+    pub tracing: bool,
+    /// OTLP endpoint
+    pub otlp_endpoint: String,
+    /// Ratio of request traces exported, between 0 and 1
+    pub trace_sample_ratio: f64,
+    /// Latency in milliseconds above which requests are summarized when their
+    /// trace is not sampled
+    pub trace_latency_threshold_ms: Option<u64>,
+    /// Latency in milliseconds above which requests to a target are logged, disabled if 0
+    pub slow_request_ms: u64,
+    /// Emit a `tx_submitted` event for each builder accepting a raw transaction
+    pub tx_events: bool,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            listeners: vec![ListenerConfig {
+                name: DEFAULT_LISTENER_NAME.to_string(),
+                addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), DEFAULT_HTTP_PORT),
+                jwt_token: None,
+                jwt_path: None,
+            }],
+            builder: TargetGroupConfig::default(),
+            l2: TargetGroupConfig::default(),
+            allowed_methods: vec![],
+            cors_origins: vec![],
+            routing: RoutingConfig::default(),
+            requests: RequestsConfig::default(),
+            errors: ErrorsConfig::default(),
+            auth: AuthConfig::default(),
+            upstream: UpstreamConfig::default(),
+            subscribe: SubscribeConfig::default(),
+            capture: CaptureConfig::default(),
+            limits: LimitsConfig::default(),
+            telemetry: TelemetryConfig::default(),
+        }
+    }
+}
+
+impl Default for TargetGroupConfig {
+    fn default() -> Self {
+        Self {
+            urls: vec![],
+            jwt_token: None,
+            jwt_path: None,
+            timeout_ms: None,
+            connect_timeout_ms: None,
+            response_timeout_ms: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            max_retry_after_secs: DEFAULT_MAX_RETRY_AFTER_SECS,
+            method_rewrites: BTreeMap::new(),
+            path_overrides: BTreeMap::new(),
+            headers: vec![],
+            no_idempotency_key: false,
+            expect_identity: None,
+            ca_file: None,
+            use_webpki_roots: false,
+            http_proxy: None,
+            max_concurrent_per_target: None,
+            jwt_claims: BTreeMap::new(),
+            maintenance: BTreeMap::new(),
+        }
+    }
+}
+
+impl Default for RoutingConfig {
+    fn default() -> Self {
+        Self {
+            selection_strategy: SelectionStrategy::default(),
+            builder_min_success: 1,
+            require_consistent_success: None,
+            strict_methods: vec![],
+            method_rewrites: BTreeMap::new(),
+            hedge_delay_ms: None,
+            hedge_categories: vec![MethodCategory::Read],
+            sticky_sender: false,
+            l2_forward_methods: vec![],
+            l2_forward_blocking: false,
+            l2_forward_on_abort: false,
+            l2_forward_overflow: L2ForwardOverflow::default(),
+            l2_ordered: false,
+            fallback_to_l2_on_builder_outage: false,
+            tee_compare: false,
+            builder_split_urls: vec![],
+            builder_split_weight: 0,
+        }
+    }
+}
+
+impl Default for RequestsConfig {
+    fn default() -> Self {
+        Self {
+            validate_responses: false,
+            validate_results: false,
+            reject_notifications: false,
+            max_fee_per_gas_cap: None,
+            order_by_nonce: false,
+            order_by_nonce_max_hold_ms: DEFAULT_MAX_HOLD_MS,
+            request_replay_window_ms: None,
+            request_replay_max_entries: DEFAULT_REPLAY_MAX_ENTRIES,
+            coalesce_inflight: false,
+            coalesce_inflight_ttl_ms: DEFAULT_COALESCE_TTL_MS,
+            local_client_version: false,
+            local_net_version: None,
+        }
+    }
+}
+
+impl Default for ErrorsConfig {
+    fn default() -> Self {
+        Self {
+            pbh_error_code: INTERNAL_ERROR_CODE,
+            pbh_error_prefix: DEFAULT_PBH_ERROR_PREFIX.to_string(),
+            unavailable_error_code: DEFAULT_UNAVAILABLE_ERROR_CODE,
+            unavailable_error_message: DEFAULT_UNAVAILABLE_ERROR_MESSAGE.to_string(),
+            retry_after_secs: None,
+            redact_patterns: vec![],
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt_clock_skew_secs: DEFAULT_JWT_CLOCK_SKEW_SECS,
+            identity_claim: None,
+            known_identities: vec![],
+        }
+    }
+}
+
+impl Default for SubscribeConfig {
+    fn default() -> Self {
+        Self {
+            ws_url: None,
+            jwt_token: None,
+            ping_interval_secs: None,
+            reconnect_backoff_ms: DEFAULT_WS_RECONNECT_BACKOFF_MS,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_connections: DEFAULT_MAX_CONCURRENT_CONNECTIONS,
+            listen_backlog: DEFAULT_LISTEN_BACKLOG,
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            max_l2_forward_inflight: None,
+            target_queue_depth: None,
+            shutdown_grace_secs: DEFAULT_SHUTDOWN_GRACE_SECS,
+            shed_latency_ms: None,
+            shed_percent: DEFAULT_SHED_PERCENT,
+            shed_window_ms: DEFAULT_SHED_WINDOW_MS,
+            saturation_threshold: None,
+            saturation_window_ms: DEFAULT_SATURATION_WINDOW_MS,
+            shed_on_upstream_saturation: false,
+        }
+    }
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            metrics: false,
+            metrics_addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), DEFAULT_METRICS_PORT),
+            latency_buckets: DEFAULT_LATENCY_BUCKETS.to_vec(),
+            metrics_jwt_age: false,
+            metrics_on_rpc_port: false,
+            metrics_auth: false,
+            rpc_metrics: false,
+            tracing: false,
+            // Formatted as the parsed `--otlp-endpoint`, with a trailing slash
+            otlp_endpoint: Uri::from_static(DEFAULT_OTLP_URL).to_string(),
+            trace_sample_ratio: 1.0,
+            trace_latency_threshold_ms: None,
+            slow_request_ms: DEFAULT_SLOW_REQUEST_MS,
+            tx_events: false,
+        }
+    }
+}
+
+/// Serializes a wei amount as a decimal string, accepting an integer or a string.
+mod wei {
+    use super::*;
+    use serde::de::Error as _;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Wei {
+        Integer(u64),
+        String(String),
+    }
+
+    pub fn serialize<S: Serializer>(wei: &Option<u128>, serializer: S) -> Result<S::Ok, S::Error> {
+        match wei {
+            Some(wei) => serializer.serialize_some(&wei.to_string()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u128>, D::Error> {
+        Option::<Wei>::deserialize(deserializer)?
+            .map(|wei| match wei {
+                Wei::Integer(wei) => Ok(wei.into()),
+                Wei::String(wei) => wei.parse().map_err(D::Error::custom),
+            })
+            .transpose()
+    }
+}
+
+/// An invalid field of a [`ProxyConfig`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// The path of the field, e.g. `builder.urls[0]`
+    pub field: String,
+    pub reason: String,
+}
+
+impl ConfigError {
+    fn new(field: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// Returned by [`ProxyConfig::validate`] with every invalid field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidConfig {
+    pub errors: Vec<ConfigError>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid config")?;
+        for (i, error) in self.errors.iter().enumerate() {
+            write!(f, "{} {error}", if i == 0 { ":" } else { ";" })?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+impl ProxyConfig {
+    /// Reads a [`ProxyConfig`] from a TOML file.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .wrap_err_with(|| format!("Failed to read config file {}", path.display()))?;
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse config file {}", path.display()))
+    }
+
+    /// Checks every field, returning all the invalid ones at once.
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        let mut errors = vec![];
+
+        if self.listeners.is_empty() {
+            errors.push(ConfigError::new(
+                "listeners",
+                "at least one listener is required",
+            ));
+        }
+        for (i, listener) in self.listeners.iter().enumerate() {
+            let field = |name: &str| format!("listeners[{i}].{name}");
+            if let Some(token) = &listener.jwt_token {
+                if let Err(err) = JwtSecret::from_hex(token) {
+                    errors.push(ConfigError::new(field("jwt_token"), err.to_string()));
+                }
+            }
+            if let Some(other) = self.listeners[..i].iter().find(|l| l.addr == listener.addr) {
+                errors.push(ConfigError::new(
+                    field("addr"),
+                    format!(
+                        "{} is already used by listener `{}`",
+                        listener.addr, other.name
+                    ),
+                ));
+            }
+        }
+
+        self.builder.validate("builder", true, &mut errors);
+        self.l2.validate("l2", false, &mut errors);
+
+        let routing = &self.routing;
+        for (i, url) in routing.builder_split_urls.iter().enumerate() {
+            if let Err(err) = url.parse::<Uri>() {
+                errors.push(ConfigError::new(
+                    format!("routing.builder_split_urls[{i}]"),
+                    format!("invalid URL `{url}`: {err}"),
+                ));
+            }
+        }
+        if routing.builder_split_weight > MAX_SPLIT_WEIGHT {
+            errors.push(ConfigError::new(
+                "routing.builder_split_weight",
+                format!("must be at most {MAX_SPLIT_WEIGHT}"),
+            ));
+        }
+        if self.upstream.timeout_jitter_pct > MAX_TIMEOUT_JITTER_PCT {
+            errors.push(ConfigError::new(
+                "upstream.timeout_jitter_pct",
+                format!("must be at most {MAX_TIMEOUT_JITTER_PCT}"),
+            ));
+        }
+        if let Err(err) = Redactor::new(&self.errors.redact_patterns) {
+            errors.push(ConfigError::new(
+                "errors.redact_patterns",
+                format!("{err:#}"),
+            ));
+        }
+        if let Some(Err(err)) = self.subscribe.jwt_token.as_deref().map(JwtSecret::from_hex) {
+            errors.push(ConfigError::new("subscribe.jwt_token", err.to_string()));
+        }
+        if self.capture.method.is_some() && self.capture.path.is_none() {
+            errors.push(ConfigError::new(
+                "capture.path",
+                "is required with capture.method",
+            ));
+        }
+
+        let limits = &self.limits;
+        if limits.max_concurrent_connections == 0 {
+            errors.push(ConfigError::new(
+                "limits.max_concurrent_connections",
+                "must be greater than zero",
+            ));
+        }
+        if limits.max_l2_forward_inflight == Some(0) {
+            errors.push(ConfigError::new(
+                "limits.max_l2_forward_inflight",
+                "must be greater than zero",
+            ));
+        }
+        if limits.target_queue_depth == Some(0) {
+            errors.push(ConfigError::new(
+                "limits.target_queue_depth",
+                "must be greater than zero",
+            ));
+        }
+        for (field, percent) in [
+            ("limits.shed_percent", Some(limits.shed_percent)),
+            ("limits.saturation_threshold", limits.saturation_threshold),
+        ] {
+            if percent.is_some_and(|percent| !(1..=100).contains(&percent)) {
+                errors.push(ConfigError::new(field, "must be between 1 and 100"));
+            }
+        }
+
+        let telemetry = &self.telemetry;
+        let buckets = &telemetry.latency_buckets;
+        if buckets.is_empty() {
+            errors.push(ConfigError::new(
+                "telemetry.latency_buckets",
+                "must not be empty",
+            ));
+        } else if buckets
+            .iter()
+            .any(|bucket| !bucket.is_finite() || *bucket <= 0.0)
+        {
+            errors.push(ConfigError::new(
+                "telemetry.latency_buckets",
+                "must be positive numbers of seconds",
+            ));
+        } else if buckets.windows(2).any(|pair| pair[0] >= pair[1]) {
+            errors.push(ConfigError::new(
+                "telemetry.latency_buckets",
+                "must be strictly increasing",
+            ));
+        }
+        if !(0.0..=1.0).contains(&telemetry.trace_sample_ratio) {
+            errors.push(ConfigError::new(
+                "telemetry.trace_sample_ratio",
+                "must be between 0 and 1",
+            ));
+        }
+        if let Err(err) = telemetry.otlp_endpoint.parse::<Uri>() {
+            errors.push(ConfigError::new(
+                "telemetry.otlp_endpoint",
+                format!("invalid URL `{}`: {err}", telemetry.otlp_endpoint),
+            ));
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig { errors })
+        }
+    }
+
+    /// Validates the config and starts an RPC server for each listener, all
+    /// sharing the same targets.
+    ///
+    /// Metrics are recorded to `metrics`, serving them and exporting traces is
+    /// left to the caller.
+    pub async fn serve(
+        &self,
+        metrics: Arc<ProxyMetrics>,
+        metrics_handle: Option<PrometheusHandle>,
+        probes: Probes,
+    ) -> Result<Vec<ServerHandle>> {
+        self.validate()?;
+        let cli = self.to_cli()?;
+        let listeners = self.resolve_listeners()?;
+        let targets = cli.targets()?;
+        cli.serve_listeners(&listeners, metrics, metrics_handle, probes, &targets)
+            .await
+    }
+
+    /// Returns the listeners, reading their JWT secrets.
+    pub fn resolve_listeners(&self) -> Result<Vec<Listener>> {
+        self.listeners.iter().map(Listener::from_config).collect()
+    }
+
+    /// Returns these settings with those left at their default taken from
+    /// `config`, field by field within each section.
+    ///
+    /// The listeners of `config` replace these listeners unless they are the
+    /// default listener, and a target group keeps its JWT secret rather than
+    /// taking the token or path of `config` if either is set.
+    pub fn with_defaults_from(&self, config: &ProxyConfig) -> Result<Self> {
+        let defaults = toml::Table::try_from(Self::default())?;
+        let mut merged = toml::Table::try_from(self)?;
+        for (key, value) in toml::Table::try_from(config)? {
+            let default = defaults.get(&key);
+            if let (
+                Some(toml::Value::Table(section)),
+                toml::Value::Table(values),
+                Some(toml::Value::Table(defaults)),
+            ) = (merged.get_mut(&key), &value, default)
+            {
+                let jwt_set = section.contains_key("jwt_token") || section.contains_key("jwt_path");
+                for (field, value) in values {
+                    if jwt_set && matches!(field.as_str(), "jwt_token" | "jwt_path") {
+                        continue;
+                    }
+                    if section.get(field) == defaults.get(field) {
+                        section.insert(field.clone(), value.clone());
+                    }
+                }
+                continue;
+            }
+
+            let replaces_listeners = key == "listeners" && Some(&value) != default;
+            if replaces_listeners || merged.get(&key) == default {
+                merged.insert(key, value);
+            }
+        }
+        Ok(merged.try_into()?)
+    }
+
+    /// Returns the [`Cli`] configured with these settings.
+    pub(crate) fn to_cli(&self) -> Result<Cli> {
+        let mut cli = Cli::try_parse_from(["tx-proxy"])?;
+        self.apply(&mut cli)?;
+        Ok(cli)
+    }
+
+    /// Sets the arguments of `cli` to these settings, leaving the
+    /// [`CLI_ONLY_ARGS`] as they are. The first listener sets the address and
+    /// JWT secret of the RPC server.
+    pub(crate) fn apply(&self, cli: &mut Cli) -> Result<()> {
+        cli.builder_targets = BuilderTargets::from_config(&self.builder)?;
+        cli.l2_targets = L2Targets::from_config(&self.l2)?;
+        if let Some(listener) = self.listeners.first() {
+            cli.http_addr = listener.addr.ip();
+            cli.http_port = listener.addr.port();
+            cli.jwt_token = listener
+                .jwt_token
+                .as_deref()
+                .map(JwtSecret::from_hex)
+                .transpose()?;
+            cli.jwt_path = listener.jwt_path.clone();
+        }
+        cli.allowed_methods = self.allowed_methods.clone();
+        cli.cors_origins = self.cors_origins.clone();
+
+        let routing = &self.routing;
+        cli.selection_strategy = routing.selection_strategy;
+        cli.builder_min_success = routing.builder_min_success;
+        cli.require_consistent_success = routing.require_consistent_success;
+        cli.strict_methods = routing.strict_methods.clone();
+        cli.method_rewrites = routing.method_rewrites.clone().into_iter().collect();
+        cli.hedge_delay_ms = routing.hedge_delay_ms;
+        cli.hedge_categories = routing.hedge_categories.clone();
+        cli.sticky_sender = routing.sticky_sender;
+        cli.l2_forward_methods = routing.l2_forward_methods.clone();
+        cli.l2_forward_blocking = routing.l2_forward_blocking;
+        cli.l2_forward_on_abort = routing.l2_forward_on_abort;
+        cli.l2_forward_overflow = routing.l2_forward_overflow;
+        cli.l2_ordered = routing.l2_ordered;
+        cli.fallback_to_l2_on_builder_outage = routing.fallback_to_l2_on_builder_outage;
+        cli.tee_compare = routing.tee_compare;
+        cli.builder_split_urls = routing
+            .builder_split_urls
+            .iter()
+            .map(|url| url.parse::<Uri>())
+            .collect::<Result<_, _>>()?;
+        cli.builder_split_weight = routing.builder_split_weight;
+
+        let requests = &self.requests;
+        cli.validate_responses = requests.validate_responses;
+        cli.validate_results = requests.validate_results;
+        cli.reject_notifications = requests.reject_notifications;
+        cli.max_fee_per_gas_cap = requests.max_fee_per_gas_cap;
+        cli.order_by_nonce = requests.order_by_nonce;
+        cli.order_by_nonce_max_hold_ms = requests.order_by_nonce_max_hold_ms;
+        cli.request_replay_window_ms = requests.request_replay_window_ms;
+        cli.request_replay_max_entries = requests.request_replay_max_entries;
+        cli.coalesce_inflight = requests.coalesce_inflight;
+        cli.coalesce_inflight_ttl_ms = requests.coalesce_inflight_ttl_ms;
+        cli.local_client_version = requests.local_client_version;
+        cli.local_net_version = requests.local_net_version;
+
+        let errors = &self.errors;
+        cli.pbh_error_code = errors.pbh_error_code;
+        cli.pbh_error_prefix = errors.pbh_error_prefix.clone();
+        cli.unavailable_error_code = errors.unavailable_error_code;
+        cli.unavailable_error_message = errors.unavailable_error_message.clone();
+        cli.retry_after_secs = errors.retry_after_secs;
+        cli.redact_patterns = errors.redact_patterns.clone();
+
+        cli.jwt_clock_skew_secs = self.auth.jwt_clock_skew_secs;
+        cli.identity_claim = self.auth.identity_claim.clone();
+        cli.known_identities = self.auth.known_identities.clone();
+
+        cli.instance_name = self.upstream.instance_name.clone();
+        cli.no_identify_upstream = self.upstream.no_identify_upstream;
+        cli.timeout_jitter_pct = self.upstream.timeout_jitter_pct;
+
+        cli.subscribe_ws_url = self.subscribe.ws_url.clone();
+        cli.subscribe_jwt_token = self
+            .subscribe
+            .jwt_token
+            .as_deref()
+            .map(JwtSecret::from_hex)
+            .transpose()?;
+        cli.ws_ping_interval_secs = self.subscribe.ping_interval_secs;
+        cli.ws_reconnect_backoff_ms = self.subscribe.reconnect_backoff_ms;
+
+        cli.capture_method = self.capture.method.clone();
+        cli.capture_path = self.capture.path.clone();
+        cli.capture_headers = self.capture.headers;
+
+        let limits = &self.limits;
+        cli.max_concurrent_connections = limits.max_concurrent_connections;
+        cli.listen_backlog = limits.listen_backlog;
+        cli.max_buffered_bytes = limits.max_buffered_bytes;
+        cli.max_l2_forward_inflight = limits.max_l2_forward_inflight;
+        cli.target_queue_depth = limits.target_queue_depth;
+        cli.shutdown_grace_secs = limits.shutdown_grace_secs;
+        cli.shed_latency_ms = limits.shed_latency_ms;
+        cli.shed_percent = limits.shed_percent;
+        cli.shed_window_ms = limits.shed_window_ms;
+        cli.saturation_threshold = limits.saturation_threshold;
+        cli.saturation_window_ms = limits.saturation_window_ms;
+        cli.shed_on_upstream_saturation = limits.shed_on_upstream_saturation;
+
+        let telemetry = &self.telemetry;
+        cli.metrics = telemetry.metrics;
+        cli.metrics_host = telemetry.metrics_addr.ip();
+        cli.metrics_port = telemetry.metrics_addr.port();
+        cli.metrics_latency_buckets = telemetry.latency_buckets.clone();
+        cli.metrics_jwt_age = telemetry.metrics_jwt_age;
+        cli.metrics_on_rpc_port = telemetry.metrics_on_rpc_port;
+        cli.metrics_auth = telemetry.metrics_auth;
+        cli.rpc_metrics = telemetry.rpc_metrics;
+        cli.tracing = telemetry.tracing;
+        cli.otlp_endpoint = telemetry.otlp_endpoint.parse()?;
+        cli.trace_sample_ratio = telemetry.trace_sample_ratio;
+        cli.trace_latency_threshold_ms = telemetry.trace_latency_threshold_ms;
+        cli.slow_request_ms = telemetry.slow_request_ms;
+        cli.tx_events = telemetry.tx_events;
+        Ok(())
+    }
+}
+
+impl From<Cli> for ProxyConfig {
+    /// Captures the settings of the command line but the [`CLI_ONLY_ARGS`],
+    /// with a single listener on `--http-addr` and `--http-port`.
+    fn from(cli: Cli) -> Self {
+        Self {
+            listeners: vec![ListenerConfig {
+                name: DEFAULT_LISTENER_NAME.to_string(),
+                addr: SocketAddr::new(cli.http_addr, cli.http_port),
+                jwt_token: cli.jwt_token.map(|secret| hex::encode(secret.as_bytes())),
+                jwt_path: cli.jwt_path,
+            }],
+            builder: cli.builder_targets.to_config(),
+            l2: cli.l2_targets.to_config(),
+            allowed_methods: cli.allowed_methods,
+            cors_origins: cli.cors_origins,
+            routing: RoutingConfig {
+                selection_strategy: cli.selection_strategy,
+                builder_min_success: cli.builder_min_success,
+                require_consistent_success: cli.require_consistent_success,
+                strict_methods: cli.strict_methods,
+                method_rewrites: cli.method_rewrites.into_iter().collect(),
+                hedge_delay_ms: cli.hedge_delay_ms,
+                hedge_categories: cli.hedge_categories,
+                sticky_sender: cli.sticky_sender,
+                l2_forward_methods: cli.l2_forward_methods,
+                l2_forward_blocking: cli.l2_forward_blocking,
+                l2_forward_on_abort: cli.l2_forward_on_abort,
+                l2_forward_overflow: cli.l2_forward_overflow,
+                l2_ordered: cli.l2_ordered,
+                fallback_to_l2_on_builder_outage: cli.fallback_to_l2_on_builder_outage,
+                tee_compare: cli.tee_compare,
+                builder_split_urls: cli.builder_split_urls.iter().map(Uri::to_string).collect(),
+                builder_split_weight: cli.builder_split_weight,
+            },
+            requests: RequestsConfig {
+                validate_responses: cli.validate_responses,
+                validate_results: cli.validate_results,
+                reject_notifications: cli.reject_notifications,
+                max_fee_per_gas_cap: cli.max_fee_per_gas_cap,
+                order_by_nonce: cli.order_by_nonce,
+                order_by_nonce_max_hold_ms: cli.order_by_nonce_max_hold_ms,
+                request_replay_window_ms: cli.request_replay_window_ms,
+                request_replay_max_entries: cli.request_replay_max_entries,
+                coalesce_inflight: cli.coalesce_inflight,
+                coalesce_inflight_ttl_ms: cli.coalesce_inflight_ttl_ms,
+                local_client_version: cli.local_client_version,
+                local_net_version: cli.local_net_version,
+            },
+            errors: ErrorsConfig {
+                pbh_error_code: cli.pbh_error_code,
+                pbh_error_prefix: cli.pbh_error_prefix,
+                unavailable_error_code: cli.unavailable_error_code,
+                unavailable_error_message: cli.unavailable_error_message,
+                retry_after_secs: cli.retry_after_secs,
+                redact_patterns: cli.redact_patterns,
+            },
+            auth: AuthConfig {
+                jwt_clock_skew_secs: cli.jwt_clock_skew_secs,
+                identity_claim: cli.identity_claim,
+                known_identities: cli.known_identities,
+            },
+            upstream: UpstreamConfig {
+                instance_name: cli.instance_name,
+                no_identify_upstream: cli.no_identify_upstream,
+                timeout_jitter_pct: cli.timeout_jitter_pct,
+            },
+            subscribe: SubscribeConfig {
+                ws_url: cli.subscribe_ws_url,
+                jwt_token: cli
+                    .subscribe_jwt_token
+                    .map(|secret| hex::encode(secret.as_bytes())),
+                ping_interval_secs: cli.ws_ping_interval_secs,
+                reconnect_backoff_ms: cli.ws_reconnect_backoff_ms,
+            },
+            capture: CaptureConfig {
+                method: cli.capture_method,
+                path: cli.capture_path,
+                headers: cli.capture_headers,
+            },
+            limits: LimitsConfig {
+                max_concurrent_connections: cli.max_concurrent_connections,
+                listen_backlog: cli.listen_backlog,
+                max_buffered_bytes: cli.max_buffered_bytes,
+                max_l2_forward_inflight: cli.max_l2_forward_inflight,
+                target_queue_depth: cli.target_queue_depth,
+                shutdown_grace_secs: cli.shutdown_grace_secs,
+                shed_latency_ms: cli.shed_latency_ms,
+                shed_percent: cli.shed_percent,
+                shed_window_ms: cli.shed_window_ms,
+                saturation_threshold: cli.saturation_threshold,
+                saturation_window_ms: cli.saturation_window_ms,
+                shed_on_upstream_saturation: cli.shed_on_upstream_saturation,
+            },
+            telemetry: TelemetryConfig {
+                metrics: cli.metrics,
+                metrics_addr: SocketAddr::new(cli.metrics_host, cli.metrics_port),
+                latency_buckets: cli.metrics_latency_buckets,
+                metrics_jwt_age: cli.metrics_jwt_age,
+                metrics_on_rpc_port: cli.metrics_on_rpc_port,
+                metrics_auth: cli.metrics_auth,
+                rpc_metrics: cli.rpc_metrics,
+                tracing: cli.tracing,
+                otlp_endpoint: cli.otlp_endpoint.to_string(),
+                trace_sample_ratio: cli.trace_sample_ratio,
+                trace_latency_threshold_ms: cli.trace_latency_threshold_ms,
+                slow_request_ms: cli.slow_request_ms,
+                tx_events: cli.tx_events,
+            },
+        }
+    }
+}
+
+impl TargetGroupConfig {
+    /// Pushes an error for every invalid field of the group named `name`.
+    /// At least one URL is required if `required` is set.
+    fn validate(&self, name: &str, required: bool, errors: &mut Vec<ConfigError>) {
+        let field = |field: &str| format!("{name}.{field}");
+        let mut error =
+            |field: String, reason: String| errors.push(ConfigError::new(field, reason));
+
+        if required && self.urls.is_empty() {
+            error(field("urls"), "at least one URL is required".to_string());
+        }
+        for (i, url) in self.urls.iter().enumerate() {
+            let field = field(&format!("urls[{i}]"));
+            match url.parse::<Uri>() {
+                Ok(uri) if uri.host().is_none() => {
+                    error(field, format!("URL `{url}` has no host"));
+                }
+                Ok(uri) if ![Some(&Scheme::HTTP), Some(&Scheme::HTTPS)].contains(&uri.scheme()) => {
+                    error(field, format!("URL `{url}` is not http or https"));
+                }
+                Ok(_) => {}
+                Err(err) => error(field, format!("invalid URL `{url}`: {err}")),
+            }
+        }
+
+        match (&self.jwt_token, &self.jwt_path) {
+            (None, None) => error(
+                field("jwt_token"),
+                "either jwt_token or jwt_path is required".to_string(),
+            ),
+            (Some(token), _) => {
+                if let Err(err) = JwtSecret::from_hex(token) {
+                    error(field("jwt_token"), err.to_string());
+                }
+            }
+            (None, Some(_)) => {}
+        }
+
+        for (timeout, value) in [
+            ("timeout_ms", self.timeout_ms),
+            ("connect_timeout_ms", self.connect_timeout_ms),
+            ("response_timeout_ms", self.response_timeout_ms),
+        ] {
+            if value == Some(0) {
+                error(field(timeout), "must be greater than zero".to_string());
+            }
+        }
+        if self.max_response_bytes == 0 {
+            error(
+                field("max_response_bytes"),
+                "must be greater than zero".to_string(),
+            );
+        }
+        for (method, path) in &self.path_overrides {
+            if let Err(err) = parse_request_path(path) {
+                error(field(&format!("path_overrides.{method}")), err);
+            }
+        }
+        for (i, header) in self.headers.iter().enumerate() {
+            if let Err(err) = parse_header(header) {
+                error(field(&format!("headers[{i}]")), err);
+            }
+        }
+        if self.max_concurrent_per_target == Some(0) {
+            error(
+                field("max_concurrent_per_target"),
+                "must be greater than zero".to_string(),
+            );
+        }
+        if self.ca_file.is_some() && self.use_webpki_roots {
+            error(
+                field("ca_file"),
+                "cannot be set with use_webpki_roots".to_string(),
+            );
+        }
+        if let Some(Err(err)) = self.http_proxy.as_deref().map(str::parse::<Uri>) {
+            error(field("http_proxy"), format!("invalid URL: {err}"));
+        }
+        for url in self.jwt_claims.keys() {
+            if let Err(err) = url.parse::<Uri>() {
+                error(
+                    field(&format!("jwt_claims.{url}")),
+                    format!("invalid URL: {err}"),
+                );
+            }
+        }
+        for (url, windows) in &self.maintenance {
+            if let Err(err) = url.parse::<Uri>() {
+                error(
+                    field(&format!("maintenance.{url}")),
+                    format!("invalid URL: {err}"),
+                );
+            }
+            for (i, window) in windows.iter().enumerate() {
+                if let Err(err) = window.parse::<MaintenanceWindow>() {
+                    error(field(&format!("maintenance.{url}[{i}]")), err);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+    /// Returns the command line defaults with `config` merged in.
+    fn merged_cli(config: ProxyConfig) -> Result<Cli> {
+        let mut cli = Cli::try_parse_from(["tx-proxy"])?;
+        cli.merge(config)?;
+        Ok(cli)
+    }
+
+    #[test]
+    fn test_builder_targets_from_config_file() -> Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("tx-proxy-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            format!(
+                r#"
+                allowed_methods = ["eth_sendRawTransaction"]
+
+                [builder]
+                urls = ["http://localhost:8551", "http://localhost:8552"]
+                jwt_token = "{SECRET}"
+                timeout_ms = 500
+                connect_timeout_ms = 100
+
+                [routing]
+                builder_min_success = 2
+                "#
+            ),
+        )?;
+        let config = ProxyConfig::from_file(&path);
+        fs::remove_file(&path)?;
+        let cli = merged_cli(config?)?;
+
+        let fanout = cli.builder_targets.build(None, 0, None)?;
+        let urls = fanout
+            .targets()
+            .iter()
+            .map(|client| client.url().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(urls, ["http://localhost:8551/", "http://localhost:8552/"]);
+        assert_eq!(cli.builder_targets.builder_timeout, Some(500));
+        assert_eq!(cli.builder_targets.builder_connect_timeout_ms, Some(100));
+        assert_eq!(cli.allowed_methods, ["eth_sendRawTransaction"]);
+        assert_eq!(cli.builder_min_success, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_file_with_previous_timeout_keys() -> Result<()> {
+        // Config files written before the timeouts were suffixed with their unit
+        let config: ProxyConfig = toml::from_str(&format!(
+            r#"
+            allowed_methods = ["eth_sendRawTransaction"]
+
+            [builder]
+            urls = ["http://localhost:8551"]
+            jwt_token = "{SECRET}"
+            timeout = 500
+            connect_timeout = 100
+            response_timeout = 400
+
+            [l2]
+            urls = ["http://localhost:9551"]
+            timeout = 2000
+            "#
+        ))?;
+        let cli = merged_cli(config)?;
+
+        assert_eq!(cli.builder_targets.builder_timeout, Some(500));
+        assert_eq!(cli.builder_targets.builder_connect_timeout_ms, Some(100));
+        assert_eq!(cli.builder_targets.builder_response_timeout_ms, Some(400));
+        assert_eq!(cli.l2_targets.l2_timeout, Some(2000));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_values_take_precedence() -> Result<()> {
+        let config: ProxyConfig = toml::from_str(&format!(
+            r#"
+            [builder]
+            urls = ["http://localhost:8551"]
+            jwt_token = "{SECRET}"
+            timeout_ms = 500
+
+            [routing]
+            selection_strategy = "lowest-latency"
+            strict_methods = ["eth_sendRawTransaction"]
+            "#
+        ))?;
+
+        let mut cli = Cli::try_parse_from([
+            "tx-proxy",
+            "--builder-urls=http://localhost:9551",
+            "--builder-jwt-path=/etc/tx-proxy/builder.jwt",
+            "--builder-timeout=2000",
+            "--selection-strategy=first-successful",
+        ])?;
+        cli.merge(config)?;
+
+        assert_eq!(
+            cli.builder_targets.builder_urls,
+            vec!["http://localhost:9551".parse::<http::Uri>()?]
+        );
+        assert_eq!(cli.builder_targets.builder_timeout, Some(2000));
+        assert_eq!(cli.selection_strategy, SelectionStrategy::FirstSuccessful);
+        // The JWT secret set on the command line is not mixed with the file's
+        assert_eq!(cli.builder_targets.builder_jwt_token, None);
+        // Values left at their default are taken from the file
+        assert_eq!(cli.strict_methods, ["eth_sendRawTransaction"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_jwt_claims_from_config() -> Result<()> {
+        let config: ProxyConfig = toml::from_str(&format!(
+            r#"
+            [builder]
+            urls = ["http://localhost:8551", "http://localhost:8552"]
+            jwt_token = "{SECRET}"
+
+            [builder.jwt_claims."http://localhost:8551"]
+            issuer = "tx-proxy-1"
+            lifetime_secs = 60
+            extra = {{ aud = "builder" }}
+            "#
+        ))?;
+        let cli = merged_cli(config)?;
+
+        let [(url, claims)] = cli.builder_targets.builder_jwt_claims.as_slice() else {
+            panic!("expected claims for one target");
+        };
+        assert_eq!(url, &"http://localhost:8551".parse::<http::Uri>()?);
+        assert_eq!(claims.issuer.as_deref(), Some("tx-proxy-1"));
+        assert_eq!(claims.lifetime_secs, Some(60));
+        assert_eq!(claims.extra["aud"], "builder");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_maintenance_from_config() -> Result<()> {
+        let config = |window: &str| -> Result<ProxyConfig> {
+            Ok(toml::from_str(&format!(
+                r#"
+                [builder]
+                urls = ["http://localhost:8551", "http://localhost:8552"]
+                jwt_token = "{SECRET}"
+
+                [builder.maintenance]
+                "http://localhost:8551" = ["{window}"]
+                "#
+            ))?)
+        };
+        let targets = |config: ProxyConfig| -> Result<BuilderTargets> {
+            Ok(merged_cli(config)?.builder_targets)
+        };
+
+        let fanout = targets(config("Tue 02:00-03:00 UTC")?)?.build(None, 0, None)?;
+        let windows = fanout
+            .targets()
+            .iter()
+            .map(|client| client.maintenance_windows())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            windows,
+            [
+                vec!["Tue 02:00-03:00 UTC".parse::<MaintenanceWindow>().unwrap()],
+                vec![]
+            ]
+        );
+
+        // Reloaded windows are replaced without rebuilding the client
+        let (rebuilt, diff) =
+            targets(config("Wed 02:00-03:00 UTC")?)?.rebuild(&fanout.targets(), None, 0, None)?;
+        assert!(diff.is_empty());
+        assert_eq!(
+            fanout.targets()[0].maintenance_windows(),
+            ["Wed 02:00-03:00 UTC".parse::<MaintenanceWindow>().unwrap()]
+        );
+        assert_eq!(rebuilt.len(), 2);
+
+        assert!(targets(config("Someday 02:00-03:00 UTC")?).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_jwt_token_is_reported() -> Result<()> {
+        let config: ProxyConfig = toml::from_str(
+            r#"
+            [builder]
+            urls = ["http://localhost:8551"]
+            jwt_token = "not-hex"
+            "#,
+        )?;
+
+        let err = config.validate().unwrap_err();
+        assert_eq!(err.errors[0].field, "builder.jwt_token");
+        assert!(merged_cli(config).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_file_listeners_replace_default_listener() -> Result<()> {
+        let config: ProxyConfig = toml::from_str(
+            r#"
+            [[listeners]]
+            name = "external"
+            addr = "0.0.0.0:8545"
+
+            [[listeners]]
+            name = "internal"
+            addr = "127.0.0.1:8546"
+            "#,
+        )?;
+
+        let merged = ProxyConfig::default().with_defaults_from(&config)?;
+        assert_eq!(merged.listeners, config.listeners);
+
+        // Unlike other settings, they also replace a listener set on the command line
+        let cli = Cli::try_parse_from(["tx-proxy", "--http-port=9545"])?;
+        let merged = ProxyConfig::from(cli).with_defaults_from(&config)?;
+        assert_eq!(merged.listeners, config.listeners);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_config_round_trip() -> Result<()> {
+        let cli = Cli::try_parse_from([
+            "tx-proxy".to_string(),
+            "--builder-urls=http://localhost:8551".to_string(),
+            format!("--builder-jwt-token={SECRET}"),
+            "--builder-timeout=500".to_string(),
+            "--builder-header=X-Api-Key=secret".to_string(),
+            "--builder-method-rewrites=eth_sendRawTransaction=eth_sendRawTransactionPass"
+                .to_string(),
+            "--l2-urls=http://localhost:9551".to_string(),
+            "--l2-jwt-path=/etc/tx-proxy/l2.jwt".to_string(),
+            format!("--jwt-token={SECRET}"),
+            "--http-port=9545".to_string(),
+            "--cors-origins=*".to_string(),
+            "--selection-strategy=lowest-latency".to_string(),
+            "--builder-min-success=2".to_string(),
+            "--strict-methods=eth_sendRawTransaction".to_string(),
+            "--l2-forward-methods=eth_sendRawTransaction".to_string(),
+            "--l2-forward-blocking".to_string(),
+            "--l2-ordered".to_string(),
+            "--hedge-delay-ms=50".to_string(),
+            "--hedge-categories=read,write".to_string(),
+            "--sticky-sender".to_string(),
+            "--method-rewrite=pbh_sendConditional=eth_sendRawTransactionConditional".to_string(),
+            "--max-fee-per-gas-cap=340282366920938463463374607431768211455".to_string(),
+            "--coalesce-inflight".to_string(),
+            "--request-replay-window-ms=1000".to_string(),
+            "--redact-pattern=secret-[0-9]+".to_string(),
+            "--retry-after-secs=2".to_string(),
+            "--identity-claim=sub".to_string(),
+            "--timeout-jitter-pct=10".to_string(),
+            format!("--subscribe-jwt-token={SECRET}"),
+            "--shed-latency-ms=200".to_string(),
+            "--target-queue-depth=8".to_string(),
+            "--metrics-latency-buckets=0.1,0.5,1".to_string(),
+            "--trace-sample-ratio=0.5".to_string(),
+            "--tx-events".to_string(),
+        ])?;
+        let config = ProxyConfig::from(cli);
+        assert_eq!(config.builder.timeout_ms, Some(500));
+        assert_eq!(config.builder.headers, ["x-api-key=secret"]);
+        assert_eq!(config.listeners[0].addr.port(), 9545);
+        assert_eq!(
+            config.routing.selection_strategy,
+            SelectionStrategy::LowestLatency
+        );
+        assert_eq!(config.routing.builder_min_success, 2);
+        assert!(config.routing.l2_forward_blocking);
+        assert_eq!(config.requests.max_fee_per_gas_cap, Some(u128::MAX));
+        assert_eq!(config.upstream.timeout_jitter_pct, 10);
+        assert_eq!(config.limits.target_queue_depth, Some(8));
+        assert_eq!(config.limits.shed_latency_ms, Some(200));
+
+        let serialized = toml::to_string(&config)?;
+        assert_eq!(toml::from_str::<ProxyConfig>(&serialized)?, config);
+        assert_eq!(ProxyConfig::from(config.to_cli()?), config);
+        config.validate()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_config_defaults_match_cli() -> Result<()> {
+        let cli = Cli::try_parse_from(["tx-proxy"])?;
+        assert_eq!(ProxyConfig::from(cli), ProxyConfig::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_only_args_are_not_configurable() -> Result<()> {
+        let command = Cli::command();
+        for id in CLI_ONLY_ARGS {
+            assert!(
+                command.get_arguments().any(|arg| arg.get_id() == *id),
+                "{id} is not an argument"
+            );
+            assert!(
+                toml::from_str::<ProxyConfig>(&format!("{id} = 1")).is_err(),
+                "{id} is in the config"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_proxy_config_reports_every_error() -> Result<()> {
+        let config: ProxyConfig = toml::from_str(&format!(
+            r#"
+            [builder]
+            jwt_token = "{SECRET}"
+            timeout_ms = 0
+
+            [l2]
+            urls = ["http://localhost:9551"]
+            jwt_token = "{SECRET}"
+
+            [telemetry]
+            latency_buckets = [0.5, 0.1]
+            "#
+        ))?;
+
+        let err = config.validate().unwrap_err();
+        let fields = err
+            .errors
+            .iter()
+            .map(|error| error.field.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fields,
+            [
+                "builder.urls",
+                "builder.timeout_ms",
+                "telemetry.latency_buckets"
+            ]
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid config: builder.urls: at least one URL is required; \
+             builder.timeout_ms: must be greater than zero; \
+             telemetry.latency_buckets: must be strictly increasing"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    fmt,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use http::Uri;
+use jsonrpsee::http_client::HttpBody;
+use rustls::pki_types::ServerName;
+use serde::Serialize;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+
+use crate::{
+    client::{HttpClient, without_userinfo},
+    rpc::RpcRequest,
+};
+
+/// How long each network check of a diagnosis may take.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of a single check of a diagnosis.
+#[derive(Clone, Debug, Serialize)]
+pub struct Check {
+    /// What was checked, e.g. `dns` or `jwt`.
+    pub name: String,
+    /// The URL, path or address checked, if any.
+    pub target: Option<String>,
+    pub passed: bool,
+    /// Whether a failure of the check fails the diagnosis.
+    pub critical: bool,
+    /// How long the check took, for network checks.
+    pub latency_ms: Option<u64>,
+    /// The result of the check, or why it failed.
+    pub detail: String,
+}
+
+impl Check {
+    /// Creates a passed check.
+    pub fn passed(name: &str, target: Option<String>, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            target,
+            passed: true,
+            critical: true,
+            latency_ms: None,
+            detail: detail.into(),
+        }
+    }
+
+    /// Creates a failed check.
+    pub fn failed(name: &str, target: Option<String>, detail: impl Into<String>) -> Self {
+        Self {
+            passed: false,
+            ..Self::passed(name, target, detail)
+        }
+    }
+
+    /// Creates a check passed if `result` is `Ok`.
+    pub fn from_result<E: fmt::Display>(
+        name: &str,
+        target: Option<String>,
+        result: Result<String, E>,
+    ) -> Self {
+        match result {
+            Ok(detail) => Self::passed(name, target, detail),
+            Err(err) => Self::failed(name, target, err.to_string()),
+        }
+    }
+
+    /// Sets whether a failure of the check fails the diagnosis.
+    pub fn with_critical(mut self, critical: bool) -> Self {
+        self.critical = critical;
+        self
+    }
+
+    fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency_ms = Some(latency.as_millis() as u64);
+        self
+    }
+}
+
+/// The checks of the configuration and targets of the proxy, printed by `tx-proxy diagnose`.
+#[derive(Clone, Debug, Serialize)]
+pub struct DiagnosticReport {
+    pub version: String,
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl DiagnosticReport {
+    /// Creates a report from its checks, failed if any critical check failed.
+    pub fn new(checks: Vec<Check>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            passed: checks.iter().all(|check| check.passed || !check.critical),
+            checks,
+        }
+    }
+
+    /// Returns the critical checks that failed.
+    pub fn critical_failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks
+            .iter()
+            .filter(|check| !check.passed && check.critical)
+    }
+}
+
+impl fmt::Display for DiagnosticReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "tx-proxy {}", self.version)?;
+        for check in &self.checks {
+            let status = match (check.passed, check.critical) {
+                (true, _) => "ok",
+                (false, true) => "FAIL",
+                (false, false) => "warn",
+            };
+            write!(f, "[{status:>4}] {}", check.name)?;
+            if let Some(target) = &check.target {
+                write!(f, " {target}")?;
+            }
+            if let Some(latency_ms) = check.latency_ms {
+                write!(f, " ({latency_ms}ms)")?;
+            }
+            writeln!(f, ": {}", check.detail)?;
+        }
+        let result = if self.passed { "passed" } else { "failed" };
+        write!(f, "diagnosis {result}")
+    }
+}
+
+/// Resolves, connects to and, over TLS, handshakes with the target, then sends
+/// it an authenticated `net_peerCount` request.
+///
+/// Stops at the first failed check, as the following ones would fail too.
+pub async fn check_target(kind: &str, client: &HttpClient) -> Vec<Check> {
+    let url = client.url();
+    let target = Some(client.display_url().to_string());
+    let mut checks = vec![];
+
+    let Some(host) = url.host() else {
+        return vec![Check::failed(
+            &format!("{kind}.dns"),
+            target,
+            "URL has no host",
+        )];
+    };
+    let https = url.scheme_str() == Some("https");
+    let port = url.port_u16().unwrap_or(if https { 443 } else { 80 });
+
+    let start = Instant::now();
+    let addrs = match timed(tokio::net::lookup_host((host, port))).await {
+        Ok(addrs) => addrs.collect::<Vec<SocketAddr>>(),
+        Err(err) => {
+            checks.push(Check::failed(&format!("{kind}.dns"), target, err));
+            return checks;
+        }
+    };
+    let Some(addr) = addrs.first().copied() else {
+        checks.push(Check::failed(
+            &format!("{kind}.dns"),
+            target,
+            "no addresses found",
+        ));
+        return checks;
+    };
+    let resolved = addrs
+        .iter()
+        .map(|addr| addr.ip().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    checks.push(
+        Check::passed(&format!("{kind}.dns"), target.clone(), resolved)
+            .with_latency(start.elapsed()),
+    );
+
+    let start = Instant::now();
+    let stream = match timed(TcpStream::connect(addr)).await {
+        Ok(stream) => stream,
+        Err(err) => {
+            checks.push(
+                Check::failed(&format!("{kind}.tcp"), target, err).with_latency(start.elapsed()),
+            );
+            return checks;
+        }
+    };
+    checks.push(
+        Check::passed(
+            &format!("{kind}.tcp"),
+            target.clone(),
+            format!("connected to {addr}"),
+        )
+        .with_latency(start.elapsed()),
+    );
+
+    if https {
+        let start = Instant::now();
+        let connector = TlsConnector::from(Arc::new(client.tls().client_config().clone()));
+        let handshake = async {
+            let name = ServerName::try_from(host.to_string())?;
+            Ok::<_, Box<dyn std::error::Error + Send + Sync>>(
+                connector.connect(name, stream).await?,
+            )
+        };
+        let check = match timed(handshake).await {
+            Ok(_) => Check::passed(
+                &format!("{kind}.tls"),
+                target.clone(),
+                "handshake completed",
+            ),
+            Err(err) => Check::failed(&format!("{kind}.tls"), target.clone(), err),
+        };
+        let failed = !check.passed;
+        checks.push(check.with_latency(start.elapsed()));
+        if failed {
+            return checks;
+        }
+    }
+
+    let start = Instant::now();
+    let result = timed(net_peer_count(client.clone())).await;
+    checks.push(
+        Check::from_result(&format!("{kind}.rpc"), target, result).with_latency(start.elapsed()),
+    );
+    checks
+}
+
+/// Checks that a TCP connection to the host and port of the URL succeeds.
+pub async fn check_reachable(name: &str, url: &Uri) -> Check {
+    let target = Some(without_userinfo(url));
+    let Some(host) = url.host() else {
+        return Check::failed(name, target, "URL has no host");
+    };
+    let port = url
+        .port_u16()
+        .unwrap_or(if url.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+
+    let start = Instant::now();
+    let result = timed(TcpStream::connect((host, port)))
+        .await
+        .map(|_| "reachable".to_string());
+    Check::from_result(name, target, result).with_latency(start.elapsed())
+}
+
+/// Sends `net_peerCount` to the target, returning its result.
+async fn net_peer_count(mut client: HttpClient) -> eyre::Result<String> {
+    let request = http::Request::builder()
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(HttpBody::from(
+            r#"{"jsonrpc":"2.0","method":"net_peerCount","params":[],"id":1}"#,
+        ))?;
+    let response = client
+        .forward(RpcRequest::from_request(request).await?)
+        .await
+        .map_err(|err| eyre::eyre!(err))?;
+    if let Some(error) = response.error {
+        return Err(eyre::eyre!("net_peerCount failed: {error}"));
+    }
+
+    let body = serde_json::from_slice::<serde_json::Value>(&response.body)?;
+    Ok(format!("net_peerCount {}", body["result"]))
+}
+
+/// Awaits a network check, failing once [`CHECK_TIMEOUT`] elapsed.
+async fn timed<T, E: fmt::Display>(check: impl Future<Output = Result<T, E>>) -> Result<T, String> {
+    match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result.map_err(|err| err.to_string()),
+        Err(_) => Err(format!("timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    }
+}
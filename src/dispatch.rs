@@ -0,0 +1,171 @@
+use std::{
+    fmt,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll, ready},
+    time::Instant,
+};
+
+use http::Request;
+use hyper::body::{Body, Frame, SizeHint};
+use jsonrpsee::{core::BoxError, http_client::HttpBody};
+use pin_project::pin_project;
+use tokio::sync::{mpsc, oneshot};
+use tower::{Service, ServiceExt};
+
+use crate::client::HttpClientService;
+
+type DispatchResponse = <HttpClientService as Service<Request<HttpBody>>>::Response;
+
+/// Returned when a request is shed because the dispatch queue of the target is full.
+#[derive(Debug)]
+pub struct TargetQueueFull {
+    pub depth: usize,
+}
+
+impl fmt::Display for TargetQueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "queue_full: {} requests already queued for the target",
+            self.depth
+        )
+    }
+}
+
+impl std::error::Error for TargetQueueFull {}
+
+/// A request waiting in the queue of an [`OrderedDispatch`].
+struct Job {
+    req: Request<HttpBody>,
+    /// Set once the request left the queue and the client was ready to send it.
+    dispatched: Arc<OnceLock<Instant>>,
+    respond: oneshot::Sender<Result<DispatchResponse, BoxError>>,
+}
+
+/// Sends the requests to a target in the order they were queued, from a
+/// dedicated task started on first use.
+///
+/// Each request is written out before the next one is sent, while responses
+/// are awaited concurrently, so the order applies to when requests reach the
+/// target rather than when they complete. Clones share the same queue, and the
+/// task stops once every clone is dropped and the queue is drained.
+#[derive(Clone, Debug)]
+pub struct OrderedDispatch {
+    depth: usize,
+    queue: Arc<OnceLock<mpsc::Sender<Job>>>,
+}
+
+impl OrderedDispatch {
+    /// Creates a new [`OrderedDispatch`] queueing up to `depth` requests, at least one.
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth: depth.max(1),
+            queue: Arc::new(OnceLock::new()),
+        }
+    }
+
+    /// Returns the maximum number of queued requests.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Queues the request to be sent with `client`, returning a receiver for its
+    /// response. `dispatched` is set when the request leaves the queue.
+    ///
+    /// Fails with [`TargetQueueFull`] without waiting if the queue is full.
+    pub fn send(
+        &self,
+        client: &HttpClientService,
+        req: Request<HttpBody>,
+        dispatched: Arc<OnceLock<Instant>>,
+    ) -> Result<oneshot::Receiver<Result<DispatchResponse, BoxError>>, TargetQueueFull> {
+        let queue = self.queue.get_or_init(|| {
+            let (sender, receiver) = mpsc::channel(self.depth);
+            tokio::spawn(dispatch(client.clone(), receiver));
+            sender
+        });
+
+        let (respond, response) = oneshot::channel();
+        let job = Job {
+            req,
+            dispatched,
+            respond,
+        };
+        // The task only stops once every sender is dropped, so sending fails on a full queue
+        queue
+            .try_send(job)
+            .map_err(|_| TargetQueueFull { depth: self.depth })?;
+        Ok(response)
+    }
+}
+
+/// Sends the queued requests one at a time, waiting for each to be written
+/// out before sending the next.
+async fn dispatch(mut client: HttpClientService, mut queue: mpsc::Receiver<Job>) {
+    while let Some(job) = queue.recv().await {
+        let (written, written_rx) = oneshot::channel();
+        let req = job
+            .req
+            .map(|body| HttpBody::new(WrittenBody::new(body, written)));
+
+        let ready = match client.ready().await {
+            Ok(ready) => ready,
+            Err(err) => {
+                let _ = job.respond.send(Err(err));
+                continue;
+            }
+        };
+        let _ = job.dispatched.set(Instant::now());
+        let response = ready.call(req);
+        tokio::spawn(async move {
+            let _ = job.respond.send(response.await);
+        });
+
+        // Requests failing before their body is written drop it, which also resolves this
+        let _ = written_rx.await;
+    }
+}
+
+/// Wraps a request body, signaling once its last frame was handed to the connection.
+#[pin_project]
+struct WrittenBody<B> {
+    #[pin]
+    inner: B,
+    written: Option<oneshot::Sender<()>>,
+}
+
+impl<B> WrittenBody<B> {
+    fn new(inner: B, written: oneshot::Sender<()>) -> Self {
+        Self {
+            inner,
+            written: Some(written),
+        }
+    }
+}
+
+impl<B: Body> Body for WrittenBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        let end = frame.is_none() || this.inner.is_end_stream();
+        if let Some(written) = this.written.take_if(|_| end) {
+            let _ = written.send(());
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,192 @@
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use http::{HeaderMap, HeaderValue, Method, StatusCode, header};
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use tower::{Layer, Service};
+
+/// Content types accepted for JSON-RPC requests, ignoring parameters such as the charset.
+const ALLOWED_CONTENT_TYPES: &[&str] = &["application/json", "application/json-rpc"];
+
+/// The health check path served by `HealthLayer`, which accepts any method.
+const HEALTH_PATH: &str = "/healthz";
+
+/// How long browsers may cache a preflight response, in seconds.
+const CORS_MAX_AGE_SECS: &str = "86400";
+
+/// A [`Layer`] that rejects requests which cannot be JSON-RPC requests before
+/// their body is read, and handles CORS when origins are configured.
+///
+/// Only `POST` requests with a JSON content type are passed through. Other
+/// methods are answered with 405 and other content types with 415.
+#[derive(Clone, Debug, Default)]
+pub struct EdgeLayer {
+    pub cors_origins: Arc<Vec<String>>,
+}
+
+impl EdgeLayer {
+    /// Creates a new [`EdgeLayer`] with CORS disabled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the origins allowed to make cross-origin requests. `*` allows any origin.
+    ///
+    /// CORS is disabled if empty.
+    pub fn with_cors_origins(mut self, cors_origins: Vec<String>) -> Self {
+        self.cors_origins = Arc::new(cors_origins);
+        self
+    }
+}
+
+impl<S> Layer<S> for EdgeLayer {
+    type Service = EdgeService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        EdgeService {
+            cors_origins: self.cors_origins.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct EdgeService<S> {
+    cors_origins: Arc<Vec<String>>,
+    inner: S,
+}
+
+impl<S> EdgeService<S> {
+    /// Returns the `Access-Control-Allow-Origin` value for the request origin, if allowed.
+    fn allowed_origin(&self, headers: &HeaderMap) -> Option<HeaderValue> {
+        let origin = headers.get(header::ORIGIN)?;
+        if self.cors_origins.iter().any(|allowed| allowed == "*") {
+            return Some(HeaderValue::from_static("*"));
+        }
+
+        let origin_str = origin.to_str().ok()?;
+        self.cors_origins
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(origin_str))
+            .then(|| origin.clone())
+    }
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for EdgeService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        if request.uri().path() == HEALTH_PATH {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        }
+
+        let cors_enabled = !self.cors_origins.is_empty();
+        let allowed_origin = self.allowed_origin(request.headers());
+
+        let response = if request.method() == Method::OPTIONS && cors_enabled {
+            Some(preflight_response(allowed_origin.clone()))
+        } else if request.method() != Method::POST {
+            Some(
+                HttpResponse::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header(header::ALLOW, "POST")
+                    .body(HttpBody::from("Method not allowed, use POST"))
+                    .expect("valid response"),
+            )
+        } else if !is_json(request.headers()) {
+            Some(
+                HttpResponse::builder()
+                    .status(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+                    .body(HttpBody::from(
+                        "Unsupported content type, use application/json",
+                    ))
+                    .expect("valid response"),
+            )
+        } else {
+            None
+        };
+
+        if let Some(mut response) = response {
+            if request.method() != Method::OPTIONS {
+                with_cors_headers(&mut response, allowed_origin);
+            }
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let fut = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = fut.await.map_err(Into::into)?;
+            with_cors_headers(&mut response, allowed_origin);
+            Ok(response)
+        })
+    }
+}
+
+/// Returns true if the content type is JSON, ignoring parameters such as the charset.
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|content_type| {
+            ALLOWED_CONTENT_TYPES
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(content_type.trim()))
+        })
+}
+
+/// Answers a CORS preflight request, rejecting origins that are not allowed.
+fn preflight_response(allowed_origin: Option<HeaderValue>) -> HttpResponse {
+    let Some(origin) = allowed_origin else {
+        return HttpResponse::builder()
+            .status(StatusCode::FORBIDDEN)
+            .header(header::VARY, "Origin")
+            .body(HttpBody::from("Origin not allowed"))
+            .expect("valid response");
+    };
+
+    HttpResponse::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, "POST, OPTIONS")
+        .header(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            "authorization, content-type, x-request-id",
+        )
+        .header(header::ACCESS_CONTROL_MAX_AGE, CORS_MAX_AGE_SECS)
+        .header(header::VARY, "Origin")
+        .body(HttpBody::from(String::new()))
+        .expect("valid response")
+}
+
+/// Allows the origin to read the response, if it is allowed.
+fn with_cors_headers(response: &mut HttpResponse, allowed_origin: Option<HeaderValue>) {
+    if let Some(origin) = allowed_origin {
+        let headers = response.headers_mut();
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, origin);
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_static("x-request-id"),
+        );
+        headers.append(header::VARY, HeaderValue::from_static("Origin"));
+    }
+}
@@ -1,50 +1,1326 @@
-use crate::client::HttpClient;
-use crate::rpc::{RpcRequest, RpcResponse};
-use eyre::eyre;
-use futures::future::join_all;
+use crate::client::{
+    ConnectTimeout, HttpClient, IdentityMismatch, OversizeResponse, RateLimited, ResponseTimeout,
+    UpstreamStatus,
+};
+use crate::dispatch::TargetQueueFull;
+use crate::rpc::{
+    InvalidResponse, MethodCategory, MethodResultValidator, PbhErrorMatcher, RpcRequest,
+    RpcResponse,
+};
+use crate::split::BuilderSplit;
+use crate::timeline::{RequestTimeline, millis};
+use alloy_primitives::{Address, keccak256};
+use futures::future::BoxFuture;
+use futures::stream::{FuturesUnordered, StreamExt};
+use futures::{FutureExt, future::join_all};
+use http::Uri;
 use jsonrpsee::{core::BoxError, http_client::HttpBody};
-use tracing::error;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tracing::{Instrument, Span, error, field::Empty, info_span, warn};
+
+/// Determines which target response is returned to the caller.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum SelectionStrategy {
+    /// Wait for all targets and prefer responses in the order targets were
+    /// declared, see [`select_declaration_order`].
+    #[default]
+    DeclarationOrder,
+    /// Respond as soon as the first successful response arrives,
+    /// letting the remaining requests complete in the background.
+    FirstSuccessful,
+    /// Wait for all targets and prefer the response with the lowest latency.
+    LowestLatency,
+    /// Wait for all targets and prefer the target with the lowest average
+    /// latency across recent requests, so the fastest target answers consistently.
+    PreferLocal,
+}
+
+/// Determines how requests are sent to the targets of a [`FanoutWrite`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FanoutMode {
+    /// Send requests to every target at once.
+    #[default]
+    All,
+    /// Send requests to one target at a time in declaration order, until one
+    /// succeeds, see [`FanoutWrite::fan_request_sequential`].
+    Ordered,
+}
+
+/// Sends requests for some method categories to one target at a time instead
+/// of all at once, see [`FanoutWrite::fan_request_hedged`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hedge {
+    /// How long to wait for a response before also sending to the next target.
+    pub delay: Duration,
+    /// The method categories that are hedged, others are fanned out to all targets.
+    pub categories: Vec<MethodCategory>,
+}
+
+impl Hedge {
+    /// Creates a new [`Hedge`] for the given method categories.
+    pub fn new(delay: Duration, categories: Vec<MethodCategory>) -> Self {
+        Self { delay, categories }
+    }
+
+    /// Returns true if requests for the method are hedged.
+    pub fn applies_to(&self, method: &str) -> bool {
+        self.categories.contains(&MethodCategory::of(method))
+    }
+}
+
+/// Returned for a target whose forward panicked, so only that target fails.
+#[derive(Debug)]
+pub struct TargetPanicked {
+    pub message: String,
+}
+
+impl fmt::Display for TargetPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "target_panicked: {}", self.message)
+    }
+}
+
+impl std::error::Error for TargetPanicked {}
+
+/// Returned when some, but fewer than the required number of targets returned a JSON-RPC result.
+#[derive(Debug)]
+pub struct InsufficientSuccesses {
+    pub required: usize,
+    pub succeeded: usize,
+}
+
+impl fmt::Display for InsufficientSuccesses {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "insufficient_successes: {} of {} required targets succeeded",
+            self.succeeded, self.required
+        )
+    }
+}
+
+impl std::error::Error for InsufficientSuccesses {}
+
+/// Returned when no target returned a JSON-RPC response.
+#[derive(Debug)]
+pub struct AllTargetsFailed;
+
+impl fmt::Display for AllTargetsFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "All requests failed. No valid responses received.")
+    }
+}
+
+impl std::error::Error for AllTargetsFailed {}
+
+/// A single target result: the target index, the request latency, and the response.
+pub type TargetResult = (usize, Duration, Result<RpcResponse<HttpBody>, BoxError>);
+
+/// A stream of in-flight target requests yielding results as they complete.
+pub type FanoutStream = FuturesUnordered<BoxFuture<'static, TargetResult>>;
+
+/// The outcome of [`FanoutWrite::fan_request_first`].
+pub struct FirstResponse {
+    /// The response selected for the caller.
+    pub response: RpcResponse<HttpBody>,
+    /// The index of the target that returned the response.
+    pub index: usize,
+    /// The number of targets that responded before the response was selected, including itself.
+    pub responded: usize,
+    /// The requests which are still in flight.
+    pub pending: FanoutStream,
+}
+
+/// The outcome of [`FanoutWrite::fan_request_hedged`].
+pub struct HedgedResult {
+    /// The outcomes of the targets that responded or failed, in target order.
+    pub result: FanoutResult,
+    /// The number of targets sent the request because the previous one was too slow.
+    pub hedged: usize,
+    /// The number of requests still in flight when a response was selected, which were cancelled.
+    pub cancelled: usize,
+}
+
+/// The outcome of a request to a single target.
+pub enum Outcome {
+    /// The target returned a JSON-RPC result.
+    Success(RpcResponse<HttpBody>),
+    /// The target returned a JSON-RPC error.
+    RpcError(RpcResponse<HttpBody>),
+    /// The request failed before a JSON-RPC response was received.
+    TransportError(BoxError),
+}
+
+impl Outcome {
+    /// Returns the JSON-RPC response, if the target returned one.
+    pub fn response(&self) -> Option<&RpcResponse<HttpBody>> {
+        match self {
+            Self::Success(resp) | Self::RpcError(resp) => Some(resp),
+            Self::TransportError(_) => None,
+        }
+    }
+}
+
+/// The result of a request to a single target.
+pub struct TargetOutcome {
+    /// The index of the target in declaration order.
+    pub index: usize,
+    /// The URL of the target.
+    pub url: Uri,
+    /// The time taken for the target to respond or fail.
+    pub elapsed: Duration,
+    /// The latency estimate of the target, including this request.
+    pub latency_estimate: Option<Duration>,
+    pub outcome: Outcome,
+}
+
+/// The results of a request fanned out to every target, in target order.
+pub struct FanoutResult {
+    pub targets: Vec<TargetOutcome>,
+}
+
+impl FanoutResult {
+    /// Creates a [`FanoutResult`] from the target URLs and their results.
+    fn new(urls: &[Uri], mut results: Vec<TargetResult>) -> Self {
+        results.sort_by_key(|(index, _, _)| *index);
+        let targets = results
+            .into_iter()
+            .map(|(index, elapsed, res)| {
+                let outcome = match res {
+                    Ok(resp) if resp.is_error() => Outcome::RpcError(resp),
+                    Ok(resp) => Outcome::Success(resp),
+                    Err(err) => {
+                        error!(%err, "Request failed");
+                        Outcome::TransportError(err)
+                    }
+                };
+                TargetOutcome {
+                    index,
+                    url: urls[index].clone(),
+                    elapsed,
+                    latency_estimate: None,
+                    outcome,
+                }
+            })
+            .collect();
+
+        Self { targets }
+    }
+
+    /// Sets the latency estimate of each target, indexed by target.
+    pub fn with_latency_estimates(mut self, estimates: &[Option<Duration>]) -> Self {
+        for target in &mut self.targets {
+            target.latency_estimate = estimates.get(target.index).copied().flatten();
+        }
+        self
+    }
+
+    /// Returns the targets that returned a JSON-RPC result.
+    pub fn successes(&self) -> impl Iterator<Item = &TargetOutcome> {
+        self.targets
+            .iter()
+            .filter(|target| matches!(target.outcome, Outcome::Success(_)))
+    }
+
+    /// Returns the first JSON-RPC result in target order.
+    pub fn first_success(&self) -> Option<&RpcResponse<HttpBody>> {
+        self.successes()
+            .find_map(|target| target.outcome.response())
+    }
+
+    /// Returns true if no target returned a JSON-RPC response.
+    pub fn all_failed(&self) -> bool {
+        self.targets
+            .iter()
+            .all(|target| target.outcome.response().is_none())
+    }
+
+    /// Returns the targets that returned a PBH error.
+    pub fn pbh_errors<'a>(
+        &'a self,
+        matcher: &'a PbhErrorMatcher,
+    ) -> impl Iterator<Item = &'a TargetOutcome> {
+        self.targets.iter().filter(|target| {
+            target
+                .outcome
+                .response()
+                .is_some_and(|resp| resp.pbh_error_with(matcher))
+        })
+    }
+
+    /// Returns the number of targets that failed to return a JSON-RPC response.
+    pub fn failures(&self) -> usize {
+        self.targets
+            .iter()
+            .filter(|target| target.outcome.response().is_none())
+            .count()
+    }
+
+    /// Returns the number of targets whose response was not a well-formed JSON-RPC response.
+    pub fn invalid_responses(&self) -> usize {
+        self.targets
+            .iter()
+            .filter(|target| {
+                matches!(&target.outcome, Outcome::TransportError(err) if err.is::<InvalidResponse>())
+            })
+            .count()
+    }
+
+    /// Fails if a target returned a JSON-RPC result, but fewer than `min_success` did.
+    ///
+    /// Results with no successful target are left to selection, so the caller
+    /// still sees the JSON-RPC errors returned by the targets.
+    pub fn ensure_min_success(&self, min_success: usize) -> Result<(), InsufficientSuccesses> {
+        let succeeded = self.successes().count();
+        if succeeded > 0 && succeeded < min_success {
+            return Err(InsufficientSuccesses {
+                required: min_success,
+                succeeded,
+            });
+        }
+        Ok(())
+    }
+
+    /// Groups the targets that returned a JSON-RPC result by their parsed
+    /// `result`, largest group first, then in target order.
+    ///
+    /// Results are compared by value, so responses differing only in formatting agree.
+    pub fn result_groups(&self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<(Option<serde_json::Value>, Vec<usize>)> = Vec::new();
+        for target in self.successes() {
+            let result = target.outcome.response().and_then(|resp| resp.result());
+            match groups.iter_mut().find(|(group, _)| *group == result) {
+                Some((_, indexes)) => indexes.push(target.index),
+                None => groups.push((result, vec![target.index])),
+            }
+        }
+        // The sort is stable, so equal groups stay in target order
+        groups.sort_by_key(|(_, indexes)| std::cmp::Reverse(indexes.len()));
+        groups.into_iter().map(|(_, indexes)| indexes).collect()
+    }
+
+    /// Moves the given target to the front so its response is preferred during selection.
+    pub fn prefer(&mut self, index: usize) {
+        if let Some(position) = self.targets.iter().position(|t| t.index == index) {
+            let target = self.targets.remove(position);
+            self.targets.insert(0, target);
+        }
+    }
+
+    /// Returns the JSON-RPC responses ordered according to the given [`SelectionStrategy`].
+    ///
+    /// Fails if no target returned a JSON-RPC response.
+    pub fn into_responses(
+        self,
+        strategy: SelectionStrategy,
+    ) -> Result<Vec<RpcResponse<HttpBody>>, BoxError> {
+        let mut responses = self
+            .targets
+            .into_iter()
+            .filter_map(|target| match target.outcome {
+                Outcome::Success(resp) | Outcome::RpcError(resp) => {
+                    Some((target.elapsed, target.latency_estimate, resp))
+                }
+                Outcome::TransportError(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        if responses.is_empty() {
+            return Err(AllTargetsFailed.into());
+        }
+
+        match strategy {
+            SelectionStrategy::LowestLatency => {
+                responses.sort_by_key(|(elapsed, _, _)| *elapsed);
+            }
+            // Targets without an estimate are preferred last, in declaration order
+            SelectionStrategy::PreferLocal => {
+                responses.sort_by_key(|(_, estimate, _)| estimate.unwrap_or(Duration::MAX));
+            }
+            _ => {}
+        }
+
+        Ok(responses.into_iter().map(|(_, _, resp)| resp).collect())
+    }
+
+    /// Returns the JSON-RPC responses like [`FanoutResult::into_responses`], dropping
+    /// responses with the same status and body as a response ordered before them.
+    ///
+    /// Builders usually agree, so selection then only considers one response.
+    pub fn into_distinct_responses(
+        self,
+        strategy: SelectionStrategy,
+    ) -> Result<Vec<RpcResponse<HttpBody>>, BoxError> {
+        let mut distinct = Vec::<(u64, RpcResponse<HttpBody>)>::new();
+        for response in self.into_responses(strategy)? {
+            let mut hasher = DefaultHasher::new();
+            response.body.hash(&mut hasher);
+            let hash = hasher.finish();
+            let duplicate = distinct.iter().any(|(seen, kept)| {
+                *seen == hash
+                    && kept.response.status() == response.response.status()
+                    && kept.body == response.body
+            });
+            if !duplicate {
+                distinct.push((hash, response));
+            }
+        }
+
+        Ok(distinct.into_iter().map(|(_, response)| response).collect())
+    }
+}
 
 /// A FanoutWrite for fanning JSON-RPC requests to multiple
 /// Clients in a High Availability configuration.
+///
+/// Clones share the same target set, which can be replaced while requests are in flight.
+/// Disabled targets, see [`HttpClient::set_enabled`], and targets in a maintenance
+/// window, see [`HttpClient::with_maintenance`], are skipped.
 #[derive(Clone, Debug)]
 pub struct FanoutWrite {
-    pub targets: Vec<HttpClient>,
+    targets: Arc<RwLock<Arc<Vec<HttpClient>>>>,
+    /// Methods renamed before requests are forwarded to the targets.
+    method_rewrites: Arc<HashMap<String, String>>,
+    /// Whether responses are validated against the request before being selected.
+    validate_responses: bool,
+    /// Checks the result shape of success responses for known methods, if set.
+    result_validator: Option<MethodResultValidator>,
+    /// The number of targets that must return a JSON-RPC result before one is returned.
+    min_success: usize,
+    /// Requests to a target taking longer than this are logged with their timeline.
+    slow_request: Option<Duration>,
+    /// Whether requests are sent to every target or to one at a time.
+    mode: FanoutMode,
+}
+
+/// The checks applied to target responses before they can be selected.
+#[derive(Clone, Copy, Debug)]
+struct Validation {
+    responses: bool,
+    results: Option<MethodResultValidator>,
+    /// Requests taking longer than this are logged with their timeline.
+    slow_request: Option<Duration>,
+}
+
+/// The builder and L2 target sets of a running proxy.
+#[derive(Clone, Debug)]
+pub struct Targets {
+    pub builder: FanoutWrite,
+    pub l2: FanoutWrite,
+    /// New builders receiving a share of the builder requests during a cutover.
+    pub builder_split: Option<BuilderSplit>,
+}
+
+/// The URLs added, removed and rebuilt with new settings when a target set is replaced.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TargetsDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl TargetsDiff {
+    /// Returns true if the target set is unchanged.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
 }
 
 impl FanoutWrite {
     /// Creates a new [`FanoutWrite`] with the given clients.
     pub fn new(targets: Vec<HttpClient>) -> Self {
-        Self { targets }
+        Self {
+            targets: Arc::new(RwLock::new(Arc::new(targets))),
+            method_rewrites: Arc::new(HashMap::new()),
+            validate_responses: false,
+            result_validator: None,
+            min_success: 1,
+            slow_request: None,
+            mode: FanoutMode::All,
+        }
+    }
+
+    /// Sets the methods renamed before requests are forwarded to the targets,
+    /// for targets that expect a different method name.
+    pub fn with_method_rewrites(mut self, method_rewrites: HashMap<String, String>) -> Self {
+        self.method_rewrites = Arc::new(method_rewrites);
+        self
+    }
+
+    /// Validates that each response is a complete JSON-RPC 2.0 response matching
+    /// the request id. Invalid responses are treated as failed targets, so selection
+    /// falls back to the next-best response.
+    pub fn with_validate_responses(mut self, validate_responses: bool) -> Self {
+        self.validate_responses = validate_responses;
+        self
+    }
+
+    /// Sets the [`MethodResultValidator`] checking the result of success responses.
+    /// Responses with a malformed result are treated as failed targets.
+    pub fn with_result_validator(mut self, validator: Option<MethodResultValidator>) -> Self {
+        self.result_validator = validator;
+        self
+    }
+
+    /// Sets the number of targets that must return a JSON-RPC result before a
+    /// result is returned, failing with [`InsufficientSuccesses`] otherwise. Defaults to 1.
+    pub fn with_min_success(mut self, min_success: usize) -> Self {
+        self.min_success = min_success;
+        self
+    }
+
+    /// Logs a warning with the timeline of every request to a target taking
+    /// longer than `threshold`. Not logged if `None`, the default.
+    pub fn with_slow_request_threshold(mut self, threshold: Option<Duration>) -> Self {
+        self.slow_request = threshold;
+        self
+    }
+
+    /// Sets the [`FanoutMode`] the requests are sent to the targets with.
+    /// Defaults to [`FanoutMode::All`].
+    pub fn with_mode(mut self, mode: FanoutMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Returns the [`FanoutMode`] the requests are sent to the targets with.
+    pub fn mode(&self) -> FanoutMode {
+        self.mode
+    }
+
+    /// Returns the number of targets that must return a JSON-RPC result before one is returned.
+    pub fn min_success(&self) -> usize {
+        self.min_success
+    }
+
+    /// Returns true if responses are validated before being selected.
+    pub fn validates_responses(&self) -> bool {
+        self.validate_responses || self.result_validator.is_some()
+    }
+
+    fn validation(&self) -> Validation {
+        Validation {
+            responses: self.validate_responses,
+            results: self.result_validator,
+            slow_request: self.slow_request,
+        }
+    }
+
+    /// Applies the configured method rewrite, if any, to the request.
+    fn rewrite(&self, req: RpcRequest) -> RpcRequest {
+        let Some(method) = self.method_rewrites.get(&req.method) else {
+            return req;
+        };
+
+        match req.with_method(method) {
+            Ok(rewritten) => rewritten,
+            Err(err) => {
+                error!(%err, from = %req.method, to = %method, "Failed to rewrite method");
+                req
+            }
+        }
+    }
+
+    /// Returns the index of the primary target for a sender, see [`primary_target`].
+    pub fn primary_target(&self, sender: &Address) -> Option<usize> {
+        primary_target(sender, &target_urls(&self.targets()))
+    }
+
+    /// Returns the URL of the target at the given index, if it is still configured.
+    pub fn target_url(&self, index: usize) -> Option<Uri> {
+        self.targets().get(index).map(|client| client.url().clone())
+    }
+
+    /// Records a raw transaction accepted by the target at the given index.
+    pub fn record_transaction_accepted(&self, index: usize) {
+        if let Some(client) = self.targets().get(index) {
+            client.record_transaction_accepted();
+        }
+    }
+
+    /// Returns the number of targets receiving requests, excluding disabled targets.
+    pub fn enabled_count(&self) -> usize {
+        self.targets()
+            .iter()
+            .filter(|client| client.is_enabled())
+            .count()
+    }
+
+    /// Returns a snapshot of the current targets.
+    pub fn targets(&self) -> Arc<Vec<HttpClient>> {
+        self.targets.read().unwrap().clone()
+    }
+
+    /// Atomically replaces the targets.
+    ///
+    /// Requests already in flight complete on the previous targets.
+    pub fn replace_targets(&self, targets: Vec<HttpClient>) {
+        *self.targets.write().unwrap() = Arc::new(targets);
     }
 
     /// Sends a JSON-RPC request to all clients and return the responses.
+    ///
+    /// Responses are returned in the order the targets were declared.
     pub async fn fan_request(
         &mut self,
         req: RpcRequest,
     ) -> Result<Vec<RpcResponse<HttpBody>>, BoxError> {
-        let fut = self
-            .targets
-            .iter_mut()
-            .map(|client| client.forward(req.clone()))
-            .collect::<Vec<_>>();
+        self.fan_request_ordered(req, SelectionStrategy::DeclarationOrder)
+            .await
+    }
 
-        let results = join_all(fut).await;
-        let responses = results
-            .into_iter()
-            .filter_map(|res| match res {
-                Ok(resp) => Some(resp),
+    /// Sends a JSON-RPC request to all clients and return the responses
+    /// ordered according to the given [`SelectionStrategy`].
+    ///
+    /// Fails with [`InsufficientSuccesses`] if fewer than the minimum number of
+    /// targets returned a JSON-RPC result, see [`FanoutResult::ensure_min_success`].
+    pub async fn fan_request_ordered(
+        &mut self,
+        req: RpcRequest,
+        strategy: SelectionStrategy,
+    ) -> Result<Vec<RpcResponse<HttpBody>>, BoxError> {
+        let result = self.fan_request_all(req).await;
+        result.ensure_min_success(self.min_success)?;
+        result.into_responses(strategy)
+    }
+
+    /// Sends a JSON-RPC request to all clients and returns the outcome of every target.
+    pub async fn fan_request_all(&self, req: RpcRequest) -> FanoutResult {
+        let req = self.rewrite(req);
+        let targets = self.targets();
+        let fut = enabled_targets(&targets).map(|(index, client)| {
+            forward_to_target(index, client, req.clone(), self.validation())
+        });
+
+        FanoutResult::new(&target_urls(&targets), join_all(fut).await)
+            .with_latency_estimates(&latency_estimates(&targets))
+    }
+
+    /// Sends a JSON-RPC request to the primary target and awaits its response
+    /// before sending it to the remaining targets concurrently.
+    ///
+    /// Outcomes are returned in target order, use [`FanoutResult::prefer`]
+    /// to prefer the primary response during selection.
+    pub async fn fan_request_primary_first(&self, primary: usize, req: RpcRequest) -> FanoutResult {
+        let req = self.rewrite(req);
+        let targets = self.targets();
+        let mut results = Vec::with_capacity(targets.len());
+        if let Some(client) = targets.get(primary).filter(|client| client.is_enabled()) {
+            results.push(
+                forward_to_target(primary, client.clone(), req.clone(), self.validation()).await,
+            );
+        }
+
+        let fut = enabled_targets(&targets)
+            .filter(|(index, _)| *index != primary)
+            .map(|(index, client)| {
+                forward_to_target(index, client, req.clone(), self.validation())
+            });
+        results.extend(join_all(fut).await);
+
+        FanoutResult::new(&target_urls(&targets), results)
+            .with_latency_estimates(&latency_estimates(&targets))
+    }
+
+    /// Sends a JSON-RPC request to one target at a time in declaration order,
+    /// until the minimum number of targets returned a JSON-RPC result.
+    ///
+    /// The next target is sent the request when the previous one failed or
+    /// returned an error, or did not respond within `delay`, without
+    /// cancelling the slow request. Requests still in flight once enough
+    /// targets succeeded are cancelled.
+    pub async fn fan_request_hedged(&self, req: RpcRequest, delay: Duration) -> HedgedResult {
+        let req = self.rewrite(req);
+        let targets = self.targets();
+        let mut remaining = enabled_targets(&targets).collect::<VecDeque<_>>();
+        let send = |(index, client): (usize, HttpClient)| {
+            forward_to_target(index, client, req.clone(), self.validation()).boxed()
+        };
+
+        let mut pending = FanoutStream::new();
+        pending.extend(remaining.pop_front().map(send));
+        let mut results = Vec::new();
+        let mut succeeded = 0;
+        let mut hedged = 0;
+        while !pending.is_empty() {
+            tokio::select! {
+                Some(result) = pending.next() => {
+                    if matches!(&result.2, Ok(resp) if !resp.is_error()) {
+                        succeeded += 1;
+                    }
+                    results.push(result);
+                    if succeeded >= self.min_success {
+                        break;
+                    }
+                    pending.extend(remaining.pop_front().map(send));
+                }
+                _ = tokio::time::sleep(delay), if !remaining.is_empty() => {
+                    hedged += 1;
+                    pending.extend(remaining.pop_front().map(send));
+                }
+            }
+        }
+
+        HedgedResult {
+            result: FanoutResult::new(&target_urls(&targets), results)
+                .with_latency_estimates(&latency_estimates(&targets)),
+            hedged,
+            cancelled: pending.len(),
+        }
+    }
+
+    /// Sends a JSON-RPC request to one target at a time in declaration order,
+    /// until the minimum number of targets returned a JSON-RPC result.
+    ///
+    /// The next target is only sent the request once the previous one failed
+    /// or returned an error, so backups receive nothing while the first
+    /// target succeeds. Only the targets sent the request are in the result.
+    pub async fn fan_request_sequential(&self, req: RpcRequest) -> FanoutResult {
+        let req = self.rewrite(req);
+        let targets = self.targets();
+        let mut results = Vec::new();
+        let mut succeeded = 0;
+        for (index, client) in enabled_targets(&targets) {
+            let result = forward_to_target(index, client, req.clone(), self.validation()).await;
+            if matches!(&result.2, Ok(resp) if !resp.is_error()) {
+                succeeded += 1;
+            }
+            results.push(result);
+            if succeeded >= self.min_success {
+                break;
+            }
+        }
+
+        FanoutResult::new(&target_urls(&targets), results)
+            .with_latency_estimates(&latency_estimates(&targets))
+    }
+
+    /// Sends a JSON-RPC request to all clients, yielding results as they complete.
+    pub fn fan_stream(&self, req: RpcRequest) -> FanoutStream {
+        let req = self.rewrite(req);
+        enabled_targets(&self.targets())
+            .map(|(index, client)| {
+                forward_to_target(index, client, req.clone(), self.validation()).boxed()
+            })
+            .collect()
+    }
+
+    /// Sends a JSON-RPC request to all clients and resolves as soon as a
+    /// successful response with a 2xx status or a PBH error is received.
+    ///
+    /// If no target returns such a response, the first success-shaped response with
+    /// an error status is selected, followed by the first JSON-RPC error response.
+    /// Fails with [`InvalidResponse`] if no response is received and a target
+    /// returned an invalid response.
+    ///
+    /// A successful response is only returned once the minimum number of targets
+    /// returned a JSON-RPC result, failing with [`InsufficientSuccesses`] if fewer did.
+    /// The requests still in flight are returned so the caller can drive them to completion.
+    pub async fn fan_request_first(
+        &self,
+        req: RpcRequest,
+        matcher: &PbhErrorMatcher,
+    ) -> Result<FirstResponse, BoxError> {
+        let mut pending = self.fan_stream(req);
+        let mut responded = 0;
+        let mut succeeded = 0;
+        let mut first_success = None;
+        let mut fallback_success = None;
+        let mut fallback_error = None;
+        let mut invalid = None;
+
+        while let Some((index, _, res)) = pending.next().await {
+            match res {
+                Ok(resp) => {
+                    responded += 1;
+                    if resp.pbh_error_with(matcher) {
+                        return Ok(FirstResponse {
+                            response: resp,
+                            index,
+                            responded,
+                            pending,
+                        });
+                    }
+                    if !resp.is_error() {
+                        succeeded += 1;
+                    }
+                    if resp.is_success() {
+                        first_success.get_or_insert((index, resp));
+                    } else if resp.is_error() {
+                        fallback_error.get_or_insert((index, resp));
+                    } else {
+                        fallback_success.get_or_insert((index, resp));
+                    }
+                    if succeeded >= self.min_success {
+                        if let Some((index, response)) = first_success.take() {
+                            return Ok(FirstResponse {
+                                response,
+                                index,
+                                responded,
+                                pending,
+                            });
+                        }
+                    }
+                }
                 Err(err) => {
                     error!(%err, "Request failed");
-                    None
+                    if err.is::<InvalidResponse>() {
+                        invalid.get_or_insert(err);
+                    }
                 }
-            })
-            .collect::<Vec<_>>();
+            }
+        }
 
-        if responses.is_empty() {
-            return Err(eyre!("All requests failed. No valid responses received.").into());
+        if succeeded > 0 && succeeded < self.min_success {
+            return Err(InsufficientSuccesses {
+                required: self.min_success,
+                succeeded,
+            }
+            .into());
+        }
+
+        match first_success.or(fallback_success).or(fallback_error) {
+            Some((index, response)) => Ok(FirstResponse {
+                response,
+                index,
+                responded,
+                pending,
+            }),
+            // Surface response corruption over a generic failure
+            None => Err(invalid.unwrap_or_else(|| AllTargetsFailed.into())),
         }
+    }
+}
+
+/// Returns the targets receiving requests with their index in declaration order.
+///
+/// Targets skipped during a maintenance window are recorded.
+fn enabled_targets(targets: &[HttpClient]) -> impl Iterator<Item = (usize, HttpClient)> + '_ {
+    targets
+        .iter()
+        .enumerate()
+        .filter(|(_, client)| {
+            let enabled = client.is_enabled();
+            if !enabled && client.in_maintenance() {
+                client.record_skipped_maintenance();
+            }
+            enabled
+        })
+        .map(|(index, client)| (index, client.clone()))
+}
+
+fn target_urls(targets: &[HttpClient]) -> Vec<Uri> {
+    targets.iter().map(|client| client.url().clone()).collect()
+}
+
+fn latency_estimates(targets: &[HttpClient]) -> Vec<Option<Duration>> {
+    targets
+        .iter()
+        .map(|client| client.latency().estimate())
+        .collect()
+}
+
+/// Returns the index of the primary target for a sender, or `None` if there are no targets.
+///
+/// Targets are picked by rendezvous hashing of the sender and target URL, so a
+/// sender always maps to the same primary, and adding or removing a target only
+/// moves the senders whose primary was added or removed.
+pub fn primary_target(sender: &Address, targets: &[Uri]) -> Option<usize> {
+    targets
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, url)| keccak256([sender.as_slice(), url.to_string().as_bytes()].concat()))
+        .map(|(index, _)| index)
+}
+
+/// Forwards a request to a single target within a `fanout.target` span,
+/// recording the outcome and latency on the span before it closes.
+///
+/// Responses failing the given [`Validation`], either not well-formed JSON-RPC
+/// responses to the request or with a malformed result, are recorded and
+/// returned as an [`InvalidResponse`] error.
+async fn forward_to_target(
+    index: usize,
+    client: HttpClient,
+    req: RpcRequest,
+    validation: Validation,
+) -> TargetResult {
+    let span = info_span!(
+        target: "tx-proxy::fanout",
+        "fanout.target",
+        target.url = %client.display_url(),
+        target.index = index,
+        request.id = %req.request_id,
+        outcome = Empty,
+        error.code = Empty,
+        latency_ms = Empty,
+    );
 
-        Ok(responses)
+    async move {
+        let id = validation.responses.then(|| req.id());
+        let method = req.method.clone();
+        let now = Instant::now();
+        let mut forwarding = client.clone();
+        let mut timeline = RequestTimeline::default();
+        let forward = forwarding.forward_with_timeline(req, &mut timeline);
+        let res = match catch_panic(&client, forward).await {
+            Ok(resp) => {
+                let valid = id.map_or(Ok(()), |id| resp.validate(&id)).and_then(|()| {
+                    validation
+                        .results
+                        .map_or(Ok(()), |results| results.validate(&method, &resp))
+                });
+                match valid {
+                    Ok(()) => Ok(resp),
+                    Err(err) => {
+                        client.record_invalid_response();
+                        Err(err.into())
+                    }
+                }
+            }
+            res => res,
+        };
+        let latency = now.elapsed();
+        // Failures are not measured, they can be arbitrarily fast or slow
+        if res.is_ok() {
+            client.record_latency(latency);
+        }
+
+        let span = Span::current();
+        span.record("latency_ms", latency.as_millis() as u64);
+        let outcome = match &res {
+            Ok(resp) => match &resp.error {
+                Some(err) => {
+                    span.record("error.code", err.code());
+                    "error"
+                }
+                None => "success",
+            },
+            Err(err) if err.is::<OversizeResponse>() => "oversize_response",
+            Err(err) if err.is::<UpstreamStatus>() => "upstream_status",
+            Err(err) if err.is::<RateLimited>() => "rate_limited",
+            Err(err) if err.is::<TargetQueueFull>() => "queue_full",
+            Err(err) if err.is::<ConnectTimeout>() => "connect_timeout",
+            Err(err) if err.is::<ResponseTimeout>() => "response_timeout",
+            Err(err) if err.is::<IdentityMismatch>() => "identity_mismatch",
+            Err(err) if err.is::<InvalidResponse>() => "invalid_response",
+            Err(err) if err.is::<TargetPanicked>() => "panicked",
+            Err(_) => "failure",
+        };
+        span.record("outcome", outcome);
+
+        if validation
+            .slow_request
+            .is_some_and(|threshold| latency > threshold)
+        {
+            warn!(
+                target: "tx-proxy::fanout",
+                target.url = %client.display_url(),
+                %method,
+                outcome,
+                ready_ms = millis(timeline.ready_phase()),
+                connect_ms = millis(timeline.connect),
+                write_ms = millis(timeline.write_phase()),
+                ttfb_ms = millis(timeline.ttfb_phase()),
+                body_ms = millis(timeline.body_phase()),
+                total_ms = latency.as_millis() as u64,
+                "Slow request to target"
+            );
+        }
+
+        (index, latency, res)
+    }
+    .instrument(span)
+    .await
+}
+
+/// Selects the response to return to the caller from an ordered list of responses.
+///
+/// A PBH error takes precedence, followed by the first successful response with a 2xx
+/// status, then the first success-shaped response with an error status.
+/// Falls back to the first response if every response is an error.
+pub fn select_response(
+    mut responses: Vec<RpcResponse<HttpBody>>,
+    matcher: &PbhErrorMatcher,
+) -> Option<RpcResponse<HttpBody>> {
+    let index = responses
+        .iter()
+        .position(|res| res.pbh_error_with(matcher))
+        .or_else(|| responses.iter().position(|res| res.is_success()))
+        .or_else(|| responses.iter().position(|res| !res.is_error()))
+        .unwrap_or(0);
+
+    (index < responses.len()).then(|| responses.swap_remove(index))
+}
+
+/// Selects the builder response to return to the caller with
+/// [`SelectionStrategy::DeclarationOrder`].
+///
+/// As the proxy always has, the response of the first target is the fallback:
+/// a PBH error from any later target takes precedence, followed by the first
+/// later successful response. A later success-shaped response with an error
+/// status is only preferred if the first response is not a 2xx success.
+pub fn select_declaration_order(
+    mut responses: Vec<RpcResponse<HttpBody>>,
+    matcher: &PbhErrorMatcher,
+) -> Option<RpcResponse<HttpBody>> {
+    if responses.is_empty() {
+        return None;
+    }
+    let first = responses.remove(0);
+    let index = responses
+        .iter()
+        .position(|res| res.pbh_error_with(matcher))
+        .or_else(|| responses.iter().position(|res| res.is_success()))
+        .or_else(|| {
+            (!first.is_success())
+                .then(|| responses.iter().position(|res| !res.is_error()))
+                .flatten()
+        });
+
+    Some(match index {
+        Some(index) => responses.swap_remove(index),
+        None => first,
+    })
+}
+
+/// Awaits a forward to the target, converting a panic into a [`TargetPanicked`]
+/// error so the other targets of the fanout are unaffected.
+async fn catch_panic(
+    client: &HttpClient,
+    forward: impl Future<Output = Result<RpcResponse<HttpBody>, BoxError>>,
+) -> Result<RpcResponse<HttpBody>, BoxError> {
+    match AssertUnwindSafe(forward).catch_unwind().await {
+        Ok(res) => res,
+        Err(payload) => {
+            let message = panic_message(payload.as_ref());
+            error!(target: "tx-proxy::fanout", url = %client.display_url(), panic = %message, "Forward to target panicked");
+            client.record_panic();
+            Err(TargetPanicked { message }.into())
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic payload".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rpc::parse_response_payload;
+    use alloy_rpc_types_engine::JwtSecret;
+    use eyre::eyre;
+
+    const SUCCESS: &str = r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#;
+    const ERROR: &str =
+        r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"nonce too low"},"id":1}"#;
+    const PBH_ERROR: &str = r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"PBH Transaction Validation Failed"},"id":1}"#;
+
+    fn response(body: &'static str) -> RpcResponse<HttpBody> {
+        let error = parse_response_payload(body.as_bytes()).unwrap();
+        RpcResponse::new(http::Response::new(HttpBody::from(body)), error)
+    }
+
+    fn urls(count: usize) -> Vec<Uri> {
+        (0..count)
+            .map(|index| format!("http://builder-{index}").parse().unwrap())
+            .collect()
+    }
+
+    /// Returns a result with, in target order, a JSON-RPC error, a success,
+    /// a transport error and a PBH error, completing out of order.
+    fn fanout_result() -> FanoutResult {
+        FanoutResult::new(
+            &urls(4),
+            vec![
+                (
+                    2,
+                    Duration::from_millis(30),
+                    Err(eyre!("connection refused").into()),
+                ),
+                (0, Duration::from_millis(20), Ok(response(ERROR))),
+                (3, Duration::from_millis(5), Ok(response(PBH_ERROR))),
+                (1, Duration::from_millis(10), Ok(response(SUCCESS))),
+            ],
+        )
+    }
+
+    #[test]
+    fn test_fanout_result_attribution() {
+        let result = fanout_result();
+
+        for (position, target) in result.targets.iter().enumerate() {
+            assert_eq!(target.index, position);
+            assert_eq!(
+                target.url.host(),
+                Some(format!("builder-{position}").as_str())
+            );
+        }
+        let elapsed = result
+            .targets
+            .iter()
+            .map(|target| target.elapsed.as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(elapsed, [20, 10, 30, 5]);
+
+        assert!(matches!(result.targets[0].outcome, Outcome::RpcError(_)));
+        assert!(matches!(result.targets[1].outcome, Outcome::Success(_)));
+        assert!(matches!(
+            result.targets[2].outcome,
+            Outcome::TransportError(_)
+        ));
+        assert!(matches!(result.targets[3].outcome, Outcome::RpcError(_)));
+
+        assert_eq!(result.successes().map(|t| t.index).collect::<Vec<_>>(), [1]);
+        assert!(result.first_success().is_some_and(|resp| !resp.is_error()));
+        assert_eq!(
+            result
+                .pbh_errors(&PbhErrorMatcher::default())
+                .map(|t| t.index)
+                .collect::<Vec<_>>(),
+            [3]
+        );
+        assert_eq!(result.failures(), 1);
+        assert!(!result.all_failed());
+    }
+
+    #[test]
+    fn test_fanout_result_response_order() {
+        let kinds = |responses: Vec<RpcResponse<HttpBody>>| {
+            responses
+                .iter()
+                .map(|resp| match (resp.pbh_error(), resp.is_error()) {
+                    (true, _) => "pbh",
+                    (false, true) => "error",
+                    (false, false) => "success",
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let responses = fanout_result()
+            .into_responses(SelectionStrategy::DeclarationOrder)
+            .unwrap();
+        assert_eq!(kinds(responses), ["error", "success", "pbh"]);
+
+        let responses = fanout_result()
+            .into_responses(SelectionStrategy::LowestLatency)
+            .unwrap();
+        assert_eq!(kinds(responses), ["pbh", "success", "error"]);
+
+        // Targets without an estimate are preferred last
+        let responses = fanout_result()
+            .with_latency_estimates(&[
+                None,
+                Some(Duration::from_millis(80)),
+                Some(Duration::from_millis(1)),
+                Some(Duration::from_millis(40)),
+            ])
+            .into_responses(SelectionStrategy::PreferLocal)
+            .unwrap();
+        assert_eq!(kinds(responses), ["pbh", "success", "error"]);
+
+        let mut result = fanout_result();
+        result.prefer(1);
+        let responses = result
+            .into_responses(SelectionStrategy::DeclarationOrder)
+            .unwrap();
+        assert_eq!(kinds(responses), ["success", "error", "pbh"]);
+    }
+
+    #[test]
+    fn test_select_declaration_order() {
+        const OTHER_SUCCESS: &str = r#"{"jsonrpc":"2.0","result":"0x5678","id":1}"#;
+        let selected = |bodies: &[&'static str]| {
+            let responses = bodies
+                .iter()
+                .map(|body| response(body).with_body(*body))
+                .collect();
+            let selected =
+                select_declaration_order(responses, &PbhErrorMatcher::default()).unwrap();
+            String::from_utf8(selected.body.to_vec()).unwrap()
+        };
+
+        // The first response is only returned if no later response is preferred
+        assert_eq!(selected(&[SUCCESS, OTHER_SUCCESS]), OTHER_SUCCESS);
+        assert_eq!(selected(&[SUCCESS, ERROR]), SUCCESS);
+        assert_eq!(selected(&[ERROR, SUCCESS]), SUCCESS);
+        assert_eq!(selected(&[ERROR, ERROR]), ERROR);
+        assert_eq!(selected(&[PBH_ERROR, SUCCESS]), SUCCESS);
+        assert_eq!(selected(&[SUCCESS, PBH_ERROR, OTHER_SUCCESS]), PBH_ERROR);
+        assert_eq!(selected(&[SUCCESS]), SUCCESS);
+        assert!(select_declaration_order(vec![], &PbhErrorMatcher::default()).is_none());
+    }
+
+    #[test]
+    fn test_fanout_result_all_failed() {
+        let result = FanoutResult::new(
+            &urls(2),
+            vec![
+                (0, Duration::ZERO, Err(eyre!("timeout").into())),
+                (1, Duration::ZERO, Err(eyre!("connection refused").into())),
+            ],
+        );
+        assert!(result.all_failed());
+        assert_eq!(result.failures(), 2);
+        assert!(result.first_success().is_none());
+        assert!(
+            result
+                .into_responses(SelectionStrategy::DeclarationOrder)
+                .is_err()
+        );
+
+        assert!(FanoutResult::new(&[], vec![]).all_failed());
+    }
+
+    #[test]
+    fn test_fanout_result_groups() {
+        let buffered = |body: &'static str| response(body).with_body(body.as_bytes().to_vec());
+        let result = FanoutResult::new(
+            &urls(5),
+            vec![
+                (
+                    0,
+                    Duration::ZERO,
+                    Ok(buffered(r#"{"jsonrpc":"2.0","result":"0xab","id":1}"#)),
+                ),
+                (
+                    1,
+                    Duration::ZERO,
+                    Ok(buffered(r#"{"jsonrpc":"2.0","result":"0xcd","id":1}"#)),
+                ),
+                (2, Duration::ZERO, Ok(buffered(ERROR))),
+                // Formatting differences are not divergences
+                (
+                    3,
+                    Duration::ZERO,
+                    Ok(buffered(
+                        r#"{ "id": 1, "result": "0xcd", "jsonrpc": "2.0" }"#,
+                    )),
+                ),
+                (4, Duration::ZERO, Err(eyre!("timeout").into())),
+            ],
+        );
+        assert_eq!(result.result_groups(), [vec![1, 3], vec![0]]);
+        assert!(FanoutResult::new(&[], vec![]).result_groups().is_empty());
+    }
+
+    #[test]
+    fn test_fanout_result_distinct_responses() {
+        let buffered = |body: &'static str| response(body).with_body(body);
+        let result = FanoutResult::new(
+            &urls(5),
+            vec![
+                (0, Duration::ZERO, Ok(buffered(SUCCESS))),
+                (1, Duration::ZERO, Ok(buffered(ERROR))),
+                (2, Duration::ZERO, Ok(buffered(SUCCESS))),
+                (3, Duration::ZERO, Err(eyre!("timeout").into())),
+                (4, Duration::ZERO, Ok(buffered(SUCCESS))),
+            ],
+        );
+        let responses = result
+            .into_distinct_responses(SelectionStrategy::DeclarationOrder)
+            .unwrap();
+        assert_eq!(
+            responses
+                .iter()
+                .map(|response| response.body.as_ref())
+                .collect::<Vec<_>>(),
+            [SUCCESS.as_bytes(), ERROR.as_bytes()]
+        );
+
+        // The same body with another status is not a duplicate
+        let mut unavailable = buffered(SUCCESS);
+        *unavailable.response.status_mut() = http::StatusCode::SERVICE_UNAVAILABLE;
+        let result = FanoutResult::new(
+            &urls(2),
+            vec![
+                (0, Duration::ZERO, Ok(buffered(SUCCESS))),
+                (1, Duration::ZERO, Ok(unavailable)),
+            ],
+        );
+        let responses = result
+            .into_distinct_responses(SelectionStrategy::DeclarationOrder)
+            .unwrap();
+        assert_eq!(responses.len(), 2);
+    }
+
+    async fn panicking_forward() -> Result<RpcResponse<HttpBody>, BoxError> {
+        panic!("boom")
+    }
+
+    #[tokio::test]
+    async fn test_target_panic_is_isolated() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let clients = metrics::with_local_recorder(&recorder, || {
+            urls(2)
+                .into_iter()
+                .map(|url| HttpClient::new(url, JwtSecret::random(), 1000))
+                .collect::<Vec<_>>()
+        });
+
+        let results = vec![
+            (
+                0,
+                Duration::ZERO,
+                catch_panic(&clients[0], panicking_forward()).await,
+            ),
+            (
+                1,
+                Duration::ZERO,
+                catch_panic(&clients[1], async { Ok(response(SUCCESS)) }).await,
+            ),
+        ];
+        let result = FanoutResult::new(&urls(2), results);
+        assert!(matches!(
+            &result.targets[0].outcome,
+            Outcome::TransportError(err) if err.is::<TargetPanicked>()
+        ));
+
+        // The panicking target is excluded from selection
+        let responses = result
+            .into_responses(SelectionStrategy::DeclarationOrder)
+            .unwrap();
+        assert_eq!(responses.len(), 1);
+        assert!(responses[0].is_success());
+        assert!(
+            handle
+                .render()
+                .contains("target_panics_total{target=\"http://builder-0/\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn test_primary_target_is_consistent() {
+        let targets = urls(4);
+        let senders = (0..32u8).map(Address::repeat_byte).collect::<Vec<_>>();
+        let primaries = senders
+            .iter()
+            .map(|sender| primary_target(sender, &targets).unwrap())
+            .collect::<Vec<_>>();
+        assert!(primary_target(&senders[0], &[]).is_none());
+
+        // Removing a target only moves the senders it was the primary of
+        let remaining = [&targets[..2], &targets[3..]].concat();
+        for (sender, primary) in senders.iter().zip(primaries) {
+            let url = &remaining[primary_target(sender, &remaining).unwrap()];
+            if primary != 2 {
+                assert_eq!(url, &targets[primary]);
+            }
+        }
     }
 }
@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+    types::error::{METHOD_NOT_FOUND_CODE, METHOD_NOT_FOUND_MSG},
+};
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{
+    metrics::ProxyMetrics,
+    rpc::{RpcRequest, error_response},
+    validation::{notification_response, parse_error_response, with_request_id},
+};
+
+/// The method prefixes allowed by default.
+pub const ALLOWED_METHODS: &[&str; 2] = &["eth_", "net_peerCount"];
+
+/// The methods callers are allowed to send.
+///
+/// A method is allowed if it contains one of the allowed patterns, e.g. `eth_`
+/// allows every `eth_` method. Methods are checked under the name they are
+/// rewritten to, and methods answered by the proxy itself are always allowed.
+#[derive(Clone, Debug)]
+pub struct MethodFilter {
+    allowed: Vec<String>,
+    local: HashSet<String>,
+    rewrites: HashMap<String, String>,
+}
+
+impl Default for MethodFilter {
+    fn default() -> Self {
+        Self::new(ALLOWED_METHODS.iter().map(|m| m.to_string()).collect())
+    }
+}
+
+impl MethodFilter {
+    /// Creates a new [`MethodFilter`] allowing the methods matching `allowed`.
+    pub fn new(allowed: Vec<String>) -> Self {
+        Self {
+            allowed,
+            local: HashSet::new(),
+            rewrites: HashMap::new(),
+        }
+    }
+
+    /// Sets the methods answered by the proxy itself, which are always allowed.
+    pub fn with_local_methods<'a>(mut self, methods: impl IntoIterator<Item = &'a str>) -> Self {
+        self.local = methods.into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Sets the method rewrites applied after this filter, so methods are
+    /// checked under their new name.
+    pub fn with_method_rewrites(mut self, rewrites: HashMap<String, String>) -> Self {
+        self.rewrites = rewrites;
+        self
+    }
+
+    /// Returns true if callers are allowed to send `method`.
+    pub fn allows(&self, method: &str) -> bool {
+        let method = self.rewrites.get(method).map_or(method, String::as_str);
+        self.local.contains(method) || self.allowed.iter().any(|m| method.contains(m.as_str()))
+    }
+}
+
+/// A [`Layer`] rejecting requests for methods the [`MethodFilter`] does not
+/// allow with a method not found error, before they reach the inner service.
+///
+/// Notifications for methods that are not allowed are dropped without a
+/// response, and bodies that are not a JSON-RPC request are rejected with a
+/// parse error. Both rejected requests and dropped notifications are counted
+/// in `method_rejected_total`.
+#[derive(Clone, Debug)]
+pub struct MethodFilterLayer {
+    pub filter: Arc<MethodFilter>,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+impl MethodFilterLayer {
+    /// Creates a new [`MethodFilterLayer`] with the given filter.
+    pub fn new(filter: Arc<MethodFilter>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self { filter, metrics }
+    }
+}
+
+impl<S> Layer<S> for MethodFilterLayer {
+    type Service = MethodFilterService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodFilterService {
+            filter: self.filter.clone(),
+            metrics: self.metrics.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MethodFilterService<S> {
+    filter: Arc<MethodFilter>,
+    metrics: Arc<ProxyMetrics>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for MethodFilterService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let mut service = self.clone();
+        service.inner = std::mem::replace(&mut self.inner, service.inner);
+
+        let fut = async move {
+            let rpc_request = match RpcRequest::from_parsed_or_request(request).await {
+                Ok(rpc_request) => rpc_request,
+                Err(err) if err.downcast_ref::<serde_json::Error>().is_some() => {
+                    debug!(target: "tx-proxy::filter", %err, "rejecting request with malformed JSON");
+                    return Ok(parse_error_response());
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            if !service.filter.allows(&rpc_request.method) {
+                service.metrics.record_method_rejected(&rpc_request.method);
+                let request_id = &rpc_request.request_id;
+                if rpc_request.is_notification {
+                    debug!(target: "tx-proxy::filter", method = %rpc_request.method, request.id = %request_id, "dropping notification for disallowed method");
+                    return Ok(with_request_id(notification_response(), request_id));
+                }
+                debug!(target: "tx-proxy::filter", method = %rpc_request.method, request.id = %request_id, "rejecting disallowed method");
+                return Ok(with_request_id(
+                    method_not_found_response(rpc_request.id()),
+                    request_id,
+                ));
+            }
+
+            service
+                .inner
+                .call(rpc_request.into())
+                .await
+                .map_err(Into::into)
+        };
+
+        Box::pin(fut)
+    }
+}
+
+fn method_not_found_response(id: serde_json::Value) -> HttpResponse {
+    error_response(id, METHOD_NOT_FOUND_CODE, METHOD_NOT_FOUND_MSG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::StatusCode;
+    use http_body_util::BodyExt;
+    use jsonrpsee::types::error::PARSE_ERROR_CODE;
+    use serde_json::json;
+
+    /// Returns the response of a service behind `filter`, and whether the
+    /// request reached the service.
+    async fn call(filter: MethodFilter, body: &str) -> (HttpResponse, bool) {
+        let reached = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let inner_reached = reached.clone();
+        let metrics = Arc::new(ProxyMetrics::default());
+        let mut service = MethodFilterLayer::new(Arc::new(filter), metrics).layer(
+            tower::service_fn(move |_: HttpRequest<HttpBody>| {
+                inner_reached.store(true, std::sync::atomic::Ordering::Relaxed);
+                async { Ok::<_, BoxError>(HttpResponse::new(HttpBody::from(String::new()))) }
+            }),
+        );
+        let request = HttpRequest::builder()
+            .header("content-type", "application/json")
+            .body(HttpBody::from(body.to_string()))
+            .unwrap();
+        let response = service.call(request).await.unwrap();
+        (response, reached.load(std::sync::atomic::Ordering::Relaxed))
+    }
+
+    async fn json(response: HttpResponse) -> serde_json::Value {
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_allowed_method_passes_through() {
+        let body =
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"],"id":1}"#;
+        let (_, reached) = call(MethodFilter::default(), body).await;
+        assert!(reached);
+    }
+
+    #[tokio::test]
+    async fn test_disallowed_method_rejected() {
+        let body = r#"{"jsonrpc":"2.0","method":"debug_traceTransaction","params":[],"id":7}"#;
+        let (response, reached) = call(MethodFilter::default(), body).await;
+        assert!(!reached);
+        let response = json(response).await;
+        assert_eq!(response["error"]["code"], METHOD_NOT_FOUND_CODE);
+        assert_eq!(response["id"], 7);
+
+        // Notifications are dropped without a response
+        let body = r#"{"jsonrpc":"2.0","method":"debug_traceTransaction","params":[]}"#;
+        let (response, reached) = call(MethodFilter::default(), body).await;
+        assert!(!reached);
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_rejected_method_metric() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        for method in ["debug_traceTransaction", "debug_traceTransaction", "x\"y"] {
+            let body = json!({ "jsonrpc": "2.0", "method": method, "params": [], "id": 1 });
+            call(MethodFilter::default(), &body.to_string()).await;
+        }
+        let body = r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#;
+        call(MethodFilter::default(), body).await;
+
+        let rendered = handle.render();
+        assert!(
+            rendered.contains("method_rejected_total{method=\"debug_traceTransaction\"} 2\n"),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("method_rejected_total{method=\"other\"} 1\n"),
+            "{rendered}"
+        );
+        assert!(!rendered.contains("eth_chainId"), "{rendered}");
+    }
+
+    #[test]
+    fn test_rewritten_and_local_methods() {
+        let filter = MethodFilter::default()
+            .with_local_methods(["web3_clientVersion"])
+            .with_method_rewrites(HashMap::from([(
+                "pbh_sendConditional".to_string(),
+                "eth_sendRawTransactionConditional".to_string(),
+            )]));
+        assert!(filter.allows("web3_clientVersion"));
+        assert!(filter.allows("pbh_sendConditional"));
+        assert!(!filter.allows("pbh_sendRaw"));
+        assert!(!MethodFilter::default().allows("web3_clientVersion"));
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_body_rejected() {
+        // Batches are not supported, and are rejected as any other malformed body
+        for body in [
+            "not json",
+            r#"[{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}]"#,
+        ] {
+            let (response, reached) = call(MethodFilter::default(), body).await;
+            assert!(!reached);
+            assert_eq!(json(response).await["error"]["code"], PARSE_ERROR_CODE);
+        }
+    }
+}
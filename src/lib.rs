@@ -1,11 +1,43 @@
 #![cfg_attr(not(test), warn(unused_crate_dependencies))]
-use dotenvy as _;
 
+pub mod admin;
 pub mod auth;
+pub mod buffer;
+pub mod capture;
+#[cfg(feature = "cli")]
 pub mod cli;
 pub mod client;
+pub mod coalesce;
+#[cfg(feature = "cli")]
+pub mod config;
+pub mod diagnose;
+pub mod dispatch;
+pub mod edge;
 pub mod fanout;
+pub mod filter;
+pub mod maintenance;
 pub mod metrics;
+pub mod ordering;
+#[cfg(feature = "cli")]
+pub mod otlp;
+pub mod probe;
+#[cfg(feature = "metrics")]
+pub mod process;
 pub mod proxy;
+pub mod redact;
+#[cfg(feature = "cli")]
+pub mod reload;
+pub mod replay;
 pub mod rpc;
+pub mod sampling;
+pub mod saturation;
+#[cfg(feature = "metrics")]
+pub mod scrape;
+pub mod secret;
+pub mod shed;
+pub mod split;
+pub mod subscribe;
+pub mod timeline;
+pub mod tls;
+pub mod tunnel;
 pub mod validation;
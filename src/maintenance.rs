@@ -0,0 +1,460 @@
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+const SECS_PER_DAY: i64 = 24 * 60 * 60;
+const SECS_PER_WEEK: i64 = 7 * SECS_PER_DAY;
+
+/// The unix epoch was a Thursday, the fourth day of a week starting on Monday.
+const EPOCH_WEEKDAY: i64 = 3;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// The source of the wall clock time maintenance windows are evaluated against.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// The system wall clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A period during which a target is expected to be unavailable.
+///
+/// Parsed from either a recurring window in UTC, such as `Tue 02:00-03:00 UTC`
+/// or `Daily 23:30-00:30 UTC`, or an RFC 3339 interval, such as
+/// `2026-10-20T02:00:00Z/2026-10-20T03:00:00Z`. Recurring windows ending
+/// before they start end on the next day.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaintenanceWindow {
+    /// Recurs every week on the given day, Monday being 0, or every day if `None`.
+    Recurring {
+        weekday: Option<u8>,
+        /// Seconds after midnight UTC.
+        start: u32,
+        /// Seconds after midnight UTC.
+        end: u32,
+    },
+    /// Happens once, from `start` included to `end` excluded.
+    Interval { start: SystemTime, end: SystemTime },
+}
+
+impl MaintenanceWindow {
+    /// Returns true if the window is active at `now`, and when that next changes,
+    /// if it ever does.
+    fn state_at(&self, now: SystemTime) -> (bool, Option<SystemTime>) {
+        match *self {
+            Self::Recurring {
+                weekday,
+                start,
+                end,
+            } => {
+                let (period, offset) = match weekday {
+                    Some(weekday) => (
+                        SECS_PER_WEEK,
+                        (i64::from(weekday) - EPOCH_WEEKDAY).rem_euclid(7) * SECS_PER_DAY,
+                    ),
+                    None => (SECS_PER_DAY, 0),
+                };
+                let start = offset + i64::from(start);
+                let length =
+                    (i64::from(end) - i64::from(start % SECS_PER_DAY)).rem_euclid(SECS_PER_DAY);
+                let length = if length == 0 { SECS_PER_DAY } else { length };
+
+                let secs = unix_secs(now);
+                let phase = (secs - start).rem_euclid(period);
+                let (active, remaining) = if phase < length {
+                    (true, length - phase)
+                } else {
+                    (false, period - phase)
+                };
+                (active, Some(from_unix_secs(secs + remaining)))
+            }
+            Self::Interval { start, end } => {
+                if now < start {
+                    (false, Some(start))
+                } else if now < end {
+                    (true, Some(end))
+                } else {
+                    (false, None)
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for MaintenanceWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((start, end)) = s.split_once('/') {
+            let start = parse_rfc3339(start.trim())?;
+            let end = parse_rfc3339(end.trim())?;
+            if end <= start {
+                return Err(format!("maintenance interval `{s}` ends before it starts"));
+            }
+            return Ok(Self::Interval { start, end });
+        }
+
+        let invalid = || {
+            format!(
+                "invalid maintenance window `{s}`, expected e.g. `Tue 02:00-03:00 UTC` or an RFC 3339 interval"
+            )
+        };
+        let mut parts = s.split_whitespace();
+        let (Some(day), Some(times)) = (parts.next(), parts.next()) else {
+            return Err(invalid());
+        };
+        match parts.next() {
+            None => {}
+            Some(zone) if zone.eq_ignore_ascii_case("UTC") && parts.next().is_none() => {}
+            Some(_) => return Err(format!("maintenance window `{s}` must be in UTC")),
+        }
+
+        let weekday = if day.eq_ignore_ascii_case("Daily") || day == "*" {
+            None
+        } else {
+            let weekday = WEEKDAYS
+                .iter()
+                .position(|weekday| day.eq_ignore_ascii_case(weekday))
+                .ok_or_else(invalid)?;
+            Some(weekday as u8)
+        };
+        let (start, end) = times.split_once('-').ok_or_else(invalid)?;
+        let start = parse_time_of_day(start).ok_or_else(invalid)?;
+        let end = parse_time_of_day(end).ok_or_else(invalid)?;
+        if start == end {
+            return Err(format!("maintenance window `{s}` is empty"));
+        }
+        Ok(Self::Recurring {
+            weekday,
+            start,
+            end,
+        })
+    }
+}
+
+impl fmt::Display for MaintenanceWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Recurring {
+                weekday,
+                start,
+                end,
+            } => {
+                let day = weekday.map_or("Daily", |weekday| WEEKDAYS[usize::from(weekday)]);
+                write!(
+                    f,
+                    "{day} {:02}:{:02}-{:02}:{:02} UTC",
+                    start / 3600,
+                    start % 3600 / 60,
+                    end / 3600,
+                    end % 3600 / 60
+                )
+            }
+            Self::Interval { start, end } => {
+                write!(f, "{}/{}", format_rfc3339(start), format_rfc3339(end))
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ScheduleState {
+    active: bool,
+    /// The state is evaluated again once this time is reached.
+    next_transition: Option<SystemTime>,
+}
+
+/// The maintenance windows of a target, during which it is skipped by the fanout.
+///
+/// Evaluating the schedule compares the clock to the precomputed time of the
+/// next transition into or out of maintenance, and only evaluates the windows
+/// again once it is reached.
+#[derive(Debug)]
+pub struct MaintenanceSchedule {
+    windows: Vec<MaintenanceWindow>,
+    clock: Arc<dyn Clock>,
+    state: Mutex<ScheduleState>,
+}
+
+impl MaintenanceSchedule {
+    /// Creates a new [`MaintenanceSchedule`] with the given windows, evaluated
+    /// against the system clock.
+    pub fn new(windows: Vec<MaintenanceWindow>) -> Self {
+        Self {
+            windows,
+            clock: Arc::new(SystemClock),
+            state: Mutex::new(ScheduleState {
+                active: false,
+                next_transition: Some(UNIX_EPOCH),
+            }),
+        }
+    }
+
+    /// Sets the clock the windows are evaluated against.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Returns the maintenance windows.
+    pub fn windows(&self) -> &[MaintenanceWindow] {
+        &self.windows
+    }
+
+    /// Returns true if a window is active, and whether that changed since the
+    /// schedule was last evaluated. Entering a window on the first evaluation
+    /// counts as a change, not being in one does not.
+    pub fn poll(&self) -> (bool, bool) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        if state.next_transition.is_none_or(|at| now < at) {
+            return (state.active, false);
+        }
+
+        let mut active = false;
+        let mut next_transition = None::<SystemTime>;
+        for window in &self.windows {
+            let (window_active, window_next) = window.state_at(now);
+            active |= window_active;
+            next_transition = match (next_transition, window_next) {
+                (Some(next), Some(window_next)) => Some(next.min(window_next)),
+                (next, window_next) => next.or(window_next),
+            };
+        }
+        let changed = active != state.active;
+        *state = ScheduleState {
+            active,
+            next_transition,
+        };
+        (active, changed)
+    }
+}
+
+/// Parses a `HH:MM` time of day into seconds after midnight. `24:00` is midnight.
+fn parse_time_of_day(s: &str) -> Option<u32> {
+    let (hours, minutes) = s.split_once(':')?;
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+    if minutes >= 60 || hours > 24 || (hours == 24 && minutes > 0) {
+        return None;
+    }
+    Some((hours * 60 + minutes) * 60 % SECS_PER_DAY as u32)
+}
+
+/// Parses an RFC 3339 timestamp, e.g. `2026-10-20T02:00:00Z` or `2026-10-20T04:00:00+02:00`.
+fn parse_rfc3339(s: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid RFC 3339 timestamp `{s}`");
+    let number = |range: std::ops::Range<usize>| {
+        s.get(range)
+            .filter(|digits| digits.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|digits| digits.parse::<i64>().ok())
+            .ok_or_else(invalid)
+    };
+    let bytes = s.as_bytes();
+    if bytes.len() < 20
+        || bytes[4] != b'-'
+        || bytes[7] != b'-'
+        || !matches!(bytes[10], b'T' | b't' | b' ')
+        || bytes[13] != b':'
+        || bytes[16] != b':'
+    {
+        return Err(invalid());
+    }
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hours, minutes, seconds) = (number(11..13)?, number(14..16)?, number(17..19)?);
+    if !(1..=12).contains(&month)
+        || !(1..=days_in_month(year, month)).contains(&day)
+        || hours > 23
+        || minutes > 59
+        || seconds > 60
+    {
+        return Err(invalid());
+    }
+
+    // Fractional seconds are ignored
+    let mut rest = &s[19..];
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let digits = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(invalid());
+        }
+        rest = &fraction[digits..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ => {
+            let (sign, offset) = match rest.split_at_checked(1) {
+                Some(("+", offset)) => (1, offset),
+                Some(("-", offset)) => (-1, offset),
+                _ => return Err(invalid()),
+            };
+            let (hours, minutes) = offset
+                .split_once(':')
+                .filter(|(hours, minutes)| hours.len() == 2 && minutes.len() == 2)
+                .ok_or_else(invalid)?;
+            let (hours, minutes) = (
+                hours.parse::<u8>().map_err(|_| invalid())?,
+                minutes.parse::<u8>().map_err(|_| invalid())?,
+            );
+            sign * (i64::from(hours) * 3600 + i64::from(minutes) * 60)
+        }
+    };
+
+    let secs =
+        days_from_civil(year, month, day) * SECS_PER_DAY + hours * 3600 + minutes * 60 + seconds
+            - offset;
+    Ok(from_unix_secs(secs))
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, truncated to the second.
+fn format_rfc3339(time: SystemTime) -> String {
+    let secs = unix_secs(time);
+    let (year, month, day) = civil_from_days(secs.div_euclid(SECS_PER_DAY));
+    let secs = secs.rem_euclid(SECS_PER_DAY);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+fn is_leap_year(year: i64) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days between the unix epoch and a date, see
+/// <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date a number of days after the unix epoch, the inverse of [`days_from_civil`].
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+fn unix_secs(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs_f64().ceil() as i64),
+    }
+}
+
+fn from_unix_secs(secs: i64) -> SystemTime {
+    if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> SystemTime {
+        parse_rfc3339(s).unwrap()
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let window = "Tue 02:00-03:00 UTC".parse::<MaintenanceWindow>().unwrap();
+        assert_eq!(
+            window,
+            MaintenanceWindow::Recurring {
+                weekday: Some(1),
+                start: 2 * 3600,
+                end: 3 * 3600
+            }
+        );
+        assert_eq!(window.to_string(), "Tue 02:00-03:00 UTC");
+        assert_eq!(
+            "daily 23:30-00:30"
+                .parse::<MaintenanceWindow>()
+                .unwrap()
+                .to_string(),
+            "Daily 23:30-00:30 UTC"
+        );
+
+        let interval = "2026-10-20T04:00:00+02:00/2026-10-20T03:00:00Z"
+            .parse::<MaintenanceWindow>()
+            .unwrap();
+        assert_eq!(
+            interval.to_string(),
+            "2026-10-20T02:00:00Z/2026-10-20T03:00:00Z"
+        );
+
+        for invalid in [
+            "Tue 02:00-03:00 CET",
+            "Someday 02:00-03:00",
+            "Tue 02:00-02:00",
+            "Tue 25:00-26:00",
+            "2026-10-20T03:00:00Z/2026-10-20T02:00:00Z",
+            "2026-02-30T03:00:00Z/2026-03-01T02:00:00Z",
+        ] {
+            assert!(invalid.parse::<MaintenanceWindow>().is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn test_recurring_window() {
+        let window = "Tue 23:00-01:00 UTC".parse::<MaintenanceWindow>().unwrap();
+        // 2026-10-20 is a Tuesday
+        assert_eq!(
+            window.state_at(at("2026-10-20T22:59:59Z")),
+            (false, Some(at("2026-10-20T23:00:00Z")))
+        );
+        assert_eq!(
+            window.state_at(at("2026-10-21T00:30:00Z")),
+            (true, Some(at("2026-10-21T01:00:00Z")))
+        );
+        assert_eq!(
+            window.state_at(at("2026-10-21T01:00:00Z")),
+            (false, Some(at("2026-10-27T23:00:00Z")))
+        );
+    }
+
+    #[test]
+    fn test_civil_round_trip() {
+        for days in [-719_468, -1, 0, 11_016, 20_746, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
@@ -1,60 +1,734 @@
-use metrics::{Counter, Histogram, counter, histogram};
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use metrics::{
+    Counter, Gauge, Histogram, Label, counter, describe_counter, describe_gauge,
+    describe_histogram, gauge, histogram,
+};
 use metrics_derive::Metrics;
+#[cfg(feature = "metrics")]
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder};
 
-#[derive(Metrics)]
-#[metrics(scope = "metrics")]
-pub struct ProxyMetrics {
-    /// L2 Requests Latency
-    #[metric(describe = "L2 Requests Latency in seconds")]
-    pub l2_requests_latency: Histogram,
-    /// Builder Requests Latency
-    #[metric(describe = "Builder Requests Latency in seconds")]
-    pub builder_requests_latency: Histogram,
-    /// L2 Failed Requests
-    #[metric(describe = "L2 Failed Requests")]
-    pub l2_failed_requests: Histogram,
-    /// Builder Failed Requests
-    #[metric(describe = "Builder Failed Requests")]
-    pub builder_failed_requests: Histogram,
-    /// Inbound Requests
-    #[metric(describe = "Inbound Requests")]
-    pub inbound_requests: Counter,
+/// Default bucket boundaries in seconds for the request latency histograms,
+/// growing roughly exponentially from sub-millisecond to multi-second latencies.
+pub const DEFAULT_LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Methods reported by name in the `method` label of [`MethodErrorMetrics`].
+/// Any other method is reported as [`OTHER_METHOD_LABEL`], keeping the label
+/// cardinality bounded however many methods the allow-list prefixes admit.
+pub const LABELED_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendRawTransactionConditional",
+    "eth_chainId",
+    "eth_blockNumber",
+    "eth_getTransactionCount",
+    "net_peerCount",
+];
+
+/// The `method` label of methods not in [`LABELED_METHODS`].
+pub const OTHER_METHOD_LABEL: &str = "other";
+
+/// Disallowed methods reported by name in the `method` label of
+/// `method_rejected_total`. Any other rejected method is reported as
+/// [`OTHER_METHOD_LABEL`], so callers cannot grow the label set at will.
+pub const REJECTED_METHODS: &[&str] = &[
+    "admin_addPeer",
+    "admin_nodeInfo",
+    "admin_peers",
+    "debug_getRawTransaction",
+    "debug_traceBlockByNumber",
+    "debug_traceCall",
+    "debug_traceTransaction",
+    "engine_forkchoiceUpdatedV3",
+    "engine_getPayloadV3",
+    "engine_newPayloadV3",
+    "miner_setExtra",
+    "personal_sign",
+    "personal_unlockAccount",
+    "trace_block",
+    "trace_transaction",
+    "txpool_content",
+    "txpool_status",
+];
+
+/// Suffix shared by the request latency histograms in [`ProxyMetrics`].
+#[cfg(feature = "metrics")]
+const LATENCY_METRIC_SUFFIX: &str = "_requests_latency";
+
+/// Upstream phase histograms in [`TargetMetrics`], bucketed like the request latency.
+#[cfg(feature = "metrics")]
+const PHASE_METRICS: &[&str] = &["upstream_connect_seconds", "upstream_ttfb_seconds"];
+
+/// Returns a [`PrometheusBuilder`] rendering the request latency and upstream
+/// phase histograms with the given bucket boundaries.
+#[cfg(feature = "metrics")]
+pub fn prometheus_builder(latency_buckets: &[f64]) -> Result<PrometheusBuilder, BuildError> {
+    PHASE_METRICS.iter().try_fold(
+        PrometheusBuilder::new().set_buckets_for_metric(
+            Matcher::Suffix(LATENCY_METRIC_SUFFIX.to_string()),
+            latency_buckets,
+        )?,
+        // Matched by suffix as the latencies are, since the names are prefixed when installed
+        |builder, name| {
+            builder.set_buckets_for_metric(Matcher::Suffix(name.to_string()), latency_buckets)
+        },
+    )
+}
+
+/// The number of inbound requests received so far, recorded with the
+/// `inbound_requests` counter which cannot be read back.
+static INBOUND_REQUEST_COUNT: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of inbound requests received so far.
+pub fn inbound_request_count() -> u64 {
+    INBOUND_REQUEST_COUNT.load(Ordering::Relaxed)
 }
 
+/// Metrics recorded by the proxy layers.
+///
+/// Handles are resolved against the current recorder each time a metric is
+/// recorded, so metrics created before the recorder is installed still record.
+/// Without a recorder, e.g. when the library is embedded without the `metrics`
+/// feature, recording is a no-op.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProxyMetrics;
+
 impl ProxyMetrics {
     /// Creates a new instance of [`ProxyMetrics`].
     pub fn new() -> Self {
-        Self {
-            l2_requests_latency: histogram!("l2_requests_latency"),
-            builder_requests_latency: histogram!("builder_requests_latency"),
-            l2_failed_requests: histogram!("l2_failed_requests"),
-            builder_failed_requests: histogram!("builder_failed_requests"),
-            inbound_requests: counter!("inbound_requests"),
-        }
+        Self
+    }
+
+    /// Registers the metric descriptions with the installed recorder.
+    pub fn describe() {
+        describe_histogram!("l2_requests_latency", "L2 Requests Latency in seconds");
+        describe_histogram!(
+            "builder_requests_latency",
+            "Builder Requests Latency in seconds"
+        );
+        describe_histogram!("l2_failed_requests", "L2 Failed Requests");
+        describe_histogram!("builder_failed_requests", "Builder Failed Requests");
+        describe_counter!("inbound_requests", "Inbound Requests");
+        describe_counter!(
+            "inbound_notifications",
+            "Inbound JSON-RPC notifications, fanned out without waiting for responses"
+        );
+        describe_counter!(
+            "builder_late_pbh_errors",
+            "Builder PBH errors received after a response was already returned"
+        );
+        describe_counter!(
+            "builder_result_divergences",
+            "Requests for which successful builders returned different results"
+        );
+        describe_counter!(
+            "nonce_held_submissions",
+            "Submissions held waiting for a lower nonce"
+        );
+        describe_counter!(
+            "nonce_released_submissions",
+            "Held submissions released once lower nonces completed"
+        );
+        describe_counter!(
+            "nonce_expired_submissions",
+            "Held submissions released after the maximum hold expired"
+        );
+        describe_counter!(
+            "config_reloads_total",
+            "Successful target configuration reloads"
+        );
+        describe_histogram!(
+            "validation_overhead_seconds",
+            "Time spent reading, parsing and checking a request before it is fanned out, in seconds"
+        );
+        describe_counter!(
+            "fee_cap_rejected_transactions",
+            "Transactions rejected before fanout for a max fee per gas above the cap"
+        );
+        describe_counter!(
+            "degraded_forwards",
+            "Transactions forwarded directly to L2 without PBH validation because no builder was available"
+        );
+        describe_counter!(
+            "method_rejected_total",
+            "Requests and notifications rejected for a disallowed method, by method"
+        );
+        describe_counter!(
+            "tee_l2_divergences",
+            "L2 responses diverging from the builder response, by divergence"
+        );
+        describe_histogram!(
+            "jwt_age_seconds",
+            "Age in seconds of accepted JWTs, from their iat claim"
+        );
+        describe_counter!(
+            "replay_in_flight_hits",
+            "Duplicate requests rejected while the original was in flight"
+        );
+        describe_counter!(
+            "replay_cached_hits",
+            "Duplicate requests answered from the replay cache"
+        );
+        describe_counter!(
+            "coalesced_requests",
+            "Requests that joined an identical request in flight instead of being fanned out"
+        );
+        describe_counter!(
+            "l2_forward_dropped_total",
+            "Background L2 forwards dropped at the in-flight limit"
+        );
+        describe_counter!(
+            "client_aborted_requests",
+            "Requests whose caller went away before the response was ready"
+        );
+        describe_counter!(
+            "hedged_requests_total",
+            "Requests sent to another target because the previous one was slower than the hedge delay"
+        );
+        describe_counter!(
+            "hedge_cancelled_requests_total",
+            "Hedged requests cancelled in flight once another target responded"
+        );
+        describe_counter!(
+            "builder_split_requests",
+            "Requests routed to the current or new builders during a cutover"
+        );
+        describe_counter!(
+            "upstream_errors_total",
+            "Upstream JSON-RPC error responses by method"
+        );
+        describe_counter!(
+            "redactions_total",
+            "Matches redacted from error responses returned to callers, by rule"
+        );
+        describe_gauge!(
+            "buffered_body_bytes",
+            "Bytes of request and response bodies buffered in memory"
+        );
+        describe_counter!(
+            "buffer_shed_requests",
+            "Requests shed because too many body bytes were buffered"
+        );
+        describe_counter!(
+            "latency_shed_requests",
+            "Requests shed with a 503 status because the P99 latency exceeded the threshold"
+        );
+        describe_counter!(
+            "saturation_shed_requests",
+            "Requests shed because too many builders were rate limiting requests"
+        );
+        describe_gauge!(
+            "saturation_shed_percent",
+            "Percentage of new requests currently shed because builders are rate limiting requests"
+        );
+        describe_gauge!(
+            "upstream_inflight",
+            "Upstream requests currently in flight across all targets"
+        );
+        describe_gauge!("process_open_fds", "Open file descriptors of the process");
+        describe_gauge!(
+            "process_open_sockets",
+            "Open sockets of the process, including inbound and upstream connections"
+        );
+        describe_gauge!(
+            "process_resident_memory_bytes",
+            "Resident memory of the process in bytes"
+        );
+        describe_gauge!("tokio_workers", "Worker threads of the tokio runtime");
+        describe_gauge!("tokio_alive_tasks", "Tasks alive in the tokio runtime");
+        describe_gauge!(
+            "tokio_global_queue_depth",
+            "Tasks waiting in the injection queue of the tokio runtime"
+        );
+        describe_gauge!(
+            "tokio_blocking_threads",
+            "Blocking threads of the tokio runtime, with tokio_unstable only"
+        );
+        describe_gauge!(
+            "jwt_secret_age_seconds",
+            "Seconds since the JWT secret file of each target group was last modified"
+        );
+    }
+
+    /// Records the time spent reading, parsing and checking the method of a
+    /// request before it is fanned out.
+    pub fn record_validation_overhead(&self, duration: Duration) {
+        histogram!("validation_overhead_seconds").record(duration.as_secs_f64());
+    }
+
+    /// Records a transaction rejected for a max fee per gas above the cap.
+    pub fn record_fee_cap_rejected(&self) {
+        counter!("fee_cap_rejected_transactions").increment(1);
+    }
+
+    /// Records a raw transaction forwarded directly to L2 because no builder was available.
+    pub fn record_degraded_forward(&self) {
+        counter!("degraded_forwards").increment(1);
+    }
+
+    /// Records a request or notification rejected for a disallowed method.
+    ///
+    /// The label is the method if it is in [`REJECTED_METHODS`],
+    /// [`OTHER_METHOD_LABEL`] otherwise.
+    pub fn record_method_rejected(&self, method: &str) {
+        counter!("method_rejected_total", "method" => rejected_method_label(method)).increment(1);
+    }
+
+    /// Records an L2 response diverging from the builder response.
+    pub fn record_l2_divergence(&self, divergence: &'static str) {
+        counter!("tee_l2_divergences", "divergence" => divergence).increment(1);
+    }
+
+    /// Records the age of the JWT secret file of a target group.
+    pub fn record_jwt_secret_age(&self, target_group: &'static str, age: Duration) {
+        gauge!("jwt_secret_age_seconds", "target_group" => target_group).set(age.as_secs_f64());
     }
 
     /// Records the latency for a request to L2.
     pub fn record_l2_latency(&self, duration: f64) {
-        self.l2_requests_latency.record(duration);
+        histogram!("l2_requests_latency").record(duration);
     }
 
     /// Records the latency for a request to the builder.
     pub fn record_builder_latency(&self, duration: f64) {
-        self.builder_requests_latency.record(duration);
+        histogram!("builder_requests_latency").record(duration);
     }
 
     /// Records a failed request to L2.
     pub fn record_l2_failed_request(&self, duration: f64) {
-        self.l2_failed_requests.record(duration);
+        histogram!("l2_failed_requests").record(duration);
     }
 
     /// Records a failed request to the builder.
     pub fn record_builder_failed_request(&self, duration: f64) {
-        self.builder_failed_requests.record(duration);
+        histogram!("builder_failed_requests").record(duration);
+    }
+
+    /// Records an inbound request from the given caller identity.
+    pub fn record_inbound_request(&self, value: u64, identity: &str) {
+        INBOUND_REQUEST_COUNT.fetch_add(value, Ordering::Relaxed);
+        counter!("inbound_requests", "identity" => identity.to_string()).increment(value);
+    }
+
+    /// Records an inbound JSON-RPC notification.
+    pub fn record_inbound_notification(&self) {
+        counter!("inbound_notifications").increment(1);
+    }
+
+    /// Records a PBH error received from the builder after a response was already returned.
+    pub fn record_builder_late_pbh_error(&self) {
+        counter!("builder_late_pbh_errors").increment(1);
+    }
+
+    /// Records a request for which successful builders returned different results.
+    pub fn record_builder_result_divergence(&self) {
+        counter!("builder_result_divergences").increment(1);
+    }
+
+    /// Records a submission held waiting for a lower nonce.
+    pub fn record_nonce_held(&self) {
+        counter!("nonce_held_submissions").increment(1);
+    }
+
+    /// Records a held submission released once lower nonces completed.
+    pub fn record_nonce_released(&self) {
+        counter!("nonce_released_submissions").increment(1);
+    }
+
+    /// Records a held submission released after the maximum hold expired.
+    pub fn record_nonce_expired(&self) {
+        counter!("nonce_expired_submissions").increment(1);
+    }
+
+    /// Records a successful target configuration reload.
+    pub fn record_config_reload(&self) {
+        counter!("config_reloads_total").increment(1);
+    }
+
+    /// Records the age of an accepted JWT.
+    pub fn record_jwt_age(&self, age_secs: f64) {
+        histogram!("jwt_age_seconds").record(age_secs);
+    }
+
+    /// Records a duplicate request rejected while the original was in flight.
+    pub fn record_replay_in_flight(&self) {
+        counter!("replay_in_flight_hits").increment(1);
+    }
+
+    /// Records a duplicate request answered from the replay cache.
+    pub fn record_replay_cached(&self) {
+        counter!("replay_cached_hits").increment(1);
+    }
+
+    /// Records a request that joined an identical request in flight.
+    pub fn record_coalesced_request(&self) {
+        counter!("coalesced_requests").increment(1);
+    }
+
+    /// Records a background L2 forward dropped at the in-flight limit.
+    pub fn record_l2_forward_dropped(&self) {
+        counter!("l2_forward_dropped_total").increment(1);
+    }
+
+    /// Records a request whose caller went away before the response was ready.
+    pub fn record_client_aborted_request(&self) {
+        counter!("client_aborted_requests").increment(1);
+    }
+
+    /// Records requests sent to another target after the hedge delay.
+    pub fn record_hedged_requests(&self, value: u64) {
+        counter!("hedged_requests_total").increment(value);
+    }
+
+    /// Records a request routed to one side of a builder split.
+    pub fn record_builder_split_request(&self, split: &'static str) {
+        counter!("builder_split_requests", "split" => split).increment(1);
+    }
+
+    /// Records hedged requests cancelled in flight once another target responded.
+    pub fn record_hedge_cancelled_requests(&self, value: u64) {
+        counter!("hedge_cancelled_requests_total").increment(value);
+    }
+
+    /// Records a request shed because too many body bytes were buffered.
+    pub fn record_buffer_shed_request(&self) {
+        counter!("buffer_shed_requests").increment(1);
+    }
+
+    /// Records a request shed because the P99 latency exceeded the threshold.
+    pub fn record_latency_shed_request(&self) {
+        counter!("latency_shed_requests").increment(1);
+    }
+
+    /// Records a request shed because too many builders were rate limiting requests.
+    pub fn record_saturation_shed_request(&self) {
+        counter!("saturation_shed_requests").increment(1);
+    }
+
+    /// Records the percentage of new requests shed because builders are rate limiting requests.
+    pub fn record_saturation_shed_percent(&self, percent: u8) {
+        gauge!("saturation_shed_percent").set(f64::from(percent));
+    }
+
+    /// Increments the in-flight upstream gauge until the returned guard is dropped.
+    ///
+    /// Not labeled by target, so it reports the total across all fanouts.
+    pub fn start_upstream_inflight(&self) -> InflightGuard {
+        let gauge = gauge!("upstream_inflight");
+        gauge.increment(1.0);
+        InflightGuard(gauge)
+    }
+}
+
+/// Metrics recorded per upstream target.
+#[derive(Clone, Metrics)]
+#[metrics(scope = "metrics")]
+pub struct TargetMetrics {
+    /// Upstream Request Size
+    #[metric(describe = "Upstream request body size in bytes")]
+    pub upstream_request_bytes: Histogram,
+    /// Upstream Response Size
+    #[metric(describe = "Upstream response body size in bytes")]
+    pub upstream_response_bytes: Histogram,
+    /// Upstream Request Bytes Total
+    #[metric(describe = "Total upstream request bytes")]
+    pub upstream_request_bytes_total: Counter,
+    /// Upstream Response Bytes Total
+    #[metric(describe = "Total upstream response bytes")]
+    pub upstream_response_bytes_total: Counter,
+    /// Upstream Connect Duration
+    #[metric(describe = "Time taken to establish new connections to the target in seconds")]
+    pub upstream_connect_seconds: Histogram,
+    /// Upstream Time To First Byte
+    #[metric(
+        describe = "Time between sending a request and receiving the response headers in seconds"
+    )]
+    pub upstream_ttfb_seconds: Histogram,
+    /// Upstream Oversize Responses
+    #[metric(describe = "Upstream responses aborted for exceeding the size limit")]
+    pub upstream_oversize_responses: Counter,
+    /// Upstream Auth Failures
+    #[metric(describe = "Upstream responses rejecting our JWT with a 401 or 403 status")]
+    pub upstream_auth_failures: Counter,
+    /// Upstream Rate Limited Responses
+    #[metric(describe = "Upstream responses with a 429 status")]
+    pub upstream_rate_limited: Counter,
+    /// Upstream Connect Timeouts
+    #[metric(describe = "Upstream requests failing to connect within the connect timeout")]
+    pub upstream_connect_timeouts: Counter,
+    /// Upstream Response Timeouts
+    #[metric(describe = "Upstream requests not answered within the response timeout")]
+    pub upstream_response_timeouts: Counter,
+    /// Upstream Invalid Responses
+    #[metric(
+        describe = "Upstream responses that are not well-formed JSON-RPC responses to the request"
+    )]
+    pub upstream_invalid_responses: Counter,
+    /// Upstream Identity Mismatches
+    #[metric(describe = "Upstream responses without the expected identity header")]
+    pub upstream_identity_mismatches: Counter,
+    /// Upstream Queue Full
+    #[metric(describe = "Upstream requests shed because the ordered dispatch queue was full")]
+    pub upstream_queue_full: Counter,
+    /// Transactions Accepted
+    #[metric(describe = "Raw transactions accepted by the target with a transaction hash")]
+    pub transactions_accepted_total: Counter,
+    /// Target Panics
+    #[metric(describe = "Requests to the target that panicked and were treated as failed")]
+    pub target_panics_total: Counter,
+    /// Skipped During Maintenance
+    #[metric(describe = "Requests not sent to the target during one of its maintenance windows")]
+    pub skipped_maintenance: Counter,
+    /// Upstream Latency Estimate
+    #[metric(describe = "Exponentially weighted moving average of the target latency in seconds")]
+    pub upstream_latency_ewma_seconds: Gauge,
+}
+
+impl TargetMetrics {
+    /// Creates a new instance of [`TargetMetrics`] labeled with the given target.
+    pub fn new(target: &str) -> Self {
+        let labels = vec![Label::new("target", target.to_string())];
+        Self {
+            upstream_request_bytes: histogram!("upstream_request_bytes", labels.clone()),
+            upstream_response_bytes: histogram!("upstream_response_bytes", labels.clone()),
+            upstream_request_bytes_total: counter!("upstream_request_bytes_total", labels.clone()),
+            upstream_response_bytes_total: counter!(
+                "upstream_response_bytes_total",
+                labels.clone()
+            ),
+            upstream_connect_seconds: histogram!("upstream_connect_seconds", labels.clone()),
+            upstream_ttfb_seconds: histogram!("upstream_ttfb_seconds", labels.clone()),
+            upstream_oversize_responses: counter!("upstream_oversize_responses", labels.clone()),
+            upstream_auth_failures: counter!("upstream_auth_failures", labels.clone()),
+            upstream_rate_limited: counter!("upstream_rate_limited", labels.clone()),
+            upstream_connect_timeouts: counter!("upstream_connect_timeouts", labels.clone()),
+            upstream_response_timeouts: counter!("upstream_response_timeouts", labels.clone()),
+            upstream_invalid_responses: counter!("upstream_invalid_responses", labels.clone()),
+            upstream_identity_mismatches: counter!("upstream_identity_mismatches", labels.clone()),
+            upstream_queue_full: counter!("upstream_queue_full", labels.clone()),
+            transactions_accepted_total: counter!("transactions_accepted_total", labels.clone()),
+            target_panics_total: counter!("target_panics_total", labels.clone()),
+            skipped_maintenance: counter!("skipped_maintenance", labels.clone()),
+            upstream_latency_ewma_seconds: gauge!("upstream_latency_ewma_seconds", labels),
+        }
+    }
+
+    /// Records the size of a request body sent to the target.
+    pub fn record_request_bytes(&self, bytes: usize) {
+        self.upstream_request_bytes.record(bytes as f64);
+        self.upstream_request_bytes_total.increment(bytes as u64);
+    }
+
+    /// Records the size of a response body received from the target.
+    pub fn record_response_bytes(&self, bytes: usize) {
+        self.upstream_response_bytes.record(bytes as f64);
+        self.upstream_response_bytes_total.increment(bytes as u64);
+    }
+
+    /// Records the time taken to establish a new connection to the target.
+    pub fn record_connect(&self, duration: Duration) {
+        self.upstream_connect_seconds.record(duration.as_secs_f64());
+    }
+
+    /// Records the time the target took to start responding to a request.
+    pub fn record_ttfb(&self, duration: Duration) {
+        self.upstream_ttfb_seconds.record(duration.as_secs_f64());
+    }
+
+    /// Records a response aborted for exceeding the size limit.
+    pub fn record_oversize_response(&self) {
+        self.upstream_oversize_responses.increment(1);
+    }
+
+    /// Records a response rejecting our JWT.
+    pub fn record_auth_failure(&self) {
+        self.upstream_auth_failures.increment(1);
+    }
+
+    /// Records a rate limited response.
+    pub fn record_rate_limited(&self) {
+        self.upstream_rate_limited.increment(1);
+    }
+
+    /// Records a request failing to connect within the connect timeout.
+    pub fn record_connect_timeout(&self) {
+        self.upstream_connect_timeouts.increment(1);
+    }
+
+    /// Records a request not answered within the response timeout.
+    pub fn record_response_timeout(&self) {
+        self.upstream_response_timeouts.increment(1);
     }
 
-    /// Records an inbound request.
-    pub fn record_inbound_request(&self, value: u64) {
-        self.inbound_requests.increment(value);
+    /// Records a response that failed JSON-RPC validation.
+    pub fn record_invalid_response(&self) {
+        self.upstream_invalid_responses.increment(1);
+    }
+
+    /// Records a response without the expected identity.
+    pub fn record_identity_mismatch(&self) {
+        self.upstream_identity_mismatches.increment(1);
+    }
+
+    /// Records a request shed because the ordered dispatch queue was full.
+    pub fn record_queue_full(&self) {
+        self.upstream_queue_full.increment(1);
+    }
+
+    /// Records a raw transaction accepted by the target.
+    pub fn record_transaction_accepted(&self) {
+        self.transactions_accepted_total.increment(1);
+    }
+
+    /// Records a request to the target that panicked.
+    pub fn record_panic(&self) {
+        self.target_panics_total.increment(1);
+    }
+
+    /// Records a request not sent to the target during a maintenance window.
+    pub fn record_skipped_maintenance(&self) {
+        self.skipped_maintenance.increment(1);
+    }
+
+    /// Records the current latency estimate of the target, in seconds.
+    pub fn record_latency_estimate(&self, seconds: f64) {
+        self.upstream_latency_ewma_seconds.set(seconds);
+    }
+}
+
+/// Upstream JSON-RPC error responses of a target, counted per method.
+///
+/// The counters are registered up front for [`LABELED_METHODS`] and
+/// [`OTHER_METHOD_LABEL`], so recording never creates new label values.
+#[derive(Clone)]
+pub struct MethodErrorMetrics {
+    labeled: Vec<Counter>,
+    other: Counter,
+}
+
+impl MethodErrorMetrics {
+    /// Creates a new instance of [`MethodErrorMetrics`] labeled with the given target.
+    pub fn new(target: &str) -> Self {
+        let errors = |method: &'static str| {
+            counter!(
+                "upstream_errors_total",
+                "target" => target.to_string(),
+                "method" => method
+            )
+        };
+        Self {
+            labeled: LABELED_METHODS.iter().copied().map(errors).collect(),
+            other: errors(OTHER_METHOD_LABEL),
+        }
+    }
+
+    /// Records an error response to a request for the given method.
+    pub fn record_error(&self, method: &str) {
+        LABELED_METHODS
+            .iter()
+            .position(|labeled| *labeled == method)
+            .map_or(&self.other, |index| &self.labeled[index])
+            .increment(1);
+    }
+}
+
+impl fmt::Debug for MethodErrorMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MethodErrorMetrics").finish_non_exhaustive()
+    }
+}
+
+/// Decrements the in-flight upstream gauge when dropped.
+pub struct InflightGuard(Gauge);
+
+impl Drop for InflightGuard {
+    fn drop(&mut self) {
+        self.0.decrement(1.0);
+    }
+}
+
+/// Returns the label of a rejected method in `method_rejected_total`.
+fn rejected_method_label(method: &str) -> &'static str {
+    REJECTED_METHODS
+        .iter()
+        .find(|rejected| **rejected == method)
+        .copied()
+        .unwrap_or(OTHER_METHOD_LABEL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fanout::FanoutWrite, validation::ValidationLayer};
+    use jsonrpsee::{
+        core::BoxError,
+        http_client::{HttpBody, HttpRequest, HttpResponse},
+    };
+    use std::sync::Arc;
+    use tower::{Layer, Service};
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_latency_histograms_render_buckets() {
+        let recorder = prometheus_builder(DEFAULT_LATENCY_BUCKETS)
+            .unwrap()
+            .build_recorder();
+        let handle = recorder.handle();
+
+        metrics::with_local_recorder(&recorder, || {
+            let metrics = ProxyMetrics::new();
+            metrics.record_l2_latency(0.003);
+            metrics.record_builder_latency(1.2);
+        });
+
+        let rendered = handle.render();
+        assert!(rendered.contains(r#"l2_requests_latency_bucket{le="0.0025"} 0"#));
+        assert!(rendered.contains(r#"l2_requests_latency_bucket{le="0.005"} 1"#));
+        assert!(rendered.contains(r#"builder_requests_latency_bucket{le="0.5"} 0"#));
+        assert!(rendered.contains(r#"builder_requests_latency_bucket{le="2.5"} 1"#));
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn test_empty_latency_buckets_rejected() {
+        assert!(prometheus_builder(&[]).is_err());
+    }
+
+    #[test]
+    fn test_rejected_method_label() {
+        assert_eq!(
+            rejected_method_label("debug_traceTransaction"),
+            "debug_traceTransaction"
+        );
+        assert_eq!(rejected_method_label("debug_xyz123"), OTHER_METHOD_LABEL);
+        assert_eq!(rejected_method_label("x\"y"), OTHER_METHOD_LABEL);
+    }
+
+    #[test]
+    fn test_metrics_created_before_recorder() {
+        // Build the layer before any recorder is installed
+        let layer =
+            ValidationLayer::new(FanoutWrite::new(vec![]), Arc::new(ProxyMetrics::default()));
+        let mut service = layer.layer(tower::service_fn(|_: HttpRequest<HttpBody>| async {
+            Ok::<_, BoxError>(HttpResponse::new(HttpBody::from(String::new())))
+        }));
+
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        metrics::with_local_recorder(&recorder, || {
+            // The inbound request is recorded before the returned future is polled
+            let _ = service.call(HttpRequest::new(HttpBody::from(String::new())));
+        });
+
+        assert!(
+            handle
+                .render()
+                .contains("inbound_requests{identity=\"anonymous\"} 1\n")
+        );
     }
 }
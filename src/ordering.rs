@@ -0,0 +1,230 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use alloy_primitives::Address;
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use tokio::{sync::Notify, time::Instant};
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{metrics::ProxyMetrics, rpc::RpcRequest};
+
+/// The default maximum time a submission is held waiting for a lower nonce.
+pub const DEFAULT_MAX_HOLD_MS: u64 = 250;
+
+/// The default maximum number of senders tracked at once.
+pub const DEFAULT_MAX_SENDERS: usize = 10_000;
+
+/// Tracks in-flight nonces per sender so that higher nonce submissions
+/// can be held until lower nonces from the same sender have been processed.
+#[derive(Debug)]
+pub struct NonceTracker {
+    senders: Mutex<HashMap<Address, SenderState>>,
+    notify: Notify,
+    max_hold: Duration,
+    max_senders: usize,
+}
+
+#[derive(Debug, Default)]
+struct SenderState {
+    /// Nonces currently being processed by the proxy.
+    in_flight: BTreeSet<u64>,
+}
+
+impl SenderState {
+    /// Returns true if a lower nonce is in flight. A gap to the last nonce seen is not
+    /// waited on, as the missing nonces may have been submitted elsewhere.
+    fn must_wait(&self, nonce: u64) -> bool {
+        self.in_flight.range(..nonce).next().is_some()
+    }
+}
+
+impl NonceTracker {
+    /// Creates a new [`NonceTracker`].
+    pub fn new(max_hold: Duration, max_senders: usize) -> Self {
+        Self {
+            senders: Mutex::new(HashMap::new()),
+            notify: Notify::new(),
+            max_hold,
+            max_senders,
+        }
+    }
+
+    /// Waits until no lower nonce from the same sender is in flight, or the maximum
+    /// hold time expires, and marks the nonce as in flight.
+    pub async fn acquire(
+        self: &Arc<Self>,
+        sender: Address,
+        nonce: u64,
+        metrics: &ProxyMetrics,
+    ) -> NonceGuard {
+        let deadline = Instant::now() + self.max_hold;
+        let mut held = false;
+
+        loop {
+            // Register for notifications before checking the state to avoid missed wakeups.
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(guard) = self.try_admit(sender, nonce, false) {
+                if held {
+                    metrics.record_nonce_released();
+                }
+                return guard;
+            }
+
+            if !held {
+                debug!(target: "tx-proxy::ordering", %sender, nonce, "holding submission for lower nonce");
+                metrics.record_nonce_held();
+                held = true;
+            }
+
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                debug!(target: "tx-proxy::ordering", %sender, nonce, "hold expired");
+                metrics.record_nonce_expired();
+                return self
+                    .try_admit(sender, nonce, true)
+                    .expect("forced admission always succeeds");
+            }
+        }
+    }
+
+    /// Admits the nonce if it does not need to wait, or unconditionally if `force` is set.
+    fn try_admit(self: &Arc<Self>, sender: Address, nonce: u64, force: bool) -> Option<NonceGuard> {
+        let mut senders = self.senders.lock().unwrap();
+
+        if !senders.contains_key(&sender) && senders.len() >= self.max_senders {
+            // Evict idle senders, and stop tracking new senders if still at capacity.
+            senders.retain(|_, state| !state.in_flight.is_empty());
+            if senders.len() >= self.max_senders {
+                return Some(NonceGuard {
+                    tracker: None,
+                    sender,
+                    nonce,
+                });
+            }
+        }
+
+        let state = senders.entry(sender).or_default();
+        if !force && state.must_wait(nonce) {
+            return None;
+        }
+
+        state.in_flight.insert(nonce);
+
+        Some(NonceGuard {
+            tracker: Some(self.clone()),
+            sender,
+            nonce,
+        })
+    }
+
+    fn release(&self, sender: Address, nonce: u64) {
+        if let Some(state) = self.senders.lock().unwrap().get_mut(&sender) {
+            state.in_flight.remove(&nonce);
+        }
+        self.notify.notify_waiters();
+    }
+}
+
+/// Marks a nonce as in flight until dropped.
+#[derive(Debug)]
+pub struct NonceGuard {
+    tracker: Option<Arc<NonceTracker>>,
+    sender: Address,
+    nonce: u64,
+}
+
+impl Drop for NonceGuard {
+    fn drop(&mut self) {
+        if let Some(tracker) = &self.tracker {
+            tracker.release(self.sender, self.nonce);
+        }
+    }
+}
+
+/// A [`Layer`] that orders `eth_sendRawTransaction` submissions by nonce per sender.
+///
+/// When no [`NonceTracker`] is configured requests are passed through untouched.
+pub struct NonceOrderingLayer {
+    pub tracker: Option<Arc<NonceTracker>>,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+impl NonceOrderingLayer {
+    /// Creates a new [`NonceOrderingLayer`] with the given tracker.
+    pub fn new(tracker: Option<Arc<NonceTracker>>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self { tracker, metrics }
+    }
+}
+
+impl<S> Layer<S> for NonceOrderingLayer {
+    type Service = NonceOrderingService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        NonceOrderingService {
+            tracker: self.tracker.clone(),
+            metrics: self.metrics.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct NonceOrderingService<S> {
+    tracker: Option<Arc<NonceTracker>>,
+    metrics: Arc<ProxyMetrics>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for NonceOrderingService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(tracker) = self.tracker.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        let mut service = self.clone();
+        let metrics = self.metrics.clone();
+        service.inner = std::mem::replace(&mut self.inner, service.inner);
+
+        let fut = async move {
+            let rpc_request = RpcRequest::from_parsed_or_request(request).await?;
+            let _guard = match rpc_request.sender_and_nonce() {
+                Some((sender, nonce)) => Some(tracker.acquire(sender, nonce, &metrics).await),
+                None => None,
+            };
+
+            service
+                .inner
+                .call(rpc_request.into())
+                .await
+                .map_err(Into::into)
+        };
+
+        Box::pin(fut)
+    }
+}
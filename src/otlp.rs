@@ -0,0 +1,307 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use eyre::{Context, Result};
+use http::Uri;
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+use opentelemetry::{
+    KeyValue,
+    metrics::{Meter, MeterProvider as _},
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    Resource,
+    metrics::{PeriodicReader, SdkMeterProvider},
+};
+
+/// Default interval in milliseconds between exports of metrics to the OTLP endpoint.
+pub const DEFAULT_METRICS_OTLP_INTERVAL_MS: u64 = 60_000;
+
+/// Returns the resource describing the proxy in exported traces and metrics.
+pub fn resource() -> Resource {
+    Resource::builder_empty()
+        .with_attributes([
+            KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ])
+        .build()
+}
+
+/// Returns a meter provider exporting metrics to the OTLP endpoint at the given interval.
+pub fn meter_provider(endpoint: &Uri, interval: Duration) -> Result<SdkMeterProvider> {
+    let exporter = opentelemetry_otlp::MetricExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint.to_string())
+        .build()
+        .context("Failed to create OTLP metrics exporter")?;
+    let reader = PeriodicReader::builder(exporter)
+        .with_interval(interval)
+        .build();
+
+    Ok(SdkMeterProvider::builder()
+        .with_reader(reader)
+        .with_resource(resource())
+        .build())
+}
+
+/// Records metrics as OpenTelemetry instruments, exported by its meter provider.
+///
+/// Descriptions are attached to instruments when they are first recorded, so
+/// metrics described after that are exported without a description.
+pub struct OtlpRecorder {
+    // Exports stop once the provider is dropped
+    _provider: SdkMeterProvider,
+    meter: Meter,
+    descriptions: Mutex<HashMap<String, String>>,
+    counters: Mutex<HashMap<Key, Arc<OtlpCounter>>>,
+    gauges: Mutex<HashMap<Key, Arc<OtlpGauge>>>,
+    histograms: Mutex<HashMap<Key, Arc<OtlpHistogram>>>,
+}
+
+impl OtlpRecorder {
+    /// Creates a new [`OtlpRecorder`] recording to a meter of the given provider.
+    pub fn new(provider: SdkMeterProvider) -> Self {
+        Self {
+            meter: provider.meter(env!("CARGO_PKG_NAME")),
+            _provider: provider,
+            descriptions: Mutex::default(),
+            counters: Mutex::default(),
+            gauges: Mutex::default(),
+            histograms: Mutex::default(),
+        }
+    }
+
+    fn describe(&self, key: KeyName, description: SharedString) {
+        self.descriptions
+            .lock()
+            .unwrap()
+            .insert(key.as_str().to_string(), String::from(&*description));
+    }
+
+    fn description(&self, key: &Key) -> String {
+        self.descriptions
+            .lock()
+            .unwrap()
+            .get(key.name())
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(key, description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(key, description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, _unit: Option<Unit>, description: SharedString) {
+        self.describe(key, description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let counter = self
+            .counters
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(OtlpCounter {
+                    counter: self
+                        .meter
+                        .u64_counter(key.name().to_string())
+                        .with_description(self.description(key))
+                        .build(),
+                    attributes: attributes(key),
+                    value: AtomicU64::new(0),
+                })
+            })
+            .clone();
+        Counter::from_arc(counter)
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let gauge = self
+            .gauges
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(OtlpGauge {
+                    gauge: self
+                        .meter
+                        .f64_gauge(key.name().to_string())
+                        .with_description(self.description(key))
+                        .build(),
+                    attributes: attributes(key),
+                    value: AtomicU64::new(0f64.to_bits()),
+                })
+            })
+            .clone();
+        Gauge::from_arc(gauge)
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let histogram = self
+            .histograms
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_insert_with(|| {
+                Arc::new(OtlpHistogram {
+                    histogram: self
+                        .meter
+                        .f64_histogram(key.name().to_string())
+                        .with_description(self.description(key))
+                        .build(),
+                    attributes: attributes(key),
+                })
+            })
+            .clone();
+        Histogram::from_arc(histogram)
+    }
+}
+
+/// Returns the labels of the key as attributes.
+fn attributes(key: &Key) -> Vec<KeyValue> {
+    key.labels()
+        .map(|label| KeyValue::new(label.key().to_string(), label.value().to_string()))
+        .collect()
+}
+
+struct OtlpCounter {
+    counter: opentelemetry::metrics::Counter<u64>,
+    attributes: Vec<KeyValue>,
+    /// The total, to turn absolute values into increments.
+    value: AtomicU64,
+}
+
+impl CounterFn for OtlpCounter {
+    fn increment(&self, value: u64) {
+        self.value.fetch_add(value, Ordering::Relaxed);
+        self.counter.add(value, &self.attributes);
+    }
+
+    fn absolute(&self, value: u64) {
+        let previous = self.value.fetch_max(value, Ordering::Relaxed);
+        self.counter
+            .add(value.saturating_sub(previous), &self.attributes);
+    }
+}
+
+struct OtlpGauge {
+    gauge: opentelemetry::metrics::Gauge<f64>,
+    attributes: Vec<KeyValue>,
+    /// The bits of the current value, as OpenTelemetry gauges are only set.
+    value: AtomicU64,
+}
+
+impl OtlpGauge {
+    fn update(&self, f: impl Fn(f64) -> f64) {
+        let previous = self
+            .value
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+                Some(f(f64::from_bits(bits)).to_bits())
+            })
+            .expect("the update always succeeds");
+        self.gauge
+            .record(f(f64::from_bits(previous)), &self.attributes);
+    }
+}
+
+impl GaugeFn for OtlpGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.update(|_| value);
+    }
+}
+
+struct OtlpHistogram {
+    histogram: opentelemetry::metrics::Histogram<f64>,
+    attributes: Vec<KeyValue>,
+}
+
+impl HistogramFn for OtlpHistogram {
+    fn record(&self, value: f64) {
+        self.histogram.record(value, &self.attributes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry_sdk::metrics::{
+        InMemoryMetricExporter,
+        data::{Gauge as GaugeData, Sum},
+    };
+
+    #[test]
+    fn test_recorder_exports_metrics() {
+        let exporter = InMemoryMetricExporter::default();
+        let provider = SdkMeterProvider::builder()
+            .with_reader(PeriodicReader::builder(exporter.clone()).build())
+            .build();
+        let recorder = OtlpRecorder::new(provider.clone());
+
+        metrics::with_local_recorder(&recorder, || {
+            metrics::describe_counter!("requests", "Requests");
+            metrics::counter!("requests", "target" => "a").increment(2);
+            metrics::counter!("requests", "target" => "a").absolute(5);
+            metrics::gauge!("inflight").increment(3.0);
+            metrics::gauge!("inflight").decrement(1.0);
+            metrics::histogram!("latency").record(0.5);
+        });
+        provider.force_flush().unwrap();
+
+        let exported = exporter.get_finished_metrics().unwrap();
+        let metrics = exported
+            .iter()
+            .flat_map(|resource| &resource.scope_metrics)
+            .flat_map(|scope| &scope.metrics)
+            .collect::<Vec<_>>();
+        let metric = |name: &str| {
+            metrics
+                .iter()
+                .rev()
+                .find(|metric| metric.name == name)
+                .unwrap_or_else(|| panic!("{name} was not exported"))
+        };
+
+        let requests = metric("requests");
+        assert_eq!(requests.description, "Requests");
+        let sum = requests.data.as_any().downcast_ref::<Sum<u64>>().unwrap();
+        assert_eq!(sum.data_points[0].value, 5);
+        assert_eq!(
+            sum.data_points[0].attributes,
+            [KeyValue::new("target", "a")]
+        );
+
+        let inflight = metric("inflight");
+        let gauge = inflight
+            .data
+            .as_any()
+            .downcast_ref::<GaugeData<f64>>()
+            .unwrap();
+        assert_eq!(gauge.data_points[0].value, 2.0);
+
+        metric("latency");
+    }
+}
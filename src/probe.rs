@@ -0,0 +1,243 @@
+use std::{
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use eyre::eyre;
+use futures::future::join_all;
+use http::{Response, StatusCode, Uri};
+use http_body_util::Full;
+use hyper::body::Bytes;
+use jsonrpsee::http_client::HttpBody;
+use tokio::{net::TcpStream, time::Instant};
+use tracing::debug;
+
+use crate::{
+    client::{HttpClient, TargetHealth, without_userinfo},
+    fanout::FanoutWrite,
+    rpc::RpcRequest,
+};
+
+pub const DEFAULT_LIVENESS_PATH: &str = "/healthz";
+pub const DEFAULT_READINESS_PATH: &str = "/readyz";
+
+/// The default time to wait for a builder to be reachable at startup, in seconds.
+pub const DEFAULT_STARTUP_PROBE_TIMEOUT_SECS: u64 = 30;
+
+/// How often builders are probed while waiting for one to be reachable at startup.
+const STARTUP_PROBE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Liveness and readiness probes served without authentication on the metrics listener.
+#[derive(Clone, Debug)]
+pub struct Probes {
+    liveness_path: String,
+    readiness_path: String,
+    rpc_bound: Arc<AtomicBool>,
+    /// Cleared until the startup probe completes, see [`Probes::with_startup_gate`].
+    started: Arc<AtomicBool>,
+    builders: Arc<RwLock<Vec<TargetHealth>>>,
+}
+
+impl Default for Probes {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIVENESS_PATH, DEFAULT_READINESS_PATH)
+    }
+}
+
+impl Probes {
+    /// Creates a new [`Probes`] served on the given paths.
+    pub fn new(liveness_path: impl Into<String>, readiness_path: impl Into<String>) -> Self {
+        Self {
+            liveness_path: liveness_path.into(),
+            readiness_path: readiness_path.into(),
+            rpc_bound: Arc::new(AtomicBool::new(false)),
+            started: Arc::new(AtomicBool::new(true)),
+            builders: Arc::new(RwLock::new(Vec::new())),
+        }
+    }
+
+    /// Marks whether the RPC server is bound and serving requests.
+    pub fn set_rpc_bound(&self, bound: bool) {
+        self.rpc_bound.store(bound, Ordering::Relaxed);
+    }
+
+    /// Reports not ready until [`Probes::set_started`] is called, once a builder
+    /// was reachable at startup.
+    pub fn with_startup_gate(self) -> Self {
+        self.started.store(false, Ordering::Relaxed);
+        self
+    }
+
+    /// Marks the startup probe as complete, lifting the startup gate.
+    pub fn set_started(&self) {
+        self.started.store(true, Ordering::Relaxed);
+    }
+
+    /// Sets the builder targets whose health determines readiness.
+    pub fn set_builders(&self, fanout: &FanoutWrite) {
+        *self.builders.write().unwrap() = fanout.targets().iter().map(|t| t.health()).collect();
+    }
+
+    /// Returns true if the RPC server is bound, the startup probe completed
+    /// and at least one builder is reachable.
+    pub fn is_ready(&self) -> bool {
+        self.rpc_bound.load(Ordering::Relaxed)
+            && self.started.load(Ordering::Relaxed)
+            && self
+                .builders
+                .read()
+                .unwrap()
+                .iter()
+                .any(TargetHealth::is_healthy)
+    }
+
+    /// Returns the probe response for the given path, if it is a probe path.
+    pub fn response(&self, path: &str) -> Option<Response<Full<Bytes>>> {
+        let status = if path == self.liveness_path {
+            StatusCode::OK
+        } else if path == self.readiness_path {
+            if self.is_ready() {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+        } else {
+            return None;
+        };
+
+        Some(
+            Response::builder()
+                .status(status)
+                .body(Full::new(Bytes::from(
+                    status.canonical_reason().unwrap_or_default(),
+                )))
+                .unwrap(),
+        )
+    }
+}
+
+/// Waits until any enabled builder accepts a TCP connection, which also
+/// requires its hostname to resolve. Returns false if none did within `timeout`.
+pub async fn wait_for_builder(builders: &FanoutWrite, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let urls = builders
+            .targets()
+            .iter()
+            .filter(|client| client.is_enabled())
+            .map(|client| client.url().clone())
+            .collect::<Vec<_>>();
+        let attempt = tokio::time::timeout_at(deadline, join_all(urls.iter().map(connect)));
+        match attempt.await {
+            Ok(reached) if reached.into_iter().any(|reached| reached) => return true,
+            Ok(_) => {}
+            Err(_) => return false,
+        }
+
+        if Instant::now() + STARTUP_PROBE_INTERVAL >= deadline {
+            return false;
+        }
+        tokio::time::sleep(STARTUP_PROBE_INTERVAL).await;
+    }
+}
+
+/// Returns true if a TCP connection to the host and port of the URL succeeds.
+async fn connect(url: &Uri) -> bool {
+    let Some(host) = url.host() else {
+        return false;
+    };
+    let port = url
+        .port_u16()
+        .unwrap_or(if url.scheme_str() == Some("https") {
+            443
+        } else {
+            80
+        });
+
+    let url = without_userinfo(url);
+    match tokio::time::timeout(STARTUP_PROBE_INTERVAL * 4, TcpStream::connect((host, port))).await {
+        Ok(Ok(_)) => true,
+        Ok(Err(err)) => {
+            debug!(target: "tx-proxy::probe", %url, %err, "builder not reachable yet");
+            false
+        }
+        Err(_) => {
+            debug!(target: "tx-proxy::probe", %url, "builder connection timed out");
+            false
+        }
+    }
+}
+
+/// Queries `eth_chainId` on every enabled target of the fanout, returning the
+/// chain id reported by each target, or why it could not be read, keyed by the
+/// [display URL](HttpClient::display_url) of the target.
+pub async fn query_chain_ids(fanout: &FanoutWrite) -> Vec<(String, eyre::Result<u64>)> {
+    let targets = fanout.targets();
+    let queries = targets
+        .iter()
+        .filter(|client| client.is_enabled())
+        .map(|client| async move {
+            (
+                client.display_url().to_string(),
+                chain_id(client.clone()).await,
+            )
+        });
+    join_all(queries).await
+}
+
+/// Queries the chain id of a single target.
+async fn chain_id(mut client: HttpClient) -> eyre::Result<u64> {
+    let request = http::Request::builder()
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(HttpBody::from(
+            r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#,
+        ))?;
+    let response = client
+        .forward(RpcRequest::from_request(request).await?)
+        .await
+        .map_err(|err| eyre!(err))?;
+    if let Some(error) = response.error {
+        return Err(eyre!("eth_chainId failed: {error}"));
+    }
+
+    let body = serde_json::from_slice::<serde_json::Value>(&response.body)?;
+    let chain_id = body["result"]
+        .as_str()
+        .and_then(|result| result.strip_prefix("0x"))
+        .ok_or_else(|| {
+            eyre!(
+                "eth_chainId result is not a hex quantity: {}",
+                body["result"]
+            )
+        })?;
+    Ok(u64::from_str_radix(chain_id, 16)?)
+}
+
+/// Notifies systemd that the service is ready, if it was started with `Type=notify`.
+///
+/// Does nothing when `NOTIFY_SOCKET` is not set.
+pub fn sd_notify_ready() -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let socket = UnixDatagram::unbound()?;
+    let state = b"READY=1";
+
+    // Names starting with `@` are in the abstract namespace
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.to_str().and_then(|path| path.strip_prefix('@')) {
+        use std::os::linux::net::SocketAddrExt;
+        let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+        socket.send_to_addr(state, &addr)?;
+        return Ok(());
+    }
+
+    socket.send_to(state, path)?;
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use std::{collections::VecDeque, time::Duration};
+
+use metrics::gauge;
+use tracing::warn;
+
+use crate::metrics::inbound_request_count;
+
+/// The default interval in seconds between process metrics samples.
+pub const DEFAULT_PROCESS_METRICS_INTERVAL_SECS: u64 = 15;
+
+/// The default number of consecutive samples over which a growing file
+/// descriptor count is reported as a leak.
+pub const DEFAULT_FD_LEAK_SAMPLES: usize = 10;
+
+/// How much the number of requests per sample may vary, relative to its mean,
+/// for the request rate to be considered flat.
+const FLAT_RATE_TOLERANCE: f64 = 0.2;
+
+/// Resource usage of the process, read from `/proc` on Linux. Every value is
+/// `None` on other platforms or if it could not be read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProcessSample {
+    /// Number of open file descriptors.
+    pub open_fds: Option<u64>,
+    /// Number of open sockets, an estimate of the inbound and upstream connections.
+    pub open_sockets: Option<u64>,
+    /// Resident memory in bytes.
+    pub resident_memory_bytes: Option<u64>,
+}
+
+impl ProcessSample {
+    /// Samples the resource usage of the current process.
+    #[cfg(target_os = "linux")]
+    pub fn read() -> Self {
+        let fds = std::fs::read_dir("/proc/self/fd")
+            .ok()
+            .map(|entries| entries.filter_map(Result::ok).collect::<Vec<_>>());
+        let open_sockets = fds.as_ref().map(|fds| {
+            fds.iter()
+                .filter_map(|fd| std::fs::read_link(fd.path()).ok())
+                .filter(|target| target.to_string_lossy().starts_with("socket:"))
+                .count() as u64
+        });
+        let resident_memory_bytes = std::fs::read_to_string("/proc/self/status")
+            .ok()
+            .and_then(|status| {
+                status
+                    .lines()
+                    .find_map(|line| line.strip_prefix("VmRSS:"))?
+                    .trim()
+                    .strip_suffix("kB")?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+            .map(|kb| kb * 1024);
+
+        Self {
+            open_fds: fds.map(|fds| fds.len() as u64),
+            open_sockets,
+            resident_memory_bytes,
+        }
+    }
+
+    /// Samples the resource usage of the current process.
+    #[cfg(not(target_os = "linux"))]
+    pub fn read() -> Self {
+        Self::default()
+    }
+}
+
+/// Reports a file descriptor leak when the descriptor count grew at every one
+/// of the last samples while the request rate stayed flat, as the count of a
+/// healthy process follows its load.
+#[derive(Clone, Debug)]
+pub struct FdLeakDetector {
+    samples: usize,
+    /// The open descriptors and the requests received since the previous
+    /// sample, for the last `samples` samples.
+    window: VecDeque<(u64, u64)>,
+    last_requests: Option<u64>,
+}
+
+impl FdLeakDetector {
+    /// Creates a new [`FdLeakDetector`] over `samples` consecutive samples.
+    pub fn new(samples: usize) -> Self {
+        Self {
+            samples: samples.max(2),
+            window: VecDeque::new(),
+            last_requests: None,
+        }
+    }
+
+    /// Records a sample of the open descriptors and the total number of
+    /// requests received so far, returning true if it completes a leak signal.
+    /// The window restarts once a leak is reported.
+    pub fn record(&mut self, open_fds: u64, requests: u64) -> bool {
+        let last_requests = self.last_requests.replace(requests);
+        let Some(last_requests) = last_requests else {
+            return false;
+        };
+        if self.window.len() == self.samples {
+            self.window.pop_front();
+        }
+        self.window
+            .push_back((open_fds, requests.saturating_sub(last_requests)));
+        if self.window.len() < self.samples {
+            return false;
+        }
+
+        let growing = self
+            .window
+            .iter()
+            .zip(self.window.iter().skip(1))
+            .all(|((previous, _), (next, _))| next > previous);
+        let rates = self.window.iter().map(|(_, rate)| *rate as f64);
+        let (min, max) = rates.clone().fold((f64::MAX, 0.0_f64), |(min, max), rate| {
+            (min.min(rate), max.max(rate))
+        });
+        let mean = rates.sum::<f64>() / self.window.len() as f64;
+        let flat = max - min <= (mean * FLAT_RATE_TOLERANCE).max(1.0);
+
+        let leaking = growing && flat;
+        if leaking {
+            self.window.clear();
+        }
+        leaking
+    }
+}
+
+/// Samples the process resource usage and the tokio runtime metrics every
+/// `interval`, recording them as gauges and warning when [`FdLeakDetector`]
+/// reports a leak.
+pub async fn run_process_metrics(interval: Duration, leak_samples: usize) {
+    let mut detector = FdLeakDetector::new(leak_samples);
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        let sample = ProcessSample::read();
+        record_process_metrics(&sample);
+        record_runtime_metrics();
+
+        let Some(open_fds) = sample.open_fds else {
+            continue;
+        };
+        if detector.record(open_fds, inbound_request_count()) {
+            warn!(
+                open_fds,
+                samples = detector.samples,
+                "Open file descriptors grew at every sample while the request rate was flat, possible descriptor leak"
+            );
+        }
+    }
+}
+
+/// Records the process resource usage, skipping the values that are unknown.
+pub fn record_process_metrics(sample: &ProcessSample) {
+    if let Some(open_fds) = sample.open_fds {
+        gauge!("process_open_fds").set(open_fds as f64);
+    }
+    if let Some(open_sockets) = sample.open_sockets {
+        gauge!("process_open_sockets").set(open_sockets as f64);
+    }
+    if let Some(resident_memory_bytes) = sample.resident_memory_bytes {
+        gauge!("process_resident_memory_bytes").set(resident_memory_bytes as f64);
+    }
+}
+
+/// Records the metrics of the current tokio runtime.
+pub fn record_runtime_metrics() {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    gauge!("tokio_workers").set(metrics.num_workers() as f64);
+    gauge!("tokio_alive_tasks").set(metrics.num_alive_tasks() as f64);
+    gauge!("tokio_global_queue_depth").set(metrics.global_queue_depth() as f64);
+    // Only available with `--cfg tokio_unstable`
+    #[cfg(tokio_unstable)]
+    gauge!("tokio_blocking_threads").set(metrics.num_blocking_threads() as f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fd_leak_detector() {
+        // Descriptors growing with a flat request rate
+        let mut detector = FdLeakDetector::new(3);
+        let leaks = (0..4)
+            .map(|i| detector.record(100 + i, i * 50))
+            .collect::<Vec<_>>();
+        assert_eq!(leaks, vec![false, false, false, true]);
+
+        // Descriptors growing with the request rate
+        let mut detector = FdLeakDetector::new(3);
+        assert!(
+            ![(100, 0), (101, 10), (105, 60), (120, 260)]
+                .into_iter()
+                .any(|(fds, requests)| detector.record(fds, requests))
+        );
+
+        // Descriptors staying flat once
+        let mut detector = FdLeakDetector::new(3);
+        assert!(
+            ![(100, 0), (101, 50), (101, 100), (102, 150)]
+                .into_iter()
+                .any(|(fds, requests)| detector.record(fds, requests))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_process_metrics() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let _listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        record_process_metrics(&ProcessSample::read());
+        record_runtime_metrics();
+
+        let rendered = handle.render();
+        let gauge = |name: &str| {
+            rendered
+                .lines()
+                .find_map(|line| line.strip_prefix(&format!("{name} ")))
+                .unwrap_or_else(|| panic!("{name} missing from {rendered}"))
+                .parse::<f64>()
+                .unwrap()
+        };
+        assert!(gauge("process_open_fds") >= 3.0);
+        assert!(gauge("process_open_sockets") >= 1.0);
+        assert!(gauge("process_open_fds") >= gauge("process_open_sockets"));
+        assert!(gauge("process_resident_memory_bytes") > 1_000_000.0);
+        assert_eq!(gauge("tokio_workers"), 2.0);
+        assert!(gauge("tokio_alive_tasks") >= 0.0);
+    }
+}
@@ -1,5 +1,10 @@
-use crate::rpc::RpcRequest;
+use crate::fanout::{
+    AllTargetsFailed, FanoutMode, FirstResponse, SelectionStrategy, select_response,
+};
+use crate::redact::Redactor;
+use crate::rpc::{PbhErrorMatcher, RpcRequest, UnavailableError};
 use crate::{fanout::FanoutWrite, metrics::ProxyMetrics};
+use futures::StreamExt;
 use jsonrpsee::{
     core::BoxError,
     http_client::{HttpBody, HttpRequest, HttpResponse},
@@ -11,18 +16,46 @@ use std::{
     task::{Context, Poll},
 };
 use tower::{Layer, Service};
-use tracing::instrument;
+use tracing::{Instrument, Span, field::Empty, instrument};
 
 /// A [`Layer`] that validates responses from one fanout prior to forwarding them to the next fanout.
 pub struct ProxyLayer {
     pub fanout: FanoutWrite,
     pub metrics: Arc<ProxyMetrics>,
+    pub strategy: SelectionStrategy,
+    pub unavailable_error: Arc<UnavailableError>,
+    pub redactor: Arc<Redactor>,
 }
 
 impl ProxyLayer {
     /// Creates a new [`ProxyLayer`] with the given fanout.
     pub fn new(fanout: FanoutWrite, metrics: Arc<ProxyMetrics>) -> Self {
-        Self { fanout, metrics }
+        Self {
+            fanout,
+            metrics,
+            strategy: SelectionStrategy::default(),
+            unavailable_error: Arc::new(UnavailableError::default()),
+            redactor: Arc::new(Redactor::default()),
+        }
+    }
+
+    /// Sets the [`SelectionStrategy`] used to pick the L2 response returned to the caller.
+    pub fn with_selection_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the [`UnavailableError`] returned when no L2 target responded.
+    pub fn with_unavailable_error(mut self, unavailable_error: UnavailableError) -> Self {
+        self.unavailable_error = Arc::new(unavailable_error);
+        self
+    }
+
+    /// Sets the [`Redactor`] applied to the L2 error responses returned to the
+    /// caller. Nothing is redacted by default.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Arc::new(redactor);
+        self
     }
 }
 
@@ -32,6 +65,9 @@ impl<S> Layer<S> for ProxyLayer {
         ProxyService {
             fanout: self.fanout.clone(),
             metrics: self.metrics.clone(),
+            strategy: self.strategy,
+            unavailable_error: self.unavailable_error.clone(),
+            redactor: self.redactor.clone(),
             inner,
         }
     }
@@ -41,6 +77,9 @@ impl<S> Layer<S> for ProxyLayer {
 pub struct ProxyService<S> {
     fanout: FanoutWrite,
     metrics: Arc<ProxyMetrics>,
+    strategy: SelectionStrategy,
+    unavailable_error: Arc<UnavailableError>,
+    redactor: Arc<Redactor>,
     inner: S,
 }
 
@@ -60,21 +99,91 @@ where
         self.inner.poll_ready(cx).map_err(Into::into)
     }
 
-    #[instrument(skip(self, request), target = "tx-proxy::proxy")]
+    #[instrument(
+        skip(self, request),
+        target = "tx-proxy::proxy",
+        fields(request.id = Empty, l2.successes = Empty, l2.failures = Empty)
+    )]
     fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
         let mut service = self.clone();
-        let mut fanout = self.fanout.clone();
+        let fanout = self.fanout.clone();
         let metrics = self.metrics.clone();
+        let strategy = self.strategy;
+        let unavailable_error = self.unavailable_error.clone();
+        let redactor = self.redactor.clone();
         service.inner = std::mem::replace(&mut self.inner, service.inner);
+        let span = Span::current();
         let fut = async move {
-            let rpc_request = RpcRequest::from_request(request).await?;
+            let rpc_request = RpcRequest::from_parsed_or_request(request).await?;
+            span.record("request.id", rpc_request.request_id.as_str());
             let now = Instant::now();
-            let mut result = fanout.fan_request(rpc_request.clone()).await?;
+            let id = rpc_request.id();
+
+            // Targets tried in order are awaited one at a time regardless of the strategy
+            if strategy == SelectionStrategy::FirstSuccessful && fanout.mode() == FanoutMode::All {
+                let FirstResponse {
+                    response,
+                    mut responded,
+                    mut pending,
+                    ..
+                } = match fanout
+                    .fan_request_first(rpc_request, &PbhErrorMatcher::default())
+                    .await
+                {
+                    Ok(first) => first,
+                    Err(err) if err.is::<AllTargetsFailed>() => {
+                        return Ok(unavailable_error.response(id));
+                    }
+                    Err(err) => return Err(err),
+                };
+
+                tokio::spawn(
+                    async move {
+                        while let Some((_, _, res)) = pending.next().await {
+                            if res.is_ok() {
+                                responded += 1;
+                            }
+                        }
+
+                        let failures = fanout.enabled_count().saturating_sub(responded);
+                        span.record("l2.successes", responded);
+                        span.record("l2.failures", failures);
+                        metrics.record_l2_latency(now.elapsed().as_secs_f64());
+                        metrics.record_l2_failed_request(failures as f64);
+                    }
+                    .in_current_span(),
+                );
+
+                return Ok::<HttpResponse<HttpBody>, BoxError>(redactor.redact(response));
+            }
+
+            let result = match fanout.mode() {
+                FanoutMode::All => fanout.fan_request_all(rpc_request).await,
+                FanoutMode::Ordered => fanout.fan_request_sequential(rpc_request).await,
+            };
+            let failures = result.failures();
+            let responded = result.targets.len() - failures;
+            let result = match result.into_distinct_responses(strategy) {
+                Ok(result) => result,
+                Err(err) if err.is::<AllTargetsFailed>() => {
+                    return Ok(unavailable_error.response(id));
+                }
+                Err(err) => return Err(err),
+            };
+            span.record("l2.successes", responded);
+            span.record("l2.failures", failures);
             metrics.record_l2_latency(now.elapsed().as_secs_f64());
-            metrics.record_l2_failed_request(fanout.targets.len() as f64 - result.len() as f64);
-            Ok::<HttpResponse<HttpBody>, BoxError>(result.remove(0).response)
+            metrics.record_l2_failed_request(failures as f64);
+            // In declaration order, the first L2 response is returned as it always has been
+            let response = match strategy {
+                SelectionStrategy::DeclarationOrder => result.into_iter().next(),
+                _ => select_response(result, &PbhErrorMatcher::default()),
+            }
+            .expect("fanout returns at least one response");
+
+            Ok(redactor.redact(response))
         };
 
-        Box::pin(fut)
+        Box::pin(fut.instrument(Span::current()))
     }
 }
@@ -0,0 +1,233 @@
+#[cfg(feature = "cli")]
+use eyre::{Context, Result};
+#[cfg(feature = "cli")]
+use http::{HeaderValue, header::CONTENT_LENGTH};
+#[cfg(feature = "cli")]
+use hyper::body::Bytes;
+use jsonrpsee::http_client::{HttpBody, HttpResponse};
+#[cfg(feature = "cli")]
+use metrics::{Counter, counter};
+#[cfg(feature = "cli")]
+use regex::Regex;
+#[cfg(feature = "cli")]
+use serde_json::Value;
+#[cfg(feature = "cli")]
+use std::borrow::Cow;
+use std::fmt;
+#[cfg(feature = "cli")]
+use tracing::debug;
+
+use crate::rpc::RpcResponse;
+
+/// The text replacing redacted matches.
+pub const REDACTED: &str = "[redacted]";
+
+/// The rules applied by default, by name: IPv4 addresses and `internal.` hostnames.
+#[cfg(feature = "cli")]
+pub const DEFAULT_REDACT_RULES: &[(&str, &str)] = &[
+    ("ipv4", r"\b(?:\d{1,3}\.){3}\d{1,3}\b"),
+    (
+        "internal_hostname",
+        r"\b(?:[A-Za-z0-9-]+\.)*internal\.[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*",
+    ),
+];
+
+/// A pattern replaced with [`REDACTED`], counting its redactions.
+#[cfg(feature = "cli")]
+#[derive(Clone)]
+struct RedactRule {
+    name: String,
+    pattern: Regex,
+    redactions: Counter,
+}
+
+#[cfg(feature = "cli")]
+impl RedactRule {
+    fn new(name: &str, pattern: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            pattern: Regex::new(pattern)
+                .with_context(|| format!("Invalid redact pattern {pattern}"))?,
+            redactions: counter!("redactions_total", "rule" => name.to_string()),
+        })
+    }
+}
+
+/// Redacts sensitive details, such as internal addresses, from the error
+/// message and data of JSON-RPC error responses before they are returned to
+/// the caller. Success responses are never modified.
+///
+/// Patterns require the `cli` feature, without it nothing is redacted.
+#[derive(Clone, Default)]
+pub struct Redactor {
+    #[cfg(feature = "cli")]
+    rules: Vec<RedactRule>,
+}
+
+#[cfg(not(feature = "cli"))]
+impl Redactor {
+    /// Returns the HTTP response to return to the caller as is.
+    pub fn redact(&self, response: RpcResponse<HttpBody>) -> HttpResponse {
+        response.response
+    }
+}
+
+#[cfg(feature = "cli")]
+impl Redactor {
+    /// Creates a [`Redactor`] applying the [`DEFAULT_REDACT_RULES`] and the given
+    /// patterns, each named after its pattern in the `redactions_total` metric.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let defaults = DEFAULT_REDACT_RULES
+            .iter()
+            .map(|(name, pattern)| RedactRule::new(name, pattern));
+        let custom = patterns
+            .iter()
+            .map(|pattern| RedactRule::new(pattern, pattern));
+        Ok(Self {
+            rules: defaults.chain(custom).collect::<Result<_>>()?,
+        })
+    }
+
+    /// Returns the HTTP response to return to the caller, with the error message
+    /// and data redacted if the response is a JSON-RPC error.
+    pub fn redact(&self, response: RpcResponse<HttpBody>) -> HttpResponse {
+        if self.rules.is_empty() || response.error.is_none() {
+            return response.response;
+        }
+        let Ok(mut payload) = serde_json::from_slice::<Value>(&response.body) else {
+            return response.response;
+        };
+        let Some(error) = payload.get_mut("error").and_then(Value::as_object_mut) else {
+            return response.response;
+        };
+
+        let mut redacted = false;
+        for field in ["message", "data"] {
+            if let Some(value) = error.get_mut(field) {
+                redacted |= self.redact_value(value);
+            }
+        }
+        if !redacted {
+            return response.response;
+        }
+
+        let body = Bytes::from(serde_json::to_vec(&payload).expect("valid JSON value"));
+        let (mut parts, _) = response.response.into_parts();
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+        HttpResponse::from_parts(parts, HttpBody::from(body))
+    }
+
+    /// Redacts every string in the value, returning true if any was modified.
+    fn redact_value(&self, value: &mut Value) -> bool {
+        match value {
+            Value::String(text) => {
+                let redacted = match self.redact_str(text) {
+                    Cow::Owned(redacted) => redacted,
+                    Cow::Borrowed(_) => return false,
+                };
+                *text = redacted;
+                true
+            }
+            Value::Array(values) => values.iter_mut().fold(false, |redacted, value| {
+                self.redact_value(value) || redacted
+            }),
+            Value::Object(values) => values.values_mut().fold(false, |redacted, value| {
+                self.redact_value(value) || redacted
+            }),
+            _ => false,
+        }
+    }
+
+    fn redact_str<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for rule in &self.rules {
+            let matches = rule.pattern.find_iter(&text).count();
+            if matches == 0 {
+                continue;
+            }
+            debug!(target: "tx-proxy::redact", rule = %rule.name, matches, "Redacted error response");
+            rule.redactions.increment(matches as u64);
+            text = Cow::Owned(rule.pattern.replace_all(&text, REDACTED).into_owned());
+        }
+        text
+    }
+}
+
+impl fmt::Debug for Redactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("Redactor");
+        #[cfg(feature = "cli")]
+        debug.field(
+            "rules",
+            &self.rules.iter().map(|rule| &rule.name).collect::<Vec<_>>(),
+        );
+        debug.finish()
+    }
+}
+
+#[cfg(all(test, feature = "cli"))]
+mod tests {
+    use super::*;
+    use crate::rpc::parse_response_payload;
+    use http_body_util::BodyExt;
+
+    fn rpc_response(body: &str) -> RpcResponse<HttpBody> {
+        let error = parse_response_payload(body.as_bytes()).unwrap();
+        RpcResponse::new(
+            http::Response::builder()
+                .header(CONTENT_LENGTH, body.len())
+                .body(HttpBody::from(body.to_string()))
+                .unwrap(),
+            error,
+        )
+        .with_body(body.to_string())
+    }
+
+    async fn redacted(redactor: &Redactor, body: &str) -> Value {
+        let (parts, body) = redactor.redact(rpc_response(body)).into_parts();
+        let body = body.collect().await.unwrap().to_bytes();
+        assert_eq!(parts.headers[CONTENT_LENGTH], body.len().to_string());
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_redacts_error_message_and_data() {
+        let redactor = Redactor::new(&["trace_id=[0-9a-f]+".to_string()]).unwrap();
+        let body = r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"dial tcp 10.0.3.7:8545 failed","data":{"host":"builder-2.internal.example.com","trace":["trace_id=abc123"]}},"id":7}"#;
+
+        let payload = redacted(&redactor, body).await;
+        assert_eq!(
+            payload,
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "error": {
+                    "code": -32000,
+                    "message": "dial tcp [redacted]:8545 failed",
+                    "data": { "host": "[redacted]", "trace": ["[redacted]"] }
+                },
+                "id": 7
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_success_responses_untouched() {
+        let redactor = Redactor::new(&[]).unwrap();
+        let body = r#"{"jsonrpc":"2.0","result":"10.0.0.1 at node.internal.example","id":1}"#;
+
+        let payload = redacted(&redactor, body).await;
+        assert_eq!(payload["result"], "10.0.0.1 at node.internal.example");
+
+        // Errors without a match are returned as is
+        let body = r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"nonce too low"},"id":1}"#;
+        let payload = redacted(&redactor, body).await;
+        assert_eq!(payload["error"]["message"], "nonce too low");
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(Redactor::new(&["(".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::{Arc, Mutex};
+
+use eyre::Result;
+use tracing::{info, warn};
+
+use crate::{cli::Cli, config::ProxyConfig, fanout::Targets, metrics::ProxyMetrics, probe::Probes};
+
+/// Reloads the builder and L2 targets of a running proxy from the config file.
+///
+/// The settings of the builder and L2 target groups, such as JWT secrets,
+/// timeouts and target URLs, are reloadable. Other settings, such as listeners,
+/// metrics settings and allowed methods, require a restart.
+pub struct TargetReloader {
+    /// The command line values before the config file was merged.
+    args: Cli,
+    /// The config the current targets were built from.
+    config: Mutex<ProxyConfig>,
+    targets: Targets,
+    probes: Probes,
+    metrics: Arc<ProxyMetrics>,
+}
+
+impl TargetReloader {
+    /// Creates a new [`TargetReloader`] for the given running targets.
+    ///
+    /// `args` must hold the command line values before the config file was merged,
+    /// so that command line values keep taking precedence on reload.
+    pub fn new(
+        args: Cli,
+        config: ProxyConfig,
+        targets: Targets,
+        probes: Probes,
+        metrics: Arc<ProxyMetrics>,
+    ) -> Self {
+        Self {
+            args,
+            config: Mutex::new(config),
+            targets,
+            probes,
+            metrics,
+        }
+    }
+
+    /// Re-reads the config file and swaps in the updated target sets.
+    ///
+    /// Clients for unchanged targets are kept, and requests already in flight
+    /// complete on the previous target sets. Nothing is swapped if either set fails to build.
+    pub fn reload(&self) -> Result<()> {
+        let mut args = self.args.clone();
+        let config = args.load_config()?;
+
+        let mut current = self.config.lock().unwrap();
+        let without_targets = |config: &ProxyConfig| ProxyConfig {
+            builder: Default::default(),
+            l2: Default::default(),
+            ..config.clone()
+        };
+        if without_targets(&config) != without_targets(&current) {
+            warn!(target: "tx-proxy::reload", "Settings other than the targets changed in config file, restart required to apply");
+        }
+
+        // Only the target groups are reloaded, the other settings keep their running values
+        let mut running = self.args.clone();
+        current.apply(&mut running)?;
+        let identity = running.upstream_identity()?;
+        let (builder, builder_diff) = args.builder_targets.rebuild(
+            &self.targets.builder.targets(),
+            running.target_queue_depth,
+            running.timeout_jitter_pct,
+            identity.as_ref(),
+        )?;
+        let (l2, l2_diff) = args.l2_targets.rebuild(
+            &self.targets.l2.targets(),
+            running.target_queue_depth,
+            running.timeout_jitter_pct,
+            identity.as_ref(),
+        )?;
+
+        self.targets.builder.replace_targets(builder);
+        self.targets.l2.replace_targets(l2);
+        self.probes.set_builders(&self.targets.builder);
+        current.builder = config.builder;
+        current.l2 = config.l2;
+        self.metrics.record_config_reload();
+
+        info!(
+            target: "tx-proxy::reload",
+            builder.added = ?builder_diff.added,
+            builder.removed = ?builder_diff.removed,
+            builder.changed = ?builder_diff.changed,
+            l2.added = ?l2_diff.added,
+            l2.removed = ?l2_diff.removed,
+            l2.changed = ?l2_diff.changed,
+            unchanged = builder_diff.is_empty() && l2_diff.is_empty(),
+            "Reloaded targets"
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use alloy_primitives::{B256, keccak256};
+use http::{HeaderMap, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+    types::ErrorObject,
+};
+use tokio::time::Instant;
+use tower::{Layer, Service};
+use tracing::debug;
+
+use crate::{metrics::ProxyMetrics, rpc::RpcRequest};
+
+/// The default maximum number of recent requests tracked at once.
+pub const DEFAULT_REPLAY_MAX_ENTRIES: usize = 10_000;
+
+/// JSON-RPC error code returned for a duplicate of a request still in flight.
+pub const DUPLICATE_REQUEST_CODE: i32 = -32000;
+
+/// A client and the hash of a request body, including its JSON-RPC id.
+type ReplayKey = (String, B256);
+
+/// Tracks recently seen requests per client so that identical requests repeated
+/// within the window are not fanned out again.
+pub struct ReplayCache {
+    entries: Mutex<ReplayEntries>,
+    window: Duration,
+    max_entries: usize,
+}
+
+#[derive(Default)]
+struct ReplayEntries {
+    map: HashMap<ReplayKey, ReplayEntry>,
+    /// Keys in insertion order, which is also expiry order.
+    order: VecDeque<(ReplayKey, u64)>,
+    next_generation: u64,
+}
+
+struct ReplayEntry {
+    state: ReplayState,
+    /// Distinguishes an entry from a later entry for the same key.
+    generation: u64,
+    expires_at: Instant,
+}
+
+enum ReplayState {
+    InFlight,
+    Completed(CachedResponse),
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedResponse {
+    fn to_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::new(HttpBody::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
+}
+
+/// The outcome of looking up a request in the [`ReplayCache`].
+pub enum Replay {
+    /// The request was not seen within the window and is now in flight.
+    New(ReplayGuard),
+    /// An identical request is still in flight.
+    InFlight,
+    /// An identical request completed within the window.
+    Cached(HttpResponse),
+}
+
+impl ReplayEntries {
+    /// Removes expired entries, and the oldest entries while at capacity.
+    fn evict(&mut self, now: Instant, max_entries: usize) {
+        while let Some((key, generation)) = self.order.front() {
+            let entry = self.map.get(key).filter(|e| e.generation == *generation);
+            let live = entry.is_some();
+            if entry.is_some_and(|e| e.expires_at > now) && self.map.len() < max_entries {
+                break;
+            }
+
+            let (key, _) = self.order.pop_front().expect("front exists");
+            if live {
+                self.map.remove(&key);
+            }
+        }
+    }
+}
+
+impl ReplayCache {
+    /// Creates a new [`ReplayCache`].
+    pub fn new(window: Duration, max_entries: usize) -> Self {
+        Self {
+            entries: Mutex::new(ReplayEntries::default()),
+            window,
+            max_entries,
+        }
+    }
+
+    /// Looks up a request from the given client, marking it as in flight if it was
+    /// not seen within the window.
+    pub fn lookup(self: &Arc<Self>, client: String, body: &[u8]) -> Replay {
+        let key = (client, keccak256(body));
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.evict(now, self.max_entries);
+
+        if let Some(entry) = entries.map.get(&key) {
+            return match &entry.state {
+                ReplayState::InFlight => Replay::InFlight,
+                ReplayState::Completed(response) => Replay::Cached(response.to_response()),
+            };
+        }
+
+        let generation = entries.next_generation;
+        entries.next_generation += 1;
+        entries.map.insert(
+            key.clone(),
+            ReplayEntry {
+                state: ReplayState::InFlight,
+                generation,
+                expires_at: now + self.window,
+            },
+        );
+        entries.order.push_back((key.clone(), generation));
+
+        Replay::New(ReplayGuard {
+            cache: self.clone(),
+            key,
+            generation,
+            completed: false,
+        })
+    }
+
+    /// Returns the number of tracked requests.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Returns true if no requests are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Marks a request as in flight until completed with its response or dropped.
+///
+/// Dropping the guard without completing it forgets the request, so a retry
+/// after a failure is forwarded again.
+pub struct ReplayGuard {
+    cache: Arc<ReplayCache>,
+    key: ReplayKey,
+    generation: u64,
+    completed: bool,
+}
+
+impl ReplayGuard {
+    fn complete(mut self, response: CachedResponse) {
+        let mut entries = self.cache.entries.lock().unwrap();
+        if let Some(entry) = entries
+            .map
+            .get_mut(&self.key)
+            .filter(|e| e.generation == self.generation)
+        {
+            entry.state = ReplayState::Completed(response);
+        }
+        self.completed = true;
+    }
+}
+
+impl Drop for ReplayGuard {
+    fn drop(&mut self) {
+        if self.completed {
+            return;
+        }
+
+        let mut entries = self.cache.entries.lock().unwrap();
+        if entries
+            .map
+            .get(&self.key)
+            .is_some_and(|e| e.generation == self.generation)
+        {
+            entries.map.remove(&self.key);
+        }
+    }
+}
+
+/// A [`Layer`] that answers requests repeated by the same client with an
+/// identical body within the replay window without forwarding them again.
+///
+/// Clients are identified by the first `X-Forwarded-For` address, or
+/// `X-Real-IP`, and share a single bucket otherwise.
+/// When no [`ReplayCache`] is configured requests are passed through untouched.
+pub struct ReplayLayer {
+    pub cache: Option<Arc<ReplayCache>>,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+impl ReplayLayer {
+    /// Creates a new [`ReplayLayer`] with the given cache.
+    pub fn new(cache: Option<Arc<ReplayCache>>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self { cache, metrics }
+    }
+}
+
+impl<S> Layer<S> for ReplayLayer {
+    type Service = ReplayService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        ReplayService {
+            cache: self.cache.clone(),
+            metrics: self.metrics.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ReplayService<S> {
+    cache: Option<Arc<ReplayCache>>,
+    metrics: Arc<ProxyMetrics>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for ReplayService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(cache) = self.cache.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        let mut service = self.clone();
+        let metrics = self.metrics.clone();
+        service.inner = std::mem::replace(&mut self.inner, service.inner);
+
+        let fut = async move {
+            let client = client_id(request.headers());
+            let rpc_request = RpcRequest::from_parsed_or_request(request).await?;
+            let guard = match cache.lookup(client, &rpc_request.body) {
+                Replay::New(guard) => guard,
+                Replay::InFlight => {
+                    debug!(target: "tx-proxy::replay", method = %rpc_request.method, request.id = %rpc_request.request_id, "duplicate of in flight request");
+                    metrics.record_replay_in_flight();
+                    return Ok(duplicate_request_response(rpc_request.id()));
+                }
+                Replay::Cached(response) => {
+                    debug!(target: "tx-proxy::replay", method = %rpc_request.method, request.id = %rpc_request.request_id, "replaying cached response");
+                    metrics.record_replay_cached();
+                    return Ok(response);
+                }
+            };
+
+            let response = service
+                .inner
+                .call(rpc_request.into())
+                .await
+                .map_err(Into::into)?;
+            let (parts, body) = response.into_parts();
+            let body = body.collect().await?.to_bytes();
+            guard.complete(CachedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            });
+
+            Ok(HttpResponse::from_parts(parts, HttpBody::from(body)))
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// Identifies the client from the forwarding headers set by a load balancer.
+fn client_id(headers: &HeaderMap) -> String {
+    headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .or_else(|| {
+            headers
+                .get("x-real-ip")
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(|client| client.trim().to_string())
+        .unwrap_or_default()
+}
+
+fn duplicate_request_response(id: serde_json::Value) -> HttpResponse {
+    let error = ErrorObject::owned(DUPLICATE_REQUEST_CODE, "duplicate request", None::<()>);
+    HttpResponse::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(HttpBody::from(
+            serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": id }).to_string(),
+        ))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REQUEST: &[u8] = br#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#;
+
+    fn completed() -> CachedResponse {
+        CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_cache_eviction() {
+        let cache = Arc::new(ReplayCache::new(Duration::from_millis(50), 2));
+
+        let Replay::New(guard) = cache.lookup("a".to_string(), REQUEST) else {
+            panic!("expected a new request");
+        };
+        guard.complete(completed());
+        assert!(matches!(
+            cache.lookup("a".to_string(), REQUEST),
+            Replay::Cached(_)
+        ));
+
+        // Clients do not share entries
+        assert!(matches!(
+            cache.lookup("b".to_string(), REQUEST),
+            Replay::New(_)
+        ));
+        assert_eq!(cache.len(), 1);
+
+        // The oldest entry is evicted at capacity
+        let _b = cache.lookup("b".to_string(), REQUEST);
+        let _c = cache.lookup("c".to_string(), REQUEST);
+        assert_eq!(cache.len(), 2);
+        assert!(matches!(
+            cache.lookup("a".to_string(), REQUEST),
+            Replay::New(_)
+        ));
+
+        // Entries expire after the window
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(matches!(
+            cache.lookup("b".to_string(), REQUEST),
+            Replay::New(_)
+        ));
+        assert!(cache.is_empty());
+    }
+}
@@ -1,71 +1,593 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use alloy_consensus::{Transaction, TxEnvelope};
+use alloy_eips::eip2718::Decodable2718;
+use alloy_primitives::{Address, B256, keccak256};
 use eyre::Result;
+use http::{
+    HeaderMap, HeaderValue, StatusCode,
+    header::{CONTENT_LENGTH, RETRY_AFTER},
+};
+use hyper::body::Bytes;
 use jsonrpsee::{
     core::http_helpers,
-    http_client::HttpBody,
-    types::{ErrorObjectOwned, Request, Response, ResponsePayload, error::INTERNAL_ERROR_CODE},
+    http_client::{HttpBody, HttpResponse},
+    types::{
+        ErrorObject, ErrorObjectOwned, Notification, Request, Response, ResponsePayload,
+        error::INTERNAL_ERROR_CODE,
+    },
 };
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+
+use crate::buffer::{BufferBudget, BufferGuard};
 
 pub const MAX_REQUEST_BODY_SIZE: u32 = 15_000_000; // 15MB
 
+/// The header carrying the correlation id of a request.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// The header carrying the idempotency key of a request to the targets.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "x-idempotency-key";
+
+/// The maximum length of a correlation id adopted from a client.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The default message prefix of a PBH transaction validation error.
+pub const DEFAULT_PBH_ERROR_PREFIX: &str = "PBH Transaction Validation Failed";
+
+/// Matches JSON-RPC errors returned by builders when PBH transaction validation fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PbhErrorMatcher {
+    /// The JSON-RPC error code of a PBH error.
+    pub code: i32,
+    /// The prefix of the error message of a PBH error.
+    pub message_prefix: String,
+}
+
+impl PbhErrorMatcher {
+    /// Creates a new [`PbhErrorMatcher`].
+    pub fn new(code: i32, message_prefix: impl Into<String>) -> Self {
+        Self {
+            code,
+            message_prefix: message_prefix.into(),
+        }
+    }
+
+    /// Returns true if the error object is a PBH error.
+    pub fn matches(&self, error: &ErrorObjectOwned) -> bool {
+        error.code() == self.code && error.message().starts_with(&self.message_prefix)
+    }
+}
+
+impl Default for PbhErrorMatcher {
+    fn default() -> Self {
+        Self::new(INTERNAL_ERROR_CODE, DEFAULT_PBH_ERROR_PREFIX)
+    }
+}
+
+/// The default JSON-RPC error code returned when no target responded.
+pub const DEFAULT_UNAVAILABLE_ERROR_CODE: i32 = -32010;
+
+/// The default JSON-RPC error message returned when no target responded.
+pub const DEFAULT_UNAVAILABLE_ERROR_MESSAGE: &str = "Upstream unavailable";
+
+/// The JSON-RPC error returned to the caller when no target responded, so
+/// clients can tell an outage apart from a rejected request and retry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnavailableError {
+    /// The JSON-RPC error code.
+    pub code: i32,
+    /// The JSON-RPC error message.
+    pub message: String,
+    /// The delay sent in a `Retry-After` header, if any.
+    pub retry_after: Option<Duration>,
+}
+
+impl UnavailableError {
+    /// Creates a new [`UnavailableError`].
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Sets the delay clients are asked to wait before retrying with a
+    /// `Retry-After` header. Not sent if `None`, the default.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Returns the error as a JSON-RPC response to the request with the given id.
+    pub fn response(&self, id: serde_json::Value) -> HttpResponse {
+        let error = ErrorObject::owned(self.code, self.message.as_str(), None::<()>);
+        let mut builder = HttpResponse::builder()
+            .status(200)
+            .header("Content-Type", "application/json");
+        if let Some(retry_after) = self.retry_after {
+            builder = builder.header(RETRY_AFTER, retry_after_value(retry_after));
+        }
+        builder
+            .body(HttpBody::from(
+                serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": id }).to_string(),
+            ))
+            .expect("valid response")
+    }
+}
+
+impl Default for UnavailableError {
+    fn default() -> Self {
+        Self::new(
+            DEFAULT_UNAVAILABLE_ERROR_CODE,
+            DEFAULT_UNAVAILABLE_ERROR_MESSAGE,
+        )
+    }
+}
+
+/// Returns a JSON-RPC error response to the request with the given id.
+pub(crate) fn error_response(
+    id: serde_json::Value,
+    code: i32,
+    message: impl Into<String>,
+) -> HttpResponse {
+    let error = ErrorObject::owned(code, message.into(), None::<()>);
+    HttpResponse::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(HttpBody::from(
+            serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": id }).to_string(),
+        ))
+        .expect("valid response")
+}
+
+/// Classification of an upstream response by its HTTP status.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResponseClass {
+    /// A 2xx status.
+    Ok,
+    /// A 401 or 403 status, meaning the target rejected our JWT.
+    AuthFailure,
+    /// A 429 status, with the delay requested by the `Retry-After` header.
+    RateLimited { retry_after: Option<Duration> },
+    /// Any other 4xx status.
+    ClientError,
+    /// A 5xx status.
+    ServerError,
+}
+
+impl ResponseClass {
+    /// Classifies a response from its status and headers.
+    pub fn from_parts(status: StatusCode, headers: &HeaderMap) -> Self {
+        match status {
+            status if status.is_success() => Self::Ok,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::AuthFailure,
+            StatusCode::TOO_MANY_REQUESTS => Self::RateLimited {
+                retry_after: retry_after(headers),
+            },
+            status if status.is_server_error() => Self::ServerError,
+            _ => Self::ClientError,
+        }
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds.
+///
+/// HTTP dates are not supported and are ignored.
+pub fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Returns the value of a `Retry-After` header asking to wait for the given
+/// delay, rounded up to whole seconds.
+pub fn retry_after_value(retry_after: Duration) -> HeaderValue {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HeaderValue::from(secs)
+}
+
 /// Decomposed JSON-RPC request.
 #[derive(Clone, Debug)]
 pub struct RpcRequest {
     pub parts: http::request::Parts,
-    pub body: Vec<u8>,
+    /// The request body, shared by the clones of the request so fanning it
+    /// out to several targets does not copy it.
+    pub body: Bytes,
     pub method: String,
+    /// Correlation id, adopted from the `X-Request-Id` header or generated.
+    pub request_id: String,
+    /// Hash of the method and params, identical for repeated submissions of the same request.
+    pub idempotency_key: B256,
+    /// Whether the request is a notification, which has no id and expects no response.
+    pub is_notification: bool,
 }
 
 impl RpcRequest {
     pub async fn from_request(request: http::Request<HttpBody>) -> Result<Self> {
-        let (parts, body) = request.into_parts();
+        let (mut parts, body) = request.into_parts();
         let (body_bytes, _) =
             http_helpers::read_body(&parts.headers, body, MAX_REQUEST_BODY_SIZE).await?;
-        let method = serde_json::from_slice::<Request>(&body_bytes)?
-            .method
-            .to_string();
+        reserve_buffer(&mut parts, body_bytes.len());
+
+        let (method, is_notification) = match serde_json::from_slice::<Request>(&body_bytes) {
+            Ok(request) => (request.method.to_string(), false),
+            Err(err) => {
+                let notification = serde_json::from_slice::<
+                    Notification<'_, Option<serde_json::Value>>,
+                >(&body_bytes)
+                .map_err(|_| err)?;
+                (notification.method.to_string(), true)
+            }
+        };
+
+        // Forwarded requests carry the id so the next layer adopts it
+        let request_id = request_id(&parts.headers).unwrap_or_else(new_request_id);
+        parts.headers.insert(
+            REQUEST_ID_HEADER,
+            HeaderValue::from_str(&request_id).expect("request id is a valid header value"),
+        );
+
+        let idempotency_key = idempotency_key(&body_bytes);
 
         Ok(Self {
             parts,
-            body: body_bytes,
+            body: body_bytes.into(),
             method,
+            request_id,
+            idempotency_key,
+            is_notification,
         })
     }
+
+    /// Returns the request parsed by an outer layer, or parses it if no layer has.
+    ///
+    /// The parsed request travels in the extensions of the request it was
+    /// converted into, so the body is only read and decoded once per request.
+    pub async fn from_parsed_or_request(request: http::Request<HttpBody>) -> Result<Self> {
+        let (mut parts, body) = request.into_parts();
+        let Some(parsed) = parts.extensions.remove::<RpcRequest>() else {
+            return Self::from_request(http::Request::from_parts(parts, body)).await;
+        };
+
+        // Keep the extensions added by the layers in between
+        reserve_buffer(&mut parts, parsed.body.len());
+        Ok(Self { parts, ..parsed })
+    }
+
+    /// Returns a copy of the request with the method renamed, leaving the id and params untouched.
+    pub fn with_method(&self, method: &str) -> Result<Self> {
+        let mut body =
+            serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(&self.body)?;
+        body.insert("method".to_string(), method.into());
+        let body = serde_json::to_vec(&body)?;
+
+        // The inbound length no longer matches the re-serialized body
+        let mut parts = self.parts.clone();
+        parts
+            .headers
+            .insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+
+        Ok(Self {
+            parts,
+            idempotency_key: idempotency_key(&body),
+            body: body.into(),
+            method: method.to_string(),
+            request_id: self.request_id.clone(),
+            is_notification: self.is_notification,
+        })
+    }
+
+    /// Returns the JSON-RPC id of the request, or null if it has none.
+    pub fn id(&self) -> serde_json::Value {
+        serde_json::from_slice::<serde_json::Value>(&self.body)
+            .ok()
+            .and_then(|mut body| body.get_mut("id").map(serde_json::Value::take))
+            .unwrap_or_default()
+    }
+
+    /// Decodes the sender and nonce of an `eth_sendRawTransaction` request.
+    ///
+    /// Returns `None` for other methods or if the transaction cannot be decoded.
+    pub fn sender_and_nonce(&self) -> Option<(Address, u64)> {
+        let envelope = self.raw_transaction()?;
+        let sender = envelope.recover_signer().ok()?;
+
+        Some((sender, envelope.nonce()))
+    }
+
+    /// Computes the hash of the transaction in an `eth_sendRawTransaction` request.
+    ///
+    /// Returns `None` for other methods or if the transaction cannot be decoded.
+    pub fn tx_hash(&self) -> Option<B256> {
+        self.raw_transaction().map(|envelope| *envelope.tx_hash())
+    }
+
+    /// Returns the max fee per gas of the transaction in an `eth_sendRawTransaction`
+    /// request, its gas price for legacy and EIP-2930 transactions.
+    ///
+    /// Returns `None` for other methods or if the transaction cannot be decoded.
+    pub fn max_fee_per_gas(&self) -> Option<u128> {
+        self.raw_transaction()
+            .map(|envelope| envelope.max_fee_per_gas())
+    }
+
+    /// Decodes the transaction in `params[0]` of an `eth_sendRawTransaction` request.
+    fn raw_transaction(&self) -> Option<TxEnvelope> {
+        if self.method != "eth_sendRawTransaction" {
+            return None;
+        }
+
+        let params = serde_json::from_slice::<Request>(&self.body).ok()?.params?;
+        let (raw,) = serde_json::from_str::<(alloy_primitives::Bytes,)>(params.get()).ok()?;
+        TxEnvelope::decode_2718(&mut raw.as_ref()).ok()
+    }
+}
+
+/// Reserves the body of a request against the buffer budget in its extensions.
+///
+/// The guard travels in the extensions, with the parts cloned into forwarded requests.
+fn reserve_buffer(parts: &mut http::request::Parts, len: usize) {
+    if parts.extensions.get::<Arc<BufferGuard>>().is_none() {
+        if let Some(budget) = parts.extensions.get::<BufferBudget>() {
+            let guard = Arc::new(budget.reserve(len));
+            parts.extensions.insert(guard);
+        }
+    }
+}
+
+/// Returns the client provided correlation id, if it is usable.
+fn request_id(headers: &HeaderMap) -> Option<String> {
+    let id = headers.get(REQUEST_ID_HEADER)?.to_str().ok()?;
+    (!id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN).then(|| id.to_string())
 }
 
+/// Hashes the canonicalized method and params of a request, ignoring its id.
+///
+/// Bodies that are not a single JSON-RPC request are hashed as is.
+fn idempotency_key(body: &[u8]) -> B256 {
+    let Ok(mut request) =
+        serde_json::from_slice::<serde_json::Map<String, serde_json::Value>>(body)
+    else {
+        return keccak256(body);
+    };
+
+    // Object keys are serialized in sorted order, without whitespace
+    let canonical = serde_json::json!({
+        "method": request.remove("method").unwrap_or_default(),
+        "params": request.remove("params").unwrap_or_default(),
+    });
+    keccak256(canonical.to_string())
+}
+
+/// Generates a correlation id unique to this process.
+fn new_request_id() -> String {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    format!("{now:016x}{:08x}", NEXT.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Converts the request back into an HTTP request, carrying the parsed request
+/// in its extensions for [`RpcRequest::from_parsed_or_request`].
 impl From<RpcRequest> for http::Request<HttpBody> {
-    fn from(val: RpcRequest) -> http::Request<HttpBody> {
-        let body = HttpBody::from(val.body);
-        http::Request::from_parts(val.parts, body)
+    fn from(mut val: RpcRequest) -> http::Request<HttpBody> {
+        // The parts travel with the request, so the parsed request keeps none
+        let (empty, _) = http::Request::new(()).into_parts();
+        let mut parts = std::mem::replace(&mut val.parts, empty);
+        let body = HttpBody::from(val.body.clone());
+        parts.extensions.insert(val);
+        http::Request::from_parts(parts, body)
+    }
+}
+
+/// Returned when a target response is not a well-formed JSON-RPC 2.0 response to the request.
+#[derive(Debug)]
+pub struct InvalidResponse {
+    pub reason: String,
+}
+
+impl fmt::Display for InvalidResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid_response: {}", self.reason)
     }
 }
 
+impl std::error::Error for InvalidResponse {}
+
+/// Broad categories of methods, used to route them differently.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum MethodCategory {
+    /// Methods submitting transactions or bundles, such as `eth_sendRawTransaction`.
+    Write,
+    /// Any other method, reading state without changing it.
+    Read,
+}
+
+impl MethodCategory {
+    /// Returns the category of the given method.
+    pub fn of(method: &str) -> Self {
+        if method.starts_with("eth_send") {
+            Self::Write
+        } else {
+            Self::Read
+        }
+    }
+}
+
+/// Methods whose result must be a 32-byte transaction hash.
+const TX_HASH_METHODS: &[&str] = &[
+    "eth_sendRawTransaction",
+    "eth_sendRawTransactionConditional",
+];
+
+/// Checks the result of success responses for methods with a known result shape.
+///
+/// Error responses, and results of other methods, are not checked.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MethodResultValidator;
+
+impl MethodResultValidator {
+    /// Returns an error if the response is a success with a malformed result for the method.
+    pub fn validate<T>(
+        &self,
+        method: &str,
+        response: &RpcResponse<T>,
+    ) -> Result<(), InvalidResponse> {
+        if response.is_error() || !TX_HASH_METHODS.contains(&method) {
+            return Ok(());
+        }
+
+        match response.tx_hash() {
+            Some(_) => Ok(()),
+            None => Err(InvalidResponse {
+                reason: format!("{method} result is not a transaction hash"),
+            }),
+        }
+    }
+}
+
+/// Decomposed JSON-RPC response.
 pub struct RpcResponse<T> {
     pub response: http::Response<T>,
     pub error: Option<ErrorObjectOwned>,
+    /// Buffered copy of the response body, empty if not provided.
+    ///
+    /// Shares its buffer with the body of `response`, so cloning it does not copy.
+    pub body: Bytes,
 }
 
 impl<T> RpcResponse<T> {
     pub fn new(response: http::Response<T>, error: Option<ErrorObjectOwned>) -> Self {
-        Self { response, error }
+        Self {
+            response,
+            error,
+            body: Bytes::new(),
+        }
     }
 
-    pub fn pbh_error(&self) -> bool {
-        if let Some(ref error) = self.error {
-            return error.code() == INTERNAL_ERROR_CODE
+    /// Sets the buffered copy of the response body.
+    pub fn with_body(mut self, body: impl Into<Bytes>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Checks that the buffered body is a complete JSON-RPC 2.0 response
+    /// to the request with the given id.
+    pub fn validate(&self, id: &serde_json::Value) -> Result<(), InvalidResponse> {
+        let invalid = |reason: &str| InvalidResponse {
+            reason: reason.to_string(),
+        };
+
+        let body = self.fields().map_err(|err| InvalidResponse {
+            reason: format!("malformed body: {err}"),
+        })?;
+        // Only the small members are parsed, the result is only checked for presence
+        let field = |name: &str| {
+            body.get(name)
+                .and_then(|raw| serde_json::from_str::<serde_json::Value>(raw.get()).ok())
+        };
+        if field("jsonrpc").as_ref().and_then(|v| v.as_str()) != Some("2.0") {
+            return Err(invalid("missing jsonrpc version 2.0"));
+        }
+
+        let well_formed_error = |error: &serde_json::Value| {
+            error.get("code").is_some_and(|code| code.is_i64())
                 && error
-                    .message()
-                    .starts_with("PBH Transaction Validation Failed");
+                    .get("message")
+                    .is_some_and(|message| message.is_string())
+        };
+        match (body.contains_key("result"), field("error")) {
+            (true, None) => {}
+            (false, Some(error)) if well_formed_error(&error) => {}
+            _ => return Err(invalid("expected either a result or a well-formed error")),
         }
-        false
+
+        if field("id").as_ref() != Some(id) {
+            return Err(invalid("id does not match the request"));
+        }
+
+        Ok(())
     }
 
+    /// Returns the transaction hash in the result of a successful
+    /// `eth_sendRawTransaction` response.
+    pub fn tx_hash(&self) -> Option<B256> {
+        serde_json::from_str(self.raw_result()?.get()).ok()
+    }
+
+    /// Returns the parsed `result` of a successful response.
+    pub fn result(&self) -> Option<serde_json::Value> {
+        serde_json::from_str(self.raw_result()?.get()).ok()
+    }
+
+    /// Returns the `result` of the body without parsing the rest of the response.
+    fn raw_result(&self) -> Option<&RawValue> {
+        self.fields().ok()?.remove("result")
+    }
+
+    /// Splits the body into its members, borrowing their unparsed values.
+    fn fields(&self) -> serde_json::Result<HashMap<String, &RawValue>> {
+        serde_json::from_slice(&self.body)
+    }
+
+    /// Returns true if the response is a PBH transaction validation error.
+    pub fn pbh_error(&self) -> bool {
+        self.pbh_error_with(&PbhErrorMatcher::default())
+    }
+
+    /// Returns true if the response is a PBH transaction validation error
+    /// according to the given [`PbhErrorMatcher`].
+    pub fn pbh_error_with(&self, matcher: &PbhErrorMatcher) -> bool {
+        self.error
+            .as_ref()
+            .is_some_and(|error| matcher.matches(error))
+    }
+
+    /// Returns true if the response carries a JSON-RPC error object, including PBH errors.
+    ///
+    /// Transport failures never produce an [`RpcResponse`], so a response
+    /// without an error object is a successful JSON-RPC result.
     pub fn is_error(&self) -> bool {
         self.error.is_some()
     }
+
+    /// Classifies the response by its HTTP status.
+    pub fn class(&self) -> ResponseClass {
+        ResponseClass::from_parts(self.response.status(), self.response.headers())
+    }
+
+    /// Returns true if the response is a JSON-RPC result delivered with a 2xx status.
+    ///
+    /// Unlike `!is_error()`, this excludes success-shaped bodies returned with an error status.
+    pub fn is_success(&self) -> bool {
+        !self.is_error() && self.class() == ResponseClass::Ok
+    }
 }
 
+/// Parses the error of a JSON-RPC response, skipping over its result.
 pub fn parse_response_payload(body_bytes: &[u8]) -> Result<Option<ErrorObjectOwned>> {
-    let res = serde_json::from_slice::<Response<serde_json::Value>>(body_bytes)?;
+    let res = serde_json::from_slice::<Response<IgnoredAny>>(body_bytes)?;
     let payload = res.payload;
     match payload {
         ResponsePayload::Error(obj) => Ok(Some(obj.into_owned())),
@@ -100,6 +622,77 @@ mod tests {
         Ok(())
     }
 
+    fn rpc_response(body: &'static str) -> RpcResponse<HttpBody> {
+        let error = parse_response_payload(body.as_bytes()).expect("Failed to parse payload");
+        RpcResponse::new(Response::new(HttpBody::from(body)), error)
+    }
+
+    #[test]
+    fn test_pbh_error_predicate() {
+        let pbh = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"PBH Transaction Validation Failed: Invalid calldata encoding"},"id":1}"#,
+        );
+        assert!(pbh.pbh_error());
+        assert!(pbh.is_error());
+
+        // The PBH message must be reported with an internal error code
+        let wrong_code = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"PBH Transaction Validation Failed"},"id":1}"#,
+        );
+        assert!(!wrong_code.pbh_error());
+        assert!(wrong_code.is_error());
+
+        // An internal error without the PBH prefix is not a PBH error
+        let internal = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Internal error"},"id":1}"#,
+        );
+        assert!(!internal.pbh_error());
+        assert!(internal.is_error());
+
+        let success = rpc_response(r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#);
+        assert!(!success.pbh_error());
+        assert!(!success.is_error());
+    }
+
+    #[test]
+    fn test_custom_pbh_error_matcher() {
+        let matcher = PbhErrorMatcher::new(-32000, "PBH rejected");
+
+        let matching = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"PBH rejected: nullifier reused"},"id":1}"#,
+        );
+        assert!(matching.pbh_error_with(&matcher));
+        assert!(!matching.pbh_error());
+
+        let wrong_prefix = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"PBH Transaction Validation Failed"},"id":1}"#,
+        );
+        assert!(!wrong_prefix.pbh_error_with(&matcher));
+
+        let wrong_code = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"PBH rejected: nullifier reused"},"id":1}"#,
+        );
+        assert!(!wrong_code.pbh_error_with(&matcher));
+
+        let success = rpc_response(r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#);
+        assert!(!success.pbh_error_with(&matcher));
+    }
+
+    #[test]
+    fn test_is_error_success_payload() {
+        let response = rpc_response(r#"{"jsonrpc":"2.0","result":"ok","id":1}"#);
+        assert!(!response.is_error());
+    }
+
+    #[test]
+    fn test_is_error_error_payload() {
+        let response = rpc_response(
+            r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1}"#,
+        );
+        assert!(response.is_error());
+        assert!(!response.pbh_error());
+    }
+
     #[tokio::test]
     async fn test_parse_success_response_payload() -> Result<(), BoxError> {
         let http_response = http::Response::builder()
@@ -120,4 +713,281 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_response_class() {
+        let headers = HeaderMap::new();
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::OK, &headers),
+            ResponseClass::Ok
+        );
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::UNAUTHORIZED, &headers),
+            ResponseClass::AuthFailure
+        );
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::FORBIDDEN, &headers),
+            ResponseClass::AuthFailure
+        );
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::BAD_REQUEST, &headers),
+            ResponseClass::ClientError
+        );
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::SERVICE_UNAVAILABLE, &headers),
+            ResponseClass::ServerError
+        );
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::TOO_MANY_REQUESTS, &headers),
+            ResponseClass::RateLimited { retry_after: None }
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "3".parse().unwrap());
+        assert_eq!(
+            ResponseClass::from_parts(StatusCode::TOO_MANY_REQUESTS, &headers),
+            ResponseClass::RateLimited {
+                retry_after: Some(Duration::from_secs(3))
+            }
+        );
+
+        // HTTP dates are ignored
+        headers.insert(
+            RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after(&headers), None);
+
+        // Delays sent to clients round up to whole seconds
+        assert_eq!(retry_after_value(Duration::from_secs(3)), "3");
+        assert_eq!(retry_after_value(Duration::from_millis(2500)), "3");
+    }
+
+    #[test]
+    fn test_unavailable_error_retry_after() {
+        let response = UnavailableError::default().response(serde_json::json!(1));
+        assert!(response.headers().get(RETRY_AFTER).is_none());
+
+        let response = UnavailableError::default()
+            .with_retry_after(Some(Duration::from_secs(5)))
+            .response(serde_json::json!(1));
+        assert_eq!(response.headers()[RETRY_AFTER], "5");
+    }
+
+    #[test]
+    fn test_is_success_requires_2xx_status() {
+        let body = r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#;
+        let ok = rpc_response(body);
+        assert!(ok.is_success());
+
+        let error = parse_response_payload(body.as_bytes()).unwrap();
+        let unavailable = RpcResponse::new(
+            Response::builder()
+                .status(StatusCode::SERVICE_UNAVAILABLE)
+                .body(HttpBody::from(body))
+                .unwrap(),
+            error,
+        );
+        assert!(!unavailable.is_error());
+        assert!(!unavailable.is_success());
+        assert_eq!(unavailable.class(), ResponseClass::ServerError);
+    }
+
+    #[tokio::test]
+    async fn test_with_method() -> Result<()> {
+        let body =
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"],"id":7}"#;
+        let request = http::Request::builder()
+            .header(CONTENT_LENGTH, body.len())
+            .body(HttpBody::from(body))
+            .unwrap();
+        let original = RpcRequest::from_request(request).await?;
+        let request = original.with_method("eth_sendRawTransactionPass")?;
+        assert_eq!(request.method, "eth_sendRawTransactionPass");
+        assert_eq!(
+            request.parts.headers[CONTENT_LENGTH],
+            request.body.len().to_string()
+        );
+        assert_ne!(request.idempotency_key, original.idempotency_key);
+
+        let body = serde_json::from_slice::<serde_json::Value>(&request.body)?;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "jsonrpc": "2.0",
+                "method": "eth_sendRawTransactionPass",
+                "params": ["0x1234"],
+                "id": 7
+            })
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_from_parsed_or_request() -> Result<()> {
+        let body = r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#;
+        let parsed = RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+        let mut request: http::Request<HttpBody> = parsed.clone().into();
+        request.extensions_mut().insert("added by a later layer");
+
+        // The body is not parsed again
+        let request =
+            RpcRequest::from_parsed_or_request(request.map(|_| HttpBody::from("not json"))).await?;
+        assert_eq!(request.method, "eth_chainId");
+        assert_eq!(request.request_id, parsed.request_id);
+        assert_eq!(request.body, parsed.body);
+        assert!(request.parts.extensions.get::<&str>().is_some());
+        assert!(request.parts.extensions.get::<RpcRequest>().is_none());
+
+        // The parsed request is not nested in itself when forwarded again
+        let request: http::Request<HttpBody> = request.into();
+        let forwarded = request.extensions().get::<RpcRequest>().unwrap();
+        assert!(forwarded.parts.extensions.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tx_hash_malformed_params() -> Result<()> {
+        for body in [
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"],"id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["not hex"],"id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":[],"id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","id":1}"#,
+            r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#,
+        ] {
+            let request =
+                RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+            assert_eq!(request.tx_hash(), None, "{body}");
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_id() -> Result<()> {
+        let body = r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":1}"#;
+        let request = http::Request::builder()
+            .header(REQUEST_ID_HEADER, "client-id")
+            .body(HttpBody::from(body))
+            .unwrap();
+        let request = RpcRequest::from_request(request).await?;
+        assert_eq!(request.request_id, "client-id");
+
+        let first = RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+        let second = RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+        assert_ne!(first.request_id, second.request_id);
+        assert_eq!(
+            first.parts.headers.get(REQUEST_ID_HEADER).unwrap(),
+            first.request_id.as_str()
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_notification() -> Result<()> {
+        let body = r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"]}"#;
+        let request = RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+        assert!(request.is_notification);
+        assert_eq!(request.method, "eth_sendRawTransaction");
+
+        let body = r#"{"jsonrpc":"2.0","method":"eth_chainId","params":[],"id":null}"#;
+        let request = RpcRequest::from_request(http::Request::new(HttpBody::from(body))).await?;
+        assert!(!request.is_notification);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_idempotency_key() {
+        let key = idempotency_key(
+            br#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"],"id":1}"#,
+        );
+
+        // Ignores the id, key order and whitespace
+        assert_eq!(
+            key,
+            idempotency_key(
+                br#"{ "id": 2, "params": [ "0x1234" ], "method": "eth_sendRawTransaction", "jsonrpc": "2.0" }"#
+            )
+        );
+        assert_ne!(
+            key,
+            idempotency_key(
+                br#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x5678"],"id":1}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_validate_response() {
+        let validate = |body: &str| {
+            RpcResponse::new(Response::new(()), None)
+                .with_body(body.as_bytes().to_vec())
+                .validate(&serde_json::json!(1))
+        };
+
+        assert!(validate(r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#).is_ok());
+        assert!(
+            validate(
+                r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"nonce too low"},"id":1}"#
+            )
+            .is_ok()
+        );
+
+        // Truncated
+        assert!(validate(r#"{"jsonrpc":"2.0","result":"0x12"#).is_err());
+        // Mismatched id
+        assert!(validate(r#"{"jsonrpc":"2.0","result":"0x1234","id":2}"#).is_err());
+        // Not JSON-RPC 2.0
+        assert!(validate(r#"{"result":"0x1234","id":1}"#).is_err());
+        // Both result and error
+        assert!(
+            validate(r#"{"jsonrpc":"2.0","result":"0x1234","error":{"code":-32000,"message":"x"},"id":1}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_method_result_validator() {
+        let validate = |method: &str, body: &str| {
+            let error = parse_response_payload(body.as_bytes()).unwrap();
+            MethodResultValidator.validate(
+                method,
+                &RpcResponse::new(Response::new(()), error).with_body(body.as_bytes().to_vec()),
+            )
+        };
+        let hash = format!(
+            r#"{{"jsonrpc":"2.0","result":"0x{}","id":1}}"#,
+            "ab".repeat(32)
+        );
+
+        assert!(validate("eth_sendRawTransaction", &hash).is_ok());
+        assert!(validate("eth_sendRawTransactionConditional", &hash).is_ok());
+        assert!(
+            validate(
+                "eth_sendRawTransaction",
+                r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#
+            )
+            .is_err()
+        );
+        assert!(
+            validate(
+                "eth_sendRawTransaction",
+                r#"{"jsonrpc":"2.0","result":null,"id":1}"#
+            )
+            .is_err()
+        );
+        // Errors and other methods are not checked
+        assert!(
+            validate(
+                "eth_sendRawTransaction",
+                r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"nonce too low"},"id":1}"#
+            )
+            .is_ok()
+        );
+        assert!(validate("eth_chainId", r#"{"jsonrpc":"2.0","result":"0x1","id":1}"#).is_ok());
+    }
 }
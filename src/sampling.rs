@@ -0,0 +1,176 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+#[cfg(feature = "cli")]
+use opentelemetry::{
+    Context, KeyValue, Value,
+    trace::{Link, SpanKind, TraceContextExt, TraceId},
+};
+#[cfg(feature = "cli")]
+use opentelemetry_sdk::trace::{Sampler, SamplingDecision, SamplingResult, ShouldSample};
+use tracing::{Span, info_span};
+#[cfg(feature = "cli")]
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+/// The span attribute forcing a span to be sampled regardless of the sample ratio.
+pub const FORCE_SAMPLE_ATTRIBUTE: &str = "sampling.force";
+
+/// Samples a ratio of traces, always sampling the spans created with the
+/// [`FORCE_SAMPLE_ATTRIBUTE`] set.
+///
+/// Spans follow the decision of their parent, so a trace is exported whole or
+/// not at all, and upstreams are told the decision through the traceparent flags.
+#[cfg(feature = "cli")]
+#[derive(Clone, Debug)]
+pub struct RequestSampler {
+    inner: Sampler,
+}
+
+#[cfg(feature = "cli")]
+impl RequestSampler {
+    /// Creates a new [`RequestSampler`] sampling the given ratio of traces.
+    pub fn new(ratio: f64) -> Self {
+        Self {
+            inner: Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(ratio))),
+        }
+    }
+}
+
+#[cfg(feature = "cli")]
+impl ShouldSample for RequestSampler {
+    fn should_sample(
+        &self,
+        parent_context: Option<&Context>,
+        trace_id: TraceId,
+        name: &str,
+        span_kind: &SpanKind,
+        attributes: &[KeyValue],
+        links: &[Link],
+    ) -> SamplingResult {
+        let forced = attributes.iter().any(|attribute| {
+            attribute.key.as_str() == FORCE_SAMPLE_ATTRIBUTE && attribute.value == Value::Bool(true)
+        });
+        if forced {
+            return SamplingResult {
+                decision: SamplingDecision::RecordAndSample,
+                attributes: vec![],
+                trace_state: parent_context
+                    .map(|cx| cx.span().span_context().trace_state().clone())
+                    .unwrap_or_default(),
+            };
+        }
+
+        self.inner
+            .should_sample(parent_context, trace_id, name, span_kind, attributes, links)
+    }
+}
+
+/// Why a request is worth tracing even when its trace was not sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NotableReason {
+    /// A builder rejected the transaction with a PBH error.
+    PbhError,
+    /// A builder failed, or the request could not be answered.
+    UpstreamFailure,
+    /// The request took longer than the latency threshold.
+    Slow,
+}
+
+impl NotableReason {
+    /// Returns the `reason` attribute of the summary span.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PbhError => "pbh_error",
+            Self::UpstreamFailure => "upstream_failure",
+            Self::Slow => "slow",
+        }
+    }
+}
+
+/// Records whether a request is notable while it is handled, keeping the first reason.
+#[derive(Clone, Debug, Default)]
+pub struct Notable(Arc<Mutex<NotableState>>);
+
+#[derive(Debug, Default)]
+struct NotableState {
+    method: String,
+    request_id: String,
+    reason: Option<NotableReason>,
+}
+
+impl Notable {
+    /// Sets the method and correlation id of the request, once parsed.
+    pub fn identify(&self, method: &str, request_id: &str) {
+        let mut state = self.0.lock().unwrap();
+        state.method = method.to_string();
+        state.request_id = request_id.to_string();
+    }
+
+    /// Marks the request as notable, unless it already is.
+    pub fn mark(&self, reason: NotableReason) {
+        self.0.lock().unwrap().reason.get_or_insert(reason);
+    }
+
+    /// Returns why the request is notable, if it is.
+    pub fn reason(&self) -> Option<NotableReason> {
+        self.0.lock().unwrap().reason
+    }
+}
+
+/// Forces notable requests to be traced when head sampling dropped them.
+#[derive(Clone, Debug, Default)]
+pub struct TraceSampling {
+    /// Requests slower than this are notable.
+    pub latency_threshold: Option<Duration>,
+}
+
+impl TraceSampling {
+    /// Creates a new [`TraceSampling`] treating requests slower than `latency_threshold` as notable.
+    pub fn new(latency_threshold: Option<Duration>) -> Self {
+        Self { latency_threshold }
+    }
+
+    /// Emits a summary of the request if it is notable and the request span was
+    /// not sampled. The summary is a new trace, forced to be sampled.
+    pub fn summarize(&self, span: &Span, notable: &Notable, elapsed: Duration) {
+        let slow = self
+            .latency_threshold
+            .is_some_and(|threshold| elapsed > threshold);
+        let Some(reason) = notable.reason().or(slow.then_some(NotableReason::Slow)) else {
+            return;
+        };
+
+        let Some(unsampled_trace_id) = unsampled_trace_id(span) else {
+            return;
+        };
+
+        let state = notable.0.lock().unwrap();
+        let _summary = info_span!(
+            target: "tx-proxy::validation",
+            parent: None,
+            "request_summary",
+            sampling.force = true,
+            reason = reason.as_str(),
+            method = state.method.as_str(),
+            request.id = state.request_id.as_str(),
+            latency_ms = elapsed.as_millis() as u64,
+            unsampled_trace_id = %unsampled_trace_id,
+        );
+    }
+}
+
+/// Returns the trace id of the span, or `None` if its trace was sampled.
+#[cfg(feature = "cli")]
+fn unsampled_trace_id(span: &Span) -> Option<String> {
+    let cx = span.context();
+    let span_context = cx.span().span_context().clone();
+    (!span_context.is_sampled()).then(|| span_context.trace_id().to_string())
+}
+
+/// Without OpenTelemetry no trace is sampled, and spans have no trace id.
+#[cfg(not(feature = "cli"))]
+fn unsampled_trace_id(_span: &Span) -> Option<String> {
+    Some(String::new())
+}
@@ -0,0 +1,250 @@
+use std::{
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use http::{StatusCode, header::RETRY_AFTER};
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::buffer::server_busy_response;
+use crate::fanout::FanoutWrite;
+use crate::metrics::ProxyMetrics;
+use crate::rpc::retry_after_value;
+
+/// The default duration in milliseconds a rate limited request counts its builder as saturated.
+pub const DEFAULT_SATURATION_WINDOW_MS: u64 = 10_000;
+
+/// The delay callers are asked to wait while saturated, if neither configured
+/// nor sent by the builders.
+pub const DEFAULT_SATURATION_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Tracks the percentage of builders that rate limited a request within a
+/// window, see [`TargetRateLimit`](crate::client::TargetRateLimit).
+///
+/// The builders are saturated once the percentage reaches a threshold. While
+/// saturated, callers are asked to back off with a `Retry-After` header and,
+/// if enabled, the same percentage of new requests is shed before being fanned
+/// out. Shedding ramps up with the number of saturated builders and stops once
+/// their rate limits are out of the window.
+#[derive(Debug)]
+pub struct SaturationMonitor {
+    fanout: FanoutWrite,
+    threshold: u8,
+    window: Duration,
+    /// The configured `Retry-After` delay, overriding the delays sent by the builders.
+    retry_after: Option<Duration>,
+    shed: bool,
+    /// Requests received while shedding, to spread the shed requests.
+    overloaded_requests: AtomicU64,
+}
+
+impl SaturationMonitor {
+    /// Creates a new [`SaturationMonitor`] of the builders in `fanout`, saturated
+    /// once `threshold` percent of them are rate limiting requests.
+    pub fn new(fanout: FanoutWrite, threshold: u8) -> Self {
+        Self {
+            fanout,
+            threshold: threshold.clamp(1, 100),
+            window: Duration::from_millis(DEFAULT_SATURATION_WINDOW_MS),
+            retry_after: None,
+            shed: false,
+            overloaded_requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the duration a rate limited request counts its builder as saturated.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the delay callers are asked to wait while saturated. If `None`, the
+    /// default, the longest delay sent by the builders is used.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Sheds new requests while saturated. Disabled by default.
+    pub fn with_shed(mut self, shed: bool) -> Self {
+        self.shed = shed;
+        self
+    }
+
+    /// Returns the percentage of enabled builders that rate limited a request within the window.
+    pub fn saturation(&self) -> u8 {
+        let targets = self.fanout.targets();
+        let (enabled, limited) = targets.iter().filter(|client| client.is_enabled()).fold(
+            (0, 0),
+            |(enabled, limited), client| {
+                let is_limited = client.rate_limit().is_recent(self.window);
+                (enabled + 1, limited + usize::from(is_limited))
+            },
+        );
+        if enabled == 0 {
+            return 0;
+        }
+        (limited * 100 / enabled) as u8
+    }
+
+    /// Returns true if enough builders are rate limiting requests.
+    pub fn is_saturated(&self) -> bool {
+        self.saturation() >= self.threshold
+    }
+
+    /// Returns the delay callers are asked to wait before retrying, if saturated.
+    pub fn retry_after(&self) -> Option<Duration> {
+        if !self.is_saturated() {
+            return None;
+        }
+        let upstream = || {
+            self.fanout
+                .targets()
+                .iter()
+                .filter_map(|client| client.rate_limit().retry_after(self.window))
+                .max()
+        };
+        Some(
+            self.retry_after
+                .or_else(upstream)
+                .unwrap_or(DEFAULT_SATURATION_RETRY_AFTER),
+        )
+    }
+
+    /// Returns the percentage of new requests currently shed, the saturation
+    /// once the threshold is reached if shedding is enabled.
+    pub fn shed_percent(&self) -> u8 {
+        let saturation = self.saturation();
+        if self.shed && saturation >= self.threshold {
+            saturation
+        } else {
+            0
+        }
+    }
+
+    /// Returns true if the next request must be shed while `percent` percent
+    /// of the requests are shed.
+    ///
+    /// Shed requests are spread evenly rather than randomly, as in
+    /// [`LatencyShedder`](crate::shed::LatencyShedder).
+    pub fn should_shed(&self, percent: u8) -> bool {
+        if percent == 0 {
+            return false;
+        }
+        let percent = u64::from(percent);
+        let n = self.overloaded_requests.fetch_add(1, Ordering::Relaxed) % 100;
+        // Requests crossing the next multiple of 100 are shed
+        let shed = (n + 1) * percent / 100 > n * percent / 100;
+        if shed {
+            warn!(target: "tx-proxy::saturation", shed_percent = percent, "Shedding request, builders are rate limiting requests");
+        }
+        shed
+    }
+}
+
+/// A [`Layer`] asking callers to back off with a `Retry-After` header while
+/// the builders are saturated, and shedding a share of new requests with a
+/// server busy error if enabled.
+///
+/// When no [`SaturationMonitor`] is configured requests are passed through untouched.
+#[derive(Clone, Debug)]
+pub struct SaturationLayer {
+    pub monitor: Option<Arc<SaturationMonitor>>,
+    pub metrics: Arc<ProxyMetrics>,
+}
+
+impl SaturationLayer {
+    /// Creates a new [`SaturationLayer`] with the given monitor.
+    pub fn new(monitor: Option<Arc<SaturationMonitor>>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self { monitor, metrics }
+    }
+}
+
+impl<S> Layer<S> for SaturationLayer {
+    type Service = SaturationService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        SaturationService {
+            monitor: self.monitor.clone(),
+            metrics: self.metrics.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SaturationService<S> {
+    monitor: Option<Arc<SaturationMonitor>>,
+    metrics: Arc<ProxyMetrics>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for SaturationService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(monitor) = self.monitor.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        let percent = monitor.shed_percent();
+        self.metrics.record_saturation_shed_percent(percent);
+        if monitor.should_shed(percent) {
+            self.metrics.record_saturation_shed_request();
+            let response = server_busy_response(StatusCode::OK, monitor.retry_after());
+            return Box::pin(async { Ok(response) });
+        }
+
+        let fut = self.inner.call(request);
+        Box::pin(async move {
+            let mut response = fut.await.map_err(Into::into)?;
+            // Reflects the responses of the builders to this request
+            if let Some(retry_after) = monitor.retry_after() {
+                response
+                    .headers_mut()
+                    .entry(RETRY_AFTER)
+                    .or_insert_with(|| retry_after_value(retry_after));
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sheds_percent() {
+        let monitor = SaturationMonitor::new(FanoutWrite::new(vec![]), 50).with_shed(true);
+        assert_eq!(monitor.saturation(), 0);
+        assert_eq!(monitor.shed_percent(), 0);
+        assert_eq!(monitor.retry_after(), None);
+
+        assert_eq!((0..100).filter(|_| monitor.should_shed(0)).count(), 0);
+        assert_eq!((0..100).filter(|_| monitor.should_shed(75)).count(), 75);
+    }
+}
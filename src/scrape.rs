@@ -0,0 +1,212 @@
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Method, header};
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use metrics_exporter_prometheus::PrometheusHandle;
+use tower::{Layer, Service};
+
+#[cfg(feature = "cli")]
+use crate::auth::JwtAuthValidator;
+
+/// The path Prometheus metrics are served on.
+pub const METRICS_PATH: &str = "/metrics";
+
+/// The JSON-RPC method answered with a snapshot of the metrics, see [`metrics_snapshot`].
+pub const METRICS_METHOD: &str = "proxy_metrics";
+
+/// A [`Layer`] that answers `GET /metrics` with the Prometheus metrics, so they
+/// can be scraped from the RPC port instead of a separate metrics listener.
+///
+/// It sits in front of the rest of the middleware, requests for any other path
+/// or method are passed through untouched. Scrapes are only authenticated when
+/// a [`JwtAuthValidator`] is set, which requires the `cli` feature.
+#[derive(Clone, Debug)]
+pub struct ScrapeLayer {
+    pub handle: Option<PrometheusHandle>,
+    #[cfg(feature = "cli")]
+    pub validator: Option<JwtAuthValidator>,
+}
+
+impl ScrapeLayer {
+    /// Creates a new [`ScrapeLayer`], disabled when no handle is given.
+    pub fn new(handle: Option<PrometheusHandle>) -> Self {
+        Self {
+            handle,
+            #[cfg(feature = "cli")]
+            validator: None,
+        }
+    }
+
+    /// Requires scrapes to carry a JWT accepted by the validator.
+    #[cfg(feature = "cli")]
+    pub fn with_validator(mut self, validator: Option<JwtAuthValidator>) -> Self {
+        self.validator = validator;
+        self
+    }
+}
+
+impl<S> Layer<S> for ScrapeLayer {
+    type Service = ScrapeService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        ScrapeService {
+            handle: self.handle.clone(),
+            #[cfg(feature = "cli")]
+            validator: self.validator.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ScrapeService<S> {
+    handle: Option<PrometheusHandle>,
+    #[cfg(feature = "cli")]
+    validator: Option<JwtAuthValidator>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for ScrapeService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let handle = match &self.handle {
+            Some(handle)
+                if request.method() == Method::GET && request.uri().path() == METRICS_PATH =>
+            {
+                handle
+            }
+            _ => {
+                let fut = self.inner.call(request);
+                return Box::pin(async move { fut.await.map_err(Into::into) });
+            }
+        };
+
+        #[cfg(feature = "cli")]
+        if let Some(Err(response)) = self
+            .validator
+            .as_ref()
+            .map(|validator| validator.validate(request.headers()))
+        {
+            return Box::pin(async move { Ok(response) });
+        }
+
+        let response = HttpResponse::builder()
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body(HttpBody::from(handle.render()))
+            .expect("valid response");
+        Box::pin(async move { Ok(response) })
+    }
+}
+
+/// Converts metrics rendered in the Prometheus text format into a JSON object
+/// mapping each metric name to its series, with their labels and value.
+///
+/// Histogram buckets, sums and counts are series of their own, as in the text
+/// format. Values that are not finite are `null`.
+pub fn metrics_snapshot(rendered: &str) -> serde_json::Value {
+    let mut snapshot = serde_json::Map::new();
+    for line in rendered.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let Some((series, value)) = line.rsplit_once(' ') else {
+            continue;
+        };
+        let (name, labels) = match series.split_once('{') {
+            Some((name, labels)) => (name, parse_labels(labels.trim_end_matches('}'))),
+            None => (series, serde_json::Map::new()),
+        };
+        let value = value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(serde_json::Value::Null, serde_json::Value::Number);
+
+        let entry = snapshot
+            .entry(name.trim().to_string())
+            .or_insert_with(|| serde_json::Value::Array(vec![]));
+        if let serde_json::Value::Array(entries) = entry {
+            entries.push(serde_json::json!({ "labels": labels, "value": value }));
+        }
+    }
+    serde_json::Value::Object(snapshot)
+}
+
+/// Parses `name="value"` pairs separated by commas, unescaping the values.
+fn parse_labels(labels: &str) -> serde_json::Map<String, serde_json::Value> {
+    let mut parsed = serde_json::Map::new();
+    let mut chars = labels.chars();
+    loop {
+        let name = chars.by_ref().take_while(|c| *c != '=').collect::<String>();
+        let name = name.trim_matches(|c: char| c == ',' || c.is_whitespace());
+        if name.is_empty() || chars.next() != Some('"') {
+            return parsed;
+        }
+
+        let mut value = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some(c) => value.push(c),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        parsed.insert(name.to_string(), value.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let rendered = r#"
+# TYPE tx_proxy_inbound_requests counter
+tx_proxy_inbound_requests{identity="world-app"} 3
+tx_proxy_inbound_requests{identity="a \"quoted\", name"} 1
+tx_proxy_l2_requests_latency_bucket{le="+Inf"} 2
+tx_proxy_saturation_shed_percent 0
+"#;
+        let snapshot = metrics_snapshot(rendered);
+        assert_eq!(
+            snapshot["tx_proxy_inbound_requests"],
+            serde_json::json!([
+                { "labels": { "identity": "world-app" }, "value": 3.0 },
+                { "labels": { "identity": "a \"quoted\", name" }, "value": 1.0 },
+            ])
+        );
+        assert_eq!(
+            snapshot["tx_proxy_l2_requests_latency_bucket"][0]["labels"]["le"],
+            "+Inf"
+        );
+        assert_eq!(
+            snapshot["tx_proxy_saturation_shed_percent"][0]["value"],
+            0.0
+        );
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tracing::{debug, error, warn};
+
+use crate::metrics::ProxyMetrics;
+
+/// How often the age of the JWT secret files is checked.
+pub const SECRET_AGE_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// The fraction of the maximum age after which a warning is logged.
+const SECRET_AGE_WARN_RATIO: f64 = 0.8;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// A JWT secret read from a file, whose modification time is taken as the
+/// time the secret was issued.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SecretFile {
+    target_group: &'static str,
+    path: PathBuf,
+}
+
+impl SecretFile {
+    /// Creates a new [`SecretFile`] for the secret of `target_group` read from `path`.
+    pub fn new(target_group: &'static str, path: PathBuf) -> Self {
+        Self { target_group, path }
+    }
+
+    /// Returns the target group using the secret, e.g. `builder`.
+    pub fn target_group(&self) -> &'static str {
+        self.target_group
+    }
+
+    /// Returns the path of the secret file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the time elapsed since the secret file was last modified.
+    pub fn age(&self) -> io::Result<Duration> {
+        let modified = std::fs::metadata(&self.path)?.modified()?;
+        Ok(SystemTime::now()
+            .duration_since(modified)
+            .unwrap_or_default())
+    }
+}
+
+/// Returns the whole number of days in `age`.
+pub fn age_days(age: Duration) -> u64 {
+    age.as_secs() / SECS_PER_DAY
+}
+
+/// The outcome of checking the age of a JWT secret.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SecretAge {
+    /// The secret is inline or its file could not be read, so its age is unknown.
+    Unknown,
+    /// The secret is younger than the warning threshold, or no maximum age is set.
+    Fresh,
+    /// The secret is past 80% of the maximum age.
+    Expiring,
+    /// The secret is past the maximum age.
+    Expired,
+}
+
+/// Periodically records the age of the JWT secrets of the target groups to
+/// the `jwt_secret_age_seconds` gauge, warning once a secret file is past 80%
+/// of the maximum age and logging an error once it is past the maximum age.
+///
+/// Secrets passed inline have no issuance time, so their age is unknown and
+/// they are not checked.
+#[derive(Clone, Debug)]
+pub struct SecretAgeMonitor {
+    secrets: Vec<(&'static str, Option<SecretFile>)>,
+    max_age: Option<Duration>,
+    metrics: Arc<ProxyMetrics>,
+}
+
+impl SecretAgeMonitor {
+    /// Creates a new [`SecretAgeMonitor`] without any secrets.
+    pub fn new(max_age: Option<Duration>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self {
+            secrets: Vec::new(),
+            max_age,
+            metrics,
+        }
+    }
+
+    /// Adds the secret of `target_group`, read from `file` or inline if `None`.
+    pub fn with_secret(mut self, target_group: &'static str, file: Option<SecretFile>) -> Self {
+        self.secrets.push((target_group, file));
+        self
+    }
+
+    /// Checks the age of every secret, recording and logging it.
+    pub fn check(&self) -> Vec<(&'static str, SecretAge)> {
+        self.secrets
+            .iter()
+            .map(|(target_group, file)| (*target_group, self.check_secret(target_group, file)))
+            .collect()
+    }
+
+    fn check_secret(&self, target_group: &'static str, file: &Option<SecretFile>) -> SecretAge {
+        let Some(file) = file else {
+            debug!(target: "tx-proxy::secret", target_group, "JWT secret passed inline, its age is unknown");
+            return SecretAge::Unknown;
+        };
+        let age = match file.age() {
+            Ok(age) => age,
+            Err(err) => {
+                warn!(target: "tx-proxy::secret", target_group, path = %file.path.display(), %err, "Failed to read the age of the JWT secret file");
+                return SecretAge::Unknown;
+            }
+        };
+        self.metrics.record_jwt_secret_age(target_group, age);
+
+        let Some(max_age) = self.max_age else {
+            return SecretAge::Fresh;
+        };
+        let days = age_days(age);
+        let max_days = age_days(max_age);
+        if age >= max_age {
+            error!(target: "tx-proxy::secret", target_group, path = %file.path.display(), days, max_days, "JWT secret file is past its maximum age, rotate it");
+            SecretAge::Expired
+        } else if age.as_secs_f64() >= max_age.as_secs_f64() * SECRET_AGE_WARN_RATIO {
+            warn!(target: "tx-proxy::secret", target_group, path = %file.path.display(), days, max_days, "JWT secret file is nearing its maximum age, rotate it soon");
+            SecretAge::Expiring
+        } else {
+            SecretAge::Fresh
+        }
+    }
+
+    /// Checks the age of every secret now and then every `interval`.
+    pub async fn run(self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            self.check();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    const DAY: Duration = Duration::from_secs(SECS_PER_DAY);
+
+    /// Writes a secret file last modified `age` ago.
+    fn secret_file(name: &str, age: Duration) -> SecretFile {
+        let path =
+            std::env::temp_dir().join(format!("tx-proxy-secret-{name}-{}.hex", std::process::id()));
+        std::fs::write(&path, "00".repeat(32)).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+        SecretFile::new("builder", path)
+    }
+
+    #[test]
+    fn test_secret_age() {
+        let recorder = metrics_exporter_prometheus::PrometheusBuilder::new().build_recorder();
+        let handle = recorder.handle();
+        let _guard = metrics::set_default_local_recorder(&recorder);
+
+        let fresh = secret_file("fresh", 10 * DAY);
+        let expiring = secret_file("expiring", 94 * DAY);
+        let expired = secret_file("expired", 120 * DAY);
+        let metrics = Arc::new(ProxyMetrics::default());
+        let check = |file: &SecretFile| {
+            SecretAgeMonitor::new(Some(100 * DAY), metrics.clone())
+                .with_secret(file.target_group(), Some(file.clone()))
+                .check()[0]
+                .1
+        };
+        assert_eq!(check(&fresh), SecretAge::Fresh);
+        assert_eq!(check(&expired), SecretAge::Expired);
+        assert_eq!(check(&expiring), SecretAge::Expiring);
+
+        // The last recorded age is reported
+        let rendered = handle.render();
+        let age = rendered
+            .lines()
+            .find_map(|line| line.strip_prefix("jwt_secret_age_seconds{target_group=\"builder\"} "))
+            .unwrap_or_else(|| panic!("{rendered}"));
+        assert_eq!(age_days(Duration::from_secs_f64(age.parse().unwrap())), 94);
+
+        // Inline secrets are not checked
+        let monitor = SecretAgeMonitor::new(Some(DAY), metrics).with_secret("l2", None);
+        assert_eq!(monitor.check(), vec![("l2", SecretAge::Unknown)]);
+        assert!(!handle.render().contains("l2"));
+
+        for file in [fresh, expiring, expired] {
+            std::fs::remove_file(file.path()).unwrap();
+        }
+    }
+}
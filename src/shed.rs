@@ -0,0 +1,230 @@
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use http::StatusCode;
+use jsonrpsee::{
+    core::BoxError,
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+};
+use tower::{Layer, Service};
+use tracing::warn;
+
+use crate::buffer::server_busy_response;
+use crate::metrics::ProxyMetrics;
+
+/// The default percentage of requests shed while the latency is above the threshold.
+pub const DEFAULT_SHED_PERCENT: u8 = 50;
+
+/// The default duration in milliseconds the P99 latency is computed over.
+pub const DEFAULT_SHED_WINDOW_MS: u64 = 10_000;
+
+/// The maximum number of latencies kept, the oldest are dropped beyond it.
+const MAX_SAMPLES: usize = 1000;
+
+/// Tracks the P99 latency of recent requests, shedding a share of new requests
+/// while it is above a threshold.
+///
+/// Shed requests are spread evenly rather than randomly, as in
+/// [`BuilderSplit`](crate::split::BuilderSplit). Latencies expire after the
+/// window, so shedding stops once slow requests are no longer recent.
+#[derive(Debug)]
+pub struct LatencyShedder {
+    threshold: Duration,
+    window: Duration,
+    percent: u8,
+    /// When each recent request completed and how long it took.
+    samples: Mutex<VecDeque<(Instant, Duration)>>,
+    /// Requests received while overloaded, to spread the shed requests.
+    overloaded_requests: AtomicU64,
+}
+
+impl LatencyShedder {
+    /// Creates a new [`LatencyShedder`] shedding requests while the P99 latency
+    /// is above `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            window: Duration::from_millis(DEFAULT_SHED_WINDOW_MS),
+            percent: DEFAULT_SHED_PERCENT,
+            samples: Mutex::new(VecDeque::new()),
+            overloaded_requests: AtomicU64::new(0),
+        }
+    }
+
+    /// Sets the duration the P99 latency is computed over.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the percentage of requests shed while overloaded, capped at 100.
+    pub fn with_percent(mut self, percent: u8) -> Self {
+        self.percent = percent.min(100);
+        self
+    }
+
+    /// Records the latency of a completed request.
+    pub fn record(&self, latency: Duration) {
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), latency));
+    }
+
+    /// Returns the P99 latency of the requests completed within the window, if any.
+    pub fn p99(&self) -> Option<Duration> {
+        let mut samples = self.samples.lock().unwrap();
+        while samples
+            .front()
+            .is_some_and(|(at, _)| at.elapsed() > self.window)
+        {
+            samples.pop_front();
+        }
+        let mut latencies = samples
+            .iter()
+            .map(|(_, latency)| *latency)
+            .collect::<Vec<_>>();
+        if latencies.is_empty() {
+            return None;
+        }
+        let index = (latencies.len() * 99).div_ceil(100) - 1;
+        Some(*latencies.select_nth_unstable(index).1)
+    }
+
+    /// Returns true if the next request must be shed.
+    pub fn should_shed(&self) -> bool {
+        let Some(p99) = self.p99().filter(|p99| *p99 > self.threshold) else {
+            return false;
+        };
+        let percent = u64::from(self.percent);
+        let n = self.overloaded_requests.fetch_add(1, Ordering::Relaxed) % 100;
+        // Requests crossing the next multiple of 100 are shed
+        let shed = (n + 1) * percent / 100 > n * percent / 100;
+        if shed {
+            warn!(target: "tx-proxy::shed", p99_ms = p99.as_millis() as u64, threshold_ms = self.threshold.as_millis() as u64, "Shedding request, latency above threshold");
+        }
+        shed
+    }
+}
+
+/// A [`Layer`] that rejects a share of new requests with a 503 status while the
+/// P99 latency of the requests it passes on is above a threshold.
+///
+/// When no [`LatencyShedder`] is configured requests are passed through untouched.
+#[derive(Clone, Debug)]
+pub struct LatencyShedLayer {
+    pub shedder: Option<Arc<LatencyShedder>>,
+    pub metrics: Arc<ProxyMetrics>,
+    /// The delay sent in a `Retry-After` header with shed requests, if any.
+    pub retry_after: Option<Duration>,
+}
+
+impl LatencyShedLayer {
+    /// Creates a new [`LatencyShedLayer`] with the given shedder.
+    pub fn new(shedder: Option<Arc<LatencyShedder>>, metrics: Arc<ProxyMetrics>) -> Self {
+        Self {
+            shedder,
+            metrics,
+            retry_after: None,
+        }
+    }
+
+    /// Sets the delay clients are asked to wait before retrying a shed request
+    /// with a `Retry-After` header. Not sent if `None`, the default.
+    pub fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+}
+
+impl<S> Layer<S> for LatencyShedLayer {
+    type Service = LatencyShedService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        LatencyShedService {
+            shedder: self.shedder.clone(),
+            metrics: self.metrics.clone(),
+            retry_after: self.retry_after,
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LatencyShedService<S> {
+    shedder: Option<Arc<LatencyShedder>>,
+    metrics: Arc<ProxyMetrics>,
+    retry_after: Option<Duration>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for LatencyShedService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(shedder) = self.shedder.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        if shedder.should_shed() {
+            self.metrics.record_latency_shed_request();
+            let response = server_busy_response(StatusCode::SERVICE_UNAVAILABLE, self.retry_after);
+            return Box::pin(async { Ok(response) });
+        }
+
+        let start = Instant::now();
+        let fut = self.inner.call(request);
+        Box::pin(async move {
+            let result = fut.await.map_err(Into::into);
+            shedder.record(start.elapsed());
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_p99() {
+        let shedder = LatencyShedder::new(Duration::from_millis(100));
+        assert_eq!(shedder.p99(), None);
+
+        for ms in 1..=200 {
+            shedder.record(Duration::from_millis(ms));
+        }
+        assert_eq!(shedder.p99(), Some(Duration::from_millis(198)));
+    }
+
+    #[test]
+    fn test_sheds_percent_while_overloaded() {
+        let shedder = LatencyShedder::new(Duration::from_millis(100)).with_percent(25);
+        assert_eq!((0..100).filter(|_| shedder.should_shed()).count(), 0);
+
+        shedder.record(Duration::from_millis(150));
+        assert_eq!((0..100).filter(|_| shedder.should_shed()).count(), 25);
+    }
+}
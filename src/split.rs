@@ -0,0 +1,127 @@
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, AtomicU64, Ordering},
+    },
+};
+
+use crate::fanout::FanoutWrite;
+
+/// The weight sending all requests to the new builders.
+pub const MAX_SPLIT_WEIGHT: u8 = 100;
+
+/// Returned when a split weight above [`MAX_SPLIT_WEIGHT`] is set.
+#[derive(Debug)]
+pub struct InvalidSplitWeight(pub u8);
+
+impl fmt::Display for InvalidSplitWeight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid_split_weight: {} is above {MAX_SPLIT_WEIGHT}",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for InvalidSplitWeight {}
+
+/// Which side of a [`BuilderSplit`] a request is routed to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitSide {
+    /// The builders configured with `--builder-urls`.
+    Current,
+    /// The builders being cut over to.
+    New,
+}
+
+impl SplitSide {
+    /// Returns the `split` label of the side in metrics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::New => "new",
+        }
+    }
+}
+
+/// Splits builder traffic between the current builders and new ones, for a
+/// gradual cutover.
+///
+/// The weight is the percentage of requests sent to the new builders. Requests
+/// are spread evenly rather than randomly, so out of every 100 requests exactly
+/// `weight` are sent to the new builders. Clones share the same weight, which
+/// can be changed while requests are in flight.
+#[derive(Clone, Debug)]
+pub struct BuilderSplit {
+    fanout: FanoutWrite,
+    weight: Arc<AtomicU8>,
+    requests: Arc<AtomicU64>,
+}
+
+impl BuilderSplit {
+    /// Creates a new [`BuilderSplit`] sending `weight` percent of requests to `fanout`.
+    pub fn new(fanout: FanoutWrite, weight: u8) -> Result<Self, InvalidSplitWeight> {
+        let split = Self {
+            fanout,
+            weight: Arc::new(AtomicU8::new(0)),
+            requests: Arc::new(AtomicU64::new(0)),
+        };
+        split.set_weight(weight)?;
+        Ok(split)
+    }
+
+    /// Returns the fanout of the new builders.
+    pub fn fanout(&self) -> &FanoutWrite {
+        &self.fanout
+    }
+
+    /// Returns the percentage of requests sent to the new builders.
+    pub fn weight(&self) -> u8 {
+        self.weight.load(Ordering::Relaxed)
+    }
+
+    /// Sets the percentage of requests sent to the new builders.
+    pub fn set_weight(&self, weight: u8) -> Result<(), InvalidSplitWeight> {
+        if weight > MAX_SPLIT_WEIGHT {
+            return Err(InvalidSplitWeight(weight));
+        }
+        self.weight.store(weight, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns the side the next request is routed to.
+    pub fn next_side(&self) -> SplitSide {
+        let weight = u64::from(self.weight());
+        let max = u64::from(MAX_SPLIT_WEIGHT);
+        let n = self.requests.fetch_add(1, Ordering::Relaxed) % max;
+        // Requests crossing the next multiple of the maximum go to the new builders
+        if (n + 1) * weight / max > n * weight / max {
+            SplitSide::New
+        } else {
+            SplitSide::Current
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routed_to_new(weight: u8) -> usize {
+        let split = BuilderSplit::new(FanoutWrite::new(vec![]), weight).unwrap();
+        (0..200)
+            .filter(|_| split.next_side() == SplitSide::New)
+            .count()
+    }
+
+    #[test]
+    fn test_split_weight() {
+        assert_eq!(routed_to_new(0), 0);
+        assert_eq!(routed_to_new(10), 20);
+        assert_eq!(routed_to_new(50), 100);
+        assert_eq!(routed_to_new(100), 200);
+        assert!(BuilderSplit::new(FanoutWrite::new(vec![]), 101).is_err());
+    }
+}
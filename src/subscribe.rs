@@ -0,0 +1,289 @@
+use std::{
+    convert::Infallible,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use alloy_rpc_types_engine::{Claims, JwtSecret};
+use futures::{StreamExt, stream};
+use http::{HeaderMap, HeaderValue, header};
+use http_body_util::StreamBody;
+use hyper::body::{Bytes, Frame};
+use jsonrpsee::{
+    core::{
+        BoxError,
+        client::{Subscription, SubscriptionClientT, SubscriptionKind},
+    },
+    http_client::{HttpBody, HttpRequest, HttpResponse},
+    rpc_params,
+    types::{
+        Request,
+        error::{INTERNAL_ERROR_CODE, INVALID_PARAMS_CODE},
+    },
+    ws_client::{PingConfig, WsClient, WsClientBuilder},
+};
+use tower::{Layer, Service};
+use tracing::{debug, error, warn};
+
+use crate::rpc::{RpcRequest, error_response};
+
+/// The subscription kinds bridged to the upstream WebSocket backend.
+pub const SUPPORTED_SUBSCRIPTIONS: &[&str] = &["newHeads"];
+
+/// The default delay in milliseconds before resubscribing to the backend
+/// after it dropped a subscription.
+pub const DEFAULT_WS_RECONNECT_BACKOFF_MS: u64 = 1000;
+
+/// The WebSocket backend `eth_subscribe` requests are bridged to.
+#[derive(Clone, Debug)]
+pub struct SubscribeBackend {
+    url: String,
+    secret: Option<JwtSecret>,
+    /// Interval of the WebSocket pings keeping idle connections alive, if any.
+    ping_interval: Option<Duration>,
+    /// Delay before resubscribing after the backend dropped a subscription.
+    reconnect_backoff: Duration,
+}
+
+impl SubscribeBackend {
+    /// Creates a new [`SubscribeBackend`] for the given WebSocket URL.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret: None,
+            ping_interval: None,
+            reconnect_backoff: Duration::from_millis(DEFAULT_WS_RECONNECT_BACKOFF_MS),
+        }
+    }
+
+    /// Sets the JWT secret used to authenticate to the backend.
+    pub fn with_jwt_secret(mut self, secret: Option<JwtSecret>) -> Self {
+        self.secret = secret;
+        self
+    }
+
+    /// Pings the backend every `interval`, so intermediaries do not drop idle
+    /// connections. A connection that received nothing for twice the interval
+    /// is considered dropped. No pings are sent if `None`, the default.
+    pub fn with_ping_interval(mut self, interval: Option<Duration>) -> Self {
+        self.ping_interval = interval;
+        self
+    }
+
+    /// Sets the delay before resubscribing after the backend dropped a
+    /// subscription, and between failed attempts.
+    pub fn with_reconnect_backoff(mut self, backoff: Duration) -> Self {
+        self.reconnect_backoff = backoff;
+        self
+    }
+
+    /// Connects to the backend and subscribes to the given kind.
+    async fn subscribe(
+        &self,
+        kind: &str,
+    ) -> Result<(WsClient, Subscription<serde_json::Value>), BoxError> {
+        let mut headers = HeaderMap::new();
+        if let Some(secret) = &self.secret {
+            let iat = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            let jwt = secret.encode(&Claims { iat, exp: None })?;
+            headers.insert(
+                header::AUTHORIZATION,
+                HeaderValue::from_str(&format!("Bearer {jwt}"))?,
+            );
+        }
+
+        let mut builder = WsClientBuilder::default().set_headers(headers);
+        if let Some(interval) = self.ping_interval {
+            builder = builder.enable_ws_ping(
+                PingConfig::new()
+                    .ping_interval(interval)
+                    .inactive_limit(interval * 2),
+            );
+        }
+        let client = builder.build(&self.url).await?;
+        let subscription = client
+            .subscribe("eth_subscribe", rpc_params![kind], "eth_unsubscribe")
+            .await?;
+        Ok((client, subscription))
+    }
+
+    /// Subscribes to the given kind again, waiting for the reconnect backoff
+    /// before each attempt until one succeeds.
+    async fn resubscribe(&self, kind: &str) -> (WsClient, Subscription<serde_json::Value>) {
+        loop {
+            tokio::time::sleep(self.reconnect_backoff).await;
+            match self.subscribe(kind).await {
+                Ok(subscribed) => return subscribed,
+                Err(err) => {
+                    warn!(target: "tx-proxy::subscribe", %err, %kind, "Failed to resubscribe to backend")
+                }
+            }
+        }
+    }
+}
+
+/// A [`Layer`] that answers `eth_subscribe` requests with a stream of
+/// Server-Sent Events bridged from a WebSocket backend, for clients that can
+/// only use HTTP.
+///
+/// When no [`SubscribeBackend`] is configured requests are passed through untouched.
+pub struct SubscribeLayer {
+    pub backend: Option<Arc<SubscribeBackend>>,
+}
+
+impl SubscribeLayer {
+    /// Creates a new [`SubscribeLayer`] with the given backend.
+    pub fn new(backend: Option<Arc<SubscribeBackend>>) -> Self {
+        Self { backend }
+    }
+}
+
+impl<S> Layer<S> for SubscribeLayer {
+    type Service = SubscribeService<S>;
+    fn layer(&self, inner: S) -> Self::Service {
+        SubscribeService {
+            backend: self.backend.clone(),
+            inner,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct SubscribeService<S> {
+    backend: Option<Arc<SubscribeBackend>>,
+    inner: S,
+}
+
+impl<S> Service<HttpRequest<HttpBody>> for SubscribeService<S>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse> + Send + Sync + Clone + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Response: 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Future: Send + 'static,
+    <S as Service<HttpRequest<HttpBody>>>::Error: Into<BoxError> + Send,
+{
+    type Response = HttpResponse;
+    type Error = BoxError;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
+        let Some(backend) = self.backend.clone() else {
+            let fut = self.inner.call(request);
+            return Box::pin(async move { fut.await.map_err(Into::into) });
+        };
+
+        let mut service = self.clone();
+        service.inner = std::mem::replace(&mut self.inner, service.inner);
+
+        let fut = async move {
+            let rpc_request = RpcRequest::from_parsed_or_request(request).await?;
+            if rpc_request.method != "eth_subscribe" {
+                return service
+                    .inner
+                    .call(rpc_request.into())
+                    .await
+                    .map_err(Into::into);
+            }
+
+            let id = rpc_request.id();
+            let Some(kind) = subscription_kind(&rpc_request.body)
+                .filter(|kind| SUPPORTED_SUBSCRIPTIONS.contains(&kind.as_str()))
+            else {
+                return Ok(error_response(
+                    id,
+                    INVALID_PARAMS_CODE,
+                    format!(
+                        "Unsupported subscription, expected one of {}",
+                        SUPPORTED_SUBSCRIPTIONS.join(", ")
+                    ),
+                ));
+            };
+
+            let (client, subscription) = match backend.subscribe(&kind).await {
+                Ok(subscribed) => subscribed,
+                Err(err) => {
+                    error!(target: "tx-proxy::subscribe", %err, %kind, "Failed to subscribe to backend");
+                    return Ok(error_response(
+                        id,
+                        INTERNAL_ERROR_CODE,
+                        "Failed to subscribe to backend",
+                    ));
+                }
+            };
+            debug!(target: "tx-proxy::subscribe", %kind, request.id = %rpc_request.request_id, "bridging subscription");
+
+            Ok(sse_response(backend, kind, client, subscription))
+        };
+
+        Box::pin(fut)
+    }
+}
+
+/// Returns the subscription kind, the first param of an `eth_subscribe` request.
+fn subscription_kind(body: &[u8]) -> Option<String> {
+    let params = serde_json::from_slice::<Request>(body).ok()?.params?;
+    let params = serde_json::from_str::<Vec<serde_json::Value>>(params.get()).ok()?;
+    params.first()?.as_str().map(str::to_string)
+}
+
+/// Streams each notification as an `eth_subscription` event until the client
+/// disconnects. If the backend drops the subscription, e.g. because its
+/// connection was closed, it is resubscribed under the same subscription id.
+fn sse_response(
+    backend: Arc<SubscribeBackend>,
+    kind: String,
+    client: WsClient,
+    subscription: Subscription<serde_json::Value>,
+) -> HttpResponse {
+    let subscription_id = match subscription.kind() {
+        SubscriptionKind::Subscription(id) => serde_json::to_value(id).unwrap_or_default(),
+        SubscriptionKind::Method(_) => serde_json::Value::Null,
+    };
+
+    // The client is moved into the stream so the connection lives as long as the response
+    let events = stream::unfold(
+        (client, subscription),
+        move |(mut client, mut subscription)| {
+            let backend = backend.clone();
+            let kind = kind.clone();
+            async move {
+                loop {
+                    match subscription.next().await {
+                        Some(Ok(result)) => return Some((result, (client, subscription))),
+                        Some(Err(err)) => {
+                            error!(target: "tx-proxy::subscribe", %err, "Invalid notification from backend");
+                            return None;
+                        }
+                        None => {
+                            warn!(target: "tx-proxy::subscribe", %kind, "Backend dropped the subscription, resubscribing");
+                            (client, subscription) = backend.resubscribe(&kind).await;
+                        }
+                    }
+                }
+            }
+        },
+    )
+    .map(move |result| {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "eth_subscription",
+            "params": { "subscription": subscription_id, "result": result },
+        });
+        Ok::<_, Infallible>(Frame::data(Bytes::from(format!(
+            "data: {notification}\n\n"
+        ))))
+    });
+
+    HttpResponse::builder()
+        .status(200)
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(HttpBody::new(StreamBody::new(events)))
+        .expect("valid response")
+}
@@ -0,0 +1,246 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    sync::{Arc, OnceLock},
+    task::{Context, Poll, ready},
+    time::{Duration, Instant},
+};
+
+use http::Uri;
+use hyper::{
+    body::{Body, Frame, SizeHint},
+    rt::{Read, ReadBufCursor, Write},
+};
+use hyper_util::client::legacy::connect::{Connected, Connection};
+use pin_project::pin_project;
+use tower::Service;
+use tracing::Span;
+
+/// The default duration above which a request to a target is logged with its timeline.
+pub const DEFAULT_SLOW_REQUEST_MS: u64 = 500;
+
+/// When a connection to a target was established and how long it took,
+/// attached to the extensions of every response received on it.
+#[derive(Clone, Copy, Debug)]
+pub struct ConnectTiming {
+    pub established_at: Instant,
+    pub duration: Duration,
+}
+
+/// Wraps a connector, timing the connections it establishes.
+#[derive(Clone, Debug)]
+pub struct TimedConnector<C>(pub C);
+
+impl<C> Service<Uri> for TimedConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = TimedConnection<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.0.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let start = Instant::now();
+        let connecting = self.0.call(uri);
+        Box::pin(async move {
+            let io = connecting.await?;
+            Ok(TimedConnection {
+                io,
+                timing: ConnectTiming {
+                    established_at: Instant::now(),
+                    duration: start.elapsed(),
+                },
+            })
+        })
+    }
+}
+
+/// A connection established by a [`TimedConnector`].
+#[pin_project]
+pub struct TimedConnection<T> {
+    #[pin]
+    io: T,
+    timing: ConnectTiming,
+}
+
+impl<T: Connection> Connection for TimedConnection<T> {
+    fn connected(&self) -> Connected {
+        self.io.connected().extra(self.timing)
+    }
+}
+
+impl<T: Read> Read for TimedConnection<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: ReadBufCursor<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.project().io.poll_read(cx, buf)
+    }
+}
+
+impl<T: Write> Write for TimedConnection<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.project().io.poll_shutdown(cx)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.io.is_write_vectored()
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.project().io.poll_write_vectored(cx, bufs)
+    }
+}
+
+/// Wraps a request body, recording when its last frame was handed to the connection.
+#[pin_project]
+pub struct TimedBody<B> {
+    #[pin]
+    inner: B,
+    written: Arc<OnceLock<Instant>>,
+}
+
+impl<B> TimedBody<B> {
+    /// Wraps the body, setting `written` once it was fully read.
+    pub fn new(inner: B, written: Arc<OnceLock<Instant>>) -> Self {
+        Self { inner, written }
+    }
+}
+
+impl<B: Body> Body for TimedBody<B> {
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let mut this = self.project();
+        let frame = ready!(this.inner.as_mut().poll_frame(cx));
+        if frame.is_none() || this.inner.is_end_stream() {
+            let _ = this.written.set(Instant::now());
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// When each phase of a request to a target completed, relative to its start.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RequestTimeline {
+    /// The client was ready to send the request.
+    pub ready: Option<Duration>,
+    /// The time taken to connect, `None` if a pooled connection was reused.
+    pub connect: Option<Duration>,
+    /// The request body was handed to the connection.
+    pub written: Option<Duration>,
+    /// The response headers were received.
+    pub first_byte: Option<Duration>,
+    /// The response body was received.
+    pub body_complete: Option<Duration>,
+    /// The request completed, successfully or not.
+    pub total: Duration,
+}
+
+impl RequestTimeline {
+    /// Returns the time spent waiting for the client to be ready.
+    pub fn ready_phase(&self) -> Option<Duration> {
+        self.ready
+    }
+
+    /// Returns the time spent writing the request, excluding connecting.
+    pub fn write_phase(&self) -> Option<Duration> {
+        let written = self.written?;
+        Some(written.saturating_sub(self.ready? + self.connect.unwrap_or_default()))
+    }
+
+    /// Returns the time between writing the request and receiving the response headers.
+    pub fn ttfb_phase(&self) -> Option<Duration> {
+        Some(
+            self.first_byte?
+                .saturating_sub(self.written.or(self.ready)?),
+        )
+    }
+
+    /// Returns the time spent receiving the response body.
+    pub fn body_phase(&self) -> Option<Duration> {
+        Some(self.body_complete?.saturating_sub(self.first_byte?))
+    }
+
+    /// Records the phase durations on the `timeline.*` fields of the span.
+    pub fn record(&self, span: &Span) {
+        span.record("timeline.ready_ms", millis(self.ready_phase()));
+        span.record("timeline.connect_ms", millis(self.connect));
+        span.record("timeline.write_ms", millis(self.write_phase()));
+        span.record("timeline.ttfb_ms", millis(self.ttfb_phase()));
+        span.record("timeline.body_ms", millis(self.body_phase()));
+        span.record("timeline.total_ms", self.total.as_millis() as u64);
+    }
+}
+
+/// Returns the duration in milliseconds, if any.
+pub fn millis(duration: Option<Duration>) -> Option<u64> {
+    duration.map(|duration| duration.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeline_phases() {
+        let ms = Duration::from_millis;
+        let timeline = RequestTimeline {
+            ready: Some(ms(1)),
+            connect: Some(ms(10)),
+            written: Some(ms(12)),
+            first_byte: Some(ms(312)),
+            body_complete: Some(ms(315)),
+            total: ms(316),
+        };
+        assert_eq!(timeline.ready_phase(), Some(ms(1)));
+        assert_eq!(timeline.write_phase(), Some(ms(1)));
+        assert_eq!(timeline.ttfb_phase(), Some(ms(300)));
+        assert_eq!(timeline.body_phase(), Some(ms(3)));
+
+        // A request failing before the response has no later phases
+        let failed = RequestTimeline {
+            ready: Some(ms(1)),
+            total: ms(1000),
+            ..Default::default()
+        };
+        assert_eq!(failed.write_phase(), None);
+        assert_eq!(failed.ttfb_phase(), None);
+        assert_eq!(failed.body_phase(), None);
+    }
+}
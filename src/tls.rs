@@ -0,0 +1,142 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+
+use rustls::{
+    ClientConfig, RootCertStore,
+    pki_types::{CertificateDer, pem::PemObject},
+};
+use tracing::warn;
+
+/// The root certificates trusted to authenticate targets over TLS.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TlsRoots {
+    /// The certificates of the platform's native store.
+    #[default]
+    Native,
+    /// The Mozilla root certificates compiled into the binary.
+    WebPki,
+    /// Only the certificates in the given PEM bundle.
+    CaFile(PathBuf),
+}
+
+impl TlsRoots {
+    /// Loads the root certificates, building a TLS configuration shared by the
+    /// clients of every target.
+    pub fn load(&self) -> Result<TlsConfig, TlsRootsError> {
+        let mut roots = RootCertStore::empty();
+        match self {
+            Self::Native => {
+                let certs = rustls_native_certs::load_native_certs().certs;
+                let (added, _) = roots.add_parsable_certificates(certs);
+                if added == 0 {
+                    return Err(TlsRootsError::NoNativeRoots);
+                }
+            }
+            Self::WebPki => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+            Self::CaFile(path) => {
+                for cert in read_pem_certs(path)? {
+                    roots.add(cert).map_err(|err| TlsRootsError::CaFile {
+                        path: path.clone(),
+                        reason: err.to_string(),
+                    })?;
+                }
+            }
+        }
+
+        let config = ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        Ok(TlsConfig {
+            roots: self.clone(),
+            config: Arc::new(config),
+        })
+    }
+}
+
+/// Reads the certificates of a PEM bundle, failing if it has none.
+fn read_pem_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsRootsError> {
+    let invalid = |reason: String| TlsRootsError::CaFile {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(|err| invalid(err.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| invalid(err.to_string()))?;
+    if certs.is_empty() {
+        return Err(invalid("no certificates found".to_string()));
+    }
+    Ok(certs)
+}
+
+/// Returned when the root certificates trusted to authenticate targets cannot be loaded.
+#[derive(Debug)]
+pub enum TlsRootsError {
+    /// The platform has no native certificate store, as in distroless images.
+    NoNativeRoots,
+    /// The CA bundle could not be read or holds no valid certificate.
+    CaFile { path: PathBuf, reason: String },
+}
+
+impl fmt::Display for TlsRootsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoNativeRoots => write!(
+                f,
+                "no native root CA certificates found, provide a CA bundle or use the bundled webpki roots"
+            ),
+            Self::CaFile { path, reason } => {
+                write!(f, "invalid CA file {}: {reason}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TlsRootsError {}
+
+/// A TLS configuration shared by the clients of a set of targets.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    roots: TlsRoots,
+    config: Arc<ClientConfig>,
+}
+
+impl TlsConfig {
+    /// Returns the root certificates the configuration was loaded from.
+    pub fn roots(&self) -> &TlsRoots {
+        &self.roots
+    }
+
+    /// Returns the rustls client configuration.
+    pub fn client_config(&self) -> &ClientConfig {
+        &self.config
+    }
+}
+
+impl Default for TlsConfig {
+    /// Returns the configuration trusting the native roots, loaded once per process.
+    ///
+    /// Without a native store no root is trusted, so requests to TLS targets fail
+    /// rather than the process panicking.
+    fn default() -> Self {
+        static NATIVE: OnceLock<TlsConfig> = OnceLock::new();
+        NATIVE
+            .get_or_init(|| {
+                TlsRoots::Native.load().unwrap_or_else(|err| {
+                    warn!(target: "tx-proxy::tls", %err, "Trusting no root certificates");
+                    TlsConfig {
+                        roots: TlsRoots::Native,
+                        config: Arc::new(
+                            ClientConfig::builder()
+                                .with_root_certificates(RootCertStore::empty())
+                                .with_no_client_auth(),
+                        ),
+                    }
+                })
+            })
+            .clone()
+    }
+}
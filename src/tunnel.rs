@@ -0,0 +1,124 @@
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use http::{Uri, uri::Scheme};
+use hyper_util::{client::legacy::connect::HttpConnector, rt::TokioIo};
+use jsonrpsee::core::BoxError;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tower::Service;
+
+/// The maximum size of the proxy response to a `CONNECT` request.
+const MAX_CONNECT_RESPONSE_BYTES: usize = 8 * 1024;
+
+/// Returned when the HTTP proxy fails to open a tunnel to the target.
+#[derive(Debug)]
+pub struct ProxyTunnelError {
+    pub proxy: Uri,
+    pub reason: String,
+}
+
+impl fmt::Display for ProxyTunnelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "proxy_tunnel: {} failed to open a tunnel, {}",
+            self.proxy, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ProxyTunnelError {}
+
+/// Connects to targets directly, or through a tunnel opened with an HTTP
+/// `CONNECT` request to a forward proxy if one is set.
+///
+/// TLS is negotiated with the target over the tunnel, so the proxy only sees
+/// the host and port of the target.
+#[derive(Clone, Debug)]
+pub struct TunnelConnector {
+    http: HttpConnector,
+    proxy: Option<Uri>,
+}
+
+impl TunnelConnector {
+    /// Creates a new [`TunnelConnector`] connecting with `http`, to the proxy if set.
+    pub fn new(http: HttpConnector, proxy: Option<Uri>) -> Self {
+        Self { http, proxy }
+    }
+}
+
+impl Service<Uri> for TunnelConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.http.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let Some(proxy) = self.proxy.clone() else {
+            let connecting = self.http.call(uri);
+            return Box::pin(async move { Ok(connecting.await?) });
+        };
+
+        let connecting = self.http.call(proxy.clone());
+        Box::pin(async move {
+            let stream = connecting.await?.into_inner();
+            Ok(TokioIo::new(tunnel(stream, &proxy, &uri).await?))
+        })
+    }
+}
+
+/// Asks the proxy on the other end of `stream` to open a tunnel to the target at `uri`.
+async fn tunnel(
+    mut stream: TcpStream,
+    proxy: &Uri,
+    uri: &Uri,
+) -> Result<TcpStream, ProxyTunnelError> {
+    let error = |reason: String| ProxyTunnelError {
+        proxy: proxy.clone(),
+        reason,
+    };
+    let host = uri
+        .host()
+        .ok_or_else(|| error("the target URL has no host".to_string()))?;
+    let default_port = match uri.scheme() {
+        Some(scheme) if *scheme == Scheme::HTTPS => 443,
+        _ => 80,
+    };
+    let authority = format!("{host}:{}", uri.port_u16().unwrap_or(default_port));
+
+    let request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n\r\n");
+    stream
+        .write_all(request.as_bytes())
+        .await
+        .map_err(|err| error(err.to_string()))?;
+
+    // Read a byte at a time, so nothing sent through the tunnel is consumed
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() == MAX_CONNECT_RESPONSE_BYTES {
+            return Err(error("the response is too large".to_string()));
+        }
+        let byte = stream
+            .read_u8()
+            .await
+            .map_err(|err| error(err.to_string()))?;
+        response.push(byte);
+    }
+
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(stream),
+        _ => Err(error(format!("the proxy responded with `{status_line}`"))),
+    }
+}
@@ -1,32 +1,401 @@
 use std::{
+    collections::HashMap,
     pin::Pin,
-    sync::Arc,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
 
+use alloy_primitives::B256;
+use futures::StreamExt;
+use http::{HeaderValue, StatusCode};
+use http_body_util::BodyExt;
+use hyper::body::Bytes;
 use jsonrpsee::{
-    core::BoxError,
+    core::{BoxError, http_helpers::HttpError},
     http_client::{HttpBody, HttpRequest, HttpResponse},
-    types::ErrorObject,
+    types::{
+        ErrorObject,
+        error::{INTERNAL_ERROR_CODE, INVALID_REQUEST_CODE, PARSE_ERROR_CODE, PARSE_ERROR_MSG},
+    },
 };
+#[cfg(feature = "metrics")]
+use metrics_exporter_prometheus::PrometheusHandle;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use tower::{Layer, Service};
-use tracing::{debug, instrument};
+use tracing::{Instrument, Span, debug, error, field::Empty, info, instrument, warn};
 
-use crate::{fanout::FanoutWrite, metrics::ProxyMetrics, rpc::RpcRequest};
+#[cfg(feature = "metrics")]
+use crate::scrape::{METRICS_METHOD, metrics_snapshot};
+use crate::{
+    auth::CallerIdentity,
+    capture::{Capture, CapturedResponse},
+    client::should_log,
+    fanout::{
+        AllTargetsFailed, FanoutWrite, FirstResponse, Hedge, HedgedResult, InsufficientSuccesses,
+        Outcome, SelectionStrategy, select_declaration_order, select_response,
+    },
+    metrics::ProxyMetrics,
+    redact::Redactor,
+    rpc::{
+        InvalidResponse, PbhErrorMatcher, REQUEST_ID_HEADER, RpcRequest, UnavailableError,
+        error_response,
+    },
+    sampling::{Notable, NotableReason, TraceSampling},
+    split::{BuilderSplit, SplitSide},
+};
+
+/// The tracing target of `tx_submitted` events, so they can be routed separately.
+pub const TX_EVENTS_TARGET: &str = "tx-proxy::tx_events";
+
+/// JSON-RPC error code returned for a transaction above the max fee per gas cap.
+pub const FEE_CAP_EXCEEDED_CODE: i32 = -32011;
+
+/// The response header marking a transaction forwarded directly to L2 because
+/// no builder was available.
+pub const DEGRADED_HEADER: &str = "x-txproxy-degraded";
+
+/// The response header reporting how the L2 response diverged from the
+/// builder response, see [`ValidationLayer::with_tee_compare`].
+pub const L2_DIVERGENCE_HEADER: &str = "x-txproxy-l2-divergence";
+
+/// The maximum number of bytes of each response logged when they diverge.
+const DIVERGENCE_LOG_BYTES: usize = 1024;
+
+/// The minimum interval between degraded forward logs.
+const DEGRADED_LOG_INTERVAL: Duration = Duration::from_secs(10);
 
-pub const ALLOWED_METHODS: &[&str; 2] = &["eth_", "net_peerCount"];
+/// When a degraded forward was last logged, shared by every listener.
+static DEGRADED_LOGGED_AT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// What to do with a background L2 forward once the in-flight limit is reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum L2ForwardOverflow {
+    /// Skip the background task and record it in `l2_forward_dropped_total`.
+    ///
+    /// With the first successful strategy, the remaining builder requests are
+    /// abandoned along with the L2 forward.
+    #[default]
+    Drop,
+    /// Wait for an in-flight forward to complete before responding to the caller.
+    Wait,
+}
+
+/// Methods describing the proxy itself, answered without fanning out.
+#[derive(Clone, Debug, Default)]
+pub struct LocalMethods {
+    results: HashMap<String, serde_json::Value>,
+    #[cfg(feature = "metrics")]
+    metrics_handle: Option<PrometheusHandle>,
+}
+
+impl LocalMethods {
+    /// Answers `web3_clientVersion` with the name and version of the proxy.
+    pub fn with_client_version(mut self) -> Self {
+        self.results.insert(
+            "web3_clientVersion".to_string(),
+            format!("{}/v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION")).into(),
+        );
+        self
+    }
+
+    /// Answers `net_version` with the given network id.
+    pub fn with_net_version(mut self, net_version: u64) -> Self {
+        self.results
+            .insert("net_version".to_string(), net_version.to_string().into());
+        self
+    }
+
+    /// Answers `proxy_metrics` with a JSON snapshot of the metrics rendered by
+    /// the handle, see [`metrics_snapshot`].
+    #[cfg(feature = "metrics")]
+    pub fn with_metrics_snapshot(mut self, metrics_handle: Option<PrometheusHandle>) -> Self {
+        self.metrics_handle = metrics_handle;
+        self
+    }
+
+    /// Returns the methods answered locally.
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        let methods = self.results.keys().map(String::as_str);
+        #[cfg(feature = "metrics")]
+        let methods = methods.chain(self.metrics_handle.as_ref().map(|_| METRICS_METHOD));
+        methods
+    }
+
+    /// Returns the result of the method if it is answered locally.
+    pub fn result(&self, method: &str) -> Option<serde_json::Value> {
+        #[cfg(feature = "metrics")]
+        if let Some(handle) = self
+            .metrics_handle
+            .as_ref()
+            .filter(|_| method == METRICS_METHOD)
+        {
+            return Some(metrics_snapshot(&handle.render()));
+        }
+        self.results.get(method).cloned()
+    }
+}
+
+/// Bounds the number of background tasks completing builder requests and
+/// forwarding to L2, including the fanout of notifications.
+#[derive(Clone, Debug)]
+pub struct L2ForwardLimit {
+    semaphore: Arc<Semaphore>,
+    overflow: L2ForwardOverflow,
+}
+
+impl L2ForwardLimit {
+    /// Creates a new [`L2ForwardLimit`] allowing `max_inflight` forwards at once.
+    pub fn new(max_inflight: usize, overflow: L2ForwardOverflow) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_inflight)),
+            overflow,
+        }
+    }
+
+    /// Returns a permit held by the background task, or `None` if the forward is dropped.
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        match self.overflow {
+            L2ForwardOverflow::Drop => self.semaphore.clone().try_acquire_owned().ok(),
+            L2ForwardOverflow::Wait => self.semaphore.clone().acquire_owned().await.ok(),
+        }
+    }
+}
 
 /// A [`Layer`] that validates responses from one fanout prior to forwarding them to the next fanout.
 pub struct ValidationLayer {
     pub fanout: FanoutWrite,
     pub metrics: Arc<ProxyMetrics>,
+    pub strategy: SelectionStrategy,
+    pub pbh_error_matcher: Arc<PbhErrorMatcher>,
+    pub unavailable_error: Arc<UnavailableError>,
+    pub redactor: Arc<Redactor>,
+    pub sticky_sender: bool,
+    pub l2_forward_methods: Arc<Vec<String>>,
+    pub capture: Option<Arc<Capture>>,
+    pub tx_events: bool,
+    pub l2_forward_limit: Option<L2ForwardLimit>,
+    pub reject_notifications: bool,
+    pub l2_forward_on_abort: bool,
+    pub l2_forward_blocking: bool,
+    pub require_consistent_success: Option<usize>,
+    pub local_methods: Arc<LocalMethods>,
+    pub strict_methods: Arc<Vec<String>>,
+    pub method_rewrites: Arc<HashMap<String, String>>,
+    pub hedge: Option<Arc<Hedge>>,
+    pub builder_split: Option<BuilderSplit>,
+    pub trace_sampling: Option<Arc<TraceSampling>>,
+    pub max_fee_per_gas_cap: Option<u128>,
+    pub fallback_to_l2: bool,
+    pub tee_compare: bool,
 }
 
 impl ValidationLayer {
     /// Creates a new [`ValidationLayer`] with the given fanout.
     pub fn new(fanout: FanoutWrite, metrics: Arc<ProxyMetrics>) -> Self {
-        Self { fanout, metrics }
+        Self {
+            fanout,
+            metrics,
+            strategy: SelectionStrategy::default(),
+            pbh_error_matcher: Arc::new(PbhErrorMatcher::default()),
+            unavailable_error: Arc::new(UnavailableError::default()),
+            redactor: Arc::new(Redactor::default()),
+            sticky_sender: false,
+            l2_forward_methods: Arc::new(vec![]),
+            capture: None,
+            tx_events: false,
+            l2_forward_limit: None,
+            reject_notifications: false,
+            l2_forward_on_abort: true,
+            l2_forward_blocking: false,
+            require_consistent_success: None,
+            local_methods: Arc::new(LocalMethods::default()),
+            strict_methods: Arc::new(vec![]),
+            method_rewrites: Arc::new(HashMap::new()),
+            hedge: None,
+            builder_split: None,
+            trace_sampling: None,
+            max_fee_per_gas_cap: None,
+            fallback_to_l2: false,
+            tee_compare: false,
+        }
+    }
+
+    /// Sets the [`SelectionStrategy`] used to pick the builder response returned to the caller.
+    pub fn with_selection_strategy(mut self, strategy: SelectionStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Sets the [`PbhErrorMatcher`] used to detect PBH errors in builder responses.
+    pub fn with_pbh_error_matcher(mut self, matcher: PbhErrorMatcher) -> Self {
+        self.pbh_error_matcher = Arc::new(matcher);
+        self
+    }
+
+    /// Sets the [`UnavailableError`] returned when no builder responded.
+    pub fn with_unavailable_error(mut self, unavailable_error: UnavailableError) -> Self {
+        self.unavailable_error = Arc::new(unavailable_error);
+        self
+    }
+
+    /// Sets the [`Redactor`] applied to the builder error responses returned to
+    /// the caller. Nothing is redacted by default.
+    pub fn with_redactor(mut self, redactor: Redactor) -> Self {
+        self.redactor = Arc::new(redactor);
+        self
+    }
+
+    /// Sends raw transactions to a primary builder picked from the sender before
+    /// fanning out to the remaining builders, preferring the primary response.
+    pub fn with_sticky_sender(mut self, sticky_sender: bool) -> Self {
+        self.sticky_sender = sticky_sender;
+        self
+    }
+
+    /// Sets the methods forwarded to the L2 fanout once validated. Other methods
+    /// are answered by the builders only.
+    ///
+    /// All methods are forwarded if empty.
+    pub fn with_l2_forward_methods(mut self, l2_forward_methods: Vec<String>) -> Self {
+        self.l2_forward_methods = Arc::new(l2_forward_methods);
+        self
+    }
+
+    /// Sets the [`Capture`] writing requests for a single method and the builder
+    /// responses to a file.
+    pub fn with_capture(mut self, capture: Option<Arc<Capture>>) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Emits a `tx_submitted` event on [`TX_EVENTS_TARGET`] for each builder
+    /// accepting a raw transaction.
+    pub fn with_tx_events(mut self, tx_events: bool) -> Self {
+        self.tx_events = tx_events;
+        self
+    }
+
+    /// Sets the [`L2ForwardLimit`] bounding background L2 forwards. Unbounded if `None`.
+    pub fn with_l2_forward_limit(mut self, l2_forward_limit: Option<L2ForwardLimit>) -> Self {
+        self.l2_forward_limit = l2_forward_limit;
+        self
+    }
+
+    /// Rejects notifications, requests without an id, instead of fanning them out.
+    pub fn with_reject_notifications(mut self, reject_notifications: bool) -> Self {
+        self.reject_notifications = reject_notifications;
+        self
+    }
+
+    /// Still forwards a validated request to L2 when its caller went away
+    /// before the builders responded. Enabled by default.
+    pub fn with_l2_forward_on_abort(mut self, l2_forward_on_abort: bool) -> Self {
+        self.l2_forward_on_abort = l2_forward_on_abort;
+        self
+    }
+
+    /// Awaits the L2 forward of a validated request before answering its caller,
+    /// instead of forwarding in the background.
+    pub fn with_l2_forward_blocking(mut self, l2_forward_blocking: bool) -> Self {
+        self.l2_forward_blocking = l2_forward_blocking;
+        self
+    }
+
+    /// Only forwards a request to L2 if at least this many builders returned
+    /// the same result, returning the result of the majority of builders if
+    /// they diverged. Disabled if `None`.
+    ///
+    /// Requests are sent to every builder before a response is returned, as
+    /// with a primary builder or hedging.
+    pub fn with_require_consistent_success(
+        mut self,
+        require_consistent_success: Option<usize>,
+    ) -> Self {
+        self.require_consistent_success = require_consistent_success;
+        self
+    }
+
+    /// Sets the [`LocalMethods`] answered by the proxy instead of the builders.
+    /// They must be allowed by the [`MethodFilter`](crate::filter::MethodFilter)
+    /// in front, see [`LocalMethods::methods`].
+    pub fn with_local_methods(mut self, local_methods: LocalMethods) -> Self {
+        self.local_methods = Arc::new(local_methods);
+        self
+    }
+
+    /// Sets the methods that must be accepted by every builder. If any builder
+    /// fails or returns a JSON-RPC error for one of them, the caller gets an
+    /// error and the request is not forwarded to L2.
+    ///
+    /// Requests for these methods are sent to every builder before a response
+    /// is returned, as with a primary builder or hedging.
+    pub fn with_strict_methods(mut self, strict_methods: Vec<String>) -> Self {
+        self.strict_methods = Arc::new(strict_methods);
+        self
+    }
+
+    /// Sets the methods renamed before the requests are fanned out, so callers
+    /// can use legacy names for the methods the builders expect. The id, params
+    /// and response are left untouched.
+    pub fn with_method_rewrites(mut self, method_rewrites: HashMap<String, String>) -> Self {
+        self.method_rewrites = Arc::new(method_rewrites);
+        self
+    }
+
+    /// Sets the [`Hedge`] sending requests for its method categories to one
+    /// builder at a time. Other methods, and all methods if `None`, are fanned
+    /// out to every builder.
+    pub fn with_hedge(mut self, hedge: Option<Hedge>) -> Self {
+        self.hedge = hedge.map(Arc::new);
+        self
+    }
+
+    /// Sets the [`BuilderSplit`] routing a share of requests to new builders
+    /// instead of the fanout.
+    pub fn with_builder_split(mut self, builder_split: Option<BuilderSplit>) -> Self {
+        self.builder_split = builder_split;
+        self
+    }
+
+    /// Sets the [`TraceSampling`] summarizing notable requests whose trace was
+    /// not sampled. Disabled if `None`.
+    pub fn with_trace_sampling(mut self, trace_sampling: Option<TraceSampling>) -> Self {
+        self.trace_sampling = trace_sampling.map(Arc::new);
+        self
+    }
+
+    /// Rejects `eth_sendRawTransaction` requests whose max fee per gas, or gas
+    /// price for legacy transactions, is above the cap in wei before they are
+    /// fanned out. Disabled if `None`.
+    pub fn with_max_fee_per_gas_cap(mut self, max_fee_per_gas_cap: Option<u128>) -> Self {
+        self.max_fee_per_gas_cap = max_fee_per_gas_cap;
+        self
+    }
+
+    /// Forwards `eth_sendRawTransaction` requests directly to L2 when no builder
+    /// responded, returning the L2 response marked with the [`DEGRADED_HEADER`].
+    /// The transaction is not PBH validated. Disabled by default.
+    ///
+    /// Strict methods, and methods not forwarded to L2, never fall back.
+    pub fn with_fallback_to_l2(mut self, fallback_to_l2: bool) -> Self {
+        self.fallback_to_l2 = fallback_to_l2;
+        self
+    }
+
+    /// Awaits the L2 forward of a validated request, as with blocking forwards,
+    /// and compares the L2 response to the builder response. When they diverge,
+    /// the builder response is still returned, with the [`L2_DIVERGENCE_HEADER`],
+    /// and the divergence is logged. Disabled by default.
+    pub fn with_tee_compare(mut self, tee_compare: bool) -> Self {
+        self.tee_compare = tee_compare;
+        self
     }
 }
 
@@ -36,6 +405,28 @@ impl<S> Layer<S> for ValidationLayer {
         ValidationService {
             fanout: self.fanout.clone(),
             metrics: self.metrics.clone(),
+            strategy: self.strategy,
+            pbh_error_matcher: self.pbh_error_matcher.clone(),
+            unavailable_error: self.unavailable_error.clone(),
+            redactor: self.redactor.clone(),
+            sticky_sender: self.sticky_sender,
+            l2_forward_methods: self.l2_forward_methods.clone(),
+            capture: self.capture.clone(),
+            tx_events: self.tx_events,
+            l2_forward_limit: self.l2_forward_limit.clone(),
+            reject_notifications: self.reject_notifications,
+            l2_forward_on_abort: self.l2_forward_on_abort,
+            l2_forward_blocking: self.l2_forward_blocking,
+            require_consistent_success: self.require_consistent_success,
+            local_methods: self.local_methods.clone(),
+            strict_methods: self.strict_methods.clone(),
+            method_rewrites: self.method_rewrites.clone(),
+            hedge: self.hedge.clone(),
+            builder_split: self.builder_split.clone(),
+            trace_sampling: self.trace_sampling.clone(),
+            max_fee_per_gas_cap: self.max_fee_per_gas_cap,
+            fallback_to_l2: self.fallback_to_l2,
+            tee_compare: self.tee_compare,
             inner,
         }
     }
@@ -45,6 +436,28 @@ impl<S> Layer<S> for ValidationLayer {
 pub struct ValidationService<S> {
     fanout: FanoutWrite,
     metrics: Arc<ProxyMetrics>,
+    strategy: SelectionStrategy,
+    pbh_error_matcher: Arc<PbhErrorMatcher>,
+    unavailable_error: Arc<UnavailableError>,
+    redactor: Arc<Redactor>,
+    sticky_sender: bool,
+    l2_forward_methods: Arc<Vec<String>>,
+    capture: Option<Arc<Capture>>,
+    tx_events: bool,
+    l2_forward_limit: Option<L2ForwardLimit>,
+    reject_notifications: bool,
+    l2_forward_on_abort: bool,
+    l2_forward_blocking: bool,
+    require_consistent_success: Option<usize>,
+    local_methods: Arc<LocalMethods>,
+    strict_methods: Arc<Vec<String>>,
+    method_rewrites: Arc<HashMap<String, String>>,
+    hedge: Option<Arc<Hedge>>,
+    builder_split: Option<BuilderSplit>,
+    trace_sampling: Option<Arc<TraceSampling>>,
+    max_fee_per_gas_cap: Option<u128>,
+    fallback_to_l2: bool,
+    tee_compare: bool,
     inner: S,
 }
 
@@ -64,67 +477,818 @@ where
         self.inner.poll_ready(cx).map_err(Into::into)
     }
 
-    #[instrument(skip(self, request), target = "tx-proxy::validation")]
+    #[instrument(
+        skip(self, request),
+        target = "tx-proxy::validation",
+        fields(
+            request.id = Empty,
+            caller.identity = Empty,
+            tx.computed_hash = Empty,
+            tx.hash = Empty,
+            builder.successes = Empty,
+            builder.failures = Empty
+        )
+    )]
     fn call(&mut self, request: HttpRequest<HttpBody>) -> Self::Future {
-        self.metrics.record_inbound_request(1);
+        // Requests on listeners without authentication carry no identity
+        let identity = CallerIdentity::from_extensions(request.extensions());
+        self.metrics.record_inbound_request(1, identity.as_str());
+        Span::current().record("caller.identity", identity.as_str());
         let mut service = self.clone();
-        let mut fanout = self.fanout.clone();
+        let fanout = match &self.builder_split {
+            Some(split) => {
+                let side = split.next_side();
+                self.metrics.record_builder_split_request(side.as_str());
+                match side {
+                    SplitSide::Current => self.fanout.clone(),
+                    SplitSide::New => split.fanout().clone(),
+                }
+            }
+            None => self.fanout.clone(),
+        };
         let metrics = self.metrics.clone();
+        let strategy = self.strategy;
+        let matcher = self.pbh_error_matcher.clone();
+        let unavailable_error = self.unavailable_error.clone();
+        let redactor = self.redactor.clone();
+        let sticky_sender = self.sticky_sender;
+        let l2_forward_methods = self.l2_forward_methods.clone();
+        let capture = self.capture.clone();
+        let tx_events = self.tx_events;
+        let l2_forward_limit = self.l2_forward_limit.clone();
+        let reject_notifications = self.reject_notifications;
+        let l2_forward_on_abort = self.l2_forward_on_abort;
+        let tee_compare = self.tee_compare;
+        // Comparing needs the L2 response before answering the caller
+        let l2_forward_blocking = self.l2_forward_blocking || tee_compare;
+        let require_consistent_success = self.require_consistent_success;
+        let local_methods = self.local_methods.clone();
+        let strict_methods = self.strict_methods.clone();
+        let method_rewrites = self.method_rewrites.clone();
+        let hedge = self.hedge.clone();
+        let trace_sampling = self.trace_sampling.clone();
+        let max_fee_per_gas_cap = self.max_fee_per_gas_cap;
+        let fallback_to_l2 = self.fallback_to_l2;
         service.inner = std::mem::replace(&mut self.inner, service.inner);
 
+        let span = Span::current();
+        let aborted = Arc::new(AtomicBool::new(false));
+        let guard = AbortGuard(Some(aborted.clone()));
+        let abort_metrics = self.metrics.clone();
+        let work_aborted = aborted.clone();
+        let notable = Notable::default();
+        let work_notable = notable.clone();
+        let started = Instant::now();
+
         let fut = async move {
-            let rpc_request = RpcRequest::from_request(request).await?;
-            if !ALLOWED_METHODS
-                .iter()
-                .any(|m| rpc_request.method.contains(m))
-            {
-                return Ok::<HttpResponse<HttpBody>, BoxError>(invalid_method_response());
+            let rpc_request = match RpcRequest::from_parsed_or_request(request).await {
+                Ok(rpc_request) => rpc_request,
+                Err(err) => {
+                    if matches!(err.downcast_ref::<HttpError>(), Some(HttpError::Stream(_))) {
+                        aborted.store(true, Ordering::Relaxed);
+                    }
+                    if err.downcast_ref::<serde_json::Error>().is_some() {
+                        debug!(target: "tx-proxy::validation", %err, "rejecting request with malformed JSON");
+                        return Ok(parse_error_response());
+                    }
+                    return Err(err.into());
+                }
+            };
+            let rpc_request = match method_rewrites.get(&rpc_request.method) {
+                Some(method) => match rpc_request.with_method(method) {
+                    Ok(rewritten) => {
+                        debug!(target: "tx-proxy::validation", from = %rpc_request.method, to = %method, request.id = %rpc_request.request_id, "rewriting method");
+                        rewritten
+                    }
+                    Err(err) => {
+                        error!(target: "tx-proxy::validation", %err, from = %rpc_request.method, to = %method, "Failed to rewrite method");
+                        rpc_request
+                    }
+                },
+                None => rpc_request,
+            };
+            let should_forward_to_l2 = move |forward_to_l2: bool| {
+                forward_to_l2 && (l2_forward_on_abort || !aborted.load(Ordering::Relaxed))
+            };
+            let request_id = rpc_request.request_id.clone();
+            span.record("request.id", request_id.as_str());
+            notable.identify(&rpc_request.method, &request_id);
+            if let Some(hash) = rpc_request.tx_hash() {
+                span.record("tx.computed_hash", hash.to_string());
+                debug!(target: "tx-proxy::validation", request.id = %request_id, tx.computed_hash = %hash, "decoded raw transaction");
+            }
+            let over_fee_cap = max_fee_per_gas_cap.and_then(|cap| {
+                let max_fee = rpc_request.max_fee_per_gas()?;
+                (max_fee > cap).then_some((max_fee, cap))
+            });
+            // Reading and parsing the body dominate, the fanout is not included
+            metrics.record_validation_overhead(started.elapsed());
+            if rpc_request.is_notification {
+                metrics.record_inbound_notification();
+                if reject_notifications {
+                    debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "rejecting notification");
+                    return Ok(with_request_id(
+                        notification_rejected_response(),
+                        &request_id,
+                    ));
+                }
+                if let Some((max_fee, cap)) = over_fee_cap {
+                    metrics.record_fee_cap_rejected();
+                    debug!(target: "tx-proxy::validation", request.id = %request_id, max_fee, cap, "dropping notification above the fee cap");
+                } else if let Some(permit) =
+                    reserve_l2_forward(l2_forward_limit.as_ref(), &metrics).await
+                {
+                    let forward_to_l2 = l2_forward_methods.is_empty()
+                        || l2_forward_methods.contains(&rpc_request.method);
+                    forward_l2(
+                        async move {
+                            let _permit = permit;
+                            fan_notification(
+                                fanout,
+                                &metrics,
+                                &matcher,
+                                rpc_request,
+                                forward_to_l2.then_some(service.inner),
+                            )
+                            .await;
+                        }
+                        .in_current_span(),
+                        l2_forward_blocking,
+                    )
+                    .await;
+                }
+
+                // Per spec notifications are never answered
+                return Ok(with_request_id(notification_response(), &request_id));
+            }
+
+            if let Some(result) = local_methods.result(&rpc_request.method) {
+                debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "answering request locally");
+                return Ok(with_request_id(
+                    local_response(rpc_request.id(), &result),
+                    &request_id,
+                ));
             }
 
-            debug!(target: "tx-proxy::validation", method = %rpc_request.method, "forwarding request to builder fanout");
+            if let Some((max_fee, cap)) = over_fee_cap {
+                metrics.record_fee_cap_rejected();
+                debug!(target: "tx-proxy::validation", request.id = %request_id, max_fee, cap, "rejecting transaction above the fee cap");
+                return Ok(with_request_id(
+                    fee_cap_exceeded_response(rpc_request.id(), max_fee, cap),
+                    &request_id,
+                ));
+            }
+
+            let forward_to_l2 =
+                l2_forward_methods.is_empty() || l2_forward_methods.contains(&rpc_request.method);
+            let capture = capture.filter(|capture| capture.matches(&rpc_request.method));
+            let is_submission = rpc_request.method == "eth_sendRawTransaction";
+            let strict = strict_methods.contains(&rpc_request.method);
+            let fallback_to_l2 = fallback_to_l2 && is_submission && forward_to_l2 && !strict;
+
+            debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, request.idempotency_key = %rpc_request.idempotency_key, "forwarding request to builder fanout");
             let now = Instant::now();
-            let mut responses = fanout.fan_request(rpc_request.clone()).await?;
-            metrics.record_builder_latency(now.elapsed().as_secs_f64());
-            metrics.record_builder_failed_request(
-                fanout.targets.len() as f64 - responses.len() as f64,
-            );
-            if responses.iter().all(|res| !res.pbh_error()) {
-                debug!(target: "tx-proxy::validation", method = %rpc_request.method, "forwarding request to l2 fanout");
-                tokio::spawn(async move {
-                    let _ = service.inner.call(rpc_request.into()).await;
+            let primary = sticky_sender
+                .then(|| rpc_request.sender_and_nonce())
+                .flatten()
+                .and_then(|(sender, _)| fanout.primary_target(&sender));
+            let hedge_delay = hedge
+                .filter(|hedge| {
+                    primary.is_none() && !strict && hedge.applies_to(&rpc_request.method)
+                })
+                .map(|hedge| hedge.delay);
+
+            if strategy == SelectionStrategy::FirstSuccessful
+                && primary.is_none()
+                && hedge_delay.is_none()
+                && require_consistent_success.is_none()
+                && !strict
+            {
+                let FirstResponse {
+                    response,
+                    index,
+                    mut responded,
+                    mut pending,
+                } = match fanout
+                    .fan_request_first(rpc_request.clone(), &matcher)
+                    .await
+                {
+                    Ok(first) => first,
+                    Err(err) if fanout.validates_responses() && err.is::<InvalidResponse>() => {
+                        warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "no valid builder response received");
+                        notable.mark(NotableReason::UpstreamFailure);
+                        return Ok(with_request_id(
+                            upstream_corruption_response(rpc_request.id()),
+                            &request_id,
+                        ));
+                    }
+                    Err(err) if err.is::<AllTargetsFailed>() => {
+                        warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "no builder responded");
+                        notable.mark(NotableReason::UpstreamFailure);
+                        if fallback_to_l2 {
+                            let response =
+                                forward_degraded(service.inner, &metrics, rpc_request).await?;
+                            return Ok(with_request_id(response, &request_id));
+                        }
+                        return Ok(with_request_id(
+                            unavailable_error.response(rpc_request.id()),
+                            &request_id,
+                        ));
+                    }
+                    Err(err) => match err.downcast::<InsufficientSuccesses>() {
+                        Ok(err) => {
+                            warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, %err, "too few builders succeeded");
+                            notable.mark(NotableReason::UpstreamFailure);
+                            return Ok(with_request_id(
+                                insufficient_successes_response(rpc_request.id(), &err),
+                                &request_id,
+                            ));
+                        }
+                        Err(err) => return Err(err),
+                    },
+                };
+
+                let mut pbh_error = response.pbh_error_with(&matcher);
+                if pbh_error {
+                    notable.mark(NotableReason::PbhError);
+                }
+                let mut accepted = Vec::new();
+                if let Some(hash) = response.tx_hash().filter(|_| is_submission) {
+                    accepted.push(AcceptedTransaction {
+                        index,
+                        elapsed: now.elapsed(),
+                        hash,
+                    });
+                }
+                let mut captured = capture.as_ref().map(|_| {
+                    vec![CapturedResponse {
+                        target: fanout.target_url(index),
+                        response: Ok(response.body.clone()),
+                    }]
                 });
-            }
+                let Some(permit) = reserve_l2_forward(l2_forward_limit.as_ref(), &metrics).await
+                else {
+                    return Ok(with_request_id(redactor.redact(response), &request_id));
+                };
+                let tee = tee_compare.then(|| Arc::new(OnceLock::new()));
+                let l2_tee = tee.clone();
+                let builder_body = response.body.clone();
+                let tee_metrics = metrics.clone();
+                forward_l2(async move {
+                    let _permit = permit;
+                    while let Some((index, elapsed, res)) = pending.next().await {
+                        if let Some(captured) = captured.as_mut() {
+                            captured.push(CapturedResponse::new(fanout.target_url(index), &res));
+                        }
+                        let Ok(res) = res else {
+                            continue;
+                        };
+                        responded += 1;
+                        if let Some(hash) = res.tx_hash().filter(|_| is_submission) {
+                            accepted.push(AcceptedTransaction {
+                                index,
+                                elapsed,
+                                hash,
+                            });
+                        }
+                        if res.pbh_error_with(&matcher) {
+                            warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %rpc_request.request_id, index, "received PBH error after response was returned");
+                            metrics.record_builder_late_pbh_error();
+                            pbh_error = true;
+                        }
+                    }
 
-            let res_0 = responses.remove(0).response;
+                    if let (Some(capture), Some(captured)) = (&capture, &captured) {
+                        capture.record(&rpc_request, captured);
+                    }
+                    record_accepted_transactions(&fanout, &rpc_request, &accepted, tx_events, &span);
+                    let failures = fanout.enabled_count().saturating_sub(responded);
+                    span.record("builder.successes", responded);
+                    span.record("builder.failures", failures);
+                    metrics.record_builder_latency(now.elapsed().as_secs_f64());
+                    metrics.record_builder_failed_request(failures as f64);
+                    if should_forward_to_l2(forward_to_l2 && !pbh_error) {
+                        debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %rpc_request.request_id, "forwarding request to l2 fanout");
+                        let l2_response = service.inner.call(rpc_request.into()).await;
+                        if let (Some(tee), Ok(l2_response)) = (l2_tee, l2_response) {
+                            tee_l2_response(&tee, l2_response).await;
+                        }
+                    }
+                }.in_current_span(), l2_forward_blocking).await;
 
-            // Loop through each response, if pbh error, break
-            // otherwise if the response is valid, set the response
-            let mut response = None;
-            for res in responses {
-                // If the response is a pbh error, short circuit
-                if res.pbh_error() {
-                    response = Some(res.response);
-                    break;
+                let mut response = with_request_id(redactor.redact(response), &request_id);
+                if let Some(l2_body) = tee.as_ref().and_then(|tee| tee.get()) {
+                    compare_l2_response(
+                        &mut response,
+                        &builder_body,
+                        l2_body,
+                        &tee_metrics,
+                        &request_id,
+                    );
                 }
-                // If the response has not been set and res is not err, set the response
-                if response.is_none() && !res.is_error() {
-                    response = Some(res.response);
+                return Ok(response);
+            }
+
+            let mut result = match (primary, hedge_delay) {
+                (Some(primary), _) => {
+                    debug!(target: "tx-proxy::validation", primary, request.id = %request_id, "sending request to primary builder first");
+                    let mut result = fanout
+                        .fan_request_primary_first(primary, rpc_request.clone())
+                        .await;
+                    result.prefer(primary);
+                    result
+                }
+                (None, Some(delay)) => {
+                    debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "hedging request across builders");
+                    let HedgedResult {
+                        result,
+                        hedged,
+                        cancelled,
+                    } = fanout.fan_request_hedged(rpc_request.clone(), delay).await;
+                    metrics.record_hedged_requests(hedged as u64);
+                    metrics.record_hedge_cancelled_requests(cancelled as u64);
+                    result
+                }
+                (None, None) => fanout.fan_request_all(rpc_request.clone()).await,
+            };
+            if let Some(capture) = &capture {
+                let captured = result
+                    .targets
+                    .iter()
+                    .map(CapturedResponse::from)
+                    .collect::<Vec<_>>();
+                capture.record(&rpc_request, &captured);
+            }
+            if is_submission {
+                let accepted = result
+                    .targets
+                    .iter()
+                    .filter_map(|target| match &target.outcome {
+                        Outcome::Success(response) => {
+                            response.tx_hash().map(|hash| AcceptedTransaction {
+                                index: target.index,
+                                elapsed: target.elapsed,
+                                hash,
+                            })
+                        }
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>();
+                record_accepted_transactions(&fanout, &rpc_request, &accepted, tx_events, &span);
+            }
+            let failures = result.failures();
+            let invalid_responses = result.invalid_responses();
+            let pbh_error = result.pbh_errors(&matcher).next().is_some();
+            if pbh_error {
+                notable.mark(NotableReason::PbhError);
+            } else if failures > 0 {
+                notable.mark(NotableReason::UpstreamFailure);
+            }
+            // PBH errors are returned to the caller regardless of the other builders
+            if strict && !pbh_error {
+                let succeeded = result.successes().count();
+                if succeeded < result.targets.len() {
+                    let err = InsufficientSuccesses {
+                        required: result.targets.len(),
+                        succeeded,
+                    };
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, %err, "strict method not accepted by every builder");
+                    return Ok(with_request_id(
+                        insufficient_successes_response(rpc_request.id(), &err),
+                        &request_id,
+                    ));
+                }
+            }
+            if !pbh_error {
+                if let Err(err) = result.ensure_min_success(fanout.min_success()) {
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, %err, "too few builders succeeded");
+                    return Ok(with_request_id(
+                        insufficient_successes_response(rpc_request.id(), &err),
+                        &request_id,
+                    ));
+                }
+            }
+            // A builder returning a different result than its peers may have
+            // mutated or misparsed the payload
+            let mut majority_preferred = false;
+            let mut consistent = true;
+            if let Some(required) = require_consistent_success.filter(|_| !pbh_error) {
+                let groups = result.result_groups();
+                let majority = match groups.as_slice() {
+                    [first, second, ..] if first.len() == second.len() => None,
+                    [first, ..] => Some(first),
+                    [] => None,
+                };
+                if groups.len() > 1 {
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, ?groups, "builders returned different results");
+                    metrics.record_builder_result_divergence();
+                    let Some(majority) = majority else {
+                        return Ok(with_request_id(
+                            inconsistent_results_response(rpc_request.id()),
+                            &request_id,
+                        ));
+                    };
+                    result.prefer(majority[0]);
+                    majority_preferred = true;
+                }
+                let agreed = majority.map_or(0, |majority| majority.len());
+                consistent = agreed >= required;
+                if !consistent {
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, agreed, required, "too few builders returned the same result, not forwarding to l2");
+                }
+            }
+            // The primary and majority responses are preferred regardless of latency
+            let preferred = primary.is_some() || majority_preferred;
+            let order = if preferred {
+                SelectionStrategy::DeclarationOrder
+            } else {
+                strategy
+            };
+            let responded = result.targets.len() - failures;
+            let responses = match result.into_distinct_responses(order) {
+                Ok(responses) => responses,
+                Err(_) if fanout.validates_responses() && invalid_responses > 0 => {
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, invalid_responses, "no valid builder response received");
+                    return Ok(with_request_id(
+                        upstream_corruption_response(rpc_request.id()),
+                        &request_id,
+                    ));
+                }
+                Err(err) if err.is::<AllTargetsFailed>() => {
+                    warn!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %request_id, "no builder responded");
+                    if fallback_to_l2 {
+                        let response =
+                            forward_degraded(service.inner, &metrics, rpc_request).await?;
+                        return Ok(with_request_id(response, &request_id));
+                    }
+                    return Ok(with_request_id(
+                        unavailable_error.response(rpc_request.id()),
+                        &request_id,
+                    ));
+                }
+                Err(err) => return Err(err),
+            };
+            span.record("builder.successes", responded);
+            span.record("builder.failures", failures);
+            metrics.record_builder_latency(now.elapsed().as_secs_f64());
+            metrics.record_builder_failed_request(failures as f64);
+            // The preferred response comes first and is returned rather than used as a fallback
+            let response = if !preferred && strategy == SelectionStrategy::DeclarationOrder {
+                select_declaration_order(responses, &matcher)
+            } else {
+                select_response(responses, &matcher)
+            }
+            .expect("fanout returns at least one response");
+            let tee = tee_compare.then(|| Arc::new(OnceLock::new()));
+            if should_forward_to_l2(forward_to_l2 && !pbh_error && consistent) {
+                if let Some(permit) = reserve_l2_forward(l2_forward_limit.as_ref(), &metrics).await
+                {
+                    debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %rpc_request.request_id, "forwarding request to l2 fanout");
+                    let l2_tee = tee.clone();
+                    forward_l2(
+                        async move {
+                            let _permit = permit;
+                            let l2_response = service.inner.call(rpc_request.into()).await;
+                            if let (Some(tee), Ok(l2_response)) = (l2_tee, l2_response) {
+                                tee_l2_response(&tee, l2_response).await;
+                            }
+                        }
+                        .in_current_span(),
+                        l2_forward_blocking,
+                    )
+                    .await;
                 }
             }
 
-            Ok::<HttpResponse<HttpBody>, BoxError>(response.unwrap_or(res_0))
+            let builder_body = response.body.clone();
+            let mut response = with_request_id(redactor.redact(response), &request_id);
+            if let Some(l2_body) = tee.as_ref().and_then(|tee| tee.get()) {
+                compare_l2_response(&mut response, &builder_body, l2_body, &metrics, &request_id);
+            }
+            Ok::<HttpResponse<HttpBody>, BoxError>(response)
         };
 
-        Box::pin(fut)
+        // The work runs detached so a caller going away does not cancel the
+        // builder requests, or the L2 forward unless disabled
+        let work = tokio::spawn(
+            async move {
+                let result = fut.await;
+                if let Some(trace_sampling) = &trace_sampling {
+                    if result.is_err() {
+                        work_notable.mark(NotableReason::UpstreamFailure);
+                    }
+                    trace_sampling.summarize(&Span::current(), &work_notable, started.elapsed());
+                }
+                if work_aborted.load(Ordering::Relaxed) {
+                    abort_metrics.record_client_aborted_request();
+                    match &result {
+                        Ok(_) => {
+                            debug!(target: "tx-proxy::validation", "caller went away before the response was ready")
+                        }
+                        Err(err) => {
+                            debug!(target: "tx-proxy::validation", %err, "request failed after the caller went away")
+                        }
+                    }
+                }
+                result
+            }
+            .instrument(Span::current()),
+        );
+
+        Box::pin(async move {
+            let result = work.await;
+            guard.disarm();
+            result.map_err(BoxError::from).and_then(|result| result)
+        })
+    }
+}
+
+/// Marks a request as aborted by its caller when dropped before being disarmed,
+/// which happens when the server drops the response future of a closed connection.
+struct AbortGuard(Option<Arc<AtomicBool>>);
+
+impl AbortGuard {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for AbortGuard {
+    fn drop(&mut self) {
+        if let Some(aborted) = self.0.take() {
+            aborted.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Sends a notification to all builders, then to L2 unless a builder returned a PBH error.
+async fn fan_notification<S>(
+    fanout: FanoutWrite,
+    metrics: &ProxyMetrics,
+    matcher: &PbhErrorMatcher,
+    rpc_request: RpcRequest,
+    l2: Option<S>,
+) where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse>,
+{
+    debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %rpc_request.request_id, "forwarding notification to builder fanout");
+    let now = Instant::now();
+    let result = fanout.fan_request_all(rpc_request.clone()).await;
+    metrics.record_builder_latency(now.elapsed().as_secs_f64());
+    metrics.record_builder_failed_request(result.failures() as f64);
+    if result.pbh_errors(matcher).next().is_some() {
+        return;
+    }
+
+    if let Some(mut l2) = l2 {
+        debug!(target: "tx-proxy::validation", method = %rpc_request.method, request.id = %rpc_request.request_id, "forwarding notification to l2 fanout");
+        let _ = l2.call(rpc_request.into()).await;
+    }
+}
+
+/// Runs an L2 forward, awaiting it if `blocking` and in the background otherwise.
+async fn forward_l2<F>(forward: F, blocking: bool)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    if blocking {
+        forward.await;
+    } else {
+        tokio::spawn(forward);
+    }
+}
+
+/// Keeps the body of the L2 response to compare it to the builder response.
+async fn tee_l2_response(tee: &OnceLock<Bytes>, response: HttpResponse) {
+    match response.into_body().collect().await {
+        Ok(body) => {
+            let _ = tee.set(body.to_bytes());
+        }
+        Err(err) => {
+            debug!(target: "tx-proxy::validation", %err, "failed to read the l2 response to compare it")
+        }
+    }
+}
+
+/// Returns how the L2 response diverges from the builder response, if it does:
+/// `result` if both returned a different result, `error` if both returned a
+/// different error code, and `l2-error` or `builder-error` if only one of them
+/// returned an error.
+fn l2_divergence(builder_body: &[u8], l2_body: &[u8]) -> Option<&'static str> {
+    let builder = serde_json::from_slice::<serde_json::Value>(builder_body).ok()?;
+    let Ok(l2) = serde_json::from_slice::<serde_json::Value>(l2_body) else {
+        return Some("l2-error");
+    };
+    match (builder.get("result"), l2.get("result")) {
+        (Some(builder), Some(l2)) => (builder != l2).then_some("result"),
+        (Some(_), None) => Some("l2-error"),
+        (None, Some(_)) => Some("builder-error"),
+        (None, None) => (builder["error"]["code"] != l2["error"]["code"]).then_some("error"),
     }
 }
 
-fn invalid_method_response() -> HttpResponse {
+/// Marks the response returned to the caller and logs the divergence if the
+/// L2 response diverges from the builder response.
+fn compare_l2_response(
+    response: &mut HttpResponse,
+    builder_body: &[u8],
+    l2_body: &[u8],
+    metrics: &ProxyMetrics,
+    request_id: &str,
+) {
+    let Some(divergence) = l2_divergence(builder_body, l2_body) else {
+        return;
+    };
+    metrics.record_l2_divergence(divergence);
+    let truncate = |body: &[u8]| {
+        String::from_utf8_lossy(&body[..body.len().min(DIVERGENCE_LOG_BYTES)]).into_owned()
+    };
+    warn!(target: "tx-proxy::validation", request.id = %request_id, divergence, builder.response = %truncate(builder_body), l2.response = %truncate(l2_body), "l2 response diverged from the builder response");
+    response
+        .headers_mut()
+        .insert(L2_DIVERGENCE_HEADER, HeaderValue::from_static(divergence));
+}
+
+/// Forwards a raw transaction directly to L2 while no builder is available,
+/// marking the L2 response with the [`DEGRADED_HEADER`].
+async fn forward_degraded<S>(
+    mut l2: S,
+    metrics: &ProxyMetrics,
+    rpc_request: RpcRequest,
+) -> Result<HttpResponse, BoxError>
+where
+    S: Service<HttpRequest<HttpBody>, Response = HttpResponse>,
+    S::Error: Into<BoxError>,
+{
+    metrics.record_degraded_forward();
+    if should_log(&DEGRADED_LOGGED_AT, DEGRADED_LOG_INTERVAL) {
+        error!(target: "tx-proxy::validation", request.id = %rpc_request.request_id, "No builder available, forwarding transaction directly to l2 without PBH validation");
+    }
+    let mut response = l2.call(rpc_request.into()).await.map_err(Into::into)?;
+    response.headers_mut().insert(
+        DEGRADED_HEADER,
+        HeaderValue::from_static("builders-unavailable"),
+    );
+    Ok(response)
+}
+
+/// Reserves a slot for a background L2 forward, recording it if dropped.
+///
+/// Returns `None` if the forward is dropped, and a permit to hold until the
+/// forward completes otherwise. The permit is empty if forwards are unbounded.
+async fn reserve_l2_forward(
+    limit: Option<&L2ForwardLimit>,
+    metrics: &ProxyMetrics,
+) -> Option<Option<OwnedSemaphorePermit>> {
+    let Some(limit) = limit else {
+        return Some(None);
+    };
+
+    let permit = limit.acquire().await;
+    if permit.is_none() {
+        warn!(target: "tx-proxy::validation", "L2 forward limit reached, dropping forward");
+        metrics.record_l2_forward_dropped();
+    }
+    permit.map(Some)
+}
+
+/// A transaction hash returned by a builder in response to a raw transaction.
+struct AcceptedTransaction {
+    index: usize,
+    elapsed: Duration,
+    hash: B256,
+}
+
+/// Records the builders that accepted a raw transaction, warning if they
+/// returned different hashes for the same transaction.
+fn record_accepted_transactions(
+    fanout: &FanoutWrite,
+    rpc_request: &RpcRequest,
+    accepted: &[AcceptedTransaction],
+    tx_events: bool,
+    span: &Span,
+) {
+    let Some(first) = accepted.first() else {
+        return;
+    };
+    span.record("tx.hash", first.hash.to_string());
+    if accepted.iter().any(|tx| tx.hash != first.hash) {
+        let hashes = accepted
+            .iter()
+            .map(|tx| tx.hash.to_string())
+            .collect::<Vec<_>>();
+        warn!(target: "tx-proxy::validation", request.id = %rpc_request.request_id, ?hashes, "builders returned different transaction hashes");
+    }
+
+    let sender = tx_events
+        .then(|| rpc_request.sender_and_nonce())
+        .flatten()
+        .map(|(sender, _)| sender.to_string());
+    for tx in accepted {
+        fanout.record_transaction_accepted(tx.index);
+        if tx_events {
+            let target = fanout.target_url(tx.index).map(|url| url.to_string());
+            info!(
+                target: TX_EVENTS_TARGET,
+                tx.hash = %tx.hash,
+                tx.sender = sender,
+                builder = target,
+                latency_ms = tx.elapsed.as_millis() as u64,
+                request.id = %rpc_request.request_id,
+                "tx_submitted"
+            );
+        }
+    }
+}
+
+/// Returns the correlation id to the caller.
+pub(crate) fn with_request_id(mut response: HttpResponse, request_id: &str) -> HttpResponse {
+    if let Ok(value) = HeaderValue::from_str(request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Returns a JSON-RPC error to the caller when no builder returned a valid response.
+fn upstream_corruption_response(id: serde_json::Value) -> HttpResponse {
+    error_response(
+        id,
+        INTERNAL_ERROR_CODE,
+        "No valid response received from upstream, responses were malformed or corrupted",
+    )
+}
+
+/// Returns a JSON-RPC error to the caller when too few builders returned a result.
+fn insufficient_successes_response(
+    id: serde_json::Value,
+    err: &InsufficientSuccesses,
+) -> HttpResponse {
+    error_response(
+        id,
+        INTERNAL_ERROR_CODE,
+        format!(
+            "Only {} of the {} required builders accepted the request",
+            err.succeeded, err.required
+        ),
+    )
+}
+
+/// Returns a JSON-RPC error to the caller when builders returned different
+/// results and no result was returned by more builders than the others.
+fn inconsistent_results_response(id: serde_json::Value) -> HttpResponse {
+    error_response(
+        id,
+        INTERNAL_ERROR_CODE,
+        "Builders returned different results with no majority",
+    )
+}
+
+/// Returns a JSON-RPC error to the caller when the max fee per gas of a
+/// transaction is above the cap.
+fn fee_cap_exceeded_response(id: serde_json::Value, max_fee: u128, cap: u128) -> HttpResponse {
+    error_response(
+        id,
+        FEE_CAP_EXCEEDED_CODE,
+        format!("Transaction max fee per gas {max_fee} wei exceeds the cap of {cap} wei"),
+    )
+}
+
+/// Returns the result of a method answered by the proxy itself.
+fn local_response(id: serde_json::Value, result: &serde_json::Value) -> HttpResponse {
+    HttpResponse::builder()
+        .status(200)
+        .header("Content-Type", "application/json")
+        .body(HttpBody::from(
+            serde_json::json!({ "jsonrpc": "2.0", "result": result, "id": id }).to_string(),
+        ))
+        .unwrap()
+}
+
+/// Returns a JSON-RPC parse error to the caller when the body is not a valid request.
+pub(crate) fn parse_error_response() -> HttpResponse {
+    let error = ErrorObject::owned(PARSE_ERROR_CODE, PARSE_ERROR_MSG, None::<()>);
     HttpResponse::builder()
         .status(200)
         .header("Content-Type", "application/json")
         .body(HttpBody::from(
-            ErrorObject::owned(-32601, "Method not found", None::<()>).to_string(),
+            serde_json::json!({ "jsonrpc": "2.0", "error": error, "id": null }).to_string(),
         ))
         .unwrap()
 }
+
+/// Acknowledges a notification without a JSON-RPC response body.
+pub(crate) fn notification_response() -> HttpResponse {
+    HttpResponse::builder()
+        .status(StatusCode::NO_CONTENT)
+        .body(HttpBody::from(String::new()))
+        .unwrap()
+}
+
+/// Rejects a notification, which cannot be correlated with a response.
+fn notification_rejected_response() -> HttpResponse {
+    error_response(
+        serde_json::Value::Null,
+        INVALID_REQUEST_CODE,
+        "Notifications are not supported, requests must have an id",
+    )
+}
@@ -0,0 +1,197 @@
+//! Counts heap allocations with a global allocator, which sees every
+//! allocation of the process, so it lives in its own test binary and its
+//! tests run one at a time.
+
+use clap::Parser;
+use eyre::Result;
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    net::SocketAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tokio::{net::TcpListener, sync::Mutex};
+use tx_proxy::{
+    cli::Cli,
+    fanout::{SelectionStrategy, select_response},
+    rpc::{PbhErrorMatcher, RpcRequest},
+};
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+const BUILDERS: usize = 3;
+const RESULT_LEN: usize = 1024 * 1024;
+const REQUEST_LEN: usize = 200 * 1024;
+
+/// Held by each test, so the allocations of another test are not counted.
+static SERIAL: Mutex<()> = Mutex::const_new(());
+
+/// Counts the bytes allocated by the process.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATED.fetch_add(new_size, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Serves the same JSON-RPC success to every request, without copying it.
+async fn serve(body: Bytes) -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let body = body.clone();
+            tokio::spawn(async move {
+                let _ = http1::Builder::new()
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(move |_| {
+                            let body = body.clone();
+                            async move {
+                                Ok::<_, hyper::Error>(
+                                    hyper::Response::builder()
+                                        .header("content-type", "application/json")
+                                        .body(Full::new(body))
+                                        .unwrap(),
+                                )
+                            }
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+    Ok(addr)
+}
+
+async fn eth_call_request() -> Result<RpcRequest> {
+    let request = http::Request::builder()
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(jsonrpsee::http_client::HttpBody::from(
+            r#"{"jsonrpc":"2.0","method":"eth_call","params":[],"id":1}"#,
+        ))?;
+    RpcRequest::from_request(request).await
+}
+
+/// A conditional transaction with a large body.
+async fn large_request() -> Result<RpcRequest> {
+    let body = format!(
+        r#"{{"jsonrpc":"2.0","method":"eth_sendRawTransactionConditional","params":["0x{}",{{}}],"id":1}}"#,
+        "ab".repeat(REQUEST_LEN / 2)
+    );
+    let request = http::Request::builder()
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(jsonrpsee::http_client::HttpBody::from(body))?;
+    RpcRequest::from_request(request).await
+}
+
+#[tokio::test]
+async fn test_identical_responses_allocations() -> Result<()> {
+    let _serial = SERIAL.lock().await;
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let body = Bytes::from(format!(
+        r#"{{"jsonrpc":"2.0","result":"0x{}","id":1}}"#,
+        "ab".repeat(RESULT_LEN / 2)
+    ));
+    let mut args = vec!["tx-proxy".to_string()];
+    for _ in 0..BUILDERS {
+        let addr = serve(body.clone()).await?;
+        args.push(format!("--builder-urls=http://{addr}"));
+    }
+    args.extend([
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+    ]);
+    let fanout = Cli::try_parse_from(args)?.targets()?.builder;
+
+    // Opens the connections and grows the connection buffers
+    let result = fanout.fan_request_all(eth_call_request().await?).await;
+    assert_eq!(result.successes().count(), BUILDERS);
+
+    let request = eth_call_request().await?;
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    let result = fanout.fan_request_all(request).await;
+    let forwarded = ALLOCATED.load(Ordering::Relaxed) - start;
+    assert_eq!(result.successes().count(), BUILDERS);
+
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    let responses = result.into_distinct_responses(SelectionStrategy::DeclarationOrder)?;
+    let distinct = responses.len();
+    let response = select_response(responses, &PbhErrorMatcher::default()).unwrap();
+    let captured = response.body.clone();
+    let selected = ALLOCATED.load(Ordering::Relaxed) - start;
+
+    // Identical responses are considered once
+    assert_eq!(distinct, 1);
+    assert_eq!(captured, body);
+    // The body is buffered once, then shared with the response
+    assert!(
+        forwarded / BUILDERS < 3 * body.len(),
+        "allocated {} bytes per builder to forward a {} bytes body",
+        forwarded / BUILDERS,
+        body.len()
+    );
+    // Selection does not copy bodies
+    assert!(
+        selected < body.len() / 100,
+        "allocated {selected} bytes to select among {BUILDERS} {} bytes bodies",
+        body.len()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_request_fanout_allocations() -> Result<()> {
+    let _serial = SERIAL.lock().await;
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    // The builders refuse connections, so only the copies made by the proxy are counted
+    let mut args = vec!["tx-proxy".to_string()];
+    for port in 1..=BUILDERS {
+        args.push(format!("--builder-urls=http://127.0.0.1:{port}"));
+    }
+    args.extend([
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+    ]);
+    let fanout = Cli::try_parse_from(args)?.targets()?.builder;
+
+    // Sets up the connection pools
+    let _ = fanout.fan_request_all(large_request().await?).await;
+
+    let request = large_request().await?;
+    let body_len = request.body.len();
+    let start = ALLOCATED.load(Ordering::Relaxed);
+    let result = fanout.fan_request_all(request).await;
+    let forwarded = ALLOCATED.load(Ordering::Relaxed) - start;
+    assert_eq!(result.successes().count(), 0);
+
+    // The body is shared by every target rather than copied for each
+    assert!(
+        forwarded < body_len,
+        "allocated {forwarded} bytes to fan a {body_len} bytes body to {BUILDERS} builders"
+    );
+    Ok(())
+}
@@ -0,0 +1,53 @@
+//! Runs the full CLI, which installs process-wide state (TLS provider, tracing
+//! subscriber and metrics recorder), so it lives in its own test binary.
+
+use clap::Parser;
+use eyre::Result;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tx_proxy::cli::Cli;
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+#[tokio::test]
+async fn test_metrics_optional_keeps_serving() -> Result<()> {
+    // Occupy the metrics port so the metrics server fails to bind
+    let metrics_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let metrics_port = metrics_listener.local_addr()?.port();
+
+    let temp_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let http_port = temp_listener.local_addr()?.port();
+    drop(temp_listener);
+
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        "--builder-urls=http://127.0.0.1:1".to_string(),
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        format!("--http-port={http_port}"),
+        "--metrics".to_string(),
+        "--metrics-host=127.0.0.1".to_string(),
+        format!("--metrics-port={metrics_port}"),
+        "--metrics-optional".to_string(),
+    ])?;
+    let run = tokio::spawn(cli.run());
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!run.is_finished());
+
+    // Rejected by the validation layer without contacting the targets
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{http_port}"))
+        .header("content-type", "application/json")
+        .body(r#"{"jsonrpc":"2.0","method":"debug_traceTransaction","params":[],"id":1}"#)
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(response.contains("Method not found"));
+
+    run.abort();
+    drop(metrics_listener);
+    Ok(())
+}
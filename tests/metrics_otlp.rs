@@ -0,0 +1,125 @@
+//! Runs the full CLI, which installs process-wide state (TLS provider, tracing
+//! subscriber and metrics recorder), so it lives in its own test binary.
+
+use clap::Parser;
+use eyre::Result;
+use http::HeaderMap;
+use http_body_util::{BodyExt, StreamBody};
+use hyper::{
+    body::{Bytes, Frame},
+    server::conn::http2,
+    service::service_fn,
+};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::net::TcpListener;
+use tx_proxy::cli::Cli;
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+const EXPORT_PATH: &str = "/opentelemetry.proto.collector.metrics.v1.MetricsService/Export";
+
+/// The path and body of the requests received by the [`collector`].
+type Exports = Arc<Mutex<Vec<(String, Bytes)>>>;
+
+/// Serves an OTLP gRPC collector accepting every export.
+async fn collector() -> Result<(SocketAddr, Exports)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let exports = Exports::default();
+    let received = exports.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let received = received.clone();
+            tokio::spawn(async move {
+                let _ = http2::Builder::new(TokioExecutor::new())
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                            let received = received.clone();
+                            async move {
+                                let path = req.uri().path().to_string();
+                                let body = req.into_body().collect().await?.to_bytes();
+                                received.lock().unwrap().push((path, body));
+
+                                // An empty response message, then an OK status
+                                let mut trailers = HeaderMap::new();
+                                trailers.insert("grpc-status", "0".parse().unwrap());
+                                let frames = vec![
+                                    Ok::<_, Infallible>(Frame::data(Bytes::from_static(&[0; 5]))),
+                                    Ok(Frame::trailers(trailers)),
+                                ];
+                                Ok::<_, hyper::Error>(
+                                    hyper::Response::builder()
+                                        .header("content-type", "application/grpc")
+                                        .body(StreamBody::new(futures::stream::iter(frames)))
+                                        .unwrap(),
+                                )
+                            }
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+    Ok((addr, exports))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_metrics_pushed_to_otlp_endpoint() -> Result<()> {
+    let (collector_addr, exports) = collector().await?;
+
+    let temp_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let http_port = temp_listener.local_addr()?.port();
+    drop(temp_listener);
+
+    // Only the OTLP exporter, without the Prometheus endpoint
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        "--builder-urls=http://127.0.0.1:1".to_string(),
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        format!("--http-port={http_port}"),
+        "--metrics-otlp".to_string(),
+        "--metrics-otlp-interval-ms=100".to_string(),
+        format!("--otlp-endpoint=http://{collector_addr}"),
+    ])?;
+    let run = tokio::spawn(cli.run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let response = reqwest::Client::new()
+        .post(format!("http://127.0.0.1:{http_port}"))
+        .header("content-type", "application/json")
+        .body(r#"{"jsonrpc":"2.0","method":"debug_traceTransaction","params":[],"id":1}"#)
+        .send()
+        .await?
+        .text()
+        .await?;
+    assert!(response.contains("Method not found"));
+
+    let exported = |name: &[u8]| {
+        exports.lock().unwrap().iter().any(|(path, body)| {
+            path == EXPORT_PATH && body.windows(name.len()).any(|window| window == name)
+        })
+    };
+    let mut pushed = false;
+    for _ in 0..50 {
+        if exported(b"inbound_requests") {
+            pushed = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(pushed, "inbound_requests was not exported");
+    // The resource attributes of traces are reused
+    assert!(exported(b"service.name"));
+    assert!(exported(env!("CARGO_PKG_NAME").as_bytes()));
+
+    run.abort();
+    Ok(())
+}
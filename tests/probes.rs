@@ -0,0 +1,54 @@
+//! Runs the full CLI, which installs process-wide state (TLS provider and tracing
+//! subscriber), so it lives in its own test binary.
+
+use clap::Parser;
+use eyre::Result;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tx_proxy::cli::Cli;
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+/// Returns a port that was free when this was called.
+async fn free_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    Ok(listener.local_addr()?.port())
+}
+
+#[tokio::test]
+async fn test_probes_served_without_metrics() -> Result<()> {
+    let http_port = free_port().await?;
+    let metrics_port = free_port().await?;
+
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        "--builder-urls=http://127.0.0.1:1".to_string(),
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        format!("--http-port={http_port}"),
+        "--probes".to_string(),
+        "--metrics-host=127.0.0.1".to_string(),
+        format!("--metrics-port={metrics_port}"),
+    ])?;
+    let run = tokio::spawn(cli.run());
+
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!run.is_finished());
+
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        client
+            .get(format!("http://127.0.0.1:{metrics_port}{path}"))
+            .send()
+    };
+    assert_eq!(get("/healthz").await?.status(), reqwest::StatusCode::OK);
+    // Metrics are not enabled, so only the probes and admin endpoints are served
+    assert_eq!(
+        get("/metrics").await?.status(),
+        reqwest::StatusCode::NOT_FOUND
+    );
+
+    run.abort();
+    Ok(())
+}
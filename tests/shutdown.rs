@@ -0,0 +1,67 @@
+//! Runs the full CLI and stops it with a SIGTERM sent to the test process, so
+//! it lives in its own test binary.
+
+use clap::Parser;
+use eyre::Result;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tx_proxy::cli::Cli;
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_hung_request_bounded_by_shutdown_grace() -> Result<()> {
+    // A builder accepting connections but never responding
+    let builder = TcpListener::bind("127.0.0.1:0").await?;
+    let builder_addr = builder.local_addr()?;
+    tokio::spawn(async move {
+        let mut connections = vec![];
+        while let Ok((stream, _)) = builder.accept().await {
+            connections.push(stream);
+        }
+    });
+
+    let temp_listener = TcpListener::bind("127.0.0.1:0").await?;
+    let http_port = temp_listener.local_addr()?.port();
+    drop(temp_listener);
+
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        format!("--builder-urls=http://{builder_addr}"),
+        format!("--builder-jwt-token={SECRET}"),
+        "--builder-timeout=60000".to_string(),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        format!("--http-port={http_port}"),
+        "--startup-probe-timeout-secs=0".to_string(),
+        "--shutdown-grace-secs=1".to_string(),
+    ])?;
+    let run = tokio::spawn(cli.run());
+    tokio::time::sleep(Duration::from_secs(1)).await;
+
+    let request = tokio::spawn(
+        reqwest::Client::new()
+            .post(format!("http://127.0.0.1:{http_port}"))
+            .header("content-type", "application/json")
+            .body(r#"{"jsonrpc":"2.0","method":"eth_blockNumber","params":[],"id":1}"#)
+            .send(),
+    );
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(!request.is_finished());
+
+    let start = Instant::now();
+    let status = tokio::process::Command::new("kill")
+        .args(["-TERM", &std::process::id().to_string()])
+        .status()
+        .await?;
+    assert!(status.success());
+
+    // The hung request is waited for, but only for the grace period
+    tokio::time::timeout(Duration::from_secs(10), run).await???;
+    let elapsed = start.elapsed();
+    assert!(elapsed >= Duration::from_secs(1), "{elapsed:?}");
+    assert!(elapsed < Duration::from_secs(5), "{elapsed:?}");
+
+    request.abort();
+    Ok(())
+}
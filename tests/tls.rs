@@ -0,0 +1,160 @@
+//! Overrides the native certificate store through `SSL_CERT_FILE`, which is
+//! process-wide, so it lives in its own test binary.
+
+use clap::Parser;
+use eyre::Result;
+use http_body_util::Full;
+use hyper::{body::Bytes, server::conn::http1, service::service_fn};
+use hyper_util::rt::TokioIo;
+use rustls::pki_types::PrivatePkcs8KeyDer;
+use std::{net::SocketAddr, sync::Arc};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tx_proxy::cli::Cli;
+use tx_proxy::rpc::RpcRequest;
+
+const SECRET: &str = "f79ae8046bc11c9927afe911db7143c51a806c4a537cc08e0d37140b0192f430";
+const SUCCESS_BODY: &str = r#"{"jsonrpc":"2.0","result":"0x1234","id":1}"#;
+
+/// Serves a JSON-RPC success over TLS, with a certificate for `localhost`
+/// issued by a new CA. Returns the address and the PEM of the CA.
+async fn serve_tls() -> Result<(SocketAddr, String)> {
+    let ca_key = rcgen::KeyPair::generate()?;
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key)?;
+    let key = rcgen::KeyPair::generate()?;
+    let cert = rcgen::CertificateParams::new(vec!["localhost".to_string()])?
+        .signed_by(&key, &ca, &ca_key)?;
+
+    let config = rustls::ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![cert.der().clone()],
+            PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+        )?;
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let Ok(stream) = acceptor.accept(stream).await else {
+                    return;
+                };
+                let _ = http1::Builder::new()
+                    .serve_connection(
+                        TokioIo::new(stream),
+                        service_fn(|_| async {
+                            Ok::<_, hyper::Error>(hyper::Response::new(Full::new(
+                                Bytes::from_static(SUCCESS_BODY.as_bytes()),
+                            )))
+                        }),
+                    )
+                    .await;
+            });
+        }
+    });
+
+    Ok((addr, ca.pem()))
+}
+
+async fn send_raw_transaction_request() -> Result<RpcRequest> {
+    let request = http::Request::builder()
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(jsonrpsee::http_client::HttpBody::from(
+            r#"{"jsonrpc":"2.0","method":"eth_sendRawTransaction","params":["0x1234"],"id":1}"#,
+        ))?;
+    RpcRequest::from_request(request).await
+}
+
+#[tokio::test]
+async fn test_builder_ca_file() -> Result<()> {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    let (addr, ca) = serve_tls().await?;
+    let ca_path = std::env::temp_dir().join(format!("tx-proxy-ca-{}.pem", std::process::id()));
+    std::fs::write(&ca_path, ca)?;
+
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        format!("--builder-urls=https://localhost:{}", addr.port()),
+        format!("--builder-jwt-token={SECRET}"),
+        format!("--builder-ca-file={}", ca_path.display()),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        "--l2-use-webpki-roots".to_string(),
+    ])?;
+    let responses = cli
+        .targets()?
+        .builder
+        .fan_request(send_raw_transaction_request().await?)
+        .await
+        .unwrap();
+    assert!(responses[0].is_success());
+
+    // The bundled roots do not trust the CA
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        format!("--builder-urls=https://localhost:{}", addr.port()),
+        format!("--builder-jwt-token={SECRET}"),
+        "--builder-use-webpki-roots".to_string(),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+        "--l2-use-webpki-roots".to_string(),
+    ])?;
+    let result = cli
+        .targets()?
+        .builder
+        .fan_request_all(send_raw_transaction_request().await?)
+        .await;
+    assert!(result.first_success().is_none());
+
+    // Only one source of root certificates can be configured
+    assert!(
+        Cli::try_parse_from([
+            "tx-proxy".to_string(),
+            format!("--builder-ca-file={}", ca_path.display()),
+            "--builder-use-webpki-roots".to_string(),
+        ])
+        .is_err()
+    );
+
+    std::fs::remove_file(ca_path)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_native_roots() -> Result<()> {
+    let _ = rustls::crypto::ring::default_provider().install_default();
+
+    // An empty store, as in images without system certificates
+    let empty = std::env::temp_dir().join(format!("tx-proxy-no-roots-{}.pem", std::process::id()));
+    std::fs::write(&empty, "")?;
+    // SAFETY: the other test in this binary does not depend on the native roots
+    unsafe {
+        std::env::set_var("SSL_CERT_FILE", &empty);
+        std::env::remove_var("SSL_CERT_DIR");
+    }
+
+    let cli = Cli::try_parse_from([
+        "tx-proxy".to_string(),
+        "--builder-urls=https://localhost:1".to_string(),
+        format!("--builder-jwt-token={SECRET}"),
+        "--l2-urls=http://127.0.0.1:1".to_string(),
+        format!("--l2-jwt-token={SECRET}"),
+    ])?;
+    let err = cli.targets().unwrap_err();
+    let message = format!("{err:#}");
+    assert!(
+        message.contains("no native root CA certificates found"),
+        "{message}"
+    );
+    assert!(message.contains("--builder-ca-file"), "{message}");
+    assert!(message.contains("--builder-use-webpki-roots"), "{message}");
+
+    std::fs::remove_file(empty)?;
+    Ok(())
+}